@@ -2,6 +2,7 @@ use crate::mocks::monitor::MonitorActor;
 use crate::mocks::oracle::OracleActor;
 use crate::mocks::price_feed::PriceFeedActor;
 use crate::mocks::wallet::WalletActor;
+use daemon::access_control;
 use daemon::auto_rollover;
 use daemon::bdk::bitcoin::secp256k1::schnorrsig;
 use daemon::bdk::bitcoin::Amount;
@@ -9,12 +10,17 @@ use daemon::bdk::bitcoin::Network;
 use daemon::connection::connect;
 use daemon::connection::ConnectionStatus;
 use daemon::db;
+use daemon::discovery;
 use daemon::libp2p_utils::create_connect_tcp_multiaddr;
+use daemon::libp2p_utils::libp2p_socket_from_legacy_networking;
 use daemon::maker_cfd;
+use daemon::process_manager::COLLAB_SETTLEMENT_BROADCAST_DELAY;
 use daemon::projection;
 use daemon::projection::Cfd;
 use daemon::projection::Feeds;
 use daemon::projection::MakerOffers;
+use daemon::rollover_maker;
+use daemon::rollover_taker;
 use daemon::seed::RandomSeed;
 use daemon::seed::Seed;
 use daemon::MakerActorSystem;
@@ -31,9 +37,11 @@ use model::Usd;
 use model::SETTLEMENT_INTERVAL;
 use rust_decimal::Decimal;
 use rust_decimal_macros::dec;
+use std::collections::HashMap;
 use std::net::IpAddr;
 use std::net::Ipv4Addr;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::time::Duration;
 use time::OffsetDateTime;
@@ -44,8 +52,12 @@ use tracing::subscriber::DefaultGuard;
 use tracing_subscriber::filter::LevelFilter;
 use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::EnvFilter;
+use xtra::message_channel::MessageChannel;
 use xtra::Actor;
+use xtra_bitmex_price_feed::LatestQuote;
 use xtra_bitmex_price_feed::Quote;
+use xtra_libp2p::libp2p::identity::Keypair;
+use xtra_libp2p::libp2p::Multiaddr;
 use xtra_libp2p::libp2p::PeerId;
 
 pub mod flow;
@@ -77,6 +89,7 @@ pub struct MakerConfig {
     seed: RandomSeed,
     pub heartbeat_interval: Duration,
     n_payouts: usize,
+    rollover_policy: rollover_maker::Policy,
     dedicated_port: Option<u16>,
 }
 
@@ -94,6 +107,13 @@ impl MakerConfig {
             ..self
         }
     }
+
+    pub fn with_rollover_policy(self, policy: rollover_maker::Policy) -> Self {
+        Self {
+            rollover_policy: policy,
+            ..self
+        }
+    }
 }
 
 impl Default for MakerConfig {
@@ -103,6 +123,7 @@ impl Default for MakerConfig {
             seed: RandomSeed::default(),
             heartbeat_interval: HEARTBEAT_INTERVAL,
             n_payouts: N_PAYOUTS,
+            rollover_policy: rollover_maker::Policy::default(),
             dedicated_port: None,
         }
     }
@@ -114,6 +135,10 @@ pub struct TakerConfig {
     seed: RandomSeed,
     pub heartbeat_interval: Duration,
     n_payouts: usize,
+    rollover_policy: rollover_taker::Policy,
+    settlement_counter_offer_tolerance: Option<Decimal>,
+    settlement_broadcast_delay: Duration,
+    price_feed_replay: Option<(&'static str, f64)>,
 }
 
 impl TakerConfig {
@@ -123,6 +148,36 @@ impl TakerConfig {
             ..self
         }
     }
+
+    /// Replay the given price feed recording at the given speed instead of using the price feed
+    /// mock.
+    pub fn with_price_feed_replay(self, recording: &'static str, speed: f64) -> Self {
+        Self {
+            price_feed_replay: Some((recording, speed)),
+            ..self
+        }
+    }
+
+    pub fn with_rollover_policy(self, policy: rollover_taker::Policy) -> Self {
+        Self {
+            rollover_policy: policy,
+            ..self
+        }
+    }
+
+    pub fn with_settlement_counter_offer_tolerance(self, tolerance: Decimal) -> Self {
+        Self {
+            settlement_counter_offer_tolerance: Some(tolerance),
+            ..self
+        }
+    }
+
+    pub fn with_settlement_broadcast_delay(self, delay: Duration) -> Self {
+        Self {
+            settlement_broadcast_delay: delay,
+            ..self
+        }
+    }
 }
 
 impl Default for TakerConfig {
@@ -132,6 +187,10 @@ impl Default for TakerConfig {
             seed: RandomSeed::default(),
             heartbeat_interval: HEARTBEAT_INTERVAL,
             n_payouts: N_PAYOUTS,
+            rollover_policy: rollover_taker::Policy::default(),
+            settlement_counter_offer_tolerance: None,
+            settlement_broadcast_delay: COLLAB_SETTLEMENT_BROADCAST_DELAY,
+            price_feed_replay: None,
         }
     }
 }
@@ -144,6 +203,7 @@ pub struct Maker {
     pub listen_addr: SocketAddr,
     pub identity: Identity,
     pub peer_id: PeerId,
+    libp2p_identity: Keypair,
     _tasks: Tasks,
 }
 
@@ -216,10 +276,14 @@ impl Maker {
             },
             settlement_interval,
             config.n_payouts,
+            config.rollover_policy,
             projection_actor,
             identities.clone(),
             config.heartbeat_interval,
             address,
+            None,
+            access_control::Policy::default(),
+            access_control::Policy::default(),
         )
         .unwrap();
 
@@ -230,7 +294,8 @@ impl Maker {
             oracle_mock.unwrap(),
         );
 
-        let (proj_actor, feeds) = projection::Actor::new(db, Network::Testnet, &price_feed_addr);
+        let (proj_actor, feeds) =
+            projection::Actor::new(db, Network::Testnet, price_feed_addr.clone_channel());
         tasks.add(projection_context.run(proj_actor));
 
         Self {
@@ -241,9 +306,34 @@ impl Maker {
             mocks,
             _tasks: tasks,
             peer_id: identities.peer_id(),
+            libp2p_identity: identities.libp2p,
         }
     }
 
+    /// The address of the maker's libp2p endpoint, including its peer ID.
+    pub fn libp2p_address(&self) -> Multiaddr {
+        create_connect_tcp_multiaddr(
+            &libp2p_socket_from_legacy_networking(&self.listen_addr),
+            self.peer_id,
+        )
+        .expect("to be able to construct Multiaddr")
+    }
+
+    /// Register at the given rendezvous node, so that takers can discover this maker.
+    pub fn register_at_rendezvous(&mut self, rendezvous_node: Multiaddr) {
+        discovery::register_maker(
+            self.system.endpoint.clone(),
+            self.libp2p_identity.clone(),
+            rendezvous_node,
+            self.identity,
+            self.listen_addr,
+            None,
+            self.feeds.offers.clone(),
+            &mut self._tasks,
+        )
+        .expect("to be able to register at rendezvous node");
+    }
+
     pub async fn set_offer_params(&mut self, offer_params: maker_cfd::OfferParams) {
         let maker_cfd::OfferParams {
             price_long,
@@ -274,7 +364,7 @@ impl Maker {
 /// Taker Test Setup
 pub struct Taker {
     pub id: Identity,
-    pub system: daemon::TakerActorSystem<OracleActor, WalletActor, PriceFeedActor>,
+    pub system: daemon::TakerActorSystem<OracleActor, WalletActor>,
     pub mocks: mocks::Mocks,
     pub feeds: Feeds,
     _tasks: Tasks,
@@ -293,8 +383,14 @@ impl Taker {
         &mut self.feeds.quote
     }
 
-    pub fn maker_status_feed(&mut self) -> &mut watch::Receiver<ConnectionStatus> {
-        &mut self.system.maker_online_status_feed_receiver
+    pub fn maker_status_feed(
+        &mut self,
+    ) -> &mut watch::Receiver<HashMap<Identity, ConnectionStatus>> {
+        &mut self.system.makers_online_status_feed_receiver
+    }
+
+    pub fn all_offers_feed(&mut self) -> &mut watch::Receiver<Vec<projection::CfdOrder>> {
+        &mut self.feeds.all_offers
     }
 
     pub async fn start(
@@ -302,6 +398,13 @@ impl Taker {
         maker_address: SocketAddr,
         maker_identity: Identity,
         maker_peer_id: PeerId,
+    ) -> Self {
+        Self::start_with_makers(config, vec![(maker_address, maker_identity, maker_peer_id)]).await
+    }
+
+    pub async fn start_with_makers(
+        config: &TakerConfig,
+        makers: Vec<(SocketAddr, Identity, PeerId)>,
     ) -> Self {
         let identities = config.seed.derive_identities();
 
@@ -314,13 +417,31 @@ impl Taker {
 
         let wallet_addr = wallet.create(None).spawn(&mut tasks);
 
+        let price_feed: Box<dyn MessageChannel<LatestQuote>> = match config.price_feed_replay {
+            Some((recording, speed)) => {
+                xtra_bitmex_price_feed::replay::Actor::new(PathBuf::from(recording), speed)
+                    .unwrap()
+                    .create(None)
+                    .spawn(&mut tasks)
+                    .clone_channel()
+            }
+            None => price_feed.create(None).spawn(&mut tasks).clone_channel(),
+        };
+
         let (projection_actor, projection_context) = xtra::Context::new(None);
 
         let mut oracle_mock = None;
         let mut monitor_mock = None;
 
-        let maker_multiaddr = create_connect_tcp_multiaddr(&maker_address, maker_peer_id)
-            .expect("to be able to construct Multiaddr");
+        let maker_multiaddrs = makers
+            .iter()
+            .map(|(address, identity, peer_id)| {
+                let multiaddr = create_connect_tcp_multiaddr(address, *peer_id)
+                    .expect("to be able to construct Multiaddr");
+
+                (*identity, multiaddr)
+            })
+            .collect();
 
         let taker = daemon::TakerActorSystem::new(
             db.clone(),
@@ -339,13 +460,16 @@ impl Taker {
 
                 Ok(monitor)
             },
-            move || price_feed.clone(),
+            price_feed.clone_channel(),
             config.n_payouts,
+            config.rollover_policy,
+            config.settlement_counter_offer_tolerance,
             config.heartbeat_interval,
             Duration::from_secs(10),
+            config.settlement_broadcast_delay,
             projection_actor,
-            maker_identity,
-            maker_multiaddr,
+            maker_multiaddrs,
+            None,
         )
         .unwrap();
 
@@ -356,16 +480,17 @@ impl Taker {
             oracle_mock.unwrap(),
         );
 
-        let (proj_actor, feeds) =
-            projection::Actor::new(db, Network::Testnet, &taker.price_feed_actor);
+        let (proj_actor, feeds) = projection::Actor::new(db, Network::Testnet, price_feed);
         tasks.add(projection_context.run(proj_actor));
 
-        tasks.add(connect(
-            taker.maker_online_status_feed_receiver.clone(),
-            taker.connection_actor.clone(),
-            maker_identity,
-            vec![maker_address],
-        ));
+        for (maker_address, maker_identity, _) in makers {
+            tasks.add(connect(
+                taker.makers_online_status_feed_receiver.clone(),
+                taker.connection_actor.clone(),
+                maker_identity,
+                vec![maker_address.into()],
+            ));
+        }
 
         Self {
             id: model::Identity::new(identities.identity_pk),
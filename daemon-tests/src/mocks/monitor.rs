@@ -1,3 +1,4 @@
+use anyhow::bail;
 use anyhow::Result;
 use async_trait::async_trait;
 use daemon::command;
@@ -10,15 +11,13 @@ use xtra_productivity::xtra_productivity;
 /// Test Stub simulating the Monitor actor.
 /// Serves as an entrypoint for injected mock handlers.
 pub struct MonitorActor {
-    _mock: Arc<Mutex<MockMonitor>>,
+    mock: Arc<Mutex<MockMonitor>>,
 }
 
 impl MonitorActor {
     pub fn new(executor: command::Executor) -> (Self, Arc<Mutex<MockMonitor>>) {
         let mock = Arc::new(Mutex::new(MockMonitor::new(executor)));
-        let actor = Self {
-            _mock: mock.clone(),
-        };
+        let actor = Self { mock: mock.clone() };
 
         (actor, mock)
     }
@@ -39,8 +38,8 @@ impl MonitorActor {
 
     async fn handle(&mut self, _: monitor::MonitorCollaborativeSettlement) {}
 
-    async fn handle(&mut self, _: monitor::TryBroadcastTransaction) -> Result<()> {
-        Ok(())
+    async fn handle(&mut self, msg: monitor::TryBroadcastTransaction) -> Result<()> {
+        self.mock.lock().await.broadcast(msg)
     }
 
     async fn handle(&mut self, _: monitor::MonitorCetFinality) -> Result<()> {
@@ -50,11 +49,40 @@ impl MonitorActor {
 
 pub struct MockMonitor {
     executor: command::Executor,
+    failing_broadcasts: usize,
+    collaborative_settlement_broadcasts: usize,
 }
 
 impl MockMonitor {
     pub fn new(executor: command::Executor) -> Self {
-        MockMonitor { executor }
+        MockMonitor {
+            executor,
+            failing_broadcasts: 0,
+            collaborative_settlement_broadcasts: 0,
+        }
+    }
+
+    /// Fail the next `n` attempts to broadcast a transaction.
+    pub fn fail_broadcasts(&mut self, n: usize) {
+        self.failing_broadcasts = n;
+    }
+
+    /// How many times a collaborative settlement transaction was broadcast successfully.
+    pub fn collaborative_settlement_broadcasts(&self) -> usize {
+        self.collaborative_settlement_broadcasts
+    }
+
+    fn broadcast(&mut self, msg: monitor::TryBroadcastTransaction) -> Result<()> {
+        if self.failing_broadcasts > 0 {
+            self.failing_broadcasts -= 1;
+            bail!("Failed to broadcast transaction {}", msg.tx.txid());
+        }
+
+        if let monitor::TransactionKind::CollaborativeClose = msg.kind {
+            self.collaborative_settlement_broadcasts += 1;
+        }
+
+        Ok(())
     }
 
     pub async fn confirm_lock_transaction(&mut self, id: OrderId) {
@@ -1,10 +1,14 @@
 use anyhow::Context;
+use daemon::access_control;
 use daemon::bdk::bitcoin::Amount;
 use daemon::connection::ConnectionStatus;
 use daemon::connection::MAX_RECONNECT_INTERVAL_SECONDS;
+use daemon::discovery;
 use daemon::projection::CfdOrder;
 use daemon::projection::CfdState;
 use daemon::projection::MakerOffers;
+use daemon::rollover_maker;
+use daemon::rollover_taker;
 use daemon_tests::dummy_offer_params;
 use daemon_tests::dummy_quote;
 use daemon_tests::flow::is_next_offers_none;
@@ -27,10 +31,15 @@ use model::olivia;
 use model::Identity;
 use model::OrderId;
 use model::Position;
+use model::Price;
+use model::TxFeeRate;
 use model::Usd;
 use rust_decimal_macros::dec;
+use std::collections::HashSet;
 use std::time::Duration;
 use tokio::time::sleep;
+use xtra_bitmex_price_feed::Quote;
+use xtra_libp2p::libp2p::PeerId;
 
 macro_rules! confirm {
     (lock transaction, $id:expr, $maker:expr, $taker:expr) => {
@@ -134,6 +143,167 @@ async fn taker_receives_order_from_maker_on_publication() {
     assert_eq_offers(published, received);
 }
 
+#[tokio::test]
+async fn taker_receives_offers_from_all_makers() {
+    let _guard = init_tracing();
+    let mut maker_1 = Maker::start(&MakerConfig::default()).await;
+    let mut maker_2 = Maker::start(&MakerConfig::default()).await;
+    let mut taker = Taker::start_with_makers(
+        &TakerConfig::default(),
+        vec![
+            (maker_1.listen_addr, maker_1.identity, maker_1.peer_id),
+            (maker_2.listen_addr, maker_2.identity, maker_2.peer_id),
+        ],
+    )
+    .await;
+
+    let statuses = next_with(taker.maker_status_feed(), |statuses| {
+        statuses
+            .values()
+            .all(|status| *status == ConnectionStatus::Online)
+            .then(|| statuses)
+    })
+    .await
+    .unwrap();
+    assert_eq!(statuses.len(), 2);
+
+    maker_1
+        .set_offer_params(dummy_offer_params(Position::Short))
+        .await;
+    maker_2
+        .set_offer_params(dummy_offer_params(Position::Long))
+        .await;
+
+    let offers = next_with(taker.all_offers_feed(), |offers| {
+        (offers.len() == 2).then(|| offers)
+    })
+    .await
+    .unwrap();
+
+    let short = offers
+        .iter()
+        .find(|offer| offer.position_maker == Position::Short)
+        .unwrap();
+    let long = offers
+        .iter()
+        .find(|offer| offer.position_maker == Position::Long)
+        .unwrap();
+    assert_eq!(short.maker, Some(maker_1.identity));
+    assert_eq!(long.maker, Some(maker_2.identity));
+}
+
+#[tokio::test]
+async fn cfd_with_second_maker_is_not_seen_by_first_maker() {
+    let _guard = init_tracing();
+    let mut maker_1 = Maker::start(&MakerConfig::default()).await;
+    let mut maker_2 = Maker::start(&MakerConfig::default()).await;
+    let mut taker = Taker::start_with_makers(
+        &TakerConfig::default(),
+        vec![
+            (maker_1.listen_addr, maker_1.identity, maker_1.peer_id),
+            (maker_2.listen_addr, maker_2.identity, maker_2.peer_id),
+        ],
+    )
+    .await;
+
+    next_with(taker.maker_status_feed(), |statuses| {
+        (statuses.len() == 2
+            && statuses
+                .values()
+                .all(|status| *status == ConnectionStatus::Online))
+        .then(|| statuses)
+    })
+    .await
+    .unwrap();
+
+    maker_2
+        .set_offer_params(dummy_offer_params(Position::Short))
+        .await;
+
+    let maker_2_identity = maker_2.identity;
+    let order_to_take = next_with(taker.all_offers_feed(), |offers| {
+        offers
+            .into_iter()
+            .find(|offer| offer.maker == Some(maker_2_identity))
+    })
+    .await
+    .unwrap();
+
+    let announcement = OliviaData::example_0().announcement();
+    taker
+        .mocks
+        .mock_oracle_announcement_with(announcement.clone())
+        .await;
+    maker_2
+        .mocks
+        .mock_oracle_announcement_with(announcement)
+        .await;
+
+    let order_id = order_to_take.id;
+    taker
+        .system
+        .take_offer(order_id, Usd::new(dec!(5)))
+        .await
+        .unwrap();
+    wait_next_state!(order_id, maker_2, taker, CfdState::PendingSetup);
+
+    maker_2.mocks.mock_party_params().await;
+    taker.mocks.mock_party_params().await;
+
+    maker_2.mocks.mock_wallet_sign_and_broadcast().await;
+    taker.mocks.mock_wallet_sign_and_broadcast().await;
+
+    maker_2.system.accept_order(order_id).await.unwrap();
+    wait_next_state!(order_id, maker_2, taker, CfdState::ContractSetup);
+
+    sleep(Duration::from_secs(5)).await; // need to wait a bit until both transition
+    wait_next_state!(order_id, maker_2, taker, CfdState::PendingOpen);
+
+    confirm!(lock transaction, order_id, maker_2, taker);
+    wait_next_state!(order_id, maker_2, taker, CfdState::Open);
+
+    taker.trigger_rollover(order_id).await;
+    wait_next_state!(
+        order_id,
+        maker_2,
+        taker,
+        CfdState::IncomingRolloverProposal,
+        CfdState::OutgoingRolloverProposal
+    );
+
+    maker_2.system.accept_rollover(order_id).await.unwrap();
+    wait_next_state!(order_id, maker_2, taker, CfdState::RolloverSetup);
+    wait_next_state!(order_id, maker_2, taker, CfdState::Open);
+
+    taker.mocks.mock_latest_quote(Some(dummy_quote())).await;
+    maker_2.mocks.mock_latest_quote(Some(dummy_quote())).await;
+    next_with(taker.quote_feed(), |q| q).await.unwrap(); // if quote is available on feed, it propagated through the system
+
+    taker.system.propose_settlement(order_id).await.unwrap();
+    wait_next_state!(
+        order_id,
+        maker_2,
+        taker,
+        CfdState::IncomingSettlementProposal,
+        CfdState::OutgoingSettlementProposal
+    );
+
+    maker_2.system.accept_settlement(order_id).await.unwrap();
+    sleep(Duration::from_secs(5)).await; // need to wait a bit until both transition
+
+    wait_next_state!(order_id, maker_2, taker, CfdState::PendingClose);
+    confirm!(close transaction, order_id, maker_2, taker);
+
+    sleep(Duration::from_secs(5)).await; // need to wait a bit until both transition
+    wait_next_state!(order_id, maker_2, taker, CfdState::Closed);
+
+    let maker_1_cfds = maker_1.cfd_feed().borrow().clone().unwrap_or_default();
+    assert!(
+        maker_1_cfds.is_empty(),
+        "first maker must not know about the CFD with the second maker"
+    );
+}
+
 fn assert_eq_offers(published: MakerOffers, received: MakerOffers) {
     match (published.long, received.long) {
         (None, None) => (),
@@ -172,6 +342,10 @@ fn assert_eq_orders(mut published: CfdOrder, received: CfdOrder) {
     // align liquidation price so we can assert on the order
     published.liquidation_price = received.liquidation_price;
 
+    // the taker annotates every order with the maker who published it
+    assert!(received.maker.is_some());
+    published.maker = received.maker;
+
     assert_eq!(published, received);
 
     // Hard-coded to match the dummy_new_order()
@@ -305,6 +479,98 @@ async fn taker_takes_order_and_maker_accepts_and_contract_setup() {
     wait_next_state!(order_id, maker, taker, CfdState::Open);
 }
 
+#[tokio::test]
+async fn contract_setup_resumes_after_connection_dropped() {
+    let _guard = init_tracing();
+    let (mut maker, mut taker) = start_both().await;
+
+    maker
+        .set_offer_params(dummy_offer_params(Position::Short))
+        .await;
+
+    let (_, received) = next_maker_offers(maker.offers_feed(), taker.offers_feed())
+        .await
+        .unwrap();
+
+    let order_id = received.short.unwrap().id;
+
+    taker.mocks.mock_oracle_announcement().await;
+    maker.mocks.mock_oracle_announcement().await;
+
+    taker
+        .system
+        .take_offer(order_id, Usd::new(dec!(5)))
+        .await
+        .unwrap();
+    wait_next_state!(order_id, maker, taker, CfdState::PendingSetup);
+
+    maker.mocks.mock_party_params().await;
+    taker.mocks.mock_party_params().await;
+
+    maker.mocks.mock_wallet_sign_and_broadcast().await;
+    taker.mocks.mock_wallet_sign_and_broadcast().await;
+
+    // Keep the maker from building its party params so that the contract setup is stuck in the
+    // middle while the connection drops
+    let mut maker_mocks = maker.mocks.clone();
+    let maker_wallet = maker_mocks.wallet().await;
+
+    maker.system.accept_order(order_id).await.unwrap();
+    wait_next_state!(order_id, maker, taker, CfdState::ContractSetup);
+    sleep(Duration::from_secs(1)).await; // give the taker time to send its first message
+
+    drop_and_reestablish_connection(&mut maker, &taker).await;
+
+    drop(maker_wallet);
+    wait_next_state!(order_id, maker, taker, CfdState::PendingOpen);
+}
+
+#[tokio::test]
+async fn contract_setup_falls_back_to_legacy_connection_without_libp2p() {
+    let _guard = init_tracing();
+    let mut maker = Maker::start(&MakerConfig::default()).await;
+    // The taker cannot connect to the maker over libp2p, because it expects the wrong peer ID
+    let mut taker = Taker::start(
+        &TakerConfig::default(),
+        maker.listen_addr,
+        maker.identity,
+        PeerId::random(),
+    )
+    .await;
+
+    maker
+        .set_offer_params(dummy_offer_params(Position::Short))
+        .await;
+
+    let (_, received) = next_maker_offers(maker.offers_feed(), taker.offers_feed())
+        .await
+        .unwrap();
+
+    let order_id = received.short.unwrap().id;
+
+    taker.mocks.mock_oracle_announcement().await;
+    maker.mocks.mock_oracle_announcement().await;
+
+    taker
+        .system
+        .take_offer(order_id, Usd::new(dec!(5)))
+        .await
+        .unwrap();
+    wait_next_state!(order_id, maker, taker, CfdState::PendingSetup);
+
+    maker.mocks.mock_party_params().await;
+    taker.mocks.mock_party_params().await;
+
+    maker.mocks.mock_wallet_sign_and_broadcast().await;
+    taker.mocks.mock_wallet_sign_and_broadcast().await;
+
+    maker.system.accept_order(order_id).await.unwrap();
+    wait_next_state!(order_id, maker, taker, CfdState::ContractSetup);
+
+    sleep(Duration::from_secs(5)).await; // need to wait a bit until both transition
+    wait_next_state!(order_id, maker, taker, CfdState::PendingOpen);
+}
+
 #[tokio::test]
 async fn collaboratively_close_an_open_cfd_maker_going_short() {
     let _guard = init_tracing();
@@ -345,6 +611,189 @@ async fn collaboratively_close_an_open_cfd(maker_position: Position) {
     wait_next_state!(order_id, maker, taker, CfdState::Closed);
 }
 
+#[tokio::test]
+async fn taker_publishes_settlement_if_maker_withholds_it() {
+    let _guard = init_tracing();
+    let (mut maker, mut taker, order_id) = start_from_open_cfd_state_with_config(
+        OliviaData::example_0().announcement(),
+        Position::Short,
+        MakerConfig::default(),
+        TakerConfig::default().with_settlement_broadcast_delay(Duration::from_secs(2)),
+    )
+    .await;
+    taker.mocks.mock_latest_quote(Some(dummy_quote())).await;
+    maker.mocks.mock_latest_quote(Some(dummy_quote())).await;
+    next_with(taker.quote_feed(), |q| q).await.unwrap(); // if quote is available on feed, it propagated through the system
+
+    // The maker's transaction never makes it and the taker's first attempt fails as well
+    maker.mocks.monitor().await.fail_broadcasts(usize::MAX);
+    taker.mocks.monitor().await.fail_broadcasts(1);
+
+    taker.system.propose_settlement(order_id).await.unwrap();
+
+    wait_next_state!(
+        order_id,
+        maker,
+        taker,
+        CfdState::IncomingSettlementProposal,
+        CfdState::OutgoingSettlementProposal
+    );
+
+    maker.system.accept_settlement(order_id).await.unwrap();
+    sleep(Duration::from_secs(5)).await; // need to wait a bit until both transition
+
+    wait_next_state!(order_id, maker, taker, CfdState::PendingClose);
+
+    tokio::time::timeout(Duration::from_secs(30), async {
+        while taker
+            .mocks
+            .monitor()
+            .await
+            .collaborative_settlement_broadcasts()
+            == 0
+        {
+            sleep(Duration::from_millis(200)).await;
+        }
+    })
+    .await
+    .expect("taker to publish the settlement transaction");
+
+    assert_eq!(
+        maker
+            .mocks
+            .monitor()
+            .await
+            .collaborative_settlement_broadcasts(),
+        0
+    );
+}
+
+#[tokio::test]
+async fn taker_accepts_settlement_counter_offer_manually() {
+    let _guard = init_tracing();
+    let (mut maker, mut taker, order_id) = start_from_open_cfd_state_with_config(
+        OliviaData::example_0().announcement(),
+        Position::Short,
+        MakerConfig::default(),
+        TakerConfig::default(),
+    )
+    .await;
+    taker.mocks.mock_latest_quote(Some(dummy_quote())).await;
+    maker.mocks.mock_latest_quote(Some(dummy_quote())).await;
+    next_with(taker.quote_feed(), |q| q).await.unwrap(); // if quote is available on feed, it propagated through the system
+
+    taker.system.propose_settlement(order_id).await.unwrap();
+
+    wait_next_state!(
+        order_id,
+        maker,
+        taker,
+        CfdState::IncomingSettlementProposal,
+        CfdState::OutgoingSettlementProposal
+    );
+
+    // The taker is long, hence a lower price is worse for them
+    maker
+        .system
+        .counter_settlement(order_id, Price::new(dec!(48_000)).unwrap())
+        .await
+        .unwrap();
+
+    wait_next_state!(
+        order_id,
+        maker,
+        taker,
+        CfdState::OutgoingSettlementProposal,
+        CfdState::IncomingSettlementProposal
+    );
+
+    taker
+        .system
+        .accept_settlement_counter_offer(order_id)
+        .await
+        .unwrap();
+    sleep(Duration::from_secs(5)).await; // need to wait a bit until both transition
+
+    wait_next_state!(order_id, maker, taker, CfdState::PendingClose);
+}
+
+#[tokio::test]
+async fn taker_accepts_settlement_counter_offer_within_tolerance_automatically() {
+    let _guard = init_tracing();
+    let (mut maker, mut taker, order_id) = start_from_open_cfd_state_with_config(
+        OliviaData::example_0().announcement(),
+        Position::Short,
+        MakerConfig::default(),
+        TakerConfig::default().with_settlement_counter_offer_tolerance(dec!(5)),
+    )
+    .await;
+    taker.mocks.mock_latest_quote(Some(dummy_quote())).await;
+    maker.mocks.mock_latest_quote(Some(dummy_quote())).await;
+    next_with(taker.quote_feed(), |q| q).await.unwrap(); // if quote is available on feed, it propagated through the system
+
+    taker.system.propose_settlement(order_id).await.unwrap();
+
+    wait_next_state!(
+        order_id,
+        maker,
+        taker,
+        CfdState::IncomingSettlementProposal,
+        CfdState::OutgoingSettlementProposal
+    );
+
+    maker
+        .system
+        .counter_settlement(order_id, Price::new(dec!(48_000)).unwrap())
+        .await
+        .unwrap();
+    sleep(Duration::from_secs(5)).await; // need to wait a bit until both transition
+
+    wait_next_state!(order_id, maker, taker, CfdState::PendingClose);
+}
+
+#[tokio::test]
+async fn taker_judges_settlement_counter_offer_against_latest_quote() {
+    let _guard = init_tracing();
+    let (mut maker, mut taker, order_id) = start_from_open_cfd_state_with_config(
+        OliviaData::example_0().announcement(),
+        Position::Short,
+        MakerConfig::default(),
+        TakerConfig::default().with_settlement_counter_offer_tolerance(dec!(5)),
+    )
+    .await;
+    taker.mocks.mock_latest_quote(Some(dummy_quote())).await;
+    maker.mocks.mock_latest_quote(Some(dummy_quote())).await;
+    next_with(taker.quote_feed(), |q| q).await.unwrap(); // if quote is available on feed, it propagated through the system
+
+    taker.system.propose_settlement(order_id).await.unwrap();
+
+    wait_next_state!(
+        order_id,
+        maker,
+        taker,
+        CfdState::IncomingSettlementProposal,
+        CfdState::OutgoingSettlementProposal
+    );
+
+    // The market moved against the taker since they proposed
+    let moved_quote = Quote {
+        bid: dec!(46_000),
+        ask: dec!(46_000),
+        ..dummy_quote()
+    };
+    taker.mocks.mock_latest_quote(Some(moved_quote)).await;
+
+    // 10% worse than the proposal, but only ~2% worse than the latest quote
+    maker
+        .system
+        .counter_settlement(order_id, Price::new(dec!(45_000)).unwrap())
+        .await
+        .unwrap();
+    sleep(Duration::from_secs(5)).await; // need to wait a bit until both transition
+
+    wait_next_state!(order_id, maker, taker, CfdState::PendingClose);
+}
+
 #[tokio::test]
 async fn force_close_an_open_cfd_maker_going_short() {
     let _guard = init_tracing();
@@ -426,6 +875,111 @@ async fn rollover_an_open_cfd(maker_position: Position) {
     wait_next_state!(order_id, maker, taker, CfdState::Open);
 }
 
+#[tokio::test]
+async fn rollover_an_open_cfd_with_renegotiated_number_of_payouts() {
+    let _guard = init_tracing();
+    let oracle_data = OliviaData::example_0();
+    let (mut maker, mut taker, order_id) = start_from_open_cfd_state_with_config(
+        oracle_data.announcement(),
+        Position::Short,
+        MakerConfig::default().with_rollover_policy(rollover_maker::Policy {
+            n_payouts: Some(100),
+            ..rollover_maker::Policy::default()
+        }),
+        TakerConfig::default(),
+    )
+    .await;
+
+    maker
+        .set_offer_params(dummy_offer_params(Position::Short))
+        .await;
+
+    taker.trigger_rollover(order_id).await;
+
+    wait_next_state!(
+        order_id,
+        maker,
+        taker,
+        CfdState::IncomingRolloverProposal,
+        CfdState::OutgoingRolloverProposal
+    );
+
+    maker.system.accept_rollover(order_id).await.unwrap();
+
+    wait_next_state!(order_id, maker, taker, CfdState::RolloverSetup);
+    wait_next_state!(order_id, maker, taker, CfdState::Open);
+}
+
+#[tokio::test]
+async fn rollover_resumes_after_connection_dropped() {
+    let _guard = init_tracing();
+    let oracle_data = OliviaData::example_0();
+    let (mut maker, mut taker, order_id) =
+        start_from_open_cfd_state(oracle_data.announcement(), Position::Short).await;
+
+    maker
+        .set_offer_params(dummy_offer_params(Position::Short))
+        .await;
+
+    taker.trigger_rollover(order_id).await;
+
+    wait_next_state!(
+        order_id,
+        maker,
+        taker,
+        CfdState::IncomingRolloverProposal,
+        CfdState::OutgoingRolloverProposal
+    );
+
+    // Keep the maker from fetching the announcement so that the rollover is stuck in the middle
+    // while the connection drops
+    let mut maker_mocks = maker.mocks.clone();
+    let maker_oracle = maker_mocks.oracle().await;
+
+    maker.system.accept_rollover(order_id).await.unwrap();
+    wait_next_state!(order_id, maker, taker, CfdState::RolloverSetup);
+    sleep(Duration::from_secs(1)).await; // give the taker time to send its first message
+
+    drop_and_reestablish_connection(&mut maker, &taker).await;
+
+    drop(maker_oracle);
+    wait_next_state!(order_id, maker, taker, CfdState::Open);
+}
+
+#[tokio::test]
+async fn taker_declines_rollover_with_too_high_tx_fee_rate() {
+    let _guard = init_tracing();
+    let oracle_data = OliviaData::example_0();
+    let (mut maker, mut taker, order_id) = start_from_open_cfd_state_with_config(
+        oracle_data.announcement(),
+        Position::Short,
+        MakerConfig::default(),
+        TakerConfig::default().with_rollover_policy(rollover_taker::Policy {
+            max_tx_fee_rate: Some(TxFeeRate::new(1)),
+            ..rollover_taker::Policy::default()
+        }),
+    )
+    .await;
+
+    let mut offer_params = dummy_offer_params(Position::Short);
+    offer_params.tx_fee_rate = TxFeeRate::new(10);
+    maker.set_offer_params(offer_params).await;
+
+    taker.trigger_rollover(order_id).await;
+
+    wait_next_state!(
+        order_id,
+        maker,
+        taker,
+        CfdState::IncomingRolloverProposal,
+        CfdState::OutgoingRolloverProposal
+    );
+
+    maker.system.accept_rollover(order_id).await.unwrap();
+
+    wait_next_state!(order_id, maker, taker, CfdState::Open);
+}
+
 #[tokio::test]
 async fn maker_rejects_rollover_of_open_cfd() {
     let _guard = init_tracing();
@@ -592,6 +1146,37 @@ async fn open_cfd_is_refunded() {
     wait_next_state!(order_id, maker, taker, CfdState::Refunded);
 }
 
+#[tokio::test]
+async fn taker_receives_quotes_from_replayed_price_feed() {
+    let _guard = init_tracing();
+
+    let maker = Maker::start(&MakerConfig::default()).await;
+
+    // Replaying at 600x speed plays back the two minutes of the recording in 0.2 seconds
+    let taker_config = TakerConfig::default().with_price_feed_replay(
+        concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/recordings/bitmex_quotes.jsonl"
+        ),
+        600.0,
+    );
+    let mut taker = Taker::start(
+        &taker_config,
+        maker.listen_addr,
+        maker.identity,
+        maker.peer_id,
+    )
+    .await;
+
+    let quote = next_with(taker.quote_feed(), |quote| {
+        quote.filter(|quote| quote.bid() == dec!(38702))
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(quote.ask(), dec!(38702.5));
+}
+
 #[tokio::test]
 async fn taker_notices_lack_of_maker() {
     let short_interval = Duration::from_secs(1);
@@ -604,6 +1189,7 @@ async fn taker_notices_lack_of_maker() {
     let maker = Maker::start(&maker_config).await;
 
     let taker_config = TakerConfig::default().with_heartbeat_interval(short_interval);
+    let maker_identity = maker.identity;
     let mut taker = Taker::start(
         &taker_config,
         maker.listen_addr,
@@ -614,7 +1200,7 @@ async fn taker_notices_lack_of_maker() {
 
     assert_eq!(
         ConnectionStatus::Online,
-        next(taker.maker_status_feed()).await.unwrap()
+        next(taker.maker_status_feed()).await.unwrap()[&maker_identity]
     );
 
     drop(maker);
@@ -623,7 +1209,7 @@ async fn taker_notices_lack_of_maker() {
 
     assert_eq!(
         ConnectionStatus::Offline { reason: None },
-        next(taker.maker_status_feed()).await.unwrap(),
+        next(taker.maker_status_feed()).await.unwrap()[&maker_identity],
     );
 
     let _maker = Maker::start(&maker_config).await;
@@ -633,10 +1219,43 @@ async fn taker_notices_lack_of_maker() {
 
     assert_eq!(
         ConnectionStatus::Online,
-        next(taker.maker_status_feed()).await.unwrap(),
+        next(taker.maker_status_feed()).await.unwrap()[&maker_identity],
     );
 }
 
+#[tokio::test]
+async fn taker_discovers_maker_through_rendezvous_node() {
+    let _guard = init_tracing();
+
+    let rendezvous_node = Maker::start(&MakerConfig::default()).await;
+    let mut maker = Maker::start(&MakerConfig::default()).await;
+
+    maker
+        .set_offer_params(dummy_offer_params(Position::Short))
+        .await;
+    maker.register_at_rendezvous(rendezvous_node.libp2p_address());
+
+    let discovered = tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            let makers = discovery::discover_makers(rendezvous_node.libp2p_address(), None)
+                .await
+                .unwrap();
+
+            match makers.into_iter().next() {
+                Some(discovered) if discovered.record.short.is_some() => return discovered,
+                _ => sleep(Duration::from_millis(200)).await,
+            }
+        }
+    })
+    .await
+    .expect("to discover maker within timeout");
+
+    assert_eq!(discovered.peer_id, maker.peer_id);
+    assert_eq!(discovered.record.identity, maker.identity);
+    assert_eq!(discovered.record.address, maker.listen_addr);
+    assert!(discovered.record.long.is_none());
+}
+
 #[tokio::test]
 async fn maker_notices_lack_of_taker() {
     let _guard = init_tracing();
@@ -655,6 +1274,67 @@ async fn maker_notices_lack_of_taker() {
     );
 }
 
+#[tokio::test]
+async fn maker_disconnects_banned_taker() {
+    let _guard = init_tracing();
+
+    let (mut maker, taker) = start_both().await;
+    assert_eq!(
+        vec![taker.id],
+        next(maker.connected_takers_feed()).await.unwrap()
+    );
+
+    maker
+        .system
+        .set_access_policy(access_control::Policy {
+            banlist: HashSet::from([access_control::TakerId::Identity(taker.id)]),
+            ..access_control::Policy::default()
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(
+        Vec::<Identity>::new(),
+        next(maker.connected_takers_feed()).await.unwrap()
+    );
+}
+
+/// Make the maker drop the connection to the taker by banning it, then lift the ban and wait for
+/// the taker to reconnect.
+async fn drop_and_reestablish_connection(maker: &mut Maker, taker: &Taker) {
+    maker
+        .system
+        .set_access_policy(access_control::Policy {
+            banlist: HashSet::from([access_control::TakerId::Identity(taker.id)]),
+            ..access_control::Policy::default()
+        })
+        .await
+        .unwrap();
+    next_with(maker.connected_takers_feed(), |takers| {
+        takers.is_empty().then(|| ())
+    })
+    .await
+    .unwrap();
+
+    maker
+        .system
+        .set_access_policy(access_control::Policy::default())
+        .await
+        .unwrap();
+
+    // The taker waits up to `MAX_RECONNECT_INTERVAL_SECONDS` before reconnecting
+    tokio::time::timeout(
+        Duration::from_secs(2 * MAX_RECONNECT_INTERVAL_SECONDS),
+        async {
+            while !maker.connected_takers_feed().borrow().contains(&taker.id) {
+                maker.connected_takers_feed().changed().await.unwrap();
+            }
+        },
+    )
+    .await
+    .expect("taker to reconnect within timeout");
+}
+
 /// Hide the implementation detail of arriving at the Cfd open state.
 /// Useful when reading tests that should start at this point.
 /// For convenience, returns also OrderId of the opened Cfd.
@@ -663,9 +1343,24 @@ async fn start_from_open_cfd_state(
     announcement: olivia::Announcement,
     position_maker: Position,
 ) -> (Maker, Taker, OrderId) {
-    let mut maker = Maker::start(&MakerConfig::default()).await;
+    start_from_open_cfd_state_with_config(
+        announcement,
+        position_maker,
+        MakerConfig::default(),
+        TakerConfig::default(),
+    )
+    .await
+}
+
+async fn start_from_open_cfd_state_with_config(
+    announcement: olivia::Announcement,
+    position_maker: Position,
+    maker_config: MakerConfig,
+    taker_config: TakerConfig,
+) -> (Maker, Taker, OrderId) {
+    let mut maker = Maker::start(&maker_config).await;
     let mut taker = Taker::start(
-        &TakerConfig::default(),
+        &taker_config,
         maker.listen_addr,
         maker.identity,
         maker.peer_id,
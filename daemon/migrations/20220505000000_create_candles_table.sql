@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS candles (
+    period text NOT NULL,
+    start_time integer NOT NULL,
+    open text NOT NULL,
+    high text NOT NULL,
+    low text NOT NULL,
+    close text NOT NULL,
+    PRIMARY KEY (period, start_time)
+);
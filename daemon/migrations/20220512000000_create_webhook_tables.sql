@@ -0,0 +1,17 @@
+CREATE TABLE IF NOT EXISTS webhooks (
+    id integer PRIMARY KEY AUTOINCREMENT,
+    url text NOT NULL UNIQUE,
+    secret text NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS webhook_outbox (
+    id integer PRIMARY KEY AUTOINCREMENT,
+    webhook_id integer NOT NULL,
+    payload text NOT NULL,
+    attempts integer NOT NULL DEFAULT 0,
+    next_attempt_at integer NOT NULL,
+    created_at integer NOT NULL,
+    FOREIGN KEY (webhook_id) REFERENCES webhooks (id)
+);
+
+CREATE INDEX IF NOT EXISTS webhook_outbox_next_attempt_at ON webhook_outbox (next_attempt_at);
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS snapshots (
+    cfd_id integer NOT NULL,
+    aggregate text NOT NULL,
+    version integer NOT NULL,
+    data text NOT NULL,
+    PRIMARY KEY (cfd_id, aggregate),
+    FOREIGN KEY (cfd_id) REFERENCES cfds (id)
+);
@@ -0,0 +1,4 @@
+-- NULL for CFDs which were closed before the breakdown was recorded
+ALTER TABLE closed_cfds ADD COLUMN opening_fee integer;
+ALTER TABLE closed_cfds ADD COLUMN funding_fee integer;
+ALTER TABLE closed_cfds ADD COLUMN tx_fee integer;
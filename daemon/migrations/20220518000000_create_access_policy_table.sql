@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS access_policy (
+    id integer PRIMARY KEY CHECK (id = 1),
+    data text NOT NULL
+);
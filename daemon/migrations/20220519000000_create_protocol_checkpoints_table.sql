@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS protocol_checkpoints (
+    order_id text NOT NULL,
+    protocol text NOT NULL,
+    data text NOT NULL,
+    PRIMARY KEY (order_id, protocol)
+);
@@ -0,0 +1,428 @@
+//! Decides which takers the maker accepts connections from.
+//!
+//! Takers can be allowed or banned by their [`Identity`] or by the [`PeerId`] of their libp2p
+//! endpoint. On top of that, connection attempts are rate limited per IP address and per
+//! identity, and the number of concurrently connected takers can be capped.
+
+use anyhow::Context;
+use anyhow::Result;
+use libp2p_core::PeerId;
+use model::Identity;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::fmt;
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::time::Duration;
+use std::time::Instant;
+
+/// The window within which connection attempts are counted for rate limiting.
+pub const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+
+/// Identifies a taker, either by its identity or by the peer ID of its libp2p endpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TakerId {
+    Identity(Identity),
+    PeerId(PeerId),
+}
+
+impl FromStr for TakerId {
+    type Err = anyhow::Error;
+
+    /// Parses a 32 byte hex string as identity and anything else as peer ID.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut bytes = [0u8; 32];
+        if hex::decode_to_slice(s, &mut bytes).is_ok() {
+            return Ok(TakerId::Identity(Identity::new(
+                x25519_dalek::PublicKey::from(bytes),
+            )));
+        }
+
+        let peer_id = s
+            .parse()
+            .with_context(|| format!("{s} is neither an identity nor a peer ID"))?;
+
+        Ok(TakerId::PeerId(peer_id))
+    }
+}
+
+impl fmt::Display for TakerId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TakerId::Identity(identity) => write!(f, "{identity}"),
+            TakerId::PeerId(peer_id) => write!(f, "{peer_id}"),
+        }
+    }
+}
+
+impl Serialize for TakerId {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for TakerId {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Which takers are accepted and how often they may connect.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Policy {
+    /// If not empty, only these takers are accepted.
+    ///
+    /// Takers on the legacy connection are only known by their identity, hence allowing a peer ID
+    /// only allows the libp2p protocols of that taker.
+    #[serde(default)]
+    pub allowlist: HashSet<TakerId>,
+    /// Takers which are never accepted, even if they are on the allowlist.
+    #[serde(default)]
+    pub banlist: HashSet<TakerId>,
+    #[serde(default)]
+    pub limits: Limits,
+}
+
+impl Policy {
+    /// Add the takers and limits of `other` to this policy.
+    ///
+    /// Used to apply the command line flags on top of the policy stored in the database, hence the
+    /// limits of `other` take precedence if they are set.
+    pub fn merge(mut self, other: Policy) -> Self {
+        self.allowlist.extend(other.allowlist);
+        self.banlist.extend(other.banlist);
+        self.limits = Limits {
+            max_takers: other.limits.max_takers.or(self.limits.max_takers),
+            max_connections_per_ip: other
+                .limits
+                .max_connections_per_ip
+                .or(self.limits.max_connections_per_ip),
+            max_connections_per_identity: other
+                .limits
+                .max_connections_per_identity
+                .or(self.limits.max_connections_per_identity),
+        };
+
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Limits {
+    /// The maximum number of takers connected at the same time.
+    pub max_takers: Option<usize>,
+    /// The maximum number of connection attempts from a single IP address within
+    /// [`RATE_LIMIT_WINDOW`].
+    pub max_connections_per_ip: Option<usize>,
+    /// The maximum number of connection attempts from a single identity within
+    /// [`RATE_LIMIT_WINDOW`].
+    pub max_connections_per_identity: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, thiserror::Error)]
+pub enum Rejection {
+    #[error("Taker is banned")]
+    Banned,
+    #[error("Taker is not on the allowlist")]
+    NotAllowed,
+    #[error("Too many connection attempts from IP address")]
+    IpRateLimited,
+    #[error("Too many connection attempts from identity")]
+    IdentityRateLimited,
+    #[error("Maximum number of takers reached")]
+    TooManyTakers,
+}
+
+impl Rejection {
+    /// The label of this rejection in metrics.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Rejection::Banned => "banned",
+            Rejection::NotAllowed => "not_allowed",
+            Rejection::IpRateLimited => "ip_rate_limited",
+            Rejection::IdentityRateLimited => "identity_rate_limited",
+            Rejection::TooManyTakers => "too_many_takers",
+        }
+    }
+}
+
+/// Applies a [`Policy`] to incoming connections.
+#[derive(Debug, Default)]
+pub struct AccessControl {
+    policy: Policy,
+    attempts_per_ip: HashMap<IpAddr, VecDeque<Instant>>,
+    attempts_per_identity: HashMap<Identity, VecDeque<Instant>>,
+}
+
+impl AccessControl {
+    pub fn new(policy: Policy) -> Self {
+        Self {
+            policy,
+            attempts_per_ip: HashMap::new(),
+            attempts_per_identity: HashMap::new(),
+        }
+    }
+
+    pub fn set_policy(&mut self, policy: Policy) {
+        self.policy = policy;
+    }
+
+    /// Record a connection attempt from the given IP address.
+    ///
+    /// Called before the handshake, hence we do not know the taker yet.
+    pub fn check_address(&mut self, ip: IpAddr, now: Instant) -> Result<(), Rejection> {
+        let limit = self.policy.limits.max_connections_per_ip;
+
+        if record_attempt(&mut self.attempts_per_ip, ip, limit, now) {
+            return Err(Rejection::IpRateLimited);
+        }
+
+        Ok(())
+    }
+
+    /// Check whether the taker is allowed by the allow- and banlist.
+    ///
+    /// The peer ID is only known for takers using the libp2p protocols.
+    pub fn check_taker(
+        &self,
+        identity: Identity,
+        peer_id: Option<PeerId>,
+    ) -> Result<(), Rejection> {
+        let ids = std::iter::once(TakerId::Identity(identity))
+            .chain(peer_id.map(TakerId::PeerId))
+            .collect::<Vec<_>>();
+
+        if ids.iter().any(|id| self.policy.banlist.contains(id)) {
+            return Err(Rejection::Banned);
+        }
+
+        if !self.policy.allowlist.is_empty()
+            && !ids.iter().any(|id| self.policy.allowlist.contains(id))
+        {
+            return Err(Rejection::NotAllowed);
+        }
+
+        Ok(())
+    }
+
+    /// Record a connection attempt from the given taker, with `num_takers` takers already
+    /// connected.
+    pub fn check_connection(
+        &mut self,
+        identity: Identity,
+        num_takers: usize,
+        now: Instant,
+    ) -> Result<(), Rejection> {
+        self.check_taker(identity, None)?;
+
+        let limit = self.policy.limits.max_connections_per_identity;
+        if record_attempt(&mut self.attempts_per_identity, identity, limit, now) {
+            return Err(Rejection::IdentityRateLimited);
+        }
+
+        if matches!(self.policy.limits.max_takers, Some(max_takers) if num_takers >= max_takers) {
+            return Err(Rejection::TooManyTakers);
+        }
+
+        Ok(())
+    }
+}
+
+/// Record an attempt of `key` at `now`, returning whether the limit is exceeded.
+///
+/// Attempts outside of the [`RATE_LIMIT_WINDOW`] are forgotten. Rejected attempts count as well,
+/// hence a peer has to back off to be accepted again.
+fn record_attempt<K>(
+    attempts: &mut HashMap<K, VecDeque<Instant>>,
+    key: K,
+    limit: Option<usize>,
+    now: Instant,
+) -> bool
+where
+    K: Eq + Hash,
+{
+    let limit = match limit {
+        Some(limit) => limit,
+        None => return false,
+    };
+
+    attempts.retain(|_, attempts| {
+        while matches!(attempts.front(), Some(attempt) if now.duration_since(*attempt) >= RATE_LIMIT_WINDOW)
+        {
+            attempts.pop_front();
+        }
+
+        !attempts.is_empty()
+    });
+
+    let attempts = attempts.entry(key).or_default();
+    attempts.push_back(now);
+
+    attempts.len() > limit
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn banlist_takes_precedence_over_allowlist() {
+        let identity = dummy_identity(1);
+        let access_control = AccessControl::new(Policy {
+            allowlist: HashSet::from([TakerId::Identity(identity)]),
+            banlist: HashSet::from([TakerId::Identity(identity)]),
+            limits: Limits::default(),
+        });
+
+        assert_eq!(
+            access_control.check_taker(identity, None),
+            Err(Rejection::Banned)
+        );
+    }
+
+    #[test]
+    fn only_takers_on_non_empty_allowlist_are_accepted() {
+        let peer_id = PeerId::random();
+        let access_control = AccessControl::new(Policy {
+            allowlist: HashSet::from([
+                TakerId::Identity(dummy_identity(1)),
+                TakerId::PeerId(peer_id),
+            ]),
+            ..Policy::default()
+        });
+
+        assert_eq!(access_control.check_taker(dummy_identity(1), None), Ok(()));
+        assert_eq!(
+            access_control.check_taker(dummy_identity(2), Some(peer_id)),
+            Ok(())
+        );
+        assert_eq!(
+            access_control.check_taker(dummy_identity(2), None),
+            Err(Rejection::NotAllowed)
+        );
+    }
+
+    #[test]
+    fn banned_peer_id_rejects_any_identity() {
+        let peer_id = PeerId::random();
+        let access_control = AccessControl::new(Policy {
+            banlist: HashSet::from([TakerId::PeerId(peer_id)]),
+            ..Policy::default()
+        });
+
+        assert_eq!(
+            access_control.check_taker(dummy_identity(1), Some(peer_id)),
+            Err(Rejection::Banned)
+        );
+    }
+
+    #[test]
+    fn connection_attempts_are_rate_limited_within_window() {
+        let ip = IpAddr::from([127, 0, 0, 1]);
+        let mut access_control = AccessControl::new(Policy {
+            limits: Limits {
+                max_connections_per_ip: Some(2),
+                ..Limits::default()
+            },
+            ..Policy::default()
+        });
+        let now = Instant::now();
+
+        assert_eq!(access_control.check_address(ip, now), Ok(()));
+        assert_eq!(access_control.check_address(ip, now), Ok(()));
+        assert_eq!(
+            access_control.check_address(ip, now),
+            Err(Rejection::IpRateLimited)
+        );
+        assert_eq!(
+            access_control.check_address(IpAddr::from([127, 0, 0, 2]), now),
+            Ok(())
+        );
+        assert_eq!(
+            access_control.check_address(ip, now + RATE_LIMIT_WINDOW),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn number_of_takers_is_capped() {
+        let mut access_control = AccessControl::new(Policy {
+            limits: Limits {
+                max_takers: Some(1),
+                ..Limits::default()
+            },
+            ..Policy::default()
+        });
+
+        assert_eq!(
+            access_control.check_connection(dummy_identity(1), 0, Instant::now()),
+            Ok(())
+        );
+        assert_eq!(
+            access_control.check_connection(dummy_identity(2), 1, Instant::now()),
+            Err(Rejection::TooManyTakers)
+        );
+    }
+
+    #[test]
+    fn merged_policy_contains_takers_of_both() {
+        let stored = Policy {
+            banlist: HashSet::from([TakerId::Identity(dummy_identity(1))]),
+            limits: Limits {
+                max_takers: Some(10),
+                max_connections_per_ip: Some(5),
+                ..Limits::default()
+            },
+            ..Policy::default()
+        };
+        let flags = Policy {
+            banlist: HashSet::from([TakerId::Identity(dummy_identity(2))]),
+            limits: Limits {
+                max_takers: Some(20),
+                ..Limits::default()
+            },
+            ..Policy::default()
+        };
+
+        let merged = stored.merge(flags);
+
+        assert_eq!(
+            merged.banlist,
+            HashSet::from([
+                TakerId::Identity(dummy_identity(1)),
+                TakerId::Identity(dummy_identity(2))
+            ])
+        );
+        assert_eq!(merged.limits.max_takers, Some(20));
+        assert_eq!(merged.limits.max_connections_per_ip, Some(5));
+    }
+
+    #[test]
+    fn taker_id_roundtrips_through_json() {
+        let ids = vec![
+            TakerId::Identity(dummy_identity(1)),
+            TakerId::PeerId(PeerId::random()),
+        ];
+
+        let json = serde_json::to_string(&ids).unwrap();
+        let deserialized = serde_json::from_str::<Vec<TakerId>>(&json).unwrap();
+
+        assert_eq!(deserialized, ids);
+    }
+
+    fn dummy_identity(byte: u8) -> Identity {
+        Identity::new(x25519_dalek::PublicKey::from([byte; 32]))
+    }
+}
@@ -0,0 +1,312 @@
+//! Export of closed CFDs for accounting and tax purposes.
+//!
+//! Every closed CFD is exported as one entry from our perspective, i.e. profits, losses and fees
+//! are the ones of our side of the CFD. Amounts in BTC are exported with full precision, amounts
+//! in USD are rounded to cents.
+
+use crate::db;
+use anyhow::Result;
+use bdk::bitcoin::Amount;
+use bdk::bitcoin::Denomination;
+use bdk::bitcoin::SignedAmount;
+use model::calculate_margin;
+use model::calculate_profit;
+use model::Leverage;
+use model::OrderId;
+use model::Position;
+use model::Price;
+use model::Role;
+use model::Timestamp;
+use model::Usd;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use serde::Serialize;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Csv,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let format = match s {
+            "csv" => Format::Csv,
+            "json" => Format::Json,
+            unknown => anyhow::bail!("Unknown format: {unknown}"),
+        };
+
+        Ok(format)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SettlementKind {
+    Collaborative,
+    Cet,
+    Refund,
+}
+
+impl SettlementKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SettlementKind::Collaborative => "collaborative",
+            SettlementKind::Cet => "cet",
+            SettlementKind::Refund => "refund",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Entry {
+    pub order_id: OrderId,
+    pub position: Position,
+    pub role: Role,
+    pub quantity_usd: Usd,
+    pub leverage_taker: Leverage,
+    pub entry_price: Price,
+    /// The price the CFD was settled at, `None` if it was refunded.
+    pub exit_price: Option<Price>,
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub margin: Amount,
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub payout: SignedAmount,
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+    pub pnl_btc: SignedAmount,
+    /// The profit or loss valued at the exit price, `None` if the CFD was refunded.
+    pub pnl_usd: Option<Decimal>,
+    /// The fees are `None` for CFDs which were closed before they were recorded separately.
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc::opt")]
+    pub opening_fee: Option<SignedAmount>,
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc::opt")]
+    pub funding_fee: Option<SignedAmount>,
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc::opt")]
+    pub tx_fee: Option<Amount>,
+    pub settlement: SettlementKind,
+    pub settlement_txid: bdk::bitcoin::Txid,
+    pub opened_at: Option<Timestamp>,
+    pub closed_at: Timestamp,
+}
+
+impl From<db::ClosedCfdRecord> for Entry {
+    fn from(record: db::ClosedCfdRecord) -> Self {
+        let db::ClosedCfdRecord {
+            cfd,
+            fee_breakdown,
+            opened_at,
+            closed_at,
+        } = record;
+
+        let quantity_usd = Usd::new(Decimal::from(u64::from(cfd.n_contracts)));
+        let our_leverage = match cfd.role {
+            Role::Maker => Leverage::ONE,
+            Role::Taker => cfd.taker_leverage,
+        };
+        let margin = calculate_margin(cfd.initial_price, quantity_usd, our_leverage);
+
+        let (settlement, settlement_txid, payout, exit_price) = match cfd.settlement {
+            db::Settlement::Collaborative {
+                txid,
+                payout,
+                price,
+                ..
+            } => (SettlementKind::Collaborative, txid, payout, Some(price)),
+            db::Settlement::Cet {
+                txid,
+                payout,
+                price,
+                ..
+            } => (SettlementKind::Cet, txid, payout, Some(price)),
+            db::Settlement::Refund { txid, payout, .. } => {
+                (SettlementKind::Refund, txid, payout, None)
+            }
+        };
+        let payout = SignedAmount::from(payout);
+
+        let (pnl_btc, _) = calculate_profit(
+            payout,
+            margin
+                .to_signed()
+                .expect("Amount to fit into signed amount"),
+        );
+        let pnl_usd = exit_price.map(|price| {
+            (Decimal::from(pnl_btc.as_sat()) / dec!(100_000_000) * price.into_decimal()).round_dp(2)
+        });
+
+        Self {
+            order_id: cfd.id,
+            position: cfd.position,
+            role: cfd.role,
+            quantity_usd,
+            leverage_taker: cfd.taker_leverage,
+            entry_price: cfd.initial_price,
+            exit_price,
+            margin,
+            payout,
+            pnl_btc,
+            pnl_usd,
+            opening_fee: fee_breakdown.map(|fees| fees.opening_fee),
+            funding_fee: fee_breakdown.map(|fees| fees.funding_fee),
+            tx_fee: fee_breakdown.map(|fees| fees.tx_fee),
+            settlement,
+            settlement_txid: settlement_txid.into(),
+            opened_at,
+            closed_at,
+        }
+    }
+}
+
+/// Load an entry for every CFD which was closed within the given time range.
+pub async fn load_entries(
+    db: &db::Connection,
+    from: Timestamp,
+    to: Timestamp,
+) -> Result<Vec<Entry>> {
+    let entries = db
+        .load_closed_cfd_records(from, to)
+        .await?
+        .into_iter()
+        .map(Entry::from)
+        .collect();
+
+    Ok(entries)
+}
+
+const CSV_HEADER: &str = "order_id,position,role,quantity_usd,leverage_taker,entry_price,exit_price,margin_btc,payout_btc,pnl_btc,pnl_usd,opening_fee_btc,funding_fee_btc,tx_fee_btc,settlement,settlement_txid,opened_at,closed_at";
+
+/// Render the entries as CSV, with one line per entry after the header.
+///
+/// Timestamps are exported as seconds since the UNIX epoch. None of the fields can contain a
+/// comma, hence no field is quoted.
+pub fn to_csv(entries: &[Entry]) -> String {
+    let mut csv = format!("{CSV_HEADER}\n");
+
+    for entry in entries {
+        let fields = [
+            entry.order_id.to_string(),
+            format!("{:?}", entry.position),
+            format!("{:?}", entry.role),
+            entry.quantity_usd.to_string(),
+            entry.leverage_taker.get().to_string(),
+            entry.entry_price.to_string(),
+            optional(entry.exit_price),
+            entry.margin.to_string_in(Denomination::Bitcoin),
+            entry.payout.to_string_in(Denomination::Bitcoin),
+            entry.pnl_btc.to_string_in(Denomination::Bitcoin),
+            optional(entry.pnl_usd),
+            optional(
+                entry
+                    .opening_fee
+                    .map(|fee| fee.to_string_in(Denomination::Bitcoin)),
+            ),
+            optional(
+                entry
+                    .funding_fee
+                    .map(|fee| fee.to_string_in(Denomination::Bitcoin)),
+            ),
+            optional(
+                entry
+                    .tx_fee
+                    .map(|fee| fee.to_string_in(Denomination::Bitcoin)),
+            ),
+            entry.settlement.as_str().to_owned(),
+            entry.settlement_txid.to_string(),
+            optional(entry.opened_at.map(|timestamp| timestamp.seconds())),
+            entry.closed_at.seconds().to_string(),
+        ];
+
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+
+    csv
+}
+
+fn optional(value: Option<impl ToString>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use model::Fees;
+    use model::Payout;
+    use model::Txid;
+    use model::Vout;
+    use time::OffsetDateTime;
+
+    #[test]
+    fn profit_is_valued_at_exit_price() {
+        let entry = Entry::from(closed_cfd_record(
+            Price::new(dec!(40_000)).unwrap(),
+            Price::new(dec!(44_000)).unwrap(),
+            Amount::from_sat(136_364),
+        ));
+
+        assert_eq!(entry.margin, Amount::from_sat(125_000));
+        assert_eq!(entry.pnl_btc, SignedAmount::from_sat(11_364));
+        assert_eq!(entry.pnl_usd, Some(dec!(5.00)));
+        assert_eq!(entry.settlement, SettlementKind::Collaborative);
+    }
+
+    #[test]
+    fn csv_contains_header_and_one_line_per_entry() {
+        let entry = Entry::from(closed_cfd_record(
+            Price::new(dec!(40_000)).unwrap(),
+            Price::new(dec!(36_000)).unwrap(),
+            Amount::from_sat(111_111),
+        ));
+
+        let csv = to_csv(&[entry.clone(), entry]);
+        let lines = csv.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], CSV_HEADER);
+        assert_eq!(lines[1].split(',').count(), CSV_HEADER.split(',').count());
+        assert!(lines[1].contains(",-0.00013889,"));
+    }
+
+    fn closed_cfd_record(
+        entry_price: Price,
+        exit_price: Price,
+        payout: Amount,
+    ) -> db::ClosedCfdRecord {
+        let txid =
+            Txid::from_str("e6ce4e5b1b0c5b9d8bb5f8eaebb1b2f5e7ed4b2a6e8f0d5e5e4ef4ad2a67bc6d")
+                .unwrap();
+
+        db::ClosedCfdRecord {
+            cfd: db::ClosedCfd {
+                id: OrderId::default(),
+                position: Position::Long,
+                initial_price: entry_price,
+                taker_leverage: Leverage::TWO,
+                n_contracts: model::Contracts::new(100),
+                counterparty_network_identity:
+                    "69a42aa90da8b065b9532b62bff940a3ba07dbbb11d4482c7db83a7e049a9f1e"
+                        .parse()
+                        .unwrap(),
+                role: Role::Taker,
+                fees: Fees::new(SignedAmount::ZERO),
+                expiry_timestamp: OffsetDateTime::now_utc(),
+                lock: db::Lock {
+                    txid,
+                    dlc_vout: Vout::new(0),
+                },
+                settlement: db::Settlement::Collaborative {
+                    txid,
+                    vout: Vout::new(0),
+                    payout: Payout::new(payout),
+                    price: exit_price,
+                },
+            },
+            fee_breakdown: None,
+            opened_at: None,
+            closed_at: Timestamp::now(),
+        }
+    }
+}
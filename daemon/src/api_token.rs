@@ -0,0 +1,187 @@
+//! API tokens which grant access to parts of the HTTP API.
+//!
+//! Tokens are meant for bots and dashboards, which should not get the full access the password
+//! derived from the seed grants. Every token has a set of [`Scope`]s and is passed as bearer token.
+//! The secret of a token is only returned once when the token is created, the database only
+//! contains its SHA256 hash.
+
+use crate::db;
+use anyhow::Result;
+use model::Timestamp;
+use rand::Rng;
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    /// Load CFDs, offers, prices and the wallet balance.
+    Read,
+    /// Open CFDs, change offers and settle or roll over CFDs.
+    Trade,
+    /// Withdraw from the wallet.
+    Withdraw,
+    /// Everything, including access to key material and managing tokens and webhooks.
+    Admin,
+}
+
+impl Scope {
+    /// Whether a token with this scope may access routes which require the given scope.
+    ///
+    /// Every scope allows reading and the admin scope allows everything.
+    pub fn grants(&self, required: Scope) -> bool {
+        *self == Scope::Admin || *self == required || required == Scope::Read
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::Trade => "trade",
+            Scope::Withdraw => "withdraw",
+            Scope::Admin => "admin",
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+impl FromStr for Scope {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let scope = match s {
+            "read" => Scope::Read,
+            "trade" => Scope::Trade,
+            "withdraw" => Scope::Withdraw,
+            "admin" => Scope::Admin,
+            unknown => anyhow::bail!(
+                "Unknown scope: {unknown}, expected one of read, trade, withdraw, admin"
+            ),
+        };
+
+        Ok(scope)
+    }
+}
+
+/// A token as it is created via CLI or API.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct NewToken {
+    /// A unique name to recognise the token by.
+    pub name: String,
+    pub scopes: Vec<Scope>,
+}
+
+impl NewToken {
+    pub fn validate(&self) -> Result<()> {
+        if self.name.is_empty() {
+            anyhow::bail!("Token name must not be empty");
+        }
+
+        if self.scopes.is_empty() {
+            anyhow::bail!("Token must have at least one scope");
+        }
+
+        Ok(())
+    }
+}
+
+/// A stored token, without its secret.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Token {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    pub created_at: Timestamp,
+}
+
+/// A token together with its secret, as it is returned once when the token is created.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CreatedToken {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    pub secret: String,
+}
+
+/// Create a token, the returned secret cannot be retrieved later.
+pub async fn create(db: &db::Connection, token: NewToken) -> Result<CreatedToken> {
+    token.validate()?;
+
+    let secret = hex::encode(rand::thread_rng().gen::<[u8; 32]>());
+    db.insert_api_token(&token, &hash(&secret)).await?;
+
+    Ok(CreatedToken {
+        name: token.name,
+        scopes: token.scopes,
+        secret,
+    })
+}
+
+/// The hash of the secret of a token as it is stored in the database.
+pub fn hash(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+/// Encode scopes to be stored in the database.
+pub(crate) fn encode_scopes(scopes: &[Scope]) -> String {
+    scopes
+        .iter()
+        .map(Scope::as_str)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Decode scopes as stored by [`encode_scopes`].
+pub(crate) fn decode_scopes(scopes: &str) -> Result<Vec<Scope>> {
+    scopes.split(',').map(Scope::from_str).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn token_grants_its_scopes() {
+        let db = db::memory().await.unwrap();
+
+        let secret = create(
+            &db,
+            NewToken {
+                name: "dashboard".to_owned(),
+                scopes: vec![Scope::Trade],
+            },
+        )
+        .await
+        .unwrap()
+        .secret;
+
+        let scopes = db
+            .load_api_token_scopes(&hash(&secret))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(scopes.iter().any(|scope| scope.grants(Scope::Read)));
+        assert!(scopes.iter().any(|scope| scope.grants(Scope::Trade)));
+        assert!(!scopes.iter().any(|scope| scope.grants(Scope::Withdraw)));
+        assert!(!scopes.iter().any(|scope| scope.grants(Scope::Admin)));
+
+        assert!(db
+            .load_api_token_scopes(&hash("not a token"))
+            .await
+            .unwrap()
+            .is_none());
+
+        assert!(db.delete_api_token("dashboard").await.unwrap());
+        assert!(db
+            .load_api_token_scopes(&hash(&secret))
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
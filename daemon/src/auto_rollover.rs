@@ -3,6 +3,7 @@ use crate::db;
 use crate::oracle;
 use crate::process_manager;
 use crate::rollover_taker;
+use crate::setup_contract::RolloverCheckpoint;
 use anyhow::Result;
 use async_trait::async_trait;
 use futures::StreamExt;
@@ -25,6 +26,7 @@ pub struct Actor<O> {
     conn: Address<connection::Actor>,
     oracle: Address<O>,
     n_payouts: usize,
+    rollover_policy: rollover_taker::Policy,
     rollover_actors: AddressMap<OrderId, rollover_taker::Actor>,
     tasks: Tasks,
 }
@@ -37,6 +39,7 @@ impl<O> Actor<O> {
         conn: Address<connection::Actor>,
         oracle: Address<O>,
         n_payouts: usize,
+        rollover_policy: rollover_taker::Policy,
     ) -> Self {
         Self {
             db,
@@ -45,6 +48,7 @@ impl<O> Actor<O> {
             conn,
             oracle,
             n_payouts,
+            rollover_policy,
             rollover_actors: AddressMap::default(),
             tasks: Tasks::default(),
         }
@@ -78,6 +82,7 @@ where
         let addr = rollover_taker::Actor::new(
             order_id,
             self.n_payouts,
+            self.rollover_policy,
             self.oracle_pk,
             self.conn.clone(),
             &self.oracle,
@@ -91,6 +96,42 @@ where
     }
 }
 
+impl<O> Actor<O>
+where
+    O: xtra::Handler<oracle::GetAnnouncement>,
+{
+    /// Resume the rollovers which were interrupted by a restart.
+    async fn resume_rollovers(&mut self) -> Result<()> {
+        for checkpoint in RolloverCheckpoint::load_all(&self.db).await? {
+            let order_id = checkpoint.order_id;
+
+            let disconnected = match self.rollover_actors.get_disconnected(order_id) {
+                Ok(disconnected) => disconnected,
+                Err(_) => {
+                    tracing::debug!(%order_id, "Rollover already in progress");
+                    continue;
+                }
+            };
+
+            let addr = rollover_taker::Actor::resume(
+                checkpoint,
+                self.rollover_policy,
+                self.oracle_pk,
+                self.conn.clone(),
+                &self.oracle,
+                self.process_manager.clone(),
+                self.db.clone(),
+            )
+            .create(None)
+            .spawn(&mut self.tasks);
+
+            disconnected.insert(addr);
+        }
+
+        Ok(())
+    }
+}
+
 impl<O> Actor<O>
 where
     O: xtra::Handler<oracle::GetAnnouncement>,
@@ -138,6 +179,10 @@ where
     type Stop = ();
 
     async fn started(&mut self, ctx: &mut xtra::Context<Self>) {
+        if let Err(e) = self.resume_rollovers().await {
+            tracing::error!("Failed to resume rollovers: {e:#}");
+        }
+
         let this = ctx.address().expect("we are alive");
         self.tasks
             .add(this.send_interval(Duration::from_secs(5 * 60), || AutoRollover));
@@ -0,0 +1,184 @@
+//! Encrypted backups of all open CFDs.
+//!
+//! Losing the database means losing the DLCs, without which CFDs cannot be force-closed. A backup
+//! contains every open CFD together with all its events, which is everything needed to rebuild
+//! them. Backups are encrypted with a key derived from the seed, hence they can only be restored
+//! with the same seed.
+
+use crate::db;
+use crate::encryption::Cipher;
+use anyhow::Context;
+use anyhow::Result;
+use futures::StreamExt;
+use model::Cfd;
+use model::OrderId;
+use model::Timestamp;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// The version of the format of backups, to be increased on incompatible changes.
+const FORMAT_VERSION: u32 = 1;
+
+/// The key backups are encrypted with.
+#[derive(Clone)]
+pub struct Key(Cipher);
+
+impl From<[u8; 32]> for Key {
+    fn from(key: [u8; 32]) -> Self {
+        Self(Cipher::from(key))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Backup {
+    version: u32,
+    created_at: Timestamp,
+    cfds: Vec<db::ExportedCfd>,
+}
+
+/// Create an encrypted backup of all open CFDs.
+///
+/// The backup is consistent and can be created while the daemon is running.
+pub async fn create(db: &db::Connection, key: &Key) -> Result<Vec<u8>> {
+    let cfds = db.export_open_cfds().await?;
+
+    let backup = Backup {
+        version: FORMAT_VERSION,
+        created_at: Timestamp::now(),
+        cfds,
+    };
+    let plaintext = serde_json::to_vec(&backup).context("Failed to serialize backup")?;
+
+    key.0.encrypt(&plaintext)
+}
+
+/// Restore an encrypted backup into an empty database.
+///
+/// After restoring, we check that all open CFDs in the backup can be loaded from the database.
+/// Returns the number of restored CFDs.
+pub async fn restore(db: &db::Connection, key: &Key, backup: &[u8]) -> Result<usize> {
+    let plaintext = key
+        .0
+        .decrypt(backup)
+        .context("Failed to decrypt backup, was it created with a different seed?")?;
+    let backup =
+        serde_json::from_slice::<Backup>(&plaintext).context("Failed to deserialize backup")?;
+
+    anyhow::ensure!(
+        backup.version == FORMAT_VERSION,
+        "Unsupported backup version {}, expected {FORMAT_VERSION}",
+        backup.version
+    );
+    anyhow::ensure!(
+        db.export_open_cfds().await?.is_empty(),
+        "Refusing to restore backup into a database which already contains CFDs"
+    );
+
+    let num_events = backup
+        .cfds
+        .iter()
+        .map(|exported| (exported.cfd.id, exported.events.len()))
+        .collect::<HashMap<OrderId, usize>>();
+
+    for exported in backup.cfds.iter() {
+        db.import_cfd(exported).await?;
+    }
+
+    let mut cfds = db.load_all_open_cfds::<Cfd>(());
+    while let Some(cfd) = cfds.next().await {
+        let cfd = cfd.context("Restored CFD cannot be loaded")?;
+        let order_id = cfd.id();
+
+        anyhow::ensure!(
+            num_events.get(&order_id).copied() == Some(cfd.version() as usize),
+            "Restored CFD {order_id} does not contain all events of the backup"
+        );
+    }
+
+    tracing::info!(
+        created_at = %backup.created_at.seconds(),
+        "Restored {} CFDs from backup",
+        backup.cfds.len()
+    );
+
+    Ok(backup.cfds.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::seed::RandomSeed;
+    use crate::seed::Seed;
+    use bdk::bitcoin::Amount;
+    use model::EventKind;
+    use model::FundingRate;
+    use model::Leverage;
+    use model::OpeningFee;
+    use model::Position;
+    use model::Price;
+    use model::Role;
+    use model::TxFeeRate;
+    use model::Usd;
+    use rust_decimal_macros::dec;
+
+    #[tokio::test]
+    async fn restored_backup_contains_all_open_cfds() {
+        let seed = RandomSeed::default();
+        let key = seed.derive_backup_key::<Key>();
+
+        let db = db::memory().await.unwrap();
+        let cfd = open_cfd();
+        db.insert_cfd(&cfd).await.unwrap();
+        db.append_event(contract_setup_completed(cfd.id()))
+            .await
+            .unwrap();
+
+        let backup = create(&db, &key).await.unwrap();
+
+        let other_key = RandomSeed::default().derive_backup_key::<Key>();
+        let restored_db = db::memory().await.unwrap();
+        assert!(restore(&restored_db, &other_key, &backup).await.is_err());
+
+        let num_cfds = restore(&restored_db, &key, &backup).await.unwrap();
+        assert_eq!(num_cfds, 1);
+
+        let restored = restored_db
+            .load_open_cfd::<Cfd>(cfd.id(), ())
+            .await
+            .unwrap();
+        let original = db.load_open_cfd::<Cfd>(cfd.id(), ()).await.unwrap();
+        assert_eq!(restored, original);
+
+        assert!(restore(&restored_db, &key, &backup).await.is_err());
+    }
+
+    fn open_cfd() -> Cfd {
+        Cfd::new(
+            OrderId::default(),
+            Position::Long,
+            Price::new(dec!(41_772.8325)).unwrap(),
+            Leverage::TWO,
+            time::Duration::hours(24),
+            Role::Taker,
+            Usd::new(dec!(100)),
+            "69a42aa90da8b065b9532b62bff940a3ba07dbbb11d4482c7db83a7e049a9f1e"
+                .parse()
+                .unwrap(),
+            OpeningFee::new(Amount::ZERO),
+            FundingRate::default(),
+            TxFeeRate::default(),
+        )
+    }
+
+    fn contract_setup_completed(order_id: OrderId) -> model::CfdEvent {
+        let event =
+            std::fs::read_to_string("./src/test_events/contract_setup_completed.json").unwrap();
+
+        model::CfdEvent {
+            timestamp: Timestamp::now(),
+            id: order_id,
+            event: serde_json::from_str::<EventKind>(&event).unwrap(),
+        }
+    }
+}
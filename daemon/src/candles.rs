@@ -0,0 +1,354 @@
+use crate::db;
+use crate::projection::round_to_two_dp;
+use anyhow::Result;
+use async_trait::async_trait;
+use model::EventKind;
+use model::OrderId;
+use model::Position;
+use model::Price;
+use model::Timestamp;
+use parse_display::Display;
+use parse_display::FromStr;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio_tasks::Tasks;
+use xtra::prelude::MessageChannel;
+use xtra_bitmex_price_feed::LatestQuote;
+use xtra_bitmex_price_feed::Quote;
+use xtra_productivity::xtra_productivity;
+
+/// How often we ask the price feed for the latest quote.
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often candles which are past their retention period are deleted.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// The time span covered by a single candle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, FromStr, Serialize)]
+pub enum Interval {
+    #[display("1m")]
+    #[serde(rename = "1m")]
+    OneMinute,
+    #[display("1h")]
+    #[serde(rename = "1h")]
+    OneHour,
+}
+
+impl Interval {
+    pub const ALL: [Interval; 2] = [Interval::OneMinute, Interval::OneHour];
+
+    pub fn duration(&self) -> time::Duration {
+        match self {
+            Interval::OneMinute => time::Duration::minutes(1),
+            Interval::OneHour => time::Duration::hours(1),
+        }
+    }
+
+    /// How long candles of this interval are kept in the database.
+    pub fn retention(&self) -> time::Duration {
+        match self {
+            Interval::OneMinute => time::Duration::days(7),
+            Interval::OneHour => time::Duration::days(365),
+        }
+    }
+
+    /// The start of the candle which contains the given point in time.
+    pub fn candle_start(&self, timestamp: Timestamp) -> Timestamp {
+        let seconds = self.duration().whole_seconds();
+
+        Timestamp::new(timestamp.seconds().div_euclid(seconds) * seconds)
+    }
+}
+
+/// Open, high, low and close of the mid price during one [`Interval`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, sqlx::FromRow)]
+pub struct Candle {
+    #[sqlx(rename = "start_time")]
+    pub start: Timestamp,
+    #[serde(with = "round_to_two_dp")]
+    pub open: Price,
+    #[serde(with = "round_to_two_dp")]
+    pub high: Price,
+    #[serde(with = "round_to_two_dp")]
+    pub low: Price,
+    #[serde(with = "round_to_two_dp")]
+    pub close: Price,
+}
+
+impl Candle {
+    pub fn new(start: Timestamp, price: Price) -> Self {
+        Self {
+            start,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+        }
+    }
+
+    #[must_use]
+    pub fn with_price(self, price: Price) -> Self {
+        Self {
+            high: if price > self.high { price } else { self.high },
+            low: if price < self.low { price } else { self.low },
+            close: price,
+            ..self
+        }
+    }
+}
+
+/// Position of a CFD on the price chart.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Marker {
+    pub order_id: OrderId,
+    pub kind: MarkerKind,
+    pub position: Position,
+    pub timestamp: Timestamp,
+    /// The price at which the CFD was opened or settled.
+    ///
+    /// `None` for CFDs which were refunded, because there is no price associated with a refund.
+    #[serde(with = "round_to_two_dp::opt")]
+    pub price: Option<Price>,
+}
+
+impl Marker {
+    /// Create the marker for the given event of a CFD.
+    ///
+    /// The event is expected to be either the completion of the contract setup or one that
+    /// closes the CFD.
+    pub(crate) fn from_event(
+        order_id: OrderId,
+        position: Position,
+        event_name: &str,
+        timestamp: Timestamp,
+        initial_price: Price,
+        settlement_price: Option<Price>,
+    ) -> Self {
+        let (kind, price) = match event_name {
+            EventKind::CONTRACT_SETUP_COMPLETED_EVENT => (MarkerKind::Entry, Some(initial_price)),
+            _ => (MarkerKind::Exit, settlement_price),
+        };
+
+        Self {
+            order_id,
+            kind,
+            position,
+            timestamp,
+            price,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MarkerKind {
+    /// The contract setup of the CFD completed.
+    Entry,
+    /// The CFD was settled, collaboratively or through the oracle, or refunded.
+    Exit,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct History {
+    pub interval: Interval,
+    pub candles: Vec<Candle>,
+    pub markers: Vec<Marker>,
+}
+
+/// Load the price history for the given interval starting at `from`, together with the markers of
+/// all CFDs that were opened or closed in that time.
+pub async fn load_history(
+    db: &db::Connection,
+    interval: Interval,
+    from: Timestamp,
+) -> Result<History> {
+    let candles = db.load_candles(interval, from).await?;
+    let markers = db.load_cfd_markers(from).await?;
+
+    Ok(History {
+        interval,
+        candles,
+        markers,
+    })
+}
+
+/// An actor that aggregates the quotes of the price feed into candles.
+///
+/// Candles are stored in the database as soon as they change, so a restart of the daemon only
+/// loses the quotes received while it was down. Candles older than the retention period of their
+/// interval are deleted periodically.
+pub struct Actor {
+    db: db::Connection,
+    price_feed: Box<dyn MessageChannel<LatestQuote>>,
+    candles: HashMap<Interval, Candle>,
+    tasks: Tasks,
+}
+
+impl Actor {
+    pub fn new(db: db::Connection, price_feed: Box<dyn MessageChannel<LatestQuote>>) -> Self {
+        Self {
+            db,
+            price_feed,
+            candles: HashMap::new(),
+            tasks: Tasks::default(),
+        }
+    }
+
+    async fn record_price(&mut self, interval: Interval, timestamp: Timestamp, price: Price) {
+        let start = interval.candle_start(timestamp);
+
+        let candle = match self.candles.get(&interval) {
+            Some(candle) if candle.start == start => candle.with_price(price),
+            Some(candle) if candle.start > start => {
+                tracing::debug!(%interval, "Ignoring quote older than current candle");
+                return;
+            }
+            _ => match self.db.load_candle(interval, start).await {
+                // We might have been restarted within the interval of this candle
+                Ok(Some(candle)) => candle.with_price(price),
+                Ok(None) => Candle::new(start, price),
+                Err(e) => {
+                    tracing::warn!(%interval, "Failed to load candle: {e:#}");
+                    return;
+                }
+            },
+        };
+
+        if let Err(e) = self.db.upsert_candle(interval, &candle).await {
+            tracing::warn!(%interval, "Failed to store candle: {e:#}");
+            return;
+        }
+
+        self.candles.insert(interval, candle);
+    }
+}
+
+/// Private message to aggregate a new quote into the current candles.
+struct NewQuote(Quote);
+
+/// Private message to delete candles which are past their retention period.
+struct PruneCandles;
+
+#[xtra_productivity]
+impl Actor {
+    async fn handle(&mut self, msg: NewQuote) {
+        let quote = msg.0;
+
+        let price = match Price::new((quote.bid + quote.ask) / Decimal::TWO) {
+            Ok(price) => price,
+            Err(e) => {
+                tracing::warn!("Ignoring invalid quote: {e:#}");
+                return;
+            }
+        };
+        let timestamp = Timestamp::new(quote.timestamp.unix_timestamp());
+
+        for interval in Interval::ALL {
+            self.record_price(interval, timestamp, price).await;
+        }
+    }
+
+    async fn handle(&mut self, _: PruneCandles) {
+        let now = Timestamp::now().seconds();
+
+        for interval in Interval::ALL {
+            let before = Timestamp::new(now - interval.retention().whole_seconds());
+
+            match self.db.prune_candles(interval, before).await {
+                Ok(0) => {}
+                Ok(num_candles) => {
+                    tracing::debug!(%interval, %num_candles, "Pruned candles past retention")
+                }
+                Err(e) => tracing::warn!(%interval, "Failed to prune candles: {e:#}"),
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl xtra::Actor for Actor {
+    type Stop = ();
+
+    async fn started(&mut self, ctx: &mut xtra::Context<Self>) {
+        let this = ctx.address().expect("we just started");
+
+        self.tasks.add({
+            let price_feed = self.price_feed.clone_channel();
+            let this = this.clone();
+
+            async move {
+                loop {
+                    match price_feed.send(LatestQuote).await {
+                        Ok(Some(quote)) => {
+                            let _ = this.send(NewQuote(quote)).await;
+                        }
+                        Ok(None) => {
+                            tracing::trace!("No quote available to aggregate into candles");
+                        }
+                        Err(_) => {
+                            tracing::trace!("Price feed actor currently unreachable");
+                        }
+                    }
+
+                    tokio::time::sleep(POLL_INTERVAL).await;
+                }
+            }
+        });
+
+        self.tasks.add(async move {
+            loop {
+                let _ = this.send(PruneCandles).await;
+
+                tokio::time::sleep(PRUNE_INTERVAL).await;
+            }
+        });
+    }
+
+    async fn stopped(self) -> Self::Stop {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn candle_start_is_aligned_to_interval() {
+        let timestamp = Timestamp::new(1_651_234_567);
+
+        assert_eq!(
+            Interval::OneMinute.candle_start(timestamp),
+            Timestamp::new(1_651_234_560)
+        );
+        assert_eq!(
+            Interval::OneHour.candle_start(timestamp),
+            Timestamp::new(1_651_233_600)
+        );
+    }
+
+    #[test]
+    fn candle_tracks_high_low_and_close() {
+        let candle = Candle::new(Timestamp::new(0), price(dec!(40_000)))
+            .with_price(price(dec!(41_000)))
+            .with_price(price(dec!(39_000)))
+            .with_price(price(dec!(40_500)));
+
+        assert_eq!(candle.open, price(dec!(40_000)));
+        assert_eq!(candle.high, price(dec!(41_000)));
+        assert_eq!(candle.low, price(dec!(39_000)));
+        assert_eq!(candle.close, price(dec!(40_500)));
+    }
+
+    #[test]
+    fn interval_roundtrips_through_string() {
+        for interval in Interval::ALL {
+            assert_eq!(interval.to_string().parse::<Interval>().unwrap(), interval);
+        }
+    }
+
+    fn price(value: Decimal) -> Price {
+        Price::new(value).unwrap()
+    }
+}
@@ -9,6 +9,10 @@ use xtras::SendInterval;
 /// table.
 const CLOSE_CFDS_INTERVAL: Duration = Duration::from_secs(5 * 60);
 
+/// Interval at which we prune the DLCs which were superseded by a
+/// rollover.
+const PRUNE_DLCS_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
 pub struct Actor {
     db: db::Connection,
     tasks: Tasks,
@@ -31,6 +35,8 @@ impl xtra::Actor for Actor {
         let this = ctx.address().expect("we are alive");
         self.tasks
             .add(this.send_interval(CLOSE_CFDS_INTERVAL, || CloseCfds));
+        self.tasks
+            .add(this.send_interval(PRUNE_DLCS_INTERVAL, || PruneDlcs));
     }
 
     async fn stopped(self) -> Self::Stop {}
@@ -43,6 +49,16 @@ impl Actor {
             tracing::warn!("Failed to move closed CFDs to corresponding table: {e:#}");
         }
     }
+
+    async fn handle(&mut self, _: PruneDlcs) {
+        match self.db.prune_superseded_dlcs().await {
+            Ok(0) => {}
+            Ok(num_pruned) => tracing::debug!("Pruned superseded DLCs of {num_pruned} events"),
+            Err(e) => tracing::warn!("Failed to prune superseded DLCs: {e:#}"),
+        }
+    }
 }
 
 struct CloseCfds;
+
+struct PruneDlcs;
@@ -9,6 +9,7 @@ use async_trait::async_trait;
 use maia::secp256k1_zkp::Signature;
 use model::CollaborativeSettlement;
 use model::Identity;
+use model::Price;
 use model::SettlementProposal;
 use std::time::Duration;
 use tokio_tasks::Tasks;
@@ -19,7 +20,7 @@ use xtras::address_map::IPromiseIamReturningStopAllFromStopping;
 
 /// Timeout for waiting for the `Initiate` message from the taker
 ///
-/// This timeout is started when handling accept.
+/// This timeout is started when handling accept or sending a counter-offer.
 /// If the taker does not come back with `Initiate` until the timeout is triggered we fail the
 /// settlement. If the taker does come back with `Initiate` before the timeout is reached, we don't
 /// fail the settlement even if the timeout is triggered.
@@ -48,6 +49,15 @@ pub struct Initiated {
     pub sig_taker: Signature,
 }
 
+/// Reply to the proposal of the taker with a settlement at a different price.
+#[derive(Clone, Copy)]
+pub struct CounterOffer {
+    pub price: Price,
+}
+
+#[derive(Clone, Copy)]
+pub struct CounterOfferRejected;
+
 #[xtra_productivity]
 impl Actor {
     async fn handle(&mut self, _: Accepted, ctx: &mut xtra::Context<Self>) {
@@ -60,6 +70,20 @@ impl Actor {
         self.reject(ctx).await
     }
 
+    async fn handle(&mut self, msg: CounterOffer, ctx: &mut xtra::Context<Self>) {
+        if let Err(e) = self.counter(msg.price, ctx).await {
+            self.emit_failed(e, ctx).await;
+        }
+    }
+
+    async fn handle(&mut self, _: CounterOfferRejected, ctx: &mut xtra::Context<Self>) {
+        let order_id = self.proposal.order_id;
+        tracing::info!(%order_id, "Settlement counter-offer rejected by taker");
+
+        self.emit_rejected(anyhow!("Taker rejected counter-offer"), ctx)
+            .await;
+    }
+
     async fn handle(&mut self, msg: Initiated, ctx: &mut xtra::Context<Self>) {
         self.is_initiated = true;
 
@@ -75,14 +99,17 @@ impl Actor {
                 .load_open_cfd::<model::Cfd>(self.proposal.order_id, ())
                 .await?;
 
-            let settlement =
+            let (settlement, sig_maker) =
                 cfd.sign_collaborative_settlement_maker(self.proposal, msg.sig_taker)?;
 
-            anyhow::Ok(settlement)
+            anyhow::Ok((settlement, sig_maker))
         }
         .await
         {
-            Ok(settlement) => self.emit_completed(settlement, ctx).await,
+            Ok((settlement, sig_maker)) => {
+                self.finalize(sig_maker).await;
+                self.emit_completed(settlement, ctx).await
+            }
             Err(e) => self.emit_failed(e, ctx).await,
         };
     }
@@ -242,19 +269,7 @@ impl Actor {
             })
             .await?;
 
-        let timeout = {
-            let this = ctx.address().expect("self to be alive");
-            async move {
-                tokio::time::sleep(INITIATE_TIMEOUT).await;
-
-                let _ = this
-                    .send(InitiateTimeoutReached {
-                        timeout: INITIATE_TIMEOUT,
-                    })
-                    .await;
-            }
-        };
-        self.tasks.add(timeout);
+        self.start_initiate_timeout(ctx);
 
         let this = ctx.address().expect("self to be alive");
         self.connections
@@ -269,6 +284,80 @@ impl Actor {
         Ok(())
     }
 
+    async fn counter(&mut self, price: Price, ctx: &mut xtra::Context<Self>) -> Result<()> {
+        let order_id = self.proposal.order_id;
+
+        if self.has_accepted {
+            tracing::warn!(%order_id, "Settlement already accepted or countered");
+            return Ok(());
+        }
+        self.has_accepted = true;
+
+        tracing::info!(%order_id, %price, "Countering settlement proposal");
+
+        let counter_offer = self
+            .executor
+            .execute(order_id, |cfd| {
+                cfd.counter_collaborative_settlement_proposal(price, self.n_payouts)
+            })
+            .await?;
+
+        // From now on we settle according to the counter-offer, if the taker initiates
+        self.proposal = counter_offer;
+
+        self.start_initiate_timeout(ctx);
+
+        let this = ctx.address().expect("self to be alive");
+        self.connections
+            .send(maker_inc_connections::settlement::Response {
+                taker_id: self.taker_id,
+                order_id,
+                decision: maker_inc_connections::settlement::Decision::CounterOffer {
+                    proposal: counter_offer,
+                    address: this,
+                },
+            })
+            .await
+            .context("Failed to send settlement counter-offer to taker")??;
+
+        Ok(())
+    }
+
+    /// Send our signature to the taker, allowing them to publish the settlement transaction too.
+    ///
+    /// Failing to do so is not fatal because we publish the transaction ourselves.
+    async fn finalize(&mut self, sig_maker: Signature) {
+        let order_id = self.proposal.order_id;
+
+        let result = self
+            .connections
+            .send(maker_inc_connections::settlement::Response {
+                taker_id: self.taker_id,
+                order_id,
+                decision: maker_inc_connections::settlement::Decision::Finalize { sig_maker },
+            })
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|result| result);
+
+        if let Err(e) = result {
+            tracing::warn!(%order_id, "Failed to send settlement signature to taker: {e:#}");
+        }
+    }
+
+    fn start_initiate_timeout(&mut self, ctx: &mut xtra::Context<Self>) {
+        let this = ctx.address().expect("self to be alive");
+        self.tasks.add(async move {
+            tokio::time::sleep(INITIATE_TIMEOUT).await;
+
+            let _ = this
+                .send(InitiateTimeoutReached {
+                    timeout: INITIATE_TIMEOUT,
+                })
+                .await;
+        });
+    }
+
     async fn reject(&mut self, ctx: &mut xtra::Context<Self>) {
         let order_id = self.proposal.order_id;
         tracing::info!(%order_id, "Settlement proposal rejected");
@@ -3,16 +3,25 @@ use crate::connection;
 use crate::db;
 use crate::process_manager;
 use crate::wire;
+use crate::MAX_QUOTE_AGE;
 use anyhow::anyhow;
+use anyhow::Context as _;
 use anyhow::Result;
 use async_trait::async_trait;
+use maia::secp256k1_zkp::Signature;
+use model::market_closing_price;
 use model::CollaborativeSettlement;
 use model::OrderId;
+use model::Position;
 use model::Price;
+use model::Role;
 use model::SettlementProposal;
+use rust_decimal::Decimal;
 use std::time::Duration;
 use tokio_tasks::Tasks;
+use xtra::prelude::MessageChannel;
 use xtra::KeepRunning;
+use xtra_bitmex_price_feed::LatestQuote;
 use xtra_productivity::xtra_productivity;
 use xtras::address_map::IPromiseIamReturningStopAllFromStopping;
 use xtras::SendAsyncSafe;
@@ -20,11 +29,34 @@ use xtras::SendAsyncSafe;
 /// The maximum amount of time we give the maker to send us a response.
 const MAKER_RESPONSE_TIMEOUT: Duration = Duration::from_secs(30);
 
+/// The maximum amount of time the user has to decide on a counter-offer of the maker.
+///
+/// Shorter than the time the maker waits for us to initiate the settlement, so that the maker
+/// does not give up while we can still accept.
+const COUNTER_OFFER_DECISION_TIMEOUT: Duration = Duration::from_secs(60 * 4);
+
+/// The maximum amount of time we wait for the signature of the maker after initiating the
+/// settlement.
+///
+/// Makers which do not send their signature still publish the settlement transaction, hence we
+/// complete the settlement without being able to publish it ourselves after this timeout.
+const FINALIZE_TIMEOUT: Duration = Duration::from_secs(60);
+
 pub struct Actor {
     proposal: Option<SettlementProposal>,
     order_id: OrderId,
     current_price: Price,
     n_payouts: usize,
+    /// By how many percent the price of a counter-offer may be worse than the latest quote, for
+    /// us to accept it without asking the user.
+    ///
+    /// If `None`, counter-offers which are worse for us are never accepted automatically.
+    counter_offer_tolerance: Option<Decimal>,
+    price_feed: Box<dyn MessageChannel<LatestQuote>>,
+    /// The counter-offer of the maker, waiting for the user to decide on it.
+    pending_counter_offer: Option<SettlementProposal>,
+    /// The settlement lacking the signature of the maker, waiting for the maker to send it.
+    unsigned_settlement: Option<CollaborativeSettlement>,
     connection: xtra::Address<connection::Actor>,
     executor: command::Executor,
     db: db::Connection,
@@ -37,6 +69,8 @@ impl Actor {
         order_id: OrderId,
         current_price: Price,
         n_payouts: usize,
+        counter_offer_tolerance: Option<Decimal>,
+        price_feed: Box<dyn MessageChannel<LatestQuote>>,
         connection: xtra::Address<connection::Actor>,
         process_manager: xtra::Address<process_manager::Actor>,
         db: db::Connection,
@@ -46,6 +80,10 @@ impl Actor {
             order_id,
             n_payouts,
             current_price,
+            counter_offer_tolerance,
+            price_feed,
+            pending_counter_offer: None,
+            unsigned_settlement: None,
             connection,
             executor: command::Executor::new(db.clone(), process_manager),
             db,
@@ -78,7 +116,12 @@ impl Actor {
         Ok(())
     }
 
-    async fn handle_confirmed(&mut self) -> Result<CollaborativeSettlement> {
+    /// The proposal we are settling with, either our own or the counter-offer we accepted.
+    fn proposal(&self) -> Result<SettlementProposal> {
+        self.proposal.context("Settlement was not proposed")
+    }
+
+    async fn handle_confirmed(&mut self, ctx: &mut xtra::Context<Self>) -> Result<()> {
         let order_id = self.order_id;
 
         tracing::info!(%order_id, "Settlement proposal got accepted");
@@ -87,7 +130,7 @@ impl Actor {
 
         // TODO: This should happen within a dedicated state machine returned from
         // start_collaborative_settlement
-        let proposal = self.proposal.take().expect("proposal to exist");
+        let proposal = self.proposal()?;
         let (tx, sig, payout_script_pubkey) = cfd.sign_collaborative_settlement_taker(&proposal)?;
 
         self.connection
@@ -97,7 +140,133 @@ impl Actor {
             })
             .await?;
 
-        CollaborativeSettlement::new(tx, payout_script_pubkey, self.current_price)
+        self.unsigned_settlement = Some(CollaborativeSettlement::new(
+            tx,
+            payout_script_pubkey,
+            proposal.price,
+        )?);
+
+        let this = ctx.address().expect("self to be alive");
+        self.tasks.add(async move {
+            tokio::time::sleep(FINALIZE_TIMEOUT).await;
+
+            let _ = this.send(FinalizeTimeoutReached).await;
+        });
+
+        Ok(())
+    }
+
+    /// Complete the settlement with the transaction signed by both parties.
+    async fn handle_finalize(
+        &mut self,
+        unsigned_settlement: CollaborativeSettlement,
+        sig_maker: Signature,
+    ) -> CollaborativeSettlement {
+        let order_id = self.order_id;
+
+        let result = async {
+            let proposal = self.proposal()?;
+            let cfd = self.db.load_open_cfd::<model::Cfd>(order_id, ()).await?;
+
+            cfd.finalize_collaborative_settlement_taker(&proposal, sig_maker)
+        }
+        .await;
+
+        match result {
+            Ok(settlement) => settlement,
+            Err(e) => {
+                tracing::warn!(%order_id, "Failed to finalize settlement transaction, relying on maker to publish it: {e:#}");
+
+                unsigned_settlement
+            }
+        }
+    }
+
+    /// Validate and record the counter-offer of the maker.
+    ///
+    /// Returns whether the counter-offer is within our tolerance and can be accepted right away.
+    async fn handle_counter_offer(&mut self, counter_offer: SettlementProposal) -> Result<bool> {
+        let order_id = self.order_id;
+        let proposal = self.proposal()?;
+
+        let position = self
+            .executor
+            .execute(order_id, |cfd| {
+                let position = cfd.position();
+                let event = cfd.receive_collaborative_settlement_counter_offer(
+                    counter_offer,
+                    self.n_payouts,
+                )?;
+
+                Ok((event, position))
+            })
+            .await?;
+
+        let is_acceptable = match self.market_price(position).await {
+            Ok(market_price) => is_within_tolerance(
+                position,
+                market_price,
+                counter_offer.price,
+                self.counter_offer_tolerance,
+            ),
+            Err(e) => {
+                tracing::warn!(%order_id, "Cannot accept settlement counter-offer automatically: {e:#}");
+                false
+            }
+        };
+
+        tracing::info!(
+            %order_id,
+            proposed_price = %proposal.price,
+            counter_price = %counter_offer.price,
+            %is_acceptable,
+            "Received settlement counter-offer"
+        );
+
+        self.pending_counter_offer = Some(counter_offer);
+
+        Ok(is_acceptable)
+    }
+
+    /// The price we would settle at according to the latest quote.
+    async fn market_price(&self, position: Position) -> Result<Price> {
+        let quote = self
+            .price_feed
+            .send(LatestQuote)
+            .await
+            .context("Price feed not available")?
+            .context("No quote available")?;
+
+        if quote.is_older_than(MAX_QUOTE_AGE) {
+            anyhow::bail!(
+                "Latest quote is older than {} minutes",
+                MAX_QUOTE_AGE.whole_minutes()
+            );
+        }
+
+        Ok(market_closing_price(
+            Price::new(quote.bid())?,
+            Price::new(quote.ask())?,
+            Role::Taker,
+            position,
+        ))
+    }
+
+    async fn accept_counter_offer(&mut self, ctx: &mut xtra::Context<Self>) -> Result<()> {
+        let counter_offer = self
+            .pending_counter_offer
+            .take()
+            .context("No pending counter-offer")?;
+
+        self.executor
+            .execute(self.order_id, |cfd| {
+                cfd.accept_collaborative_settlement_counter_offer(&counter_offer)
+            })
+            .await?;
+
+        self.proposal = Some(counter_offer);
+
+        self.handle_confirmed(ctx).await
     }
 
     async fn emit_completed(
@@ -147,6 +316,23 @@ impl Actor {
         ctx.stop();
     }
 
+    async fn reject_counter_offer(&mut self, reason: anyhow::Error, ctx: &mut xtra::Context<Self>) {
+        let order_id = self.order_id;
+
+        if let Err(e) = self
+            .connection
+            .send_async_safe(wire::TakerToMaker::Settlement {
+                order_id,
+                msg: wire::taker_to_maker::Settlement::RejectCounterOffer,
+            })
+            .await
+        {
+            tracing::warn!(%order_id, "Failed to reject settlement counter-offer: {e:#}");
+        }
+
+        self.emit_rejected(reason, ctx).await
+    }
+
     /// Returns whether the maker has accepted our collab settlement proposal.
     fn is_accepted(&self) -> bool {
         self.maker_replied
@@ -199,16 +385,121 @@ impl Actor {
         self.maker_replied = true;
 
         match msg {
-            wire::maker_to_taker::Settlement::Confirm => match self.handle_confirmed().await {
-                Ok(settlement) => self.emit_completed(settlement, ctx).await,
-                Err(e) => self.emit_failed(e, ctx).await,
-            },
+            wire::maker_to_taker::Settlement::Confirm => {
+                if let Err(e) = self.handle_confirmed(ctx).await {
+                    self.emit_failed(e, ctx).await
+                }
+            }
             wire::maker_to_taker::Settlement::Reject => {
                 tracing::info!(%order_id, "Settlement proposal got rejected");
                 self.emit_rejected(anyhow::format_err!("unknown"), ctx)
                     .await
             }
+            wire::maker_to_taker::Settlement::CounterOffer {
+                timestamp,
+                taker,
+                maker,
+                price,
+            } => {
+                let counter_offer = SettlementProposal {
+                    order_id,
+                    timestamp,
+                    taker,
+                    maker,
+                    price,
+                };
+
+                match self.handle_counter_offer(counter_offer).await {
+                    Ok(true) => {
+                        if let Err(e) = self.accept_counter_offer(ctx).await {
+                            self.emit_failed(e, ctx).await
+                        }
+                    }
+                    Ok(false) => {
+                        // Wait for the user to accept or reject the counter-offer
+                        let this = ctx.address().expect("self to be alive");
+                        self.tasks.add(async move {
+                            tokio::time::sleep(COUNTER_OFFER_DECISION_TIMEOUT).await;
+
+                            let _ = this.send(CounterOfferDecisionTimeoutReached).await;
+                        });
+                    }
+                    Err(e) => {
+                        tracing::warn!(%order_id, "Invalid settlement counter-offer: {e:#}");
+                        self.reject_counter_offer(e, ctx).await
+                    }
+                }
+            }
+            wire::maker_to_taker::Settlement::Finalize { sig_maker } => {
+                let unsigned_settlement = match self.unsigned_settlement.take() {
+                    Some(unsigned_settlement) => unsigned_settlement,
+                    None => {
+                        tracing::warn!(%order_id, "Received settlement signature before initiating settlement");
+                        return;
+                    }
+                };
+
+                let settlement = self.handle_finalize(unsigned_settlement, sig_maker).await;
+                self.emit_completed(settlement, ctx).await
+            }
+        };
+    }
+
+    async fn handle(&mut self, _: AcceptCounterOffer, ctx: &mut xtra::Context<Self>) {
+        let order_id = self.order_id;
+
+        if self.pending_counter_offer.is_none() {
+            tracing::warn!(%order_id, "No pending settlement counter-offer to accept");
+            return;
+        }
+
+        if let Err(e) = self.accept_counter_offer(ctx).await {
+            self.emit_failed(e, ctx).await
+        }
+    }
+
+    async fn handle(&mut self, _: RejectCounterOffer, ctx: &mut xtra::Context<Self>) {
+        let order_id = self.order_id;
+
+        if self.pending_counter_offer.is_none() {
+            tracing::warn!(%order_id, "No pending settlement counter-offer to reject");
+            return;
+        }
+
+        tracing::info!(%order_id, "Rejecting settlement counter-offer");
+        self.reject_counter_offer(anyhow!("Rejected counter-offer"), ctx)
+            .await;
+    }
+
+    async fn handle(
+        &mut self,
+        _: CounterOfferDecisionTimeoutReached,
+        ctx: &mut xtra::Context<Self>,
+    ) {
+        // The user already decided on the counter-offer
+        if self.pending_counter_offer.is_none() {
+            return;
+        }
+
+        let timeout = COUNTER_OFFER_DECISION_TIMEOUT.as_secs();
+        self.reject_counter_offer(
+            anyhow!("Counter-offer was not accepted within {timeout} seconds"),
+            ctx,
+        )
+        .await
+    }
+
+    async fn handle(&mut self, _: FinalizeTimeoutReached, ctx: &mut xtra::Context<Self>) {
+        // The maker sent us their signature in time
+        let unsigned_settlement = match self.unsigned_settlement.take() {
+            Some(unsigned_settlement) => unsigned_settlement,
+            None => return,
         };
+
+        let timeout = FINALIZE_TIMEOUT.as_secs();
+        tracing::warn!(order_id = %self.order_id, "Maker did not send settlement signature within {timeout} seconds, relying on maker to publish settlement transaction");
+
+        self.emit_completed(unsigned_settlement, ctx).await
     }
 
     pub async fn handle_collab_settlement_timeout_reached(
@@ -244,6 +535,51 @@ impl Actor {
     }
 }
 
+/// Accept the counter-offer the maker replied to our settlement proposal with.
+#[derive(Clone, Copy)]
+pub struct AcceptCounterOffer;
+
+/// Reject the counter-offer the maker replied to our settlement proposal with.
+#[derive(Clone, Copy)]
+pub struct RejectCounterOffer;
+
+/// Message sent from the spawned task to `collab_settlement_taker::Actor` to notify that the
+/// maker did not send their signature in time.
+struct FinalizeTimeoutReached;
+
+/// Message sent from the spawned task to `collab_settlement_taker::Actor` to notify that the
+/// user did not decide on the counter-offer in time.
+struct CounterOfferDecisionTimeoutReached;
+
+/// Whether the price of the counter-offer is at most `tolerance` percent worse for us than the
+/// price we would settle at according to the latest quote.
+fn is_within_tolerance(
+    position: Position,
+    market_price: Price,
+    counter_price: Price,
+    tolerance: Option<Decimal>,
+) -> bool {
+    let market_price = market_price.into_decimal();
+    let counter_price = counter_price.into_decimal();
+
+    let is_better = match position {
+        Position::Long => counter_price >= market_price,
+        Position::Short => counter_price <= market_price,
+    };
+    if is_better {
+        return true;
+    }
+
+    let tolerance = match tolerance {
+        Some(tolerance) => tolerance,
+        None => return false,
+    };
+
+    let deviation = (counter_price - market_price).abs() / market_price * Decimal::ONE_HUNDRED;
+
+    deviation <= tolerance
+}
+
 /// Message sent from the spawned task to `collab_settlement_taker::Actor` to
 /// notify that the timeout has been reached.
 ///
@@ -89,6 +89,14 @@ impl db::CfdAggregate for Cfd {
     fn version(&self) -> u32 {
         self.version()
     }
+
+    fn to_snapshot(&self) -> Option<Result<String>> {
+        Some(serde_json::to_string(self).context("Failed to serialize CFD"))
+    }
+
+    fn from_snapshot(snapshot: &str) -> Result<Self> {
+        serde_json::from_str(snapshot).context("Failed to deserialize CFD")
+    }
 }
 
 // TODO: Delete this weird thing once all our commands return only an `Event` and not other stuff as
@@ -1,5 +1,7 @@
 use crate::collab_settlement_taker;
+use crate::db;
 use crate::future_ext::FutureExt;
+use crate::libp2p_wire;
 use crate::noise;
 use crate::rollover_taker;
 use crate::setup_taker;
@@ -13,16 +15,22 @@ use anyhow::Context;
 use anyhow::Result;
 use async_trait::async_trait;
 use bdk::bitcoin::Amount;
+use futures::FutureExt as _;
 use futures::SinkExt;
 use futures::StreamExt;
 use futures::TryStreamExt;
+use libp2p_core::PeerId;
+use model::Cfd;
 use model::Identity;
 use model::OrderId;
 use model::Price;
+use model::RolloverVersion;
 use model::Timestamp;
 use model::Usd;
 use rand::thread_rng;
 use rand::Rng;
+use std::collections::HashMap;
+use std::fmt;
 use std::net::SocketAddr;
 use std::time::Duration;
 use std::time::SystemTime;
@@ -32,7 +40,12 @@ use tokio::sync::watch;
 use tokio_tasks::Tasks;
 use tokio_util::codec::Framed;
 use xtra::prelude::MessageChannel;
+use xtra::Address;
 use xtra::KeepRunning;
+use xtra_libp2p::Endpoint;
+use xtra_libp2p::GetConnectionStats;
+use xtra_libp2p::GetPeerInfo;
+use xtra_libp2p::OpenSubstream;
 use xtra_productivity::xtra_productivity;
 use xtras::address_map::NotConnected;
 use xtras::AddressMap;
@@ -44,6 +57,12 @@ pub const MAX_RECONNECT_INTERVAL_SECONDS: u64 = 60;
 
 const TCP_TIMEOUT: Duration = Duration::from_secs(10);
 
+/// How long we wait for the libp2p connection to a maker after the legacy one was established.
+const LIBP2P_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often we check whether the libp2p connection to a maker is established.
+const LIBP2P_CONNECT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
 /// The "Connected" state of our connection with the maker.
 #[allow(clippy::large_enum_variant)]
 enum State {
@@ -143,8 +162,23 @@ impl State {
     }
 }
 
+/// A libp2p substream carrying the protocol currently running for an order.
+struct MakerSubstream {
+    protocol: &'static str,
+    write: libp2p_wire::Write<wire::MakerToTaker, wire::TakerToMaker>,
+    _tasks: Tasks,
+}
+
+/// The connections to all makers we are trading with.
+///
+/// Messages for a maker are routed based on the order they refer to: Each order is sent to the
+/// counterparty of the corresponding CFD.
+///
+/// Protocols are run over libp2p substreams if the maker can be reached over libp2p, otherwise
+/// over the legacy connection.
 pub struct Actor {
-    status_sender: watch::Sender<ConnectionStatus>,
+    status_sender: watch::Sender<HashMap<Identity, ConnectionStatus>>,
+    statuses: HashMap<Identity, ConnectionStatus>,
     identity_sk: x25519_dalek::StaticSecret,
     current_order: Box<dyn MessageChannel<CurrentMakerOffers>>,
     /// How often we check ("measure pulse") for heartbeat
@@ -156,24 +190,73 @@ pub struct Actor {
     heartbeat_timeout: Duration,
     /// TCP connection timeout
     connect_timeout: Duration,
-    state: State,
+    /// The SOCKS5 proxy all connections to makers are dialed through, if any.
+    socks5_proxy: Option<SocketAddr>,
+    db: db::Connection,
+    makers: HashMap<Identity, State>,
+    /// The maker of every order we sent or received a message for.
+    counterparties: HashMap<OrderId, Identity>,
+    endpoint: Address<Endpoint>,
+    /// Our libp2p peer ID, needed to prove our identity on libp2p substreams.
+    peer_id: PeerId,
+    /// The libp2p peer ID of every maker we are trading with, if known.
+    maker_peer_ids: HashMap<Identity, PeerId>,
+    order_substreams: HashMap<OrderId, MakerSubstream>,
     setup_actors: AddressMap<OrderId, setup_taker::Actor>,
     collab_settlement_actors: AddressMap<OrderId, collab_settlement_taker::Actor>,
     rollover_actors: AddressMap<OrderId, rollover_taker::Actor>,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub struct Connect {
     pub maker_identity: Identity,
-    pub maker_addr: SocketAddr,
+    pub maker_addr: MakerAddress,
+}
+
+/// The address of the legacy networking stack of a maker.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MakerAddress {
+    Socket(SocketAddr),
+    /// A host name, resolved by the SOCKS5 proxy if we are using one.
+    Host {
+        host: String,
+        port: u16,
+    },
+}
+
+impl From<SocketAddr> for MakerAddress {
+    fn from(addr: SocketAddr) -> Self {
+        MakerAddress::Socket(addr)
+    }
+}
+
+impl From<&MakerAddress> for libp2p_socks5::Target {
+    fn from(addr: &MakerAddress) -> Self {
+        match addr {
+            MakerAddress::Socket(addr) => libp2p_socks5::Target::Socket(*addr),
+            MakerAddress::Host { host, port } => libp2p_socks5::Target::Domain(host.clone(), *port),
+        }
+    }
+}
+
+impl fmt::Display for MakerAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MakerAddress::Socket(addr) => write!(f, "{addr}"),
+            MakerAddress::Host { host, port } => write!(f, "{host}:{port}"),
+        }
+    }
 }
 
 pub struct MakerStreamMessage {
+    pub maker: Identity,
     pub item: Result<wire::MakerToTaker>,
 }
 
 /// Private message to measure the current pulse (i.e. check when we received the last heartbeat).
-struct MeasurePulse;
+struct MeasurePulse {
+    maker: Identity,
+}
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum ConnectionStatus {
@@ -183,6 +266,31 @@ pub enum ConnectionStatus {
     },
 }
 
+impl ConnectionStatus {
+    /// Summarise the statuses of all makers into a single one.
+    ///
+    /// We are online as soon as we are connected to at least one maker. Otherwise, the first
+    /// reason for a closed connection is reported.
+    pub fn any_online<'a>(statuses: impl IntoIterator<Item = &'a ConnectionStatus>) -> Self {
+        let mut overall = ConnectionStatus::Offline { reason: None };
+
+        for status in statuses {
+            match (status, &overall) {
+                (ConnectionStatus::Online, _) => return ConnectionStatus::Online,
+                (
+                    ConnectionStatus::Offline { reason: Some(_) },
+                    ConnectionStatus::Offline { reason: None },
+                ) => {
+                    overall = status.clone();
+                }
+                _ => {}
+            }
+        }
+
+        overall
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum ConnectionCloseReason {
     VersionNegotiationFailed {
@@ -219,16 +327,39 @@ pub struct ProposeRollover {
     pub address: xtra::Address<rollover_taker::Actor>,
 }
 
+/// Message sent from a `setup_taker::Actor` which resumes a contract
+/// setup after a restart, so that the `connection::Actor` forwards
+/// the contract setup messages from the maker to it again.
+pub struct RegisterSetup {
+    pub order_id: OrderId,
+    pub address: xtra::Address<setup_taker::Actor>,
+}
+
+/// Message sent from a `rollover_taker::Actor` which resumes a
+/// rollover after a restart, so that the `connection::Actor` forwards
+/// the rollover messages from the maker to it again.
+pub struct RegisterRollover {
+    pub order_id: OrderId,
+    pub address: xtra::Address<rollover_taker::Actor>,
+}
+
 impl Actor {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        status_sender: watch::Sender<ConnectionStatus>,
+        status_sender: watch::Sender<HashMap<Identity, ConnectionStatus>>,
         current_order: &(impl MessageChannel<CurrentMakerOffers> + 'static),
         identity_sk: x25519_dalek::StaticSecret,
         maker_heartbeat_interval: Duration,
         connect_timeout: Duration,
+        db: db::Connection,
+        endpoint: Address<Endpoint>,
+        peer_id: PeerId,
+        maker_peer_ids: HashMap<Identity, PeerId>,
+        socks5_proxy: Option<SocketAddr>,
     ) -> Self {
         Self {
             status_sender,
+            statuses: HashMap::new(),
             identity_sk,
             current_order: current_order.clone_channel(),
             heartbeat_measuring_rate: maker_heartbeat_interval.checked_div(2).expect("to divide"),
@@ -236,37 +367,225 @@ impl Actor {
             heartbeat_timeout: maker_heartbeat_interval
                 .checked_mul(2)
                 .expect("to not overflow"),
-            state: State::Disconnected,
             setup_actors: AddressMap::default(),
             connect_timeout,
+            socks5_proxy,
+            db,
+            makers: HashMap::new(),
+            counterparties: HashMap::new(),
+            endpoint,
+            peer_id,
+            maker_peer_ids,
+            order_substreams: HashMap::new(),
             collab_settlement_actors: AddressMap::default(),
             rollover_actors: AddressMap::default(),
         }
     }
+
+    fn set_status(&mut self, maker: Identity, status: ConnectionStatus) {
+        self.statuses.insert(maker, status);
+
+        self.status_sender
+            .send(self.statuses.clone())
+            .expect("receiver to outlive the actor");
+    }
+
+    /// Look up the maker we are trading the given order with.
+    async fn counterparty(&mut self, order_id: OrderId) -> Result<Identity> {
+        if let Some(maker) = self.counterparties.get(&order_id) {
+            return Ok(*maker);
+        }
+
+        let cfd = self
+            .db
+            .load_open_cfd::<Cfd>(order_id, ())
+            .await
+            .with_context(|| format!("Failed to load CFD {order_id} to determine its maker"))?;
+        let maker = cfd.counterparty_network_identity();
+
+        self.counterparties.insert(order_id, maker);
+
+        Ok(maker)
+    }
+
+    /// Send a message to the maker of the order the message refers to.
+    ///
+    /// A message starting a protocol opens a new libp2p substream, which is used for all further
+    /// messages of that protocol. If the maker cannot be reached over libp2p, we fall back to the
+    /// legacy connection.
+    async fn send_to_maker(
+        &mut self,
+        msg: wire::TakerToMaker,
+        ctx: &mut xtra::Context<Self>,
+    ) -> Result<()> {
+        let msg_str = msg.name();
+        let order_id = msg
+            .order_id()
+            .with_context(|| format!("Cannot route {msg_str}, it does not refer to an order"))?;
+        let maker = self.counterparty(order_id).await?;
+
+        if let Some(protocol) = libp2p_wire::protocol_started_by(&msg) {
+            self.gc_order_substreams();
+
+            let this = ctx.address().expect("self to be alive");
+
+            match self.open_order_substream(maker, protocol, this).await {
+                Ok(Some(substream)) => {
+                    self.order_substreams.insert(order_id, substream);
+                }
+                Ok(None) => {
+                    self.order_substreams.remove(&order_id);
+                }
+                Err(e) => {
+                    tracing::debug!(%maker, %order_id, %protocol, "Falling back to legacy connection: {e:#}");
+
+                    self.order_substreams.remove(&order_id);
+                }
+            }
+        }
+
+        if let Some(substream) = self.order_substreams.get_mut(&order_id) {
+            tracing::trace!(target: "wire", msg_name = msg_str, %maker, "Sending over libp2p");
+
+            let result = substream
+                .write
+                .send(msg)
+                .await
+                .with_context(|| format!("Failed to send message {msg_str} to maker {maker}"));
+
+            if result.is_err() {
+                self.order_substreams.remove(&order_id);
+            }
+
+            return result;
+        }
+
+        self.makers
+            .get_mut(&maker)
+            .with_context(|| format!("Cannot send {msg_str}, not connected to maker {maker}"))?
+            .send(msg)
+            .await
+    }
+
+    /// The rollover protocol version to use with the maker, based on what it told us about itself
+    /// when connecting over libp2p.
+    async fn maker_rollover_version(&self, maker: Identity) -> Result<RolloverVersion> {
+        let peer_info = match self.maker_peer_ids.get(&maker) {
+            Some(maker_peer_id) => self
+                .endpoint
+                .send(GetPeerInfo(*maker_peer_id))
+                .await
+                .context("Endpoint actor is disconnected")?,
+            None => None,
+        };
+
+        Ok(version::rollover_version(peer_info.as_ref()))
+    }
+
+    /// Open a substream to the maker for the given protocol.
+    ///
+    /// Returns `None` if the maker cannot be reached over libp2p.
+    async fn open_order_substream(
+        &self,
+        maker: Identity,
+        protocol: &'static str,
+        this: Address<Self>,
+    ) -> Result<Option<MakerSubstream>> {
+        let maker_peer_id = match self.maker_peer_ids.get(&maker) {
+            Some(maker_peer_id) => *maker_peer_id,
+            None => return Ok(None),
+        };
+
+        if !is_connected_over_libp2p(&self.endpoint, maker_peer_id).await? {
+            return Ok(None);
+        }
+
+        let mut stream = self
+            .endpoint
+            .send(OpenSubstream::single_protocol(maker_peer_id, protocol))
+            .await
+            .context("Endpoint actor is disconnected")??;
+
+        libp2p_wire::Hello::new(
+            &self.identity_sk,
+            maker,
+            protocol,
+            self.peer_id,
+            maker_peer_id,
+        )
+        .send(&mut stream)
+        .await?;
+
+        let (write, read) = libp2p_wire::split(stream);
+
+        let mut tasks = Tasks::default();
+        tasks.add(this.attach_stream(read.map(move |item| MakerStreamMessage { maker, item })));
+
+        Ok(Some(MakerSubstream {
+            protocol,
+            write,
+            _tasks: tasks,
+        }))
+    }
+
+    /// Drop the substreams of protocols which are no longer running.
+    fn gc_order_substreams(&mut self) {
+        let Self {
+            order_substreams,
+            setup_actors,
+            rollover_actors,
+            collab_settlement_actors,
+            ..
+        } = self;
+
+        order_substreams.retain(|order_id, substream| match substream.protocol {
+            libp2p_wire::ORDER_PROTOCOL => setup_actors.get_disconnected(*order_id).is_err(),
+            libp2p_wire::ROLLOVER_PROTOCOL | libp2p_wire::ROLLOVER_V2_PROTOCOL => {
+                rollover_actors.get_disconnected(*order_id).is_err()
+            }
+            _ => collab_settlement_actors
+                .get_disconnected(*order_id)
+                .is_err(),
+        });
+    }
 }
 
 #[xtra_productivity]
 impl Actor {
-    async fn handle_taker_to_maker(&mut self, message: wire::TakerToMaker) {
-        if let Err(e) = self.state.send(message).await {
+    async fn handle_taker_to_maker(
+        &mut self,
+        message: wire::TakerToMaker,
+        ctx: &mut xtra::Context<Self>,
+    ) {
+        if let Err(e) = self.send_to_maker(message, ctx).await {
             tracing::warn!("{:#}", e);
         }
     }
 
-    async fn handle_take_order(&mut self, msg: TakeOrder) -> Result<()> {
-        self.state
-            .send(wire::TakerToMaker::TakeOrder {
+    async fn handle_take_order(
+        &mut self,
+        msg: TakeOrder,
+        ctx: &mut xtra::Context<Self>,
+    ) -> Result<()> {
+        self.send_to_maker(
+            wire::TakerToMaker::TakeOrder {
                 order_id: msg.order_id,
                 quantity: msg.quantity,
-            })
-            .await?;
+            },
+            ctx,
+        )
+        .await?;
 
         self.setup_actors.insert(msg.order_id, msg.address);
 
         Ok(())
     }
 
-    async fn handle_propose_settlement(&mut self, msg: ProposeSettlement) -> Result<()> {
+    async fn handle_propose_settlement(
+        &mut self,
+        msg: ProposeSettlement,
+        ctx: &mut xtra::Context<Self>,
+    ) -> Result<()> {
         let ProposeSettlement {
             order_id,
             timestamp,
@@ -276,8 +595,8 @@ impl Actor {
             address,
         } = msg;
 
-        self.state
-            .send(wire::TakerToMaker::Settlement {
+        self.send_to_maker(
+            wire::TakerToMaker::Settlement {
                 order_id,
                 msg: wire::taker_to_maker::Settlement::Propose {
                     timestamp,
@@ -285,31 +604,54 @@ impl Actor {
                     maker,
                     price,
                 },
-            })
-            .await?;
+            },
+            ctx,
+        )
+        .await?;
 
         self.collab_settlement_actors.insert(order_id, address);
 
         Ok(())
     }
 
-    async fn handle_propose_rollover(&mut self, msg: ProposeRollover) -> Result<()> {
+    async fn handle_propose_rollover(
+        &mut self,
+        msg: ProposeRollover,
+        ctx: &mut xtra::Context<Self>,
+    ) -> Result<RolloverVersion> {
         let ProposeRollover {
             order_id,
             timestamp,
             address,
         } = msg;
 
-        self.state
-            .send(wire::TakerToMaker::ProposeRolloverV2 {
+        let maker = self.counterparty(order_id).await?;
+        let rollover_version = self.maker_rollover_version(maker).await?;
+
+        let proposal = match rollover_version {
+            RolloverVersion::V1 => wire::TakerToMaker::ProposeRollover {
                 order_id,
                 timestamp,
-            })
-            .await?;
+            },
+            RolloverVersion::V2 => wire::TakerToMaker::ProposeRolloverV2 {
+                order_id,
+                timestamp,
+            },
+        };
+
+        self.send_to_maker(proposal, ctx).await?;
 
         self.rollover_actors.insert(order_id, address);
 
-        Ok(())
+        Ok(rollover_version)
+    }
+
+    fn handle_register_setup(&mut self, msg: RegisterSetup) {
+        self.setup_actors.insert(msg.order_id, msg.address);
+    }
+
+    fn handle_register_rollover(&mut self, msg: RegisterRollover) {
+        self.rollover_actors.insert(msg.order_id, msg.address);
     }
 }
 
@@ -326,7 +668,19 @@ impl Actor {
         tracing::debug!(address = %maker_addr, "Connecting to maker");
 
         let (mut write, mut read) = {
-            let mut connection = TcpStream::connect(&maker_addr)
+            let connection = match (&self.socks5_proxy, &maker_addr) {
+                (Some(proxy), maker_addr) => {
+                    let proxy = *proxy;
+                    let target = libp2p_socks5::Target::from(maker_addr);
+
+                    async move { libp2p_socks5::connect(proxy, &target).await }.boxed()
+                }
+                (None, MakerAddress::Socket(addr)) => TcpStream::connect(*addr).boxed(),
+                (None, MakerAddress::Host { host, port }) => {
+                    TcpStream::connect((host.clone(), *port)).boxed()
+                }
+            };
+            let mut connection = connection
                 .timeout(self.connect_timeout)
                 .await
                 .with_context(|| {
@@ -368,14 +722,15 @@ impl Actor {
             Some(wire::MakerToTaker::Hello(actual_version)) => {
                 tracing::info!(%maker_identity, %actual_version, "Received Hello message from maker");
                 if proposed_version != actual_version {
-                    self.status_sender
-                        .send(ConnectionStatus::Offline {
+                    self.set_status(
+                        maker_identity,
+                        ConnectionStatus::Offline {
                             reason: Some(ConnectionCloseReason::VersionNegotiationFailed {
                                 proposed_version: proposed_version.clone(),
                                 actual_version: actual_version.clone(),
                             }),
-                        })
-                        .expect("receiver to outlive the actor");
+                        },
+                    );
 
                     bail!(
                         "Network version mismatch, we proposed {proposed_version} but maker wants to use {actual_version}"
@@ -394,47 +749,94 @@ impl Actor {
             }
         }
 
-        tracing::info!(address = %maker_addr, "Established connection to maker");
+        tracing::info!(address = %maker_addr, %maker_identity, "Established connection to maker");
 
         let this = ctx.address().expect("self to be alive");
 
         let mut tasks = Tasks::default();
         tasks.add(
             this.clone()
-                .attach_stream(read.map(move |item| MakerStreamMessage { item })),
+                .attach_stream(read.map(move |item| MakerStreamMessage {
+                    maker: maker_identity,
+                    item,
+                })),
         );
-        tasks.add(this.send_interval(self.heartbeat_measuring_rate, || MeasurePulse));
+        tasks.add(
+            this.send_interval(self.heartbeat_measuring_rate, move || MeasurePulse {
+                maker: maker_identity,
+            }),
+        );
+        if let Some(maker_peer_id) = self.maker_peer_ids.get(&maker_identity).copied() {
+            let hello = libp2p_wire::Hello::new(
+                &self.identity_sk,
+                maker_identity,
+                libp2p_wire::OFFER_PROTOCOL,
+                self.peer_id,
+                maker_peer_id,
+            );
+
+            tasks.add_fallible(
+                subscribe_to_offers(
+                    self.endpoint.clone(),
+                    hello,
+                    maker_identity,
+                    maker_peer_id,
+                    this.clone(),
+                ),
+                move |e| async move {
+                    tracing::debug!(%maker_identity, "Receiving offers over legacy connection: {e:#}");
+                },
+            );
+        }
 
-        self.state = State::Connected {
-            last_heartbeat: SystemTime::now(),
-            last_pulse: SystemTime::now(),
-            write,
-            _tasks: tasks,
-        };
-        self.status_sender
-            .send(ConnectionStatus::Online)
-            .expect("receiver to outlive the actor");
+        self.makers.insert(
+            maker_identity,
+            State::Connected {
+                last_heartbeat: SystemTime::now(),
+                last_pulse: SystemTime::now(),
+                write,
+                _tasks: tasks,
+            },
+        );
+        self.set_status(maker_identity, ConnectionStatus::Online);
 
         Ok(())
     }
 
     async fn handle_wire_message(&mut self, message: MakerStreamMessage) -> KeepRunning {
+        let maker = message.maker;
         let msg = match message.item {
             Ok(msg) => msg,
             Err(e) => {
-                tracing::warn!("Error while receiving message from maker: {:#}", e);
+                tracing::warn!(%maker, "Error while receiving message from maker: {:#}", e);
                 return KeepRunning::Yes;
             }
         };
 
         let msg_name = msg.name();
 
-        tracing::trace!(target: "wire", msg_name, "Received");
+        tracing::trace!(target: "wire", msg_name, %maker, "Received");
 
-        match msg {
-            wire::MakerToTaker::Heartbeat => {
-                self.state.handle_incoming_heartbeat();
+        // Only the counterparty of an order is allowed to talk to us about it
+        if let Some(order_id) = msg.order_id() {
+            match self.counterparty(order_id).await {
+                Ok(counterparty) if counterparty == maker => {}
+                Ok(counterparty) => {
+                    tracing::warn!(%order_id, %maker, %counterparty, msg_name, "Ignoring message about order of a different maker");
+                    return KeepRunning::Yes;
+                }
+                Err(e) => {
+                    tracing::warn!(%order_id, %maker, msg_name, "Ignoring message: {e:#}");
+                    return KeepRunning::Yes;
+                }
             }
+        }
+
+        match msg {
+            wire::MakerToTaker::Heartbeat => match self.makers.get_mut(&maker) {
+                Some(state) => state.handle_incoming_heartbeat(),
+                None => tracing::warn!(%maker, "Received heartbeat from unknown maker"),
+            },
             wire::MakerToTaker::ConfirmOrder(order_id) => {
                 if let Err(NotConnected(_)) = self
                     .setup_actors
@@ -481,6 +883,7 @@ impl Actor {
                 oracle_event_id,
                 tx_fee_rate,
                 funding_rate,
+                n_payouts,
             } => {
                 if let Err(NotConnected(_)) = self
                     .rollover_actors
@@ -490,6 +893,7 @@ impl Actor {
                             oracle_event_id,
                             tx_fee_rate,
                             funding_rate,
+                            n_payouts,
                         },
                     )
                     .await
@@ -515,7 +919,10 @@ impl Actor {
             wire::MakerToTaker::CurrentOffers(maker_offers) => {
                 let _ = self
                     .current_order
-                    .send(CurrentMakerOffers(maker_offers))
+                    .send(CurrentMakerOffers {
+                        maker,
+                        offers: maker_offers,
+                    })
                     .log_failure("Failed to forward current order from maker")
                     .await;
             }
@@ -533,10 +940,15 @@ impl Actor {
         KeepRunning::Yes
     }
 
-    fn handle_measure_pulse(&mut self, _: MeasurePulse) {
-        tracing::trace!(target: "wire", "measuring heartbeat pulse");
+    fn handle_measure_pulse(&mut self, MeasurePulse { maker }: MeasurePulse) {
+        tracing::trace!(target: "wire", %maker, "measuring heartbeat pulse");
 
-        match self.state.update_last_pulse_time() {
+        let state = match self.makers.get_mut(&maker) {
+            Some(state) => state,
+            None => return,
+        };
+
+        match state.update_last_pulse_time() {
             Ok(duration) => {
                 if duration >= self.maker_heartbeat_interval {
                     let seconds = self.maker_heartbeat_interval.as_secs();
@@ -553,13 +965,10 @@ impl Actor {
             }
         }
 
-        if self
-            .state
-            .disconnect_if_last_heartbeat_older_than(self.heartbeat_timeout)
-        {
-            self.status_sender
-                .send(ConnectionStatus::Offline { reason: None })
-                .expect("watch receiver to outlive the actor");
+        if state.disconnect_if_last_heartbeat_older_than(self.heartbeat_timeout) {
+            tracing::warn!(%maker, "Lost connection to maker");
+
+            self.set_status(maker, ConnectionStatus::Offline { reason: None });
         }
     }
 }
@@ -571,23 +980,82 @@ impl xtra::Actor for Actor {
     async fn stopped(self) -> Self::Stop {}
 }
 
+/// Receive the offers of the maker over the libp2p offer protocol for as long as the substream is
+/// open.
+///
+/// The libp2p connection is established independently of the legacy one, hence we wait for it
+/// for a while before giving up.
+async fn subscribe_to_offers(
+    endpoint: Address<Endpoint>,
+    hello: libp2p_wire::Hello,
+    maker: Identity,
+    maker_peer_id: PeerId,
+    this: Address<Actor>,
+) -> Result<()> {
+    async {
+        while !is_connected_over_libp2p(&endpoint, maker_peer_id).await? {
+            tokio::time::sleep(LIBP2P_CONNECT_POLL_INTERVAL).await;
+        }
+
+        anyhow::Ok(())
+    }
+    .timeout(LIBP2P_CONNECT_TIMEOUT)
+    .await
+    .with_context(|| format!("No libp2p connection to maker {maker_peer_id}"))??;
+
+    let mut stream = endpoint
+        .send(OpenSubstream::single_protocol(
+            maker_peer_id,
+            libp2p_wire::OFFER_PROTOCOL,
+        ))
+        .await
+        .context("Endpoint actor is disconnected")??;
+    hello.send(&mut stream).await?;
+
+    // We never send anything on this substream, but it stays open for as long as we hold on to
+    // the sink
+    let (_write, read) = libp2p_wire::split::<wire::MakerToTaker, wire::TakerToMaker>(stream);
+
+    tracing::info!(%maker, "Receiving offers over libp2p");
+
+    this.attach_stream(read.map(move |item| MakerStreamMessage { maker, item }))
+        .await;
+
+    Ok(())
+}
+
+async fn is_connected_over_libp2p(endpoint: &Address<Endpoint>, peer: PeerId) -> Result<bool> {
+    let stats = endpoint
+        .send(GetConnectionStats)
+        .await
+        .context("Endpoint actor is disconnected")?;
+
+    Ok(stats.connected_peers.contains(&peer))
+}
+
 // TODO: Move the reconnection logic inside the connection::Actor instead of
 // depending on a watch channel
+/// Keeps the connection to a single maker alive, hence has to be spawned once for every maker.
 pub async fn connect(
-    mut maker_online_status_feed_receiver: watch::Receiver<ConnectionStatus>,
+    mut maker_online_status_feed_receiver: watch::Receiver<HashMap<Identity, ConnectionStatus>>,
     connection_actor_addr: xtra::Address<Actor>,
     maker_identity: Identity,
-    maker_addresses: Vec<SocketAddr>,
+    maker_addresses: Vec<MakerAddress>,
 ) {
     loop {
-        let connection_status = maker_online_status_feed_receiver.borrow().clone();
-        if matches!(connection_status, ConnectionStatus::Offline { .. }) {
-            tracing::debug!("No connection to the maker");
+        let is_online = matches!(
+            maker_online_status_feed_receiver
+                .borrow()
+                .get(&maker_identity),
+            Some(ConnectionStatus::Online)
+        );
+        if !is_online {
+            tracing::debug!(%maker_identity, "No connection to the maker");
             'connect: loop {
                 for address in &maker_addresses {
                     let connect_msg = Connect {
                         maker_identity,
-                        maker_addr: *address,
+                        maker_addr: address.clone(),
                     };
 
                     if let Err(e) = connection_actor_addr
@@ -595,7 +1063,7 @@ pub async fn connect(
                         .await
                         .expect("Taker actor to be present")
                     {
-                        tracing::warn!(%address, "Failed to establish connection: {:#}", e);
+                        tracing::warn!(%address, %maker_identity, "Failed to establish connection: {:#}", e);
                         continue;
                     }
                     break 'connect;
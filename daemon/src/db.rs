@@ -1,9 +1,17 @@
+use crate::access_control;
+use crate::api_token;
+use crate::candles::Candle;
+use crate::candles::Interval;
+use crate::candles::Marker;
+use crate::encryption::Cipher;
+use crate::webhook;
 use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
 use bdk::bitcoin::Amount;
 use bdk::bitcoin::OutPoint;
 use bdk::bitcoin::Script;
+use bdk::bitcoin::SignedAmount;
 use bdk::miniscript::DescriptorTrait;
 use chashmap_async::CHashMap;
 use futures::future::BoxFuture;
@@ -28,12 +36,16 @@ use model::Payout;
 use model::Position;
 use model::Price;
 use model::Role;
+use model::Timestamp;
 use model::TxFeeRate;
 use model::Txid;
 use model::Usd;
 use model::Vout;
 use model::SETTLEMENT_INTERVAL;
 use rayon::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde::Serialize;
 use sqlx::migrate::MigrateError;
 use sqlx::pool::PoolConnection;
 use sqlx::sqlite::SqliteConnectOptions;
@@ -43,15 +55,31 @@ use sqlx::SqlitePool;
 use sqlx::Transaction;
 use std::any::Any;
 use std::any::TypeId;
+use std::fmt;
 use std::path::PathBuf;
+use std::str::FromStr;
 use std::sync::Arc;
 use time::Duration;
 use time::OffsetDateTime;
+use uuid::Uuid;
+
+/// Store a snapshot of an aggregate every time this many events have been applied to it.
+const SNAPSHOT_INTERVAL: u32 = 50;
+
+/// The events whose data contains key material and is therefore encrypted at rest.
+const ENCRYPTED_EVENTS: [&str; 2] = [
+    EventKind::CONTRACT_SETUP_COMPLETED_EVENT,
+    EventKind::ROLLOVER_COMPLETED_EVENT,
+];
+
+/// Marks encrypted data, followed by the hex-encoded ciphertext.
+const ENCRYPTED_PREFIX: &str = "encrypted:";
 
 #[derive(Clone)]
 pub struct Connection {
     inner: SqlitePool,
     aggregate_cache: Arc<CHashMap<(TypeId, OrderId), Box<dyn Any + Send + Sync + 'static>>>,
+    encryption: Encryption,
 }
 
 impl Connection {
@@ -59,7 +87,103 @@ impl Connection {
         Self {
             inner: pool,
             aggregate_cache: Arc::new(CHashMap::new()),
+            encryption: Encryption::default(),
+        }
+    }
+
+    /// Encrypt the data of events containing key material and all snapshots with the given key.
+    ///
+    /// Data which was stored in plaintext before is encrypted right away.
+    pub async fn encrypt_at_rest(mut self, key: EncryptionKey) -> Result<Self> {
+        self.encryption = Encryption(Some(key.0));
+
+        let mut conn = self.inner.acquire().await?;
+        let mut db_tx = conn.begin().await?;
+
+        let events = sqlx::query_as::<_, (i64, String, String)>(
+            r#"
+            SELECT
+                id,
+                name,
+                data
+            FROM
+                events
+            WHERE
+                name IN ($1, $2) AND data NOT LIKE $3
+            "#,
+        )
+        .bind(ENCRYPTED_EVENTS[0])
+        .bind(ENCRYPTED_EVENTS[1])
+        .bind(format!("{ENCRYPTED_PREFIX}%"))
+        .fetch_all(&mut db_tx)
+        .await?;
+
+        let num_events = events.len();
+
+        for (id, name, data) in events {
+            sqlx::query(
+                r#"
+                UPDATE
+                    events
+                SET
+                    data = $1
+                WHERE
+                    id = $2
+                "#,
+            )
+            .bind(self.encryption.encrypt_event(&name, data)?)
+            .bind(id)
+            .execute(&mut db_tx)
+            .await?;
+        }
+
+        let snapshots = sqlx::query_as::<_, (i64, String, String)>(
+            r#"
+            SELECT
+                cfd_id,
+                aggregate,
+                data
+            FROM
+                snapshots
+            WHERE
+                data NOT LIKE $1
+            "#,
+        )
+        .bind(format!("{ENCRYPTED_PREFIX}%"))
+        .fetch_all(&mut db_tx)
+        .await?;
+
+        let num_snapshots = snapshots.len();
+
+        for (cfd_id, aggregate, data) in snapshots {
+            sqlx::query(
+                r#"
+                UPDATE
+                    snapshots
+                SET
+                    data = $1
+                WHERE
+                    cfd_id = $2 AND aggregate = $3
+                "#,
+            )
+            .bind(self.encryption.encrypt(data)?)
+            .bind(cfd_id)
+            .bind(aggregate)
+            .execute(&mut db_tx)
+            .await?;
+        }
+
+        db_tx.commit().await?;
+
+        if num_events > 0 || num_snapshots > 0 {
+            tracing::info!(
+                %num_events,
+                %num_snapshots,
+                "Encrypted data which was stored in plaintext"
+            );
         }
+
+        Ok(self)
     }
 
     pub async fn close(self) {
@@ -67,6 +191,55 @@ impl Connection {
     }
 }
 
+/// The key the database is encrypted with.
+pub struct EncryptionKey(Cipher);
+
+impl From<[u8; 32]> for EncryptionKey {
+    fn from(key: [u8; 32]) -> Self {
+        Self(Cipher::from(key))
+    }
+}
+
+/// Encrypts and decrypts data stored in the database, if a key was configured.
+#[derive(Clone, Default)]
+struct Encryption(Option<Cipher>);
+
+impl Encryption {
+    fn encrypt(&self, data: String) -> Result<String> {
+        let cipher = match &self.0 {
+            Some(cipher) => cipher,
+            None => return Ok(data),
+        };
+        let ciphertext = cipher.encrypt(data.as_bytes())?;
+
+        Ok(format!("{ENCRYPTED_PREFIX}{}", hex::encode(ciphertext)))
+    }
+
+    /// Encrypt the data of the event if it contains key material.
+    fn encrypt_event(&self, name: &str, data: String) -> Result<String> {
+        if !ENCRYPTED_EVENTS.contains(&name) {
+            return Ok(data);
+        }
+
+        self.encrypt(data)
+    }
+
+    /// Decrypt the data if it is encrypted, otherwise return it as is.
+    fn decrypt(&self, data: String) -> Result<String> {
+        let ciphertext = match data.strip_prefix(ENCRYPTED_PREFIX) {
+            Some(ciphertext) => hex::decode(ciphertext).context("Invalid encrypted data")?,
+            None => return Ok(data),
+        };
+        let cipher = self
+            .0
+            .as_ref()
+            .context("Data is encrypted but no key was configured")?;
+        let plaintext = cipher.decrypt(&ciphertext)?;
+
+        String::from_utf8(plaintext).context("Decrypted data is not UTF-8")
+    }
+}
+
 /// Connects to the SQLite database at the given path.
 ///
 /// If the database does not exist, it will be created. If it does exist, we load it and apply all
@@ -187,15 +360,21 @@ impl Connection {
     ///
     /// To make handling of `None` events more ergonomic, you can pass anything in here that
     /// implements `Into<Option>` event.
+    ///
+    /// If webhooks are notified about the event, the notification is put into the outbox of every
+    /// webhook within the same transaction.
     pub async fn append_event(&self, event: impl Into<Option<CfdEvent>>) -> Result<()> {
-        let mut conn = self.inner.acquire().await?;
-
         let event = match event.into() {
             Some(event) => event,
             None => return Ok(()),
         };
 
+        let mut conn = self.inner.acquire().await?;
+        let mut db_tx = conn.begin().await?;
+
+        let notification = webhook::Notification::from_event(&event);
         let (event_name, event_data) = event.event.to_json();
+        let event_data = self.encryption.encrypt_event(&event_name, event_data)?;
 
         let query_result = sqlx::query(
             r##"
@@ -213,13 +392,34 @@ impl Connection {
         .bind(&event_name)
         .bind(&event_data)
         .bind(&event.timestamp)
-        .execute(&mut conn)
+        .execute(&mut db_tx)
         .await?;
 
         if query_result.rows_affected() != 1 {
             anyhow::bail!("failed to insert event");
         }
 
+        if let Some(notification) = notification {
+            let now = Timestamp::now();
+
+            sqlx::query(
+                r#"
+                INSERT INTO webhook_outbox (
+                    webhook_id,
+                    payload,
+                    next_attempt_at,
+                    created_at
+                ) SELECT id, $1, $2, $2 FROM webhooks
+                "#,
+            )
+            .bind(serde_json::to_string(&notification)?)
+            .bind(&now)
+            .execute(&mut db_tx)
+            .await?;
+        }
+
+        db_tx.commit().await?;
+
         tracing::info!(event = %event_name, order_id = %event.id, "Appended event to database");
 
         Ok(())
@@ -238,11 +438,25 @@ impl Connection {
 
         let cfd = match self.aggregate_cache.remove(&cache_key).await {
             None => {
-                // No cache entry? Load the CFD row. Version will be 0 because we haven't applied
-                // any events, thus all events will be loaded.
-                let cfd = load_cfd_row(&mut db_tx, id).await?;
-
-                C::new(args, cfd)
+                let snapshot = load_snapshot::<C>(&mut db_tx, &self.encryption, id)
+                    .await
+                    .unwrap_or_else(|e| {
+                        tracing::warn!(order_id = %id, %aggregate, "Ignoring snapshot: {e:#}");
+                        None
+                    });
+
+                match snapshot {
+                    // No cache entry but a snapshot: Only the events after the version of the
+                    // snapshot will be loaded.
+                    Some(cfd) => cfd,
+                    None => {
+                        // No cache entry? Load the CFD row. Version will be 0 because we haven't
+                        // applied any events, thus all events will be loaded.
+                        let cfd = load_cfd_row(&mut db_tx, id).await?;
+
+                        C::new(args, cfd)
+                    }
+                }
             }
             Some(cfd) => {
                 // Got a cache entry: Downcast it to the type at hand.
@@ -253,13 +467,19 @@ impl Connection {
         };
         let cfd_version = cfd.version();
 
-        let events = load_cfd_events(&mut db_tx, id, cfd_version).await?;
+        let events = load_cfd_events(&mut db_tx, &self.encryption, id, cfd_version).await?;
         let num_events = events.len();
 
         tracing::debug!(order_id = %id, %aggregate, %cfd_version, %num_events, "Applying new events to CFD");
 
         let cfd = events.into_iter().fold(cfd, C::apply);
 
+        if cfd.version() / SNAPSHOT_INTERVAL > cfd_version / SNAPSHOT_INTERVAL {
+            if let Err(e) = store_snapshot(&mut db_tx, &self.encryption, id, &cfd).await {
+                tracing::warn!(order_id = %id, %aggregate, "Failed to store snapshot: {e:#}");
+            }
+        }
+
         self.aggregate_cache
             .insert(cache_key, Box::new(cfd.clone()))
             .await;
@@ -421,12 +641,13 @@ impl Connection {
 
         for id in ids.into_iter() {
             let pool = self.inner.clone();
+            let encryption = self.encryption.clone();
             let fut = async move {
                 let mut conn = pool.acquire().await?;
                 let mut db_tx = conn.begin().await?;
 
                 let cfd = load_cfd_row(&mut db_tx, id).await?;
-                let events = load_cfd_events(&mut db_tx, id, 0).await?;
+                let events = load_cfd_events(&mut db_tx, &encryption, id, 0).await?;
                 let event_log = EventLog::new(&events);
 
                 let closed_cfd = ClosedCfdInputAggregate::new(cfd);
@@ -436,10 +657,12 @@ impl Connection {
                     .build()?;
 
                 insert_closed_cfd(&mut db_tx, closed_cfd).await?;
+                insert_fee_breakdown(&mut db_tx, id, closed_cfd.fee_breakdown).await?;
                 insert_event_log(&mut db_tx, id, event_log).await?;
 
                 insert_settlement(&mut db_tx, id, closed_cfd.settlement).await?;
 
+                delete_from_snapshots_table(&mut db_tx, id).await?;
                 delete_from_events_table(&mut db_tx, id).await?;
                 delete_from_cfds_table(&mut db_tx, id).await?;
 
@@ -462,69 +685,194 @@ impl Connection {
         C: ClosedCfdAggregate,
     {
         let mut conn = self.inner.acquire().await?;
+        let cfd = load_closed_cfd_row(&mut conn, id).await?;
+
+        Ok(C::new_closed(args, cfd))
+    }
+
+    /// Load the CFDs which were closed within the given time range, in the order they were closed.
+    ///
+    /// A CFD is closed once the transaction settling it is confirmed.
+    pub async fn load_closed_cfd_records(
+        &self,
+        from: Timestamp,
+        to: Timestamp,
+    ) -> Result<Vec<ClosedCfdRecord>> {
+        let mut conn = self.inner.acquire().await?;
 
-        let cfd = sqlx::query!(
+        let ids = sqlx::query_scalar::<_, OrderId>(
             r#"
             SELECT
-                uuid as "uuid: model::OrderId",
-                position as "position: model::Position",
-                initial_price as "initial_price: model::Price",
-                taker_leverage as "taker_leverage: model::Leverage",
-                n_contracts as "n_contracts: model::Contracts",
-                counterparty_network_identity as "counterparty_network_identity: model::Identity",
-                role as "role: model::Role",
-                fees as "fees: model::Fees",
-                expiry_timestamp,
-                lock_txid as "lock_txid: model::Txid",
-                lock_dlc_vout as "lock_dlc_vout: model::Vout"
-            FROM
-                closed_cfds
+                uuid
+            FROM (
+                SELECT
+                    closed_cfds.uuid,
+                    (
+                        SELECT MAX(event_log.created_at) FROM event_log
+                        WHERE event_log.cfd_id = closed_cfds.id AND event_log.name IN ($1, $2, $3)
+                    ) AS closed_at
+                FROM
+                    closed_cfds
+            )
             WHERE
-                closed_cfds.uuid = $1
+                closed_at >= $4 AND closed_at <= $5
+            ORDER BY
+                closed_at
             "#,
-            id
         )
-        .fetch_one(&mut conn)
+        .bind(EventKind::COLLABORATIVE_SETTLEMENT_CONFIRMED)
+        .bind(EventKind::CET_CONFIRMED)
+        .bind(EventKind::REFUND_CONFIRMED)
+        .bind(from.seconds())
+        .bind(to.seconds())
+        .fetch_all(&mut conn)
         .await?;
 
-        let expiry_timestamp = OffsetDateTime::from_unix_timestamp(cfd.expiry_timestamp)?;
-
-        let collaborative_settlement = load_collaborative_settlement(&mut conn, id).await?;
-        let cet_settlement = load_cet_settlement(&mut conn, id).await?;
-        let refund_settlement = load_refund_settlement(&mut conn, id).await?;
-
-        let settlement = match (collaborative_settlement, cet_settlement, refund_settlement) {
-            (Some(collaborative_settlement), None, None) => collaborative_settlement,
-            (None, Some(cet), None) => cet,
-            (None, None, Some(refund)) => refund,
-            _ => {
-                bail!(
-                    "Closed CFD has insane combination of transactions:
-                       {collaborative_settlement:?},
-                       {cet_settlement:?},
-                       {refund_settlement:?}"
-                )
-            }
-        };
+        let mut records = Vec::with_capacity(ids.len());
 
-        let cfd = ClosedCfd {
-            id,
-            position: cfd.position,
-            initial_price: cfd.initial_price,
-            taker_leverage: cfd.taker_leverage,
-            n_contracts: cfd.n_contracts,
-            counterparty_network_identity: cfd.counterparty_network_identity,
-            role: cfd.role,
-            fees: cfd.fees,
-            expiry_timestamp,
-            lock: Lock {
-                txid: cfd.lock_txid,
-                dlc_vout: cfd.lock_dlc_vout,
-            },
-            settlement,
-        };
+        for id in ids {
+            records.push(load_closed_cfd_record(&mut conn, id).await?);
+        }
 
-        Ok(C::new_closed(args, cfd))
+        Ok(records)
+    }
+
+    /// Load up to `limit` open and closed CFDs matching the filter, newest first.
+    ///
+    /// CFDs are ordered by the time their first event was recorded, hence a CFD only shows up
+    /// once contract setup was completed, has failed or was rejected. Pass the cursor of the last
+    /// CFD of a page as `after` to load the next page. The cursor of a CFD does not change when
+    /// it is closed, hence pages are stable.
+    pub async fn load_cfd_history(
+        &self,
+        filter: CfdFilter,
+        after: Option<HistoryCursor>,
+        limit: u32,
+    ) -> Result<Vec<HistoricCfd>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let rows = sqlx::query_as::<
+            _,
+            (
+                OrderId,
+                String,
+                Position,
+                Role,
+                Identity,
+                Usd,
+                Leverage,
+                Price,
+                i64,
+            ),
+        >(
+            r#"
+            SELECT
+                uuid,
+                state,
+                position,
+                role,
+                counterparty_network_identity,
+                quantity_usd,
+                taker_leverage,
+                initial_price,
+                created_at
+            FROM (
+                SELECT
+                    cfds.uuid,
+                    CASE WHEN EXISTS (
+                        SELECT 1 FROM events
+                        WHERE events.cfd_id = cfds.id AND events.name IN ($1, $2)
+                    ) THEN 'failed' ELSE 'open' END AS state,
+                    cfds.position,
+                    cfds.role,
+                    cfds.counterparty_network_identity,
+                    cfds.quantity_usd,
+                    cfds.leverage AS taker_leverage,
+                    cfds.initial_price,
+                    (
+                        SELECT MIN(CAST(events.created_at AS INTEGER)) FROM events
+                        WHERE events.cfd_id = cfds.id
+                    ) AS created_at
+                FROM
+                    cfds
+                UNION ALL
+                SELECT
+                    closed_cfds.uuid,
+                    'closed' AS state,
+                    closed_cfds.position,
+                    closed_cfds.role,
+                    closed_cfds.counterparty_network_identity,
+                    CAST(closed_cfds.n_contracts AS TEXT) AS quantity_usd,
+                    closed_cfds.taker_leverage,
+                    closed_cfds.initial_price,
+                    (
+                        SELECT MIN(event_log.created_at) FROM event_log
+                        WHERE event_log.cfd_id = closed_cfds.id
+                    ) AS created_at
+                FROM
+                    closed_cfds
+            )
+            WHERE
+                created_at IS NOT NULL
+                AND ($3 IS NULL OR state = $3)
+                AND ($4 IS NULL OR position = $4)
+                AND ($5 IS NULL OR counterparty_network_identity = $5)
+                AND ($6 IS NULL OR created_at >= $6)
+                AND ($7 IS NULL OR created_at <= $7)
+                AND ($8 IS NULL OR created_at < $8 OR (created_at = $8 AND uuid < $9))
+            ORDER BY
+                created_at DESC, uuid DESC
+            LIMIT $10
+            "#,
+        )
+        .bind(EventKind::CONTRACT_SETUP_FAILED)
+        .bind(EventKind::OFFER_REJECTED)
+        .bind(filter.state.map(|state| state.as_str()))
+        .bind(filter.position)
+        .bind(filter.counterparty)
+        .bind(filter.from.map(|from| from.seconds()))
+        .bind(filter.to.map(|to| to.seconds()))
+        .bind(after.map(|cursor| cursor.created_at.seconds()))
+        .bind(after.map(|cursor| cursor.order_id))
+        .bind(limit)
+        .fetch_all(&mut conn)
+        .await?;
+
+        let mut cfds = Vec::with_capacity(rows.len());
+
+        for (
+            order_id,
+            state,
+            position,
+            role,
+            counterparty,
+            quantity_usd,
+            taker_leverage,
+            initial_price,
+            created_at,
+        ) in rows
+        {
+            let state = state.parse::<HistoryState>()?;
+            let closed = match state {
+                HistoryState::Closed => Some(load_closed_cfd_record(&mut conn, order_id).await?),
+                HistoryState::Open | HistoryState::Failed => None,
+            };
+
+            cfds.push(HistoricCfd {
+                order_id,
+                state,
+                position,
+                role,
+                counterparty,
+                quantity_usd,
+                taker_leverage,
+                initial_price,
+                created_at: Timestamp::new(created_at),
+                closed,
+            });
+        }
+
+        Ok(cfds)
     }
 
     async fn closed_cfd_ids_according_to_the_blockchain(&self) -> Result<Vec<OrderId>> {
@@ -561,78 +909,983 @@ impl Connection {
     }
 }
 
-async fn insert_settlement(
-    conn: &mut Transaction<'_, Sqlite>,
-    id: OrderId,
-    settlement: Settlement,
-) -> Result<()> {
-    match settlement {
-        Settlement::Collaborative {
-            txid,
-            vout,
-            payout,
-            price,
-        } => insert_collaborative_settlement(conn, id, txid, vout, payout, price).await?,
-        Settlement::Cet {
-            commit_txid,
-            txid,
-            vout,
-            payout,
-            price,
-        } => insert_cet_settlement(conn, id, commit_txid, txid, vout, payout, price).await?,
-        Settlement::Refund {
-            commit_txid,
-            txid,
-            vout,
-            payout,
-        } => insert_refund_settlement(conn, id, commit_txid, txid, vout, payout).await?,
-    };
+impl Connection {
+    pub async fn upsert_candle(&self, interval: Interval, candle: &Candle) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
 
-    Ok(())
-}
+        let query_result = sqlx::query(
+            r#"
+            INSERT INTO candles (
+                period,
+                start_time,
+                open,
+                high,
+                low,
+                close
+            ) VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (period, start_time) DO UPDATE SET
+                open = excluded.open,
+                high = excluded.high,
+                low = excluded.low,
+                close = excluded.close
+            "#,
+        )
+        .bind(interval.to_string())
+        .bind(&candle.start)
+        .bind(&candle.open)
+        .bind(&candle.high)
+        .bind(&candle.low)
+        .bind(&candle.close)
+        .execute(&mut conn)
+        .await?;
 
-// TODO: Make sqlx directly instantiate this struct instead of mapping manually. Need to create
-// newtype for `settlement_interval`.
-#[derive(Clone, Copy)]
-pub struct Cfd {
-    pub id: OrderId,
-    pub position: Position,
-    pub initial_price: Price,
-    pub taker_leverage: Leverage,
-    pub settlement_interval: Duration,
-    pub quantity_usd: Usd,
-    pub counterparty_network_identity: Identity,
-    pub role: Role,
-    pub opening_fee: OpeningFee,
-    pub initial_funding_rate: FundingRate,
-    pub initial_tx_fee_rate: TxFeeRate,
-}
+        if query_result.rows_affected() != 1 {
+            anyhow::bail!("failed to upsert candle");
+        }
 
-#[derive(thiserror::Error, Debug)]
-pub enum Error {
-    #[error("The CFD requested was not found in the open CFDs")]
-    OpenCfdNotFound,
-    #[error("{0:#}")]
-    Sqlx(#[source] sqlx::Error),
-    #[error("{0:#}")]
-    Other(#[source] anyhow::Error),
-}
+        Ok(())
+    }
 
-impl From<sqlx::Error> for Error {
-    fn from(e: sqlx::Error) -> Self {
-        Error::Sqlx(e)
+    pub async fn load_candle(
+        &self,
+        interval: Interval,
+        start: Timestamp,
+    ) -> Result<Option<Candle>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let candle = sqlx::query_as::<_, Candle>(
+            r#"
+            SELECT
+                start_time,
+                open,
+                high,
+                low,
+                close
+            FROM
+                candles
+            WHERE
+                period = $1 AND start_time = $2
+            "#,
+        )
+        .bind(interval.to_string())
+        .bind(&start)
+        .fetch_optional(&mut conn)
+        .await?;
+
+        Ok(candle)
     }
-}
 
-impl From<anyhow::Error> for Error {
-    fn from(e: anyhow::Error) -> Self {
-        Error::Other(e)
+    /// Load all candles of the given interval starting at or after `from`, oldest first.
+    pub async fn load_candles(&self, interval: Interval, from: Timestamp) -> Result<Vec<Candle>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let candles = sqlx::query_as::<_, Candle>(
+            r#"
+            SELECT
+                start_time,
+                open,
+                high,
+                low,
+                close
+            FROM
+                candles
+            WHERE
+                period = $1 AND start_time >= $2
+            ORDER BY
+                start_time
+            "#,
+        )
+        .bind(interval.to_string())
+        .bind(&from)
+        .fetch_all(&mut conn)
+        .await?;
+
+        Ok(candles)
     }
-}
 
-/// Data loaded from the database about a closed CFD.
-#[derive(Debug, Clone, Copy)]
-pub struct ClosedCfd {
+    /// Remove the CETs from the DLCs of contract setups and rollovers which have been superseded
+    /// by a later rollover.
+    ///
+    /// Every rollover embeds a new DLC including all CETs. Once a CFD has been rolled over, the
+    /// CETs of the previous DLCs can no longer be used because their commit transaction has been
+    /// revoked. Everything else is kept, hence the events can still be applied to aggregates.
+    ///
+    /// Returns the number of pruned events.
+    pub async fn prune_superseded_dlcs(&self) -> Result<usize> {
+        let mut conn = self.inner.acquire().await?;
+        let mut db_tx = conn.begin().await?;
+
+        let events = sqlx::query_as::<_, (i64, String, String)>(
+            r#"
+            SELECT
+                events.id,
+                events.name,
+                events.data
+            FROM
+                events
+            WHERE
+                events.name IN ($1, $2)
+                AND EXISTS (
+                    SELECT
+                        1
+                    FROM
+                        events later
+                    WHERE
+                        later.cfd_id = events.cfd_id
+                        AND later.name = $2
+                        AND later.id > events.id
+                )
+            "#,
+        )
+        .bind(EventKind::CONTRACT_SETUP_COMPLETED_EVENT)
+        .bind(EventKind::ROLLOVER_COMPLETED_EVENT)
+        .fetch_all(&mut db_tx)
+        .await?;
+
+        let mut num_pruned = 0;
+
+        for (id, name, data) in events {
+            let data = self.encryption.decrypt(data)?;
+            let mut data = serde_json::from_str::<serde_json::Value>(&data)?;
+            let cets = data
+                .pointer_mut("/dlc/cets")
+                .with_context(|| format!("Event {id} does not contain CETs"))?;
+
+            if cets.as_object().map_or(false, |cets| cets.is_empty()) {
+                continue;
+            }
+            *cets = serde_json::json!({});
+
+            sqlx::query(
+                r#"
+                UPDATE
+                    events
+                SET
+                    data = $1
+                WHERE
+                    id = $2
+                "#,
+            )
+            .bind(self.encryption.encrypt_event(&name, data.to_string())?)
+            .bind(id)
+            .execute(&mut db_tx)
+            .await?;
+
+            num_pruned += 1;
+        }
+
+        db_tx.commit().await?;
+
+        Ok(num_pruned)
+    }
+
+    /// Load up to `limit` events of open CFDs which were appended after the event at `cursor`, in
+    /// the order they were appended.
+    ///
+    /// The cursor of an event never changes, hence consumers can resume from the cursor of the
+    /// last event they processed. Pass a cursor of 0 to start from the beginning.
+    pub async fn load_events_after(&self, cursor: i64, limit: u32) -> Result<Vec<StoredEvent>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let events = sqlx::query_as::<_, StoredEvent>(
+            r#"
+            SELECT
+                events.id as cursor,
+                cfds.uuid as order_id,
+                events.name as name,
+                events.data as data,
+                events.created_at as timestamp
+            FROM
+                events
+            JOIN
+                cfds ON cfds.id = events.cfd_id
+            WHERE
+                events.id > $1
+            ORDER BY
+                events.id
+            LIMIT $2
+            "#,
+        )
+        .bind(cursor)
+        .bind(limit)
+        .fetch_all(&mut conn)
+        .await?
+        .into_iter()
+        .map(|event| {
+            Ok(StoredEvent {
+                data: self.encryption.decrypt(event.data)?,
+                ..event
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+        Ok(events)
+    }
+
+    /// Export all open CFDs together with their events.
+    ///
+    /// Everything is read within one transaction, hence the export is consistent even if events
+    /// are appended concurrently.
+    pub async fn export_open_cfds(&self) -> Result<Vec<ExportedCfd>> {
+        let mut conn = self.inner.acquire().await?;
+        let mut db_tx = conn.begin().await?;
+
+        let ids = sqlx::query_scalar::<_, OrderId>(
+            r#"
+            SELECT
+                uuid
+            FROM
+                cfds
+            ORDER BY
+                id
+            "#,
+        )
+        .fetch_all(&mut db_tx)
+        .await?;
+
+        let mut cfds = Vec::with_capacity(ids.len());
+
+        for id in ids {
+            let cfd = load_cfd_row(&mut db_tx, id).await?;
+
+            let events = sqlx::query_as::<_, ExportedEvent>(
+                r#"
+                SELECT
+                    events.name,
+                    events.data,
+                    events.created_at
+                FROM
+                    events
+                JOIN
+                    cfds ON cfds.id = events.cfd_id
+                WHERE
+                    cfds.uuid = $1
+                ORDER BY
+                    events.id
+                "#,
+            )
+            .bind(&id)
+            .fetch_all(&mut db_tx)
+            .await?
+            .into_iter()
+            .map(|event| {
+                Ok(ExportedEvent {
+                    data: self.encryption.decrypt(event.data)?,
+                    ..event
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+            cfds.push(ExportedCfd { cfd, events });
+        }
+
+        db_tx.commit().await?;
+
+        Ok(cfds)
+    }
+
+    /// Insert a CFD exported by [`Connection::export_open_cfds`] together with its events.
+    pub async fn import_cfd(&self, exported: &ExportedCfd) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+        let mut db_tx = conn.begin().await?;
+
+        let cfd = &exported.cfd;
+
+        let query_result = sqlx::query(
+            r#"
+        insert into cfds (
+            uuid,
+            position,
+            initial_price,
+            leverage,
+            settlement_time_interval_hours,
+            quantity_usd,
+            counterparty_network_identity,
+            role,
+            opening_fee,
+            initial_funding_rate,
+            initial_tx_fee_rate
+        ) values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)"#,
+        )
+        .bind(&cfd.id)
+        .bind(&cfd.position)
+        .bind(&cfd.initial_price)
+        .bind(&cfd.taker_leverage)
+        .bind(&cfd.settlement_interval.whole_hours())
+        .bind(&cfd.quantity_usd)
+        .bind(&cfd.counterparty_network_identity)
+        .bind(&cfd.role)
+        .bind(&cfd.opening_fee)
+        .bind(&cfd.initial_funding_rate)
+        .bind(&cfd.initial_tx_fee_rate)
+        .execute(&mut db_tx)
+        .await
+        .with_context(|| format!("Failed to insert CFD {}", cfd.id))?;
+
+        if query_result.rows_affected() != 1 {
+            anyhow::bail!("failed to insert cfd");
+        }
+
+        for event in exported.events.iter() {
+            let query_result = sqlx::query(
+                r##"
+            insert into events (
+                cfd_id,
+                name,
+                data,
+                created_at
+            ) values (
+                (select id from cfds where cfds.uuid = $1),
+                $2, $3, $4
+            )"##,
+            )
+            .bind(&cfd.id)
+            .bind(&event.name)
+            .bind(
+                self.encryption
+                    .encrypt_event(&event.name, event.data.clone())?,
+            )
+            .bind(&event.created_at)
+            .execute(&mut db_tx)
+            .await?;
+
+            if query_result.rows_affected() != 1 {
+                anyhow::bail!("failed to insert event");
+            }
+        }
+
+        db_tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Add a webhook, replacing the secret if a webhook with the same URL exists already.
+    pub async fn upsert_webhook(&self, webhook: &webhook::NewWebhook) -> Result<i64> {
+        let mut conn = self.inner.acquire().await?;
+
+        let id = sqlx::query_scalar::<_, i64>(
+            r#"
+            INSERT INTO webhooks (
+                url,
+                secret
+            ) VALUES ($1, $2)
+            ON CONFLICT (url) DO UPDATE SET
+                secret = excluded.secret
+            RETURNING id
+            "#,
+        )
+        .bind(&webhook.url)
+        .bind(&webhook.secret)
+        .fetch_one(&mut conn)
+        .await?;
+
+        Ok(id)
+    }
+
+    pub async fn load_webhooks(&self) -> Result<Vec<webhook::Webhook>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let webhooks = sqlx::query_as::<_, webhook::Webhook>(
+            r#"
+            SELECT
+                id,
+                url,
+                secret
+            FROM
+                webhooks
+            ORDER BY
+                id
+            "#,
+        )
+        .fetch_all(&mut conn)
+        .await?;
+
+        Ok(webhooks)
+    }
+
+    /// Delete the webhook together with its pending notifications.
+    ///
+    /// Returns whether a webhook with this ID existed.
+    pub async fn delete_webhook(&self, id: i64) -> Result<bool> {
+        let mut conn = self.inner.acquire().await?;
+        let mut db_tx = conn.begin().await?;
+
+        sqlx::query(
+            r#"
+            DELETE FROM
+                webhook_outbox
+            WHERE
+                webhook_id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(&mut db_tx)
+        .await?;
+
+        let query_result = sqlx::query(
+            r#"
+            DELETE FROM
+                webhooks
+            WHERE
+                id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(&mut db_tx)
+        .await?;
+
+        db_tx.commit().await?;
+
+        Ok(query_result.rows_affected() == 1)
+    }
+
+    /// Store a new API token, identified by the hash of its secret.
+    pub async fn insert_api_token(&self, token: &api_token::NewToken, hash: &str) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO api_tokens (
+                name,
+                hash,
+                scopes,
+                created_at
+            ) VALUES ($1, $2, $3, $4)
+            "#,
+        )
+        .bind(&token.name)
+        .bind(hash)
+        .bind(api_token::encode_scopes(&token.scopes))
+        .bind(Timestamp::now().seconds())
+        .execute(&mut conn)
+        .await
+        .with_context(|| format!("Failed to insert API token {}", token.name))?;
+
+        Ok(())
+    }
+
+    pub async fn load_api_tokens(&self) -> Result<Vec<api_token::Token>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let tokens = sqlx::query_as::<_, (String, String, i64)>(
+            r#"
+            SELECT
+                name,
+                scopes,
+                created_at
+            FROM
+                api_tokens
+            ORDER BY
+                id
+            "#,
+        )
+        .fetch_all(&mut conn)
+        .await?
+        .into_iter()
+        .map(|(name, scopes, created_at)| {
+            Ok(api_token::Token {
+                name,
+                scopes: api_token::decode_scopes(&scopes)?,
+                created_at: Timestamp::new(created_at),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+        Ok(tokens)
+    }
+
+    /// Load the scopes of the API token with the given hash, `None` if there is no such token.
+    pub async fn load_api_token_scopes(&self, hash: &str) -> Result<Option<Vec<api_token::Scope>>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let scopes = sqlx::query_scalar::<_, String>(
+            r#"
+            SELECT
+                scopes
+            FROM
+                api_tokens
+            WHERE
+                hash = $1
+            "#,
+        )
+        .bind(hash)
+        .fetch_optional(&mut conn)
+        .await?;
+
+        scopes
+            .map(|scopes| api_token::decode_scopes(&scopes))
+            .transpose()
+    }
+
+    /// Revoke the API token with the given name.
+    ///
+    /// Returns whether a token with this name existed.
+    pub async fn delete_api_token(&self, name: &str) -> Result<bool> {
+        let mut conn = self.inner.acquire().await?;
+
+        let query_result = sqlx::query(
+            r#"
+            DELETE FROM
+                api_tokens
+            WHERE
+                name = $1
+            "#,
+        )
+        .bind(name)
+        .execute(&mut conn)
+        .await?;
+
+        Ok(query_result.rows_affected() == 1)
+    }
+
+    /// Load the access policy as last stored, `None` if it was never stored.
+    pub async fn load_access_policy(&self) -> Result<Option<access_control::Policy>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let data = sqlx::query_scalar::<_, String>(
+            r#"
+            SELECT
+                data
+            FROM
+                access_policy
+            WHERE
+                id = 1
+            "#,
+        )
+        .fetch_optional(&mut conn)
+        .await?;
+
+        data.map(|data| serde_json::from_str(&data).context("Failed to deserialize access policy"))
+            .transpose()
+    }
+
+    pub async fn store_access_policy(&self, policy: &access_control::Policy) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO access_policy (
+                id,
+                data
+            ) VALUES (1, $1)
+            ON CONFLICT (id) DO UPDATE SET
+                data = excluded.data
+            "#,
+        )
+        .bind(serde_json::to_string(policy)?)
+        .execute(&mut conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Store the state of a protocol run, replacing the previously stored state of this run.
+    ///
+    /// The state contains secret keys and is therefore encrypted if a key was configured.
+    pub async fn store_protocol_checkpoint<T>(
+        &self,
+        order_id: OrderId,
+        protocol: &str,
+        checkpoint: &T,
+    ) -> Result<()>
+    where
+        T: Serialize,
+    {
+        let mut conn = self.inner.acquire().await?;
+
+        let data = serde_json::to_string(checkpoint).context("Failed to serialize checkpoint")?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO protocol_checkpoints (
+                order_id,
+                protocol,
+                data
+            ) VALUES ($1, $2, $3)
+            ON CONFLICT (order_id, protocol) DO UPDATE SET
+                data = excluded.data
+            "#,
+        )
+        .bind(&order_id)
+        .bind(protocol)
+        .bind(self.encryption.encrypt(data)?)
+        .execute(&mut conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Load the state of all runs of the given protocol which did not finish yet.
+    pub async fn load_protocol_checkpoints<T>(&self, protocol: &str) -> Result<Vec<T>>
+    where
+        T: DeserializeOwned,
+    {
+        let mut conn = self.inner.acquire().await?;
+
+        let rows = sqlx::query_scalar::<_, String>(
+            r#"
+            SELECT
+                data
+            FROM
+                protocol_checkpoints
+            WHERE
+                protocol = $1
+            "#,
+        )
+        .bind(protocol)
+        .fetch_all(&mut conn)
+        .await?;
+
+        rows.into_iter()
+            .map(|data| {
+                let data = self.encryption.decrypt(data)?;
+
+                serde_json::from_str(&data).context("Failed to deserialize checkpoint")
+            })
+            .collect()
+    }
+
+    pub async fn delete_protocol_checkpoint(
+        &self,
+        order_id: OrderId,
+        protocol: &str,
+    ) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+
+        sqlx::query(
+            r#"
+            DELETE FROM
+                protocol_checkpoints
+            WHERE
+                order_id = $1 AND protocol = $2
+            "#,
+        )
+        .bind(&order_id)
+        .bind(protocol)
+        .execute(&mut conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Load up to `limit` notifications whose next delivery attempt is due at `now`, oldest first.
+    pub async fn load_due_webhook_deliveries(
+        &self,
+        now: Timestamp,
+        limit: u32,
+    ) -> Result<Vec<webhook::Delivery>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let deliveries = sqlx::query_as::<_, webhook::Delivery>(
+            r#"
+            SELECT
+                webhook_outbox.id as id,
+                webhooks.url as url,
+                webhooks.secret as secret,
+                webhook_outbox.payload as payload,
+                webhook_outbox.attempts as attempts
+            FROM
+                webhook_outbox
+            JOIN
+                webhooks ON webhooks.id = webhook_outbox.webhook_id
+            WHERE
+                webhook_outbox.next_attempt_at <= $1
+            ORDER BY
+                webhook_outbox.id
+            LIMIT $2
+            "#,
+        )
+        .bind(&now)
+        .bind(limit)
+        .fetch_all(&mut conn)
+        .await?;
+
+        Ok(deliveries)
+    }
+
+    pub async fn delete_webhook_delivery(&self, id: i64) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+
+        sqlx::query(
+            r#"
+            DELETE FROM
+                webhook_outbox
+            WHERE
+                id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(&mut conn)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn reschedule_webhook_delivery(
+        &self,
+        id: i64,
+        attempts: u32,
+        next_attempt_at: Timestamp,
+    ) -> Result<()> {
+        let mut conn = self.inner.acquire().await?;
+
+        let query_result = sqlx::query(
+            r#"
+            UPDATE
+                webhook_outbox
+            SET
+                attempts = $1,
+                next_attempt_at = $2
+            WHERE
+                id = $3
+            "#,
+        )
+        .bind(attempts)
+        .bind(&next_attempt_at)
+        .bind(id)
+        .execute(&mut conn)
+        .await?;
+
+        if query_result.rows_affected() != 1 {
+            anyhow::bail!("failed to reschedule webhook delivery");
+        }
+
+        Ok(())
+    }
+
+    /// Delete all candles of the given interval which started before `before`.
+    ///
+    /// Returns the number of deleted candles.
+    pub async fn prune_candles(&self, interval: Interval, before: Timestamp) -> Result<u64> {
+        let mut conn = self.inner.acquire().await?;
+
+        let query_result = sqlx::query(
+            r#"
+            DELETE FROM
+                candles
+            WHERE
+                period = $1 AND start_time < $2
+            "#,
+        )
+        .bind(interval.to_string())
+        .bind(&before)
+        .execute(&mut conn)
+        .await?;
+
+        Ok(query_result.rows_affected())
+    }
+
+    /// Derive the entry and exit markers of all open and closed CFDs from the event log.
+    ///
+    /// A CFD is entered once its contract setup completed. It is exited once the settlement price
+    /// is known, i.e. when the collaborative settlement completed or the oracle attested, or once
+    /// the refund transaction was confirmed.
+    pub async fn load_cfd_markers(&self, from: Timestamp) -> Result<Vec<Marker>> {
+        let mut conn = self.inner.acquire().await?;
+
+        let open_rows =
+            sqlx::query_as::<_, (OrderId, Position, Price, String, Option<String>, i64)>(
+                r#"
+            SELECT
+                cfds.uuid,
+                cfds.position,
+                cfds.initial_price,
+                events.name,
+                CASE WHEN events.name = $1 THEN NULL ELSE events.data END,
+                CAST(events.created_at AS INTEGER) AS created_at
+            FROM
+                events
+            JOIN
+                cfds on cfds.id = events.cfd_id
+            WHERE
+                events.name IN ($1, $2, $3, $4, $5) AND
+                CAST(events.created_at AS INTEGER) >= $6
+            "#,
+            )
+            .bind(EventKind::CONTRACT_SETUP_COMPLETED_EVENT)
+            .bind(EventKind::COLLABORATIVE_SETTLEMENT_COMPLETED)
+            .bind(EventKind::ORACLE_ATTESTED_PRIOR_CET_TIMELOCK)
+            .bind(EventKind::ORACLE_ATTESTED_POST_CET_TIMELOCK)
+            .bind(EventKind::REFUND_CONFIRMED)
+            .bind(&from)
+            .fetch_all(&mut conn)
+            .await?;
+
+        let closed_rows =
+            sqlx::query_as::<_, (OrderId, Position, Price, String, Option<Price>, i64)>(
+                r#"
+            SELECT
+                closed_cfds.uuid,
+                closed_cfds.position,
+                closed_cfds.initial_price,
+                event_log.name,
+                COALESCE(collaborative_settlement_txs.price, cets.price),
+                event_log.created_at
+            FROM
+                event_log
+            JOIN
+                closed_cfds on closed_cfds.id = event_log.cfd_id
+            LEFT JOIN
+                collaborative_settlement_txs on collaborative_settlement_txs.cfd_id = closed_cfds.id
+            LEFT JOIN
+                cets on cets.cfd_id = closed_cfds.id
+            WHERE
+                event_log.name IN ($1, $2, $3, $4, $5) AND
+                event_log.created_at >= $6
+            "#,
+            )
+            .bind(EventKind::CONTRACT_SETUP_COMPLETED_EVENT)
+            .bind(EventKind::COLLABORATIVE_SETTLEMENT_COMPLETED)
+            .bind(EventKind::ORACLE_ATTESTED_PRIOR_CET_TIMELOCK)
+            .bind(EventKind::ORACLE_ATTESTED_POST_CET_TIMELOCK)
+            .bind(EventKind::REFUND_CONFIRMED)
+            .bind(&from)
+            .fetch_all(&mut conn)
+            .await?;
+
+        let open_markers = open_rows.into_iter().map(
+            |(order_id, position, initial_price, name, data, created_at)| {
+                let settlement_price = match data {
+                    Some(data) => match EventKind::from_json(name.clone(), data)? {
+                        EventKind::CollaborativeSettlementCompleted { price, .. }
+                        | EventKind::OracleAttestedPriorCetTimelock { price, .. }
+                        | EventKind::OracleAttestedPostCetTimelock { price, .. } => Some(price),
+                        _ => None,
+                    },
+                    None => None,
+                };
+
+                anyhow::Ok(Marker::from_event(
+                    order_id,
+                    position,
+                    &name,
+                    Timestamp::new(created_at),
+                    initial_price,
+                    settlement_price,
+                ))
+            },
+        );
+
+        let closed_markers = closed_rows.into_iter().map(
+            |(order_id, position, initial_price, name, settlement_price, created_at)| {
+                anyhow::Ok(Marker::from_event(
+                    order_id,
+                    position,
+                    &name,
+                    Timestamp::new(created_at),
+                    initial_price,
+                    settlement_price,
+                ))
+            },
+        );
+
+        let mut markers = open_markers
+            .chain(closed_markers)
+            .collect::<Result<Vec<_>>>()?;
+        markers.sort_by_key(|marker| (marker.timestamp, marker.kind));
+
+        Ok(markers)
+    }
+}
+
+async fn insert_settlement(
+    conn: &mut Transaction<'_, Sqlite>,
+    id: OrderId,
+    settlement: Settlement,
+) -> Result<()> {
+    match settlement {
+        Settlement::Collaborative {
+            txid,
+            vout,
+            payout,
+            price,
+        } => insert_collaborative_settlement(conn, id, txid, vout, payout, price).await?,
+        Settlement::Cet {
+            commit_txid,
+            txid,
+            vout,
+            payout,
+            price,
+        } => insert_cet_settlement(conn, id, commit_txid, txid, vout, payout, price).await?,
+        Settlement::Refund {
+            commit_txid,
+            txid,
+            vout,
+            payout,
+        } => insert_refund_settlement(conn, id, commit_txid, txid, vout, payout).await?,
+    };
+
+    Ok(())
+}
+
+/// An event as it is stored in the `events` table.
+///
+/// The data is the JSON serialization of the event, as returned by [`EventKind::to_json`].
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct StoredEvent {
+    pub cursor: i64,
+    pub order_id: OrderId,
+    pub name: String,
+    pub data: String,
+    pub timestamp: Timestamp,
+}
+
+/// An open CFD together with all its events, as it is stored in the database.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ExportedCfd {
+    pub cfd: Cfd,
+    pub events: Vec<ExportedEvent>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ExportedEvent {
+    pub name: String,
+    pub data: String,
+    pub created_at: Timestamp,
+}
+
+// TODO: Make sqlx directly instantiate this struct instead of mapping manually. Need to create
+// newtype for `settlement_interval`.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Cfd {
+    pub id: OrderId,
+    pub position: Position,
+    pub initial_price: Price,
+    pub taker_leverage: Leverage,
+    pub settlement_interval: Duration,
+    pub quantity_usd: Usd,
+    pub counterparty_network_identity: Identity,
+    pub role: Role,
+    pub opening_fee: OpeningFee,
+    pub initial_funding_rate: FundingRate,
+    pub initial_tx_fee_rate: TxFeeRate,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("The CFD requested was not found in the open CFDs")]
+    OpenCfdNotFound,
+    #[error("{0:#}")]
+    Sqlx(#[source] sqlx::Error),
+    #[error("{0:#}")]
+    Other(#[source] anyhow::Error),
+}
+
+impl From<sqlx::Error> for Error {
+    fn from(e: sqlx::Error) -> Self {
+        Error::Sqlx(e)
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(e: anyhow::Error) -> Self {
+        Error::Other(e)
+    }
+}
+
+/// Data loaded from the database about a closed CFD.
+#[derive(Debug, Clone, Copy)]
+pub struct ClosedCfd {
     pub id: OrderId,
     pub position: Position,
     pub initial_price: Price,
@@ -646,6 +1899,118 @@ pub struct ClosedCfd {
     pub settlement: Settlement,
 }
 
+/// A closed CFD together with what is needed to account for it.
+#[derive(Debug, Clone, Copy)]
+pub struct ClosedCfdRecord {
+    pub cfd: ClosedCfd,
+    /// `None` for CFDs which were closed before the breakdown of fees was recorded.
+    pub fee_breakdown: Option<FeeBreakdown>,
+    /// When the contract setup was completed.
+    pub opened_at: Option<Timestamp>,
+    /// When the transaction settling the CFD was confirmed.
+    pub closed_at: Timestamp,
+}
+
+/// Which CFDs to load with [`Connection::load_cfd_history`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CfdFilter {
+    pub state: Option<HistoryState>,
+    pub position: Option<Position>,
+    pub counterparty: Option<Identity>,
+    /// Only CFDs created at or after this time.
+    pub from: Option<Timestamp>,
+    /// Only CFDs created at or before this time.
+    pub to: Option<Timestamp>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryState {
+    /// The CFD is being set up or was set up and is not closed yet.
+    Open,
+    /// Contract setup failed or the offer was rejected.
+    Failed,
+    /// The transaction settling the CFD is confirmed.
+    Closed,
+}
+
+impl HistoryState {
+    fn as_str(&self) -> &'static str {
+        match self {
+            HistoryState::Open => "open",
+            HistoryState::Failed => "failed",
+            HistoryState::Closed => "closed",
+        }
+    }
+}
+
+impl FromStr for HistoryState {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let state = match s {
+            "open" => HistoryState::Open,
+            "failed" => HistoryState::Failed,
+            "closed" => HistoryState::Closed,
+            unknown => bail!("Unknown state: {unknown}"),
+        };
+
+        Ok(state)
+    }
+}
+
+/// The position of a CFD within the history.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HistoryCursor {
+    pub created_at: Timestamp,
+    pub order_id: OrderId,
+}
+
+impl fmt::Display for HistoryCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}_{}", self.created_at.seconds(), self.order_id)
+    }
+}
+
+impl FromStr for HistoryCursor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (created_at, order_id) = s.split_once('_').context("Missing separator")?;
+
+        Ok(Self {
+            created_at: Timestamp::new(created_at.parse()?),
+            order_id: OrderId::from(Uuid::parse_str(order_id)?),
+        })
+    }
+}
+
+/// An open or closed CFD as loaded by [`Connection::load_cfd_history`].
+#[derive(Debug, Clone, Copy)]
+pub struct HistoricCfd {
+    pub order_id: OrderId,
+    pub state: HistoryState,
+    pub position: Position,
+    pub role: Role,
+    pub counterparty: Identity,
+    pub quantity_usd: Usd,
+    pub taker_leverage: Leverage,
+    pub initial_price: Price,
+    /// When the first event of the CFD was recorded.
+    pub created_at: Timestamp,
+    /// How the CFD was closed, only set if it is closed.
+    pub closed: Option<ClosedCfdRecord>,
+}
+
+impl HistoricCfd {
+    pub fn cursor(&self) -> HistoryCursor {
+        HistoryCursor {
+            created_at: self.created_at,
+            order_id: self.order_id,
+        }
+    }
+}
+
 /// Data loaded from the database about the lock transaction of a
 /// closed CFD.
 #[derive(Debug, Clone, Copy)]
@@ -694,11 +2059,24 @@ struct ClosedCfdInput {
     counterparty_network_identity: Identity,
     role: Role,
     fees: Fees,
+    fee_breakdown: FeeBreakdown,
     expiry_timestamp: OffsetDateTime,
     lock: Lock,
     settlement: Settlement,
 }
 
+/// The fees of a closed CFD, from our perspective.
+///
+/// Opening and funding fees are positive if we paid them and negative if we received them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FeeBreakdown {
+    pub opening_fee: SignedAmount,
+    pub funding_fee: SignedAmount,
+    /// The on-chain fees of all transactions spending the lock output, which are shared by both
+    /// parties.
+    pub tx_fee: Amount,
+}
+
 /// Auxiliary type used to gradually combine a `Cfd` with its list of
 /// `CfdEvent`s.
 ///
@@ -714,6 +2092,7 @@ struct ClosedCfdInputAggregate {
     counterparty_network_identity: Identity,
     role: Role,
     fee_account: FeeAccount,
+    opening_fee: SignedAmount,
     initial_funding_fee: FundingFee,
     latest_dlc: Option<Dlc>,
     collaborative_settlement: Option<(bdk::bitcoin::Transaction, Script, Price)>,
@@ -758,6 +2137,8 @@ impl ClosedCfdInputAggregate {
             .expect("values from db to be sane")
         };
 
+        let fee_account = FeeAccount::new(position, role).add_opening_fee(opening_fee);
+
         Self {
             id,
             position,
@@ -766,7 +2147,8 @@ impl ClosedCfdInputAggregate {
             n_contracts,
             counterparty_network_identity,
             role,
-            fee_account: FeeAccount::new(position, role).add_opening_fee(opening_fee),
+            fee_account,
+            opening_fee: fee_account.balance(),
             initial_funding_fee,
             latest_dlc: None,
             collaborative_settlement: None,
@@ -797,6 +2179,8 @@ impl ClosedCfdInputAggregate {
             RolloverFailed => {}
             CollaborativeSettlementStarted { .. } => {}
             CollaborativeSettlementProposalAccepted => {}
+            CollaborativeSettlementCounterOffered { .. } => {}
+            CollaborativeSettlementCounterOfferAccepted => {}
             CollaborativeSettlementCompleted {
                 spend_tx,
                 script,
@@ -954,6 +2338,30 @@ impl ClosedCfdInputAggregate {
         })
     }
 
+    /// The on-chain fees of the transactions spending the lock output up to the given closing
+    /// transaction.
+    fn tx_fee(&self, closing_tx: &bdk::bitcoin::Transaction) -> Result<Amount> {
+        let dlc = self.latest_dlc()?;
+        let lock_amount = dlc
+            .lock
+            .0
+            .output
+            .iter()
+            .find(|output| output.script_pubkey == dlc.lock.1.script_pubkey())
+            .context("Missing DLC in lock TX")?
+            .value;
+        let closing_amount = closing_tx
+            .output
+            .iter()
+            .map(|output| output.value)
+            .sum::<u64>();
+        let tx_fee = lock_amount
+            .checked_sub(closing_amount)
+            .context("Closing TX spends more than the DLC")?;
+
+        Ok(Amount::from_sat(tx_fee))
+    }
+
     fn build(self) -> Result<ClosedCfdInput> {
         let Self {
             id,
@@ -964,20 +2372,28 @@ impl ClosedCfdInputAggregate {
             counterparty_network_identity,
             role,
             fee_account,
+            opening_fee,
             ..
         } = self;
 
         let lock = self.lock()?;
         let dlc = self.latest_dlc()?;
 
-        let settlement = match (
+        let (settlement, closing_tx) = match (
             self.collaborative_settlement_confirmed,
             self.cet_confirmed,
             self.refund_confirmed,
         ) {
-            (true, false, false) => self.collaborative_settlement()?,
-            (false, true, false) => self.cet()?,
-            (false, false, true) => self.refund()?,
+            (true, false, false) => (
+                self.collaborative_settlement()?,
+                &self
+                    .collaborative_settlement
+                    .as_ref()
+                    .context("Collaborative settlement not set")?
+                    .0,
+            ),
+            (false, true, false) => (self.cet()?, &self.cet.as_ref().context("Cet not set")?.0),
+            (false, false, true) => (self.refund()?, &dlc.refund.0),
             (collaborative_settlement, cet, refund) => bail!(
                 "Insane transaction combination:
                     Collaborative settlement: {collaborative_settlement:?},
@@ -986,6 +2402,12 @@ impl ClosedCfdInputAggregate {
             ),
         };
 
+        let fee_breakdown = FeeBreakdown {
+            opening_fee,
+            funding_fee: fee_account.balance() - opening_fee,
+            tx_fee: self.tx_fee(closing_tx)?,
+        };
+
         Ok(ClosedCfdInput {
             id,
             position,
@@ -995,6 +2417,7 @@ impl ClosedCfdInputAggregate {
             counterparty_network_identity,
             role,
             fees: Fees::new(fee_account.balance()),
+            fee_breakdown,
             expiry_timestamp: dlc.settlement_event_id.timestamp(),
             lock,
             settlement,
@@ -1035,6 +2458,20 @@ pub trait CfdAggregate: Clone + Send + Sync + 'static {
     fn new(args: Self::CtorArgs, cfd: Cfd) -> Self;
     fn apply(self, event: CfdEvent) -> Self;
     fn version(&self) -> u32;
+
+    /// Serialize the aggregate to be stored as snapshot.
+    ///
+    /// Aggregates which are expensive to rebuild should support snapshots, so that only the events
+    /// after the latest snapshot have to be applied once the aggregate is evicted from the cache.
+    /// Returns `None` if the aggregate does not support snapshots.
+    fn to_snapshot(&self) -> Option<Result<String>> {
+        None
+    }
+
+    /// Restore the aggregate from a snapshot created by [`CfdAggregate::to_snapshot`].
+    fn from_snapshot(_snapshot: &str) -> Result<Self> {
+        bail!("Aggregate does not support snapshots")
+    }
 }
 
 /// A trait for building an aggregate based on a `ClosedCfd`.
@@ -1092,6 +2529,7 @@ async fn load_cfd_row(conn: &mut Transaction<'_, Sqlite>, id: OrderId) -> Result
 /// Events will be sorted in chronological order.
 async fn load_cfd_events(
     conn: &mut Transaction<'_, Sqlite>,
+    encryption: &Encryption,
     id: OrderId,
     from_version: u32,
 ) -> Result<Vec<CfdEvent>> {
@@ -1120,7 +2558,7 @@ async fn load_cfd_events(
         Ok(CfdEvent {
             timestamp: row.created_at,
             id,
-            event: EventKind::from_json(row.name, row.data)?,
+            event: EventKind::from_json(row.name, encryption.decrypt(row.data)?)?,
         })
     })
     .collect::<Result<Vec<_>>>()?;
@@ -1173,6 +2611,37 @@ async fn insert_closed_cfd(conn: &mut Transaction<'_, Sqlite>, cfd: ClosedCfdInp
     Ok(())
 }
 
+async fn insert_fee_breakdown(
+    conn: &mut Transaction<'_, Sqlite>,
+    id: OrderId,
+    fee_breakdown: FeeBreakdown,
+) -> Result<()> {
+    let query_result = sqlx::query(
+        r#"
+        UPDATE
+            closed_cfds
+        SET
+            opening_fee = $2,
+            funding_fee = $3,
+            tx_fee = $4
+        WHERE
+            closed_cfds.uuid = $1
+        "#,
+    )
+    .bind(&id)
+    .bind(fee_breakdown.opening_fee.as_sat())
+    .bind(fee_breakdown.funding_fee.as_sat())
+    .bind(fee_breakdown.tx_fee.as_sat() as i64)
+    .execute(&mut *conn)
+    .await?;
+
+    if query_result.rows_affected() != 1 {
+        anyhow::bail!("failed to insert fee breakdown into closed_cfds");
+    }
+
+    Ok(())
+}
+
 async fn insert_collaborative_settlement(
     conn: &mut Transaction<'_, Sqlite>,
     id: OrderId,
@@ -1361,6 +2830,123 @@ async fn insert_event_log(
     Ok(())
 }
 
+async fn load_closed_cfd_row(conn: &mut PoolConnection<Sqlite>, id: OrderId) -> Result<ClosedCfd> {
+    let cfd = sqlx::query!(
+        r#"
+            SELECT
+                uuid as "uuid: model::OrderId",
+                position as "position: model::Position",
+                initial_price as "initial_price: model::Price",
+                taker_leverage as "taker_leverage: model::Leverage",
+                n_contracts as "n_contracts: model::Contracts",
+                counterparty_network_identity as "counterparty_network_identity: model::Identity",
+                role as "role: model::Role",
+                fees as "fees: model::Fees",
+                expiry_timestamp,
+                lock_txid as "lock_txid: model::Txid",
+                lock_dlc_vout as "lock_dlc_vout: model::Vout"
+            FROM
+                closed_cfds
+            WHERE
+                closed_cfds.uuid = $1
+            "#,
+        id
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let expiry_timestamp = OffsetDateTime::from_unix_timestamp(cfd.expiry_timestamp)?;
+
+    let collaborative_settlement = load_collaborative_settlement(conn, id).await?;
+    let cet_settlement = load_cet_settlement(conn, id).await?;
+    let refund_settlement = load_refund_settlement(conn, id).await?;
+
+    let settlement = match (collaborative_settlement, cet_settlement, refund_settlement) {
+        (Some(collaborative_settlement), None, None) => collaborative_settlement,
+        (None, Some(cet), None) => cet,
+        (None, None, Some(refund)) => refund,
+        _ => {
+            bail!(
+                "Closed CFD has insane combination of transactions:
+                   {collaborative_settlement:?},
+                   {cet_settlement:?},
+                   {refund_settlement:?}"
+            )
+        }
+    };
+
+    let cfd = ClosedCfd {
+        id,
+        position: cfd.position,
+        initial_price: cfd.initial_price,
+        taker_leverage: cfd.taker_leverage,
+        n_contracts: cfd.n_contracts,
+        counterparty_network_identity: cfd.counterparty_network_identity,
+        role: cfd.role,
+        fees: cfd.fees,
+        expiry_timestamp,
+        lock: Lock {
+            txid: cfd.lock_txid,
+            dlc_vout: cfd.lock_dlc_vout,
+        },
+        settlement,
+    };
+
+    Ok(cfd)
+}
+
+async fn load_closed_cfd_record(
+    conn: &mut PoolConnection<Sqlite>,
+    id: OrderId,
+) -> Result<ClosedCfdRecord> {
+    let (opening_fee, funding_fee, tx_fee, opened_at, closed_at) =
+        sqlx::query_as::<_, (Option<i64>, Option<i64>, Option<i64>, Option<i64>, i64)>(
+            r#"
+        SELECT
+            closed_cfds.opening_fee,
+            closed_cfds.funding_fee,
+            closed_cfds.tx_fee,
+            (
+                SELECT MIN(event_log.created_at) FROM event_log
+                WHERE event_log.cfd_id = closed_cfds.id AND event_log.name = $2
+            ),
+            (
+                SELECT MAX(event_log.created_at) FROM event_log
+                WHERE event_log.cfd_id = closed_cfds.id AND event_log.name IN ($3, $4, $5)
+            )
+        FROM
+            closed_cfds
+        WHERE
+            closed_cfds.uuid = $1
+        "#,
+        )
+        .bind(&id)
+        .bind(EventKind::CONTRACT_SETUP_COMPLETED_EVENT)
+        .bind(EventKind::COLLABORATIVE_SETTLEMENT_CONFIRMED)
+        .bind(EventKind::CET_CONFIRMED)
+        .bind(EventKind::REFUND_CONFIRMED)
+        .fetch_one(&mut *conn)
+        .await?;
+
+    let cfd = load_closed_cfd_row(conn, id).await?;
+
+    let fee_breakdown = match (opening_fee, funding_fee, tx_fee) {
+        (Some(opening_fee), Some(funding_fee), Some(tx_fee)) => Some(FeeBreakdown {
+            opening_fee: SignedAmount::from_sat(opening_fee),
+            funding_fee: SignedAmount::from_sat(funding_fee),
+            tx_fee: Amount::from_sat(u64::try_from(tx_fee)?),
+        }),
+        _ => None,
+    };
+
+    Ok(ClosedCfdRecord {
+        cfd,
+        fee_breakdown,
+        opened_at: opened_at.map(Timestamp::new),
+        closed_at: Timestamp::new(closed_at),
+    })
+}
+
 async fn load_collaborative_settlement(
     conn: &mut PoolConnection<Sqlite>,
     id: OrderId,
@@ -1467,6 +3053,112 @@ async fn delete_from_cfds_table(conn: &mut Transaction<'_, Sqlite>, id: OrderId)
     Ok(())
 }
 
+/// Load the latest snapshot of the aggregate of the given CFD, if any.
+async fn load_snapshot<C>(
+    conn: &mut Transaction<'_, Sqlite>,
+    encryption: &Encryption,
+    id: OrderId,
+) -> Result<Option<C>>
+where
+    C: CfdAggregate,
+{
+    let row = sqlx::query_as::<_, (u32, String)>(
+        r#"
+        SELECT
+            snapshots.version,
+            snapshots.data
+        FROM
+            snapshots
+        JOIN
+            cfds ON cfds.id = snapshots.cfd_id
+        WHERE
+            cfds.uuid = $1 AND snapshots.aggregate = $2
+        "#,
+    )
+    .bind(&id)
+    .bind(std::any::type_name::<C>())
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let (version, data) = match row {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+
+    let data = encryption.decrypt(data)?;
+    let cfd = C::from_snapshot(&data).context("Failed to restore aggregate")?;
+    anyhow::ensure!(
+        cfd.version() == version,
+        "Snapshot in version {} claims to be in version {version}",
+        cfd.version()
+    );
+
+    Ok(Some(cfd))
+}
+
+/// Store a snapshot of the aggregate, replacing the previous one.
+///
+/// Does nothing if the aggregate does not support snapshots.
+async fn store_snapshot<C>(
+    conn: &mut Transaction<'_, Sqlite>,
+    encryption: &Encryption,
+    id: OrderId,
+    cfd: &C,
+) -> Result<()>
+where
+    C: CfdAggregate,
+{
+    let data = match cfd.to_snapshot() {
+        Some(data) => encryption.encrypt(data?)?,
+        None => return Ok(()),
+    };
+
+    let query_result = sqlx::query(
+        r#"
+        INSERT INTO snapshots (
+            cfd_id,
+            aggregate,
+            version,
+            data
+        ) SELECT id, $2, $3, $4 FROM cfds WHERE cfds.uuid = $1
+        ON CONFLICT (cfd_id, aggregate) DO UPDATE SET
+            version = excluded.version,
+            data = excluded.data
+        "#,
+    )
+    .bind(&id)
+    .bind(std::any::type_name::<C>())
+    .bind(cfd.version())
+    .bind(data)
+    .execute(&mut *conn)
+    .await?;
+
+    if query_result.rows_affected() != 1 {
+        anyhow::bail!("failed to store snapshot");
+    }
+
+    Ok(())
+}
+
+async fn delete_from_snapshots_table(
+    conn: &mut Transaction<'_, Sqlite>,
+    id: OrderId,
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        DELETE FROM
+            snapshots
+        WHERE snapshots.cfd_id IN
+            (SELECT id FROM cfds WHERE cfds.uuid = $1)
+        "#,
+    )
+    .bind(&id)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
 async fn delete_from_events_table(conn: &mut Transaction<'_, Sqlite>, id: OrderId) -> Result<()> {
     let query_result = sqlx::query!(
         r#"
@@ -1490,8 +3182,12 @@ async fn delete_from_events_table(conn: &mut Transaction<'_, Sqlite>, id: OrderI
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::candles::MarkerKind;
+    use crate::seed::RandomSeed;
+    use crate::seed::Seed;
     use bdk::bitcoin::Amount;
     use bdk::bitcoin::SignedAmount;
+    use libp2p_core::PeerId;
     use model::Cfd;
     use model::Leverage;
     use model::OpeningFee;
@@ -1504,6 +3200,7 @@ mod tests {
     use pretty_assertions::assert_eq;
     use rust_decimal::Decimal;
     use rust_decimal_macros::dec;
+    use std::collections::HashSet;
     use std::str::FromStr;
 
     #[tokio::test]
@@ -1567,7 +3264,9 @@ mod tests {
         let mut conn = db.inner.acquire().await.unwrap();
 
         let mut db_tx = conn.begin().await.unwrap();
-        let events = load_cfd_events(&mut db_tx, cfd.id(), 0).await.unwrap();
+        let events = load_cfd_events(&mut db_tx, &db.encryption, cfd.id(), 0)
+            .await
+            .unwrap();
         db_tx.commit().await.unwrap();
         assert_eq!(events, vec![event1.clone()]);
 
@@ -1581,7 +3280,9 @@ mod tests {
 
         // let mut conn = db.inner.acquire().await.unwrap();
         let mut db_tx = conn.begin().await.unwrap();
-        let events = load_cfd_events(&mut db_tx, cfd.id(), 0).await.unwrap();
+        let events = load_cfd_events(&mut db_tx, &db.encryption, cfd.id(), 0)
+            .await
+            .unwrap();
         db_tx.commit().await.unwrap();
         assert_eq!(events, vec![event1, event2])
     }
@@ -1662,7 +3363,9 @@ mod tests {
         let load_from_events = {
             let mut conn = db.inner.acquire().await.unwrap();
             let mut db_tx = conn.begin().await.unwrap();
-            let res = load_cfd_events(&mut db_tx, order_id, 0).await.unwrap();
+            let res = load_cfd_events(&mut db_tx, &db.encryption, order_id, 0)
+                .await
+                .unwrap();
             db_tx.commit().await.unwrap();
 
             res
@@ -1674,6 +3377,141 @@ mod tests {
         assert!(load_from_closed.is_ok());
     }
 
+    #[tokio::test]
+    async fn closed_cfd_records_are_loaded_within_time_range() {
+        let db = memory().await.unwrap();
+
+        let (cfd, contract_setup_completed, collaborative_settlement_completed) =
+            cfd_collaboratively_settled();
+        let order_id = cfd.id();
+
+        db.insert_cfd(&cfd).await.unwrap();
+        db.append_event(contract_setup_completed).await.unwrap();
+        db.append_event(collaborative_settlement_completed)
+            .await
+            .unwrap();
+        db.append_event(collab_settlement_confirmed(&cfd))
+            .await
+            .unwrap();
+        db.move_to_closed_cfds().await.unwrap();
+
+        let records = db
+            .load_closed_cfd_records(Timestamp::new(0), Timestamp::now())
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 1);
+
+        let record = records[0];
+        assert_eq!(record.cfd.id, order_id);
+        assert!(matches!(
+            record.cfd.settlement,
+            Settlement::Collaborative { .. }
+        ));
+        assert!(record.opened_at.is_some());
+
+        let fee_breakdown = record.fee_breakdown.unwrap();
+        assert_eq!(fee_breakdown.opening_fee, SignedAmount::ZERO);
+        assert_eq!(fee_breakdown.tx_fee, Amount::from_sat(170));
+
+        let records = db
+            .load_closed_cfd_records(
+                Timestamp::new(0),
+                Timestamp::new(record.closed_at.seconds() - 1),
+            )
+            .await
+            .unwrap();
+        assert!(records.is_empty());
+    }
+
+    #[tokio::test]
+    async fn cfd_history_contains_open_and_closed_cfds() {
+        let db = memory().await.unwrap();
+
+        let (closed_cfd, contract_setup_completed, collaborative_settlement_completed) =
+            cfd_collaboratively_settled();
+        db.insert_cfd(&closed_cfd).await.unwrap();
+        db.append_event(contract_setup_completed).await.unwrap();
+        db.append_event(collaborative_settlement_completed)
+            .await
+            .unwrap();
+        db.append_event(collab_settlement_confirmed(&closed_cfd))
+            .await
+            .unwrap();
+
+        let open_cfd = dummy_cfd();
+        db.insert_cfd(&open_cfd).await.unwrap();
+        db.append_event(lock_confirmed(&open_cfd)).await.unwrap();
+
+        let failed_cfd = dummy_cfd();
+        db.insert_cfd(&failed_cfd).await.unwrap();
+        db.append_event(setup_failed(&failed_cfd)).await.unwrap();
+
+        // Without any events, the CFD is still being set up and not part of the history yet
+        db.insert_cfd(&dummy_cfd()).await.unwrap();
+
+        db.move_to_closed_cfds().await.unwrap();
+
+        let mut history = Vec::new();
+        let mut after = None;
+        loop {
+            let page = db
+                .load_cfd_history(CfdFilter::default(), after, 1)
+                .await
+                .unwrap();
+            match page.last() {
+                Some(cfd) => after = Some(cfd.cursor()),
+                None => break,
+            }
+            history.extend(page);
+        }
+
+        let mut states = history
+            .iter()
+            .map(|cfd| (cfd.order_id, cfd.state))
+            .collect::<Vec<_>>();
+        states.sort_by_key(|(order_id, _)| order_id.to_string());
+        let mut expected = vec![
+            (closed_cfd.id(), HistoryState::Closed),
+            (open_cfd.id(), HistoryState::Open),
+            (failed_cfd.id(), HistoryState::Failed),
+        ];
+        expected.sort_by_key(|(order_id, _)| order_id.to_string());
+        assert_eq!(states, expected);
+
+        let closed = db
+            .load_cfd_history(
+                CfdFilter {
+                    state: Some(HistoryState::Closed),
+                    ..CfdFilter::default()
+                },
+                None,
+                10,
+            )
+            .await
+            .unwrap();
+        assert_eq!(closed.len(), 1);
+        assert_eq!(closed[0].order_id, closed_cfd.id());
+        assert_eq!(closed[0].quantity_usd, closed_cfd.quantity());
+        assert!(closed[0].closed.is_some());
+
+        let by_counterparty = db
+            .load_cfd_history(
+                CfdFilter {
+                    counterparty: Some(open_cfd.counterparty_network_identity()),
+                    position: Some(Position::Long),
+                    ..CfdFilter::default()
+                },
+                None,
+                10,
+            )
+            .await
+            .unwrap();
+        assert_eq!(by_counterparty.len(), 2);
+
+        let cursor = closed[0].cursor();
+        assert_eq!(cursor.to_string().parse::<HistoryCursor>().unwrap(), cursor);
+    }
+
     #[tokio::test]
     async fn given_settlement_not_confirmed_when_move_cfds_to_closed_table_then_cannot_load_cfd_as_closed(
     ) {
@@ -1696,7 +3534,9 @@ mod tests {
         let load_from_events = {
             let mut conn = db.inner.acquire().await.unwrap();
             let mut db_tx = conn.begin().await.unwrap();
-            let res = load_cfd_events(&mut db_tx, order_id, 0).await.unwrap();
+            let res = load_cfd_events(&mut db_tx, &db.encryption, order_id, 0)
+                .await
+                .unwrap();
             db_tx.commit().await.unwrap();
 
             res
@@ -1921,6 +3761,376 @@ mod tests {
         assert_eq!(inserted, loaded);
     }
 
+    #[tokio::test]
+    async fn upserted_candles_can_be_loaded_and_pruned() {
+        let db = memory().await.unwrap();
+
+        let price = |value| Price::new(value).unwrap();
+        let first = Candle::new(Timestamp::new(60), price(dec!(40_000)));
+        let second = Candle::new(Timestamp::new(120), price(dec!(41_000)));
+
+        db.upsert_candle(Interval::OneMinute, &first).await.unwrap();
+        db.upsert_candle(Interval::OneMinute, &second)
+            .await
+            .unwrap();
+        let second = second.with_price(price(dec!(42_000)));
+        db.upsert_candle(Interval::OneMinute, &second)
+            .await
+            .unwrap();
+
+        let loaded = db
+            .load_candles(Interval::OneMinute, Timestamp::new(0))
+            .await
+            .unwrap();
+        assert_eq!(loaded, vec![first, second]);
+
+        let loaded = db
+            .load_candles(Interval::OneHour, Timestamp::new(0))
+            .await
+            .unwrap();
+        assert!(loaded.is_empty());
+
+        let num_pruned = db
+            .prune_candles(Interval::OneMinute, Timestamp::new(120))
+            .await
+            .unwrap();
+        let loaded = db
+            .load_candle(Interval::OneMinute, Timestamp::new(60))
+            .await
+            .unwrap();
+        assert_eq!(num_pruned, 1);
+        assert!(loaded.is_none());
+    }
+
+    #[tokio::test]
+    async fn aggregate_is_restored_from_latest_snapshot() {
+        let db = memory().await.unwrap();
+
+        let (cfd, contract_setup_completed, _) = cfd_collaboratively_settled();
+        let order_id = cfd.id();
+
+        db.insert_cfd(&cfd).await.unwrap();
+        db.append_event(contract_setup_completed).await.unwrap();
+        for _ in 1..SNAPSHOT_INTERVAL {
+            db.append_event(lock_confirmed(&cfd)).await.unwrap();
+        }
+
+        let loaded = db.load_open_cfd::<Cfd>(order_id, ()).await.unwrap();
+        assert_eq!(loaded.version(), SNAPSHOT_INTERVAL);
+
+        let snapshot = {
+            let mut conn = db.inner.acquire().await.unwrap();
+            let mut db_tx = conn.begin().await.unwrap();
+            let res = load_snapshot::<Cfd>(&mut db_tx, &db.encryption, order_id)
+                .await
+                .unwrap();
+            db_tx.commit().await.unwrap();
+
+            res
+        };
+        assert_eq!(snapshot, Some(loaded.clone()));
+
+        // A new connection does not have the aggregate in its cache
+        let db = Connection::new(db.inner.clone());
+        db.append_event(lock_confirmed(&cfd)).await.unwrap();
+
+        let reloaded = db.load_open_cfd::<Cfd>(order_id, ()).await.unwrap();
+        assert_eq!(reloaded, loaded.apply(lock_confirmed(&cfd)));
+    }
+
+    #[tokio::test]
+    async fn cets_of_superseded_dlcs_are_pruned() {
+        let db = memory().await.unwrap();
+
+        let (cfd, contract_setup_completed, _) = cfd_collaboratively_settled();
+        let order_id = cfd.id();
+        let dlc = match &contract_setup_completed.event {
+            EventKind::ContractSetupCompleted { dlc } => dlc.clone(),
+            _ => unreachable!("test event is a contract setup"),
+        };
+        let rollover_completed = || CfdEvent {
+            timestamp: Timestamp::now(),
+            id: order_id,
+            event: EventKind::RolloverCompleted {
+                dlc: dlc.clone(),
+                funding_fee: FundingFee::calculate(
+                    cfd.initial_price(),
+                    cfd.quantity(),
+                    Leverage::TWO,
+                    Leverage::TWO,
+                    FundingRate::default(),
+                    1,
+                )
+                .unwrap(),
+            },
+        };
+
+        db.insert_cfd(&cfd).await.unwrap();
+        db.append_event(contract_setup_completed).await.unwrap();
+        db.append_event(rollover_completed()).await.unwrap();
+        db.append_event(rollover_completed()).await.unwrap();
+
+        let num_pruned = db.prune_superseded_dlcs().await.unwrap();
+        assert_eq!(num_pruned, 2);
+        let num_pruned = db.prune_superseded_dlcs().await.unwrap();
+        assert_eq!(num_pruned, 0);
+
+        let events = {
+            let mut conn = db.inner.acquire().await.unwrap();
+            let mut db_tx = conn.begin().await.unwrap();
+            let res = load_cfd_events(&mut db_tx, &db.encryption, order_id, 0)
+                .await
+                .unwrap();
+            db_tx.commit().await.unwrap();
+
+            res
+        };
+        let num_cets = events
+            .into_iter()
+            .map(|event| match event.event {
+                EventKind::ContractSetupCompleted { dlc }
+                | EventKind::RolloverCompleted { dlc, .. } => dlc.cets.len(),
+                _ => unreachable!("only DLC events were appended"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(num_cets, vec![0, 0, dlc.cets.len()]);
+
+        let loaded = db.load_open_cfd::<Cfd>(order_id, ()).await.unwrap();
+        assert_eq!(loaded.version(), 3);
+    }
+
+    #[tokio::test]
+    async fn plaintext_key_material_is_encrypted_at_rest() {
+        let db = memory().await.unwrap();
+
+        let (cfd, contract_setup_completed, _) = cfd_collaboratively_settled();
+        let order_id = cfd.id();
+
+        db.insert_cfd(&cfd).await.unwrap();
+        db.append_event(contract_setup_completed).await.unwrap();
+        let loaded = db.load_open_cfd::<Cfd>(order_id, ()).await.unwrap();
+
+        let key = RandomSeed::default().derive_db_encryption_key::<EncryptionKey>();
+        let db = Connection::new(db.inner.clone())
+            .encrypt_at_rest(key)
+            .await
+            .unwrap();
+        db.append_event(lock_confirmed(&cfd)).await.unwrap();
+
+        let stored = sqlx::query_scalar::<_, String>(
+            r#"
+            SELECT
+                data
+            FROM
+                events
+            WHERE
+                name = $1
+            "#,
+        )
+        .bind(EventKind::CONTRACT_SETUP_COMPLETED_EVENT)
+        .fetch_one(&mut db.inner.acquire().await.unwrap())
+        .await
+        .unwrap();
+        assert!(stored.starts_with(ENCRYPTED_PREFIX));
+
+        let reloaded = db.load_open_cfd::<Cfd>(order_id, ()).await.unwrap();
+        assert_eq!(reloaded, loaded.apply(lock_confirmed(&cfd)));
+
+        let without_key = Connection::new(db.inner.clone());
+        assert!(without_key
+            .load_open_cfd::<Cfd>(order_id, ())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn events_can_be_loaded_after_cursor() {
+        let db = memory().await.unwrap();
+
+        let (cfd, contract_setup_completed, collaborative_settlement_completed) =
+            cfd_collaboratively_settled();
+        let (first_name, first_data) = contract_setup_completed.event.to_json();
+        let (second_name, _) = collaborative_settlement_completed.event.to_json();
+
+        db.insert_cfd(&cfd).await.unwrap();
+        db.append_event(contract_setup_completed.clone())
+            .await
+            .unwrap();
+        db.append_event(collaborative_settlement_completed)
+            .await
+            .unwrap();
+
+        let events = db.load_events_after(0, 100).await.unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].order_id, cfd.id());
+        assert_eq!(events[0].name, first_name);
+        assert_eq!(events[0].data, first_data);
+        assert_eq!(events[0].timestamp, contract_setup_completed.timestamp);
+        assert_eq!(events[1].name, second_name);
+
+        let events_after_first = db.load_events_after(events[0].cursor, 100).await.unwrap();
+        assert_eq!(events_after_first, events[1..]);
+
+        let first_only = db.load_events_after(0, 1).await.unwrap();
+        assert_eq!(first_only, events[..1]);
+
+        let none = db.load_events_after(events[1].cursor, 100).await.unwrap();
+        assert!(none.is_empty());
+    }
+
+    #[tokio::test]
+    async fn notable_events_are_put_into_outbox_of_every_webhook() {
+        let db = memory().await.unwrap();
+
+        let webhook = webhook::NewWebhook {
+            url: "https://example.com/hook".to_owned(),
+            secret: "s3cr3t".to_owned(),
+        };
+        let id = db.upsert_webhook(&webhook).await.unwrap();
+
+        let (cfd, contract_setup_completed, collaborative_settlement_completed) =
+            cfd_collaboratively_settled();
+        let notification = webhook::Notification::from_event(&contract_setup_completed).unwrap();
+
+        db.insert_cfd(&cfd).await.unwrap();
+        db.append_event(contract_setup_completed).await.unwrap();
+        db.append_event(collaborative_settlement_completed)
+            .await
+            .unwrap();
+
+        let now = Timestamp::now();
+        let deliveries = db.load_due_webhook_deliveries(now, 100).await.unwrap();
+        assert_eq!(deliveries.len(), 2);
+        assert_eq!(deliveries[0].url, webhook.url);
+        assert_eq!(deliveries[0].secret, webhook.secret);
+        assert_eq!(deliveries[0].attempts, 0);
+        assert_eq!(
+            serde_json::from_str::<webhook::Notification>(&deliveries[0].payload).unwrap(),
+            notification
+        );
+
+        db.reschedule_webhook_delivery(deliveries[0].id, 1, Timestamp::new(now.seconds() + 60))
+            .await
+            .unwrap();
+        db.delete_webhook_delivery(deliveries[1].id).await.unwrap();
+        let deliveries = db.load_due_webhook_deliveries(now, 100).await.unwrap();
+        assert!(deliveries.is_empty());
+
+        assert!(db.delete_webhook(id).await.unwrap());
+        let deliveries = db
+            .load_due_webhook_deliveries(Timestamp::new(now.seconds() + 60), 100)
+            .await
+            .unwrap();
+        assert!(deliveries.is_empty());
+        assert!(db.load_webhooks().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn stored_access_policy_replaces_previous_one() {
+        let db = memory().await.unwrap();
+        assert!(db.load_access_policy().await.unwrap().is_none());
+
+        let taker = access_control::TakerId::PeerId(PeerId::random());
+        let banned = access_control::Policy {
+            banlist: HashSet::from([taker]),
+            ..access_control::Policy::default()
+        };
+        db.store_access_policy(&banned).await.unwrap();
+        assert_eq!(db.load_access_policy().await.unwrap(), Some(banned));
+
+        let allowed = access_control::Policy {
+            allowlist: HashSet::from([taker]),
+            ..access_control::Policy::default()
+        };
+        db.store_access_policy(&allowed).await.unwrap();
+        assert_eq!(db.load_access_policy().await.unwrap(), Some(allowed));
+    }
+
+    #[tokio::test]
+    async fn protocol_checkpoints_are_stored_encrypted_until_deleted() {
+        let key = RandomSeed::default().derive_db_encryption_key::<EncryptionKey>();
+        let db = memory().await.unwrap().encrypt_at_rest(key).await.unwrap();
+        let order_id = OrderId::default();
+
+        db.store_protocol_checkpoint(order_id, "setup", &vec![0])
+            .await
+            .unwrap();
+        db.store_protocol_checkpoint(order_id, "setup", &vec![0, 1])
+            .await
+            .unwrap();
+        db.store_protocol_checkpoint(order_id, "rollover", &vec![2])
+            .await
+            .unwrap();
+
+        let stored = sqlx::query_scalar::<_, String>(
+            r#"
+            SELECT
+                data
+            FROM
+                protocol_checkpoints
+            "#,
+        )
+        .fetch_all(&mut db.inner.acquire().await.unwrap())
+        .await
+        .unwrap();
+        assert!(stored.iter().all(|data| data.starts_with(ENCRYPTED_PREFIX)));
+
+        let loaded = db
+            .load_protocol_checkpoints::<Vec<u8>>("setup")
+            .await
+            .unwrap();
+        assert_eq!(loaded, vec![vec![0, 1]]);
+
+        db.delete_protocol_checkpoint(order_id, "setup")
+            .await
+            .unwrap();
+
+        assert!(db
+            .load_protocol_checkpoints::<Vec<u8>>("setup")
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            db.load_protocol_checkpoints::<Vec<u8>>("rollover")
+                .await
+                .unwrap(),
+            vec![vec![2]]
+        );
+    }
+
+    #[tokio::test]
+    async fn given_collaboratively_settled_cfd_then_markers_survive_move_to_closed_cfds() {
+        let db = memory().await.unwrap();
+
+        let (cfd, contract_setup_completed, collaborative_settlement_completed) =
+            cfd_collaboratively_settled();
+
+        db.insert_cfd(&cfd).await.unwrap();
+        db.append_event(contract_setup_completed).await.unwrap();
+        db.append_event(collaborative_settlement_completed)
+            .await
+            .unwrap();
+        db.append_event(collab_settlement_confirmed(&cfd))
+            .await
+            .unwrap();
+
+        let open_markers = db.load_cfd_markers(Timestamp::new(0)).await.unwrap();
+
+        db.move_to_closed_cfds().await.unwrap();
+
+        let closed_markers = db.load_cfd_markers(Timestamp::new(0)).await.unwrap();
+
+        assert_eq!(open_markers.len(), 2);
+        assert_eq!(open_markers, closed_markers);
+        assert!(open_markers
+            .iter()
+            .any(|marker| marker.kind == MarkerKind::Entry
+                && marker.price == Some(cfd.initial_price())));
+        assert!(open_markers
+            .iter()
+            .any(|marker| marker.kind == MarkerKind::Exit && marker.price.is_some()));
+    }
+
     async fn insert_dummy_closed_cfd(
         conn: &mut Transaction<'_, Sqlite>,
         id: OrderId,
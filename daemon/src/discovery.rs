@@ -0,0 +1,198 @@
+use crate::libp2p_utils;
+use crate::projection;
+use crate::ENDPOINT_CONNECTION_TIMEOUT;
+use anyhow::Context;
+use anyhow::Result;
+use libp2p_core::identity::Keypair;
+use libp2p_core::multiaddr::Protocol;
+use libp2p_core::Multiaddr;
+use libp2p_core::PeerId;
+use model::Identity;
+use model::Price;
+use model::Usd;
+use serde::Deserialize;
+use serde::Serialize;
+use std::net::SocketAddr;
+use tokio::sync::watch;
+use tokio_tasks::Tasks;
+use xtra::Actor as _;
+use xtra::Address;
+use xtra_libp2p::Endpoint;
+use xtra_libp2p_rendezvous::client;
+
+/// The namespace under which makers register at a rendezvous node.
+pub const NAMESPACE: &str = "itchysats/makers";
+
+/// What a maker publishes about itself at a rendezvous node.
+///
+/// Trading still happens over the legacy networking stack, hence the record contains everything a
+/// taker needs to connect to the maker on that stack.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MakerRecord {
+    pub identity: Identity,
+    /// The address of the maker's legacy networking stack.
+    pub address: SocketAddr,
+    /// The offer where the maker's position is long.
+    pub long: Option<OfferSummary>,
+    /// The offer where the maker's position is short.
+    pub short: Option<OfferSummary>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct OfferSummary {
+    pub price: Price,
+    pub min_quantity: Usd,
+    pub max_quantity: Usd,
+}
+
+impl From<&projection::CfdOrder> for OfferSummary {
+    fn from(order: &projection::CfdOrder) -> Self {
+        Self {
+            price: order.price,
+            min_quantity: order.min_quantity,
+            max_quantity: order.max_quantity,
+        }
+    }
+}
+
+/// A maker found at a rendezvous node, whose record was signed by the maker's `peer_id`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoveredMaker {
+    pub peer_id: PeerId,
+    /// The addresses of the maker's libp2p endpoint, possibly including an onion address.
+    pub addresses: Vec<Multiaddr>,
+    pub record: MakerRecord,
+}
+
+impl DiscoveredMaker {
+    /// The onion address of the maker's libp2p endpoint, if it announced one.
+    pub fn onion_address(&self) -> Option<&Multiaddr> {
+        self.addresses
+            .iter()
+            .find(|address| matches!(address.iter().next(), Some(Protocol::Onion3(_))))
+    }
+}
+
+/// Register the maker at the given rendezvous node for as long as the returned actor is alive.
+///
+/// The registration is updated whenever the maker's offers change. If the maker's libp2p endpoint
+/// is also reachable through an onion service, its address is announced as well.
+#[allow(clippy::too_many_arguments)]
+pub fn register_maker(
+    endpoint: Address<Endpoint>,
+    identity: Keypair,
+    rendezvous_node: Multiaddr,
+    maker_identity: Identity,
+    external_address: SocketAddr,
+    onion_service: Option<Multiaddr>,
+    mut offers: watch::Receiver<projection::MakerOffers>,
+    tasks: &mut Tasks,
+) -> Result<Address<client::Actor>> {
+    let libp2p_address = libp2p_utils::create_listen_tcp_multiaddr(
+        &libp2p_utils::libp2p_socket_from_legacy_networking(&external_address),
+    )?;
+    let addresses = std::iter::once(libp2p_address)
+        .chain(onion_service)
+        .collect::<Vec<_>>();
+
+    let client = client::Actor::new(endpoint, identity, rendezvous_node, client::DEFAULT_TTL)?
+        .create(None)
+        .spawn(tasks);
+
+    tasks.add({
+        let client = client.clone();
+
+        async move {
+            loop {
+                let record = {
+                    let offers = offers.borrow();
+
+                    MakerRecord {
+                        identity: maker_identity,
+                        address: external_address,
+                        long: offers.long.as_ref().map(OfferSummary::from),
+                        short: offers.short.as_ref().map(OfferSummary::from),
+                    }
+                };
+
+                let registration = serde_json::to_value(&record).map(|payload| client::Register {
+                    namespace: NAMESPACE.to_owned(),
+                    addresses: addresses.clone(),
+                    payload,
+                });
+
+                match registration {
+                    Ok(registration) => match client.send(registration).await {
+                        Ok(Ok(())) => {}
+                        Ok(Err(e)) => {
+                            tracing::warn!("Failed to register at rendezvous node, retrying: {e:#}")
+                        }
+                        Err(_) => return,
+                    },
+                    Err(e) => tracing::error!("Failed to serialize maker record: {e:#}"),
+                }
+
+                if offers.changed().await.is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    Ok(client)
+}
+
+/// Ask the given rendezvous node for all registered makers.
+///
+/// Discovery uses a throwaway identity, hence the rendezvous node cannot link the query to the
+/// taker. If a SOCKS5 proxy is given, the rendezvous node is contacted through the proxy.
+pub async fn discover_makers(
+    rendezvous_node: Multiaddr,
+    socks5_proxy: Option<SocketAddr>,
+) -> Result<Vec<DiscoveredMaker>> {
+    let mut tasks = Tasks::default();
+    let identity = Keypair::generate_ed25519();
+
+    let endpoint = Endpoint::new(
+        libp2p_utils::transport(socks5_proxy),
+        identity.clone(),
+        ENDPOINT_CONNECTION_TIMEOUT,
+        [],
+    )
+    .create(None)
+    .spawn(&mut tasks);
+
+    let client = client::Actor::new(
+        endpoint,
+        identity,
+        rendezvous_node.clone(),
+        client::DEFAULT_TTL,
+    )?
+    .create(None)
+    .spawn(&mut tasks);
+
+    let records = client
+        .send(client::Discover {
+            namespace: NAMESPACE.to_owned(),
+        })
+        .await
+        .context("Rendezvous client actor is disconnected")?
+        .with_context(|| format!("Failed to discover makers at {rendezvous_node}"))?;
+
+    let makers = records
+        .into_iter()
+        .filter_map(|record| match record.payload::<MakerRecord>() {
+            Ok(maker) => Some(DiscoveredMaker {
+                peer_id: record.peer_id,
+                addresses: record.addresses,
+                record: maker,
+            }),
+            Err(e) => {
+                tracing::warn!(peer_id = %record.peer_id, "Ignoring malformed maker record: {e:#}");
+                None
+            }
+        })
+        .collect();
+
+    Ok(makers)
+}
@@ -0,0 +1,66 @@
+//! Authenticated encryption with keys derived from the seed.
+
+use anyhow::anyhow;
+use anyhow::Result;
+use chacha20poly1305::aead::Aead;
+use chacha20poly1305::aead::NewAead;
+use chacha20poly1305::ChaCha20Poly1305;
+use chacha20poly1305::Key;
+use chacha20poly1305::Nonce;
+use rand::Rng;
+
+const NONCE_LEN: usize = 12;
+
+/// Encrypts and decrypts data with ChaCha20-Poly1305.
+#[derive(Clone)]
+pub struct Cipher(ChaCha20Poly1305);
+
+impl Cipher {
+    /// Encrypt the plaintext with a random nonce, which is prepended to the ciphertext.
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill(&mut nonce);
+
+        let ciphertext = self
+            .0
+            .encrypt(Nonce::from_slice(&nonce), plaintext)
+            .map_err(|_| anyhow!("Failed to encrypt"))?;
+
+        Ok([nonce.as_slice(), &ciphertext].concat())
+    }
+
+    /// Decrypt a ciphertext created by [`Cipher::encrypt`].
+    ///
+    /// Fails if the ciphertext was tampered with or encrypted with a different key.
+    pub fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        anyhow::ensure!(ciphertext.len() >= NONCE_LEN, "Ciphertext is too short");
+        let (nonce, ciphertext) = ciphertext.split_at(NONCE_LEN);
+
+        self.0
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("Failed to decrypt, ciphertext is corrupted or key is wrong"))
+    }
+}
+
+impl From<[u8; 32]> for Cipher {
+    fn from(key: [u8; 32]) -> Self {
+        Self(ChaCha20Poly1305::new(Key::from_slice(&key)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ciphertext_can_only_be_decrypted_with_same_key() {
+        let cipher = Cipher::from([1u8; 32]);
+        let other_cipher = Cipher::from([2u8; 32]);
+
+        let ciphertext = cipher.encrypt(b"revocation secret").unwrap();
+
+        assert_eq!(cipher.decrypt(&ciphertext).unwrap(), b"revocation secret");
+        assert!(other_cipher.decrypt(&ciphertext).is_err());
+        assert_ne!(cipher.encrypt(b"revocation secret").unwrap(), ciphertext);
+    }
+}
@@ -8,6 +8,7 @@ use bdk::bitcoin::Amount;
 use bdk::FeeRate;
 use connection::ConnectionStatus;
 use libp2p_core::Multiaddr;
+use libp2p_core::PeerId;
 use libp2p_tcp::TokioTcpConfig;
 use maia::secp256k1_zkp::schnorrsig;
 use model::olivia;
@@ -20,10 +21,11 @@ use model::Price;
 use model::Role;
 use model::TxFeeRate;
 use model::Usd;
+use rust_decimal::Decimal;
 use seed::Identities;
+use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::time::Duration;
-use time::ext::NumericalDuration;
 use tokio::sync::watch;
 use tokio_tasks::Tasks;
 use xtra::prelude::*;
@@ -31,20 +33,31 @@ use xtra_bitmex_price_feed::QUOTE_INTERVAL_MINUTES;
 use xtra_libp2p::dialer;
 use xtra_libp2p::listener;
 use xtra_libp2p::Endpoint;
+use xtra_libp2p::MultiaddrExt;
+use xtra_libp2p_ping::ConnectionQuality;
 use xtras::supervisor;
 
 pub use bdk;
 pub use maia;
+pub use xtra_libp2p_ping;
 
+pub mod access_control;
+pub mod accounting;
+pub mod api_token;
 pub mod auto_rollover;
+pub mod backup;
+pub mod candles;
 mod close_cfds;
 pub mod collab_settlement_maker;
 pub mod collab_settlement_taker;
 pub mod command;
 pub mod connection;
 pub mod db;
+pub mod discovery;
+pub mod encryption;
 mod future_ext;
 pub mod libp2p_utils;
+pub mod libp2p_wire;
 pub mod maker_cfd;
 pub mod maker_inc_connections;
 pub mod monitor;
@@ -62,6 +75,7 @@ pub mod taker_cfd;
 mod transaction_ext;
 pub mod version;
 pub mod wallet;
+pub mod webhook;
 pub mod wire;
 
 /// Duration between the heartbeats sent by the maker, used by the taker to
@@ -71,15 +85,28 @@ pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
 pub const ENDPOINT_CONNECTION_TIMEOUT: Duration = Duration::from_secs(20);
 pub const PING_INTERVAL: Option<Duration> = Some(Duration::from_secs(5));
 
+/// How often the taker publishes the quality of the connection to its makers.
+const CONNECTION_QUALITY_PUBLISH_INTERVAL: Duration = Duration::from_secs(5);
+
 pub const N_PAYOUTS: usize = 200;
 
+/// Quotes older than this are neither used for aggregating the price feed nor for settling.
+pub const MAX_QUOTE_AGE: time::Duration = time::Duration::minutes(QUOTE_INTERVAL_MINUTES * 2);
+
 pub struct MakerActorSystem<O, W> {
     pub cfd_actor: Address<maker_cfd::Actor<O, maker_inc_connections::Actor, W>>,
+    inc_conn_actor: Address<maker_inc_connections::Actor>,
+    ping_actor: Address<xtra_libp2p_ping::Actor>,
     wallet_actor: Address<W>,
     _close_cfds_actor: Address<close_cfds::Actor>,
     executor: command::Executor,
+    db: db::Connection,
+    /// The takers and limits given on the command line, applied on top of the stored policy.
+    access_policy_overlay: access_control::Policy,
+    /// The libp2p endpoint, which also acts as rendezvous node for other makers.
+    pub endpoint: Address<Endpoint>,
     _tasks: Tasks,
-    _listener_supervisor: Address<supervisor::Actor<listener::Actor, listener::Error>>,
+    _listener_supervisors: Vec<Address<supervisor::Actor<listener::Actor, listener::Error>>>,
 }
 
 impl<O, W> MakerActorSystem<O, W>
@@ -102,10 +129,14 @@ where
         monitor_constructor: impl FnOnce(command::Executor) -> Result<M>,
         settlement_interval: time::Duration,
         n_payouts: usize,
+        rollover_policy: rollover_maker::Policy,
         projection_actor: Address<projection::Actor>,
         identity: Identities,
         heartbeat_interval: Duration,
         p2p_socket: SocketAddr,
+        p2p_ws_socket: Option<SocketAddr>,
+        access_policy: access_control::Policy,
+        access_policy_overlay: access_control::Policy,
     ) -> Result<Self>
     where
         M: Handler<monitor::StartMonitoring>
@@ -127,6 +158,7 @@ where
         tasks.add(process_manager_ctx.run(process_manager::Actor::new(
             db.clone(),
             Role::Maker,
+            process_manager::COLLAB_SETTLEMENT_BROADCAST_DELAY,
             &projection_actor,
             &monitor_addr,
             &monitor_addr,
@@ -142,9 +174,10 @@ where
             oracle_pk,
             projection_actor,
             process_manager_addr,
-            inc_conn_addr,
+            inc_conn_addr.clone(),
             oracle_addr,
             n_payouts,
+            rollover_policy,
         )
         .create(None)
         .spawn(&mut tasks);
@@ -155,31 +188,99 @@ where
             .create(None)
             .spawn(&mut tasks);
 
+        let rendezvous_address = xtra_libp2p_rendezvous::server::Actor::default()
+            .create(None)
+            .spawn(&mut tasks);
+
+        let peer_id = identity.libp2p.public().to_peer_id();
+        let [offer_handler, order_handler, rollover_handler, rollover_v2_handler, settlement_handler] =
+            [
+                libp2p_wire::OFFER_PROTOCOL,
+                libp2p_wire::ORDER_PROTOCOL,
+                libp2p_wire::ROLLOVER_PROTOCOL,
+                libp2p_wire::ROLLOVER_V2_PROTOCOL,
+                libp2p_wire::SETTLEMENT_PROTOCOL,
+            ]
+            .map(|protocol| {
+                libp2p_wire::InboundHandler::new(
+                    protocol,
+                    identity.identity_sk.clone(),
+                    peer_id,
+                    &inc_conn_addr,
+                )
+                .create(None)
+                .spawn(&mut tasks)
+            });
+
         let endpoint = Endpoint::new(
             TokioTcpConfig::new(),
             identity.libp2p,
             ENDPOINT_CONNECTION_TIMEOUT,
-            [(
-                xtra_libp2p_ping::PROTOCOL_NAME,
-                xtra::message_channel::StrongMessageChannel::clone_channel(&ping_address),
-            )],
-        );
+            [
+                (
+                    xtra_libp2p_ping::PROTOCOL_NAME,
+                    xtra::message_channel::StrongMessageChannel::clone_channel(&ping_address),
+                ),
+                (
+                    xtra_libp2p_rendezvous::PROTOCOL_NAME,
+                    xtra::message_channel::StrongMessageChannel::clone_channel(&rendezvous_address),
+                ),
+                (
+                    libp2p_wire::OFFER_PROTOCOL,
+                    xtra::message_channel::StrongMessageChannel::clone_channel(&offer_handler),
+                ),
+                (
+                    libp2p_wire::ORDER_PROTOCOL,
+                    xtra::message_channel::StrongMessageChannel::clone_channel(&order_handler),
+                ),
+                (
+                    libp2p_wire::ROLLOVER_PROTOCOL,
+                    xtra::message_channel::StrongMessageChannel::clone_channel(&rollover_handler),
+                ),
+                (
+                    libp2p_wire::ROLLOVER_V2_PROTOCOL,
+                    xtra::message_channel::StrongMessageChannel::clone_channel(
+                        &rollover_v2_handler,
+                    ),
+                ),
+                (
+                    libp2p_wire::SETTLEMENT_PROTOCOL,
+                    xtra::message_channel::StrongMessageChannel::clone_channel(&settlement_handler),
+                ),
+            ],
+        )
+        .with_identify_info(version::version(), version::MAKER_AGENT);
 
         tasks.add(endpoint_context.run(endpoint));
 
         let libp2p_socket = libp2p_utils::libp2p_socket_from_legacy_networking(&p2p_socket);
         let endpoint_listen =
-            libp2p_utils::create_listen_tcp_multiaddr(&libp2p_socket).expect("to parse properly");
-
-        let (supervisor, _listener_actor) = supervisor::Actor::with_policy(
-            move || {
-                let endpoint_addr = endpoint_addr.clone();
-                let endpoint_listen = endpoint_listen.clone();
-                listener::Actor::new(endpoint_addr, endpoint_listen)
-            },
-            |_: &listener::Error| true, // always restart listener actor
-        );
-        let listener_supervisor = supervisor.create(None).spawn(&mut tasks);
+            std::iter::once(libp2p_utils::create_listen_tcp_multiaddr(&libp2p_socket))
+                .chain(
+                    p2p_ws_socket
+                        .as_ref()
+                        .map(libp2p_utils::create_listen_ws_multiaddr),
+                )
+                .collect::<Result<Vec<_>>>()?;
+
+        let listener_supervisors = endpoint_listen
+            .into_iter()
+            .map(|endpoint_listen| {
+                let (supervisor, _listener_actor) = supervisor::Actor::with_policy(
+                    {
+                        let endpoint_addr = endpoint_addr.clone();
+                        move || {
+                            let endpoint_addr = endpoint_addr.clone();
+                            let endpoint_listen = endpoint_listen.clone();
+                            listener::Actor::new(endpoint_addr, endpoint_listen)
+                        }
+                    },
+                    |_: &listener::Error| true, // always restart listener actor
+                );
+
+                supervisor.create(None).spawn(&mut tasks)
+            })
+            .collect();
 
         tasks.add(inc_conn_ctx.run(maker_inc_connections::Actor::new(
             Box::new(cfd_actor_addr.clone()),
@@ -188,23 +289,31 @@ where
             identity.identity_sk,
             heartbeat_interval,
             p2p_socket,
+            access_policy.merge(access_policy_overlay.clone()),
         )));
 
         tasks.add(monitor_ctx.run(monitor_constructor(executor.clone())?));
 
         tasks.add(oracle_ctx.run(oracle_constructor(executor.clone())));
 
-        let close_cfds_actor = close_cfds::Actor::new(db).create(None).spawn(&mut tasks);
+        let close_cfds_actor = close_cfds::Actor::new(db.clone())
+            .create(None)
+            .spawn(&mut tasks);
 
         tracing::debug!("Maker actor system ready");
 
         Ok(Self {
             cfd_actor: cfd_actor_addr,
+            inc_conn_actor: inc_conn_addr,
+            ping_actor: ping_address,
             wallet_actor: wallet_addr,
             _close_cfds_actor: close_cfds_actor,
             executor,
+            db,
+            access_policy_overlay,
+            endpoint: endpoint_addr,
             _tasks: tasks,
-            _listener_supervisor: listener_supervisor,
+            _listener_supervisors: listener_supervisors,
         })
     }
 
@@ -239,6 +348,54 @@ where
         Ok(())
     }
 
+    /// The policy deciding which takers are accepted, as stored in the database.
+    ///
+    /// Does not include the takers and limits given on the command line, as these are not stored.
+    pub async fn access_policy(&self) -> Result<access_control::Policy> {
+        let policy = self.db.load_access_policy().await?.unwrap_or_default();
+
+        Ok(policy)
+    }
+
+    /// Replace the policy deciding which takers are accepted.
+    ///
+    /// The policy is stored in the database, so that it survives a restart. The takers and limits
+    /// given on the command line are applied on top of it. Takers which are no longer accepted are
+    /// disconnected.
+    pub async fn set_access_policy(&self, policy: access_control::Policy) -> Result<()> {
+        self.db.store_access_policy(&policy).await?;
+
+        let policy = policy.merge(self.access_policy_overlay.clone());
+        self.inc_conn_actor
+            .send(maker_inc_connections::SetAccessPolicy(policy))
+            .await?;
+
+        Ok(())
+    }
+
+    /// The quality of the libp2p connection to every connected taker, keyed by its identity.
+    ///
+    /// Only takers which opened a libp2p substream are included.
+    pub async fn taker_connection_qualities(
+        &self,
+    ) -> Result<HashMap<Identity, (PeerId, ConnectionQuality)>> {
+        let taker_peers = self
+            .inc_conn_actor
+            .send(maker_inc_connections::GetTakerPeers)
+            .await?;
+        let mut qualities = self
+            .ping_actor
+            .send(xtra_libp2p_ping::GetConnectionQualities)
+            .await?;
+
+        let taker_qualities = taker_peers
+            .into_iter()
+            .filter_map(|(taker, peer)| Some((taker, (peer, qualities.remove(&peer)?))))
+            .collect();
+
+        Ok(taker_qualities)
+    }
+
     pub async fn accept_order(&self, order_id: OrderId) -> Result<()> {
         self.cfd_actor
             .send(maker_cfd::AcceptOrder { order_id })
@@ -267,6 +424,13 @@ where
         Ok(())
     }
 
+    pub async fn counter_settlement(&self, order_id: OrderId, price: Price) -> Result<()> {
+        self.cfd_actor
+            .send(maker_cfd::CounterSettlement { order_id, price })
+            .await??;
+        Ok(())
+    }
+
     pub async fn accept_rollover(&self, order_id: OrderId) -> Result<()> {
         self.cfd_actor
             .send(maker_cfd::AcceptRollover { order_id })
@@ -305,26 +469,28 @@ where
     }
 }
 
-pub struct TakerActorSystem<O, W, P> {
+pub struct TakerActorSystem<O, W> {
     pub cfd_actor: Address<taker_cfd::Actor<O, W>>,
     pub connection_actor: Address<connection::Actor>,
     wallet_actor: Address<W>,
     pub auto_rollover_actor: Address<auto_rollover::Actor<O>>,
-    pub price_feed_actor: Address<P>,
+    price_feed: Box<dyn MessageChannel<xtra_bitmex_price_feed::LatestQuote>>,
     executor: command::Executor,
-    /// Keep this one around to avoid the supervisor being dropped due to ref-count changes on the
-    /// address.
-    _price_feed_supervisor: Address<supervisor::Actor<P, xtra_bitmex_price_feed::Error>>,
-    _dialer_actor: Address<dialer::Actor>,
-    _dialer_supervisor: Address<supervisor::Actor<dialer::Actor, dialer::Error>>,
+    _dialer_actors: Vec<Address<dialer::Actor>>,
+    _dialer_supervisors: Vec<Address<supervisor::Actor<dialer::Actor, dialer::Error>>>,
     _close_cfds_actor: Address<close_cfds::Actor>,
 
-    pub maker_online_status_feed_receiver: watch::Receiver<ConnectionStatus>,
+    /// The connection status of every maker we are trading with.
+    pub makers_online_status_feed_receiver: watch::Receiver<HashMap<Identity, ConnectionStatus>>,
+
+    /// The quality of the libp2p connection to every maker we have been connected to recently.
+    pub makers_connection_quality_feed_receiver:
+        watch::Receiver<HashMap<Identity, ConnectionQuality>>,
 
     _tasks: Tasks,
 }
 
-impl<O, W, P> TakerActorSystem<O, W, P>
+impl<O, W> TakerActorSystem<O, W>
 where
     O: Handler<oracle::MonitorAttestation>
         + Handler<oracle::GetAnnouncement>
@@ -334,7 +500,6 @@ where
         + Handler<wallet::Sign>
         + Handler<wallet::Withdraw>
         + Actor<Stop = ()>,
-    P: Handler<xtra_bitmex_price_feed::LatestQuote> + Actor<Stop = xtra_bitmex_price_feed::Error>,
 {
     #[allow(clippy::too_many_arguments)]
     pub fn new<M>(
@@ -344,13 +509,16 @@ where
         identity: Identities,
         oracle_constructor: impl FnOnce(command::Executor) -> O,
         monitor_constructor: impl FnOnce(command::Executor) -> Result<M>,
-        price_feed_constructor: impl (Fn() -> P) + Send + 'static,
+        price_feed: Box<dyn MessageChannel<xtra_bitmex_price_feed::LatestQuote>>,
         n_payouts: usize,
+        rollover_policy: rollover_taker::Policy,
+        settlement_counter_offer_tolerance: Option<Decimal>,
         maker_heartbeat_interval: Duration,
         connect_timeout: Duration,
+        settlement_broadcast_delay: Duration,
         projection_actor: Address<projection::Actor>,
-        maker_identity: Identity,
-        maker_multiaddr: Multiaddr,
+        makers: Vec<(Identity, Multiaddr)>,
+        socks5_proxy: Option<SocketAddr>,
     ) -> Result<Self>
     where
         M: Handler<monitor::StartMonitoring>
@@ -360,8 +528,12 @@ where
             + Handler<monitor::TryBroadcastTransaction>
             + Actor<Stop = ()>,
     {
-        let (maker_online_status_feed_sender, maker_online_status_feed_receiver) =
-            watch::channel(ConnectionStatus::Offline { reason: None });
+        let (makers_online_status_feed_sender, makers_online_status_feed_receiver) = watch::channel(
+            makers
+                .iter()
+                .map(|(identity, _)| (*identity, ConnectionStatus::Offline { reason: None }))
+                .collect(),
+        );
 
         let (monitor_addr, monitor_ctx) = Context::new(None);
         let (oracle_addr, oracle_ctx) = Context::new(None);
@@ -374,6 +546,7 @@ where
         tasks.add(process_manager_ctx.run(process_manager::Actor::new(
             db.clone(),
             Role::Taker,
+            settlement_broadcast_delay,
             &projection_actor,
             &monitor_addr,
             &monitor_addr,
@@ -392,7 +565,8 @@ where
             connection_actor_addr.clone(),
             oracle_addr.clone(),
             n_payouts,
-            maker_identity,
+            settlement_counter_offer_tolerance,
+            price_feed.clone_channel(),
         )
         .create(None)
         .spawn(&mut tasks);
@@ -404,6 +578,7 @@ where
             connection_actor_addr.clone(),
             oracle_addr,
             n_payouts,
+            rollover_policy,
         )
         .create(None)
         .spawn(&mut tasks);
@@ -413,51 +588,74 @@ where
             .checked_mul(2)
             .expect("not to overflow");
 
+        let (endpoint_addr, endpoint_context) = Context::new(None);
+
+        let maker_peer_ids = makers
+            .iter()
+            .filter_map(|(maker_identity, maker_multiaddr)| {
+                let maker_peer_id = maker_multiaddr.clone().extract_peer_id()?;
+
+                Some((*maker_identity, maker_peer_id))
+            })
+            .collect();
+
         tasks.add(connection_actor_ctx.run(connection::Actor::new(
-            maker_online_status_feed_sender,
+            makers_online_status_feed_sender,
             &cfd_actor_addr,
             identity.identity_sk,
             taker_heartbeat_timeout,
             connect_timeout,
+            db.clone(),
+            endpoint_addr.clone(),
+            identity.libp2p.public().to_peer_id(),
+            maker_peer_ids.clone(),
+            socks5_proxy,
         )));
 
         tasks.add(monitor_ctx.run(monitor_constructor(executor.clone())?));
 
         tasks.add(oracle_ctx.run(oracle_constructor(executor.clone())));
 
-        let (endpoint_addr, endpoint_context) = Context::new(None);
-
         let ping_address = xtra_libp2p_ping::Actor::new(endpoint_addr.clone(), PING_INTERVAL)
             .create(None)
             .spawn(&mut tasks);
 
+        let (makers_connection_quality_feed_sender, makers_connection_quality_feed_receiver) =
+            watch::channel(HashMap::new());
+        tasks.add(publish_makers_connection_quality(
+            ping_address.clone(),
+            maker_peer_ids,
+            makers_connection_quality_feed_sender,
+        ));
+
         let endpoint = Endpoint::new(
-            TokioTcpConfig::new(),
+            libp2p_utils::transport(socks5_proxy),
             identity.libp2p,
             ENDPOINT_CONNECTION_TIMEOUT,
             [(
                 xtra_libp2p_ping::PROTOCOL_NAME,
                 xtra::message_channel::StrongMessageChannel::clone_channel(&ping_address),
             )],
-        );
+        )
+        .with_identify_info(version::version(), version::TAKER_AGENT);
 
         tasks.add(endpoint_context.run(endpoint));
 
-        let dialer_constructor =
-            { move || dialer::Actor::new(endpoint_addr.clone(), maker_multiaddr.clone()) };
+        let (dialer_supervisors, dialer_actors) = makers
+            .into_iter()
+            .map(|(_, maker_multiaddr)| {
+                let endpoint_addr = endpoint_addr.clone();
+                let dialer_constructor =
+                    move || dialer::Actor::new(endpoint_addr.clone(), maker_multiaddr.clone());
 
-        let (supervisor, dialer_actor) = supervisor::Actor::with_policy(
-            dialer_constructor,
-            |_: &dialer::Error| true, // always restart dialer actor
-        );
-        let dialer_supervisor = supervisor.create(None).spawn(&mut tasks);
+                let (supervisor, dialer_actor) = supervisor::Actor::with_policy(
+                    dialer_constructor,
+                    |_: &dialer::Error| true, // always restart dialer actor
+                );
 
-        let (supervisor, price_feed_actor) = supervisor::Actor::with_policy(
-            price_feed_constructor,
-            |_| true, // always restart price feed actor
-        );
-
-        let price_feed_supervisor = supervisor.create(None).spawn(&mut tasks);
+                (supervisor.create(None).spawn(&mut tasks), dialer_actor)
+            })
+            .unzip();
 
         let close_cfds_actor = close_cfds::Actor::new(db).create(None).spawn(&mut tasks);
 
@@ -468,14 +666,14 @@ where
             connection_actor: connection_actor_addr,
             wallet_actor: wallet_actor_addr,
             auto_rollover_actor: auto_rollover_addr,
-            price_feed_actor,
+            price_feed,
             executor,
-            _price_feed_supervisor: price_feed_supervisor,
-            _dialer_actor: dialer_actor,
-            _dialer_supervisor: dialer_supervisor,
+            _dialer_actors: dialer_actors,
+            _dialer_supervisors: dialer_supervisors,
             _close_cfds_actor: close_cfds_actor,
             _tasks: tasks,
-            maker_online_status_feed_receiver,
+            makers_online_status_feed_receiver,
+            makers_connection_quality_feed_receiver,
         })
     }
 
@@ -496,7 +694,7 @@ where
 
     pub async fn propose_settlement(&self, order_id: OrderId) -> Result<()> {
         let latest_quote = self
-            .price_feed_actor
+            .price_feed
             .send(xtra_bitmex_price_feed::LatestQuote)
             .await
             .context("Price feed not available")?
@@ -507,12 +705,10 @@ where
             .format(&time::format_description::well_known::Rfc3339)
             .context("Failed to format timestamp")?;
 
-        let threshold = QUOTE_INTERVAL_MINUTES.minutes() * 2;
-
-        if latest_quote.is_older_than(threshold) {
+        if latest_quote.is_older_than(MAX_QUOTE_AGE) {
             anyhow::bail!(
                 "Latest quote is older than {} minutes. Refusing to settle with old price.",
-                threshold.whole_minutes()
+                MAX_QUOTE_AGE.whole_minutes()
             )
         }
 
@@ -526,6 +722,20 @@ where
             .await?
     }
 
+    pub async fn accept_settlement_counter_offer(&self, order_id: OrderId) -> Result<()> {
+        self.cfd_actor
+            .send(taker_cfd::AcceptSettlementCounterOffer { order_id })
+            .await??;
+        Ok(())
+    }
+
+    pub async fn reject_settlement_counter_offer(&self, order_id: OrderId) -> Result<()> {
+        self.cfd_actor
+            .send(taker_cfd::RejectSettlementCounterOffer { order_id })
+            .await??;
+        Ok(())
+    }
+
     pub async fn withdraw(
         &self,
         amount: Option<Amount>,
@@ -541,3 +751,35 @@ where
             .await?
     }
 }
+
+/// Regularly publish the quality of the libp2p connection to every maker, as measured by the ping
+/// actor.
+async fn publish_makers_connection_quality(
+    ping_actor: Address<xtra_libp2p_ping::Actor>,
+    maker_peer_ids: HashMap<Identity, PeerId>,
+    sender: watch::Sender<HashMap<Identity, ConnectionQuality>>,
+) {
+    loop {
+        let mut qualities = match ping_actor
+            .send(xtra_libp2p_ping::GetConnectionQualities)
+            .await
+        {
+            Ok(qualities) => qualities,
+            Err(_) => {
+                tracing::warn!("Cannot publish connection quality because ping actor is down");
+                return;
+            }
+        };
+
+        let maker_qualities = maker_peer_ids
+            .iter()
+            .filter_map(|(maker, peer)| Some((*maker, qualities.remove(peer)?)))
+            .collect();
+
+        if sender.send(maker_qualities).is_err() {
+            return;
+        }
+
+        tokio::time::sleep(CONNECTION_QUALITY_PUBLISH_INTERVAL).await;
+    }
+}
@@ -1,10 +1,17 @@
+use anyhow::bail;
 use anyhow::ensure;
 use anyhow::Context;
 use anyhow::Result;
-use std::net::SocketAddr;
-
+use data_encoding::BASE32;
+use libp2p_core::multiaddr::Protocol;
+use libp2p_core::transport::OptionalTransport;
+use libp2p_core::transport::OrTransport;
 use libp2p_core::Multiaddr;
 use libp2p_core::PeerId;
+use libp2p_socks5::Socks5TokioTcpConfig;
+use libp2p_tcp::TokioTcpConfig;
+use std::net::IpAddr;
+use std::net::SocketAddr;
 
 /// Creates MultiAddr from SocketAddr and PeerId
 pub fn create_connect_tcp_multiaddr(
@@ -31,6 +38,13 @@ pub fn create_listen_tcp_multiaddr(socket_addr: &SocketAddr) -> Result<Multiaddr
         .with_context(|| "failed to construct multiaddr")
 }
 
+/// Creates a WebSocket MultiAddr from SocketAddr
+pub fn create_listen_ws_multiaddr(socket_addr: &SocketAddr) -> Result<Multiaddr> {
+    let listen = create_listen_tcp_multiaddr(socket_addr)?;
+
+    Ok(listen.with(Protocol::Ws("/".into())))
+}
+
 /// By convention we increment the port by 1 for libp2p-based connections.
 ///
 /// The obvious drawback is that when doing blue/green deployment, we need to
@@ -38,3 +52,101 @@ pub fn create_listen_tcp_multiaddr(socket_addr: &SocketAddr) -> Result<Multiaddr
 pub fn libp2p_socket_from_legacy_networking(legacy_addr: &SocketAddr) -> SocketAddr {
     SocketAddr::new(legacy_addr.ip(), legacy_addr.port() + 1)
 }
+
+/// Creates a Multiaddr for dialing the given host, e.g. an IP address, a domain name or an onion
+/// address.
+///
+/// Domain names are not resolved locally, hence they can be resolved by a proxy.
+pub fn create_connect_multiaddr(host: &str, port: u16, peer_id: PeerId) -> Result<Multiaddr> {
+    let address = match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => format!("/ip4/{ip}/tcp/{port}/p2p/{peer_id}"),
+        Ok(IpAddr::V6(ip)) => format!("/ip6/{ip}/tcp/{port}/p2p/{peer_id}"),
+        Err(_) => match host.strip_suffix(".onion") {
+            Some(onion) => format!("/onion3/{onion}:{port}/p2p/{peer_id}"),
+            None => format!("/dns/{host}/tcp/{port}/p2p/{peer_id}"),
+        },
+    };
+
+    address
+        .parse::<Multiaddr>()
+        .with_context(|| format!("failed to construct multiaddr for {host}:{port}"))
+}
+
+/// The host and port of the legacy networking stack belonging to the given libp2p address.
+///
+/// This is the inverse of [`libp2p_socket_from_legacy_networking`].
+pub fn legacy_networking_from_libp2p(addr: &Multiaddr) -> Result<(String, u16)> {
+    let mut protocols = addr.iter();
+
+    let (host, port) = match (protocols.next(), protocols.next()) {
+        (Some(Protocol::Ip4(ip)), Some(Protocol::Tcp(port))) => (ip.to_string(), port),
+        (Some(Protocol::Ip6(ip)), Some(Protocol::Tcp(port))) => (ip.to_string(), port),
+        (
+            Some(Protocol::Dns(host) | Protocol::Dns4(host) | Protocol::Dns6(host)),
+            Some(Protocol::Tcp(port)),
+        ) => (host.into_owned(), port),
+        (Some(Protocol::Onion3(onion)), _) => {
+            let host = format!("{}.onion", BASE32.encode(onion.hash()).to_lowercase());
+
+            (host, onion.port())
+        }
+        _ => bail!("unsupported address {addr}"),
+    };
+
+    let port = port
+        .checked_sub(1)
+        .with_context(|| format!("no legacy port below port {port} of {addr}"))?;
+
+    Ok((host, port))
+}
+
+/// The transport of our libp2p endpoints.
+///
+/// Connections are either all dialed through a SOCKS5 proxy or all dialed directly.
+pub type Transport =
+    OrTransport<OptionalTransport<Socks5TokioTcpConfig>, OptionalTransport<TokioTcpConfig>>;
+
+/// Creates the transport of our libp2p endpoints, dialing through the given SOCKS5 proxy, if any.
+///
+/// Listening is only supported without a proxy.
+pub fn transport(socks5_proxy: Option<SocketAddr>) -> Transport {
+    match socks5_proxy {
+        Some(proxy) => OrTransport::new(
+            OptionalTransport::some(Socks5TokioTcpConfig::new(proxy)),
+            OptionalTransport::none(),
+        ),
+        None => OrTransport::new(
+            OptionalTransport::none(),
+            OptionalTransport::some(TokioTcpConfig::new()),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn onion_address_round_trips_through_multiaddr() {
+        let onion = "vww6ybal4bd7szmgncyruucpgfkqahzddi37ktceo3ah7ngmcopnpyyd.onion";
+        let peer_id = PeerId::random();
+
+        let multiaddr = create_connect_multiaddr(onion, 10001, peer_id).unwrap();
+        let (host, port) = legacy_networking_from_libp2p(&multiaddr).unwrap();
+
+        assert_eq!(host, onion);
+        assert_eq!(port, 10000);
+    }
+
+    #[test]
+    fn domain_names_are_not_resolved() {
+        let peer_id = PeerId::random();
+
+        let multiaddr = create_connect_multiaddr("maker.example", 10001, peer_id).unwrap();
+
+        assert_eq!(
+            multiaddr.to_string(),
+            format!("/dns/maker.example/tcp/10001/p2p/{peer_id}")
+        );
+    }
+}
@@ -0,0 +1,399 @@
+//! The trading protocols on top of `xtra-libp2p` substreams.
+//!
+//! Every trading protocol is negotiated as its own libp2p protocol. The substreams carry the same
+//! messages as the legacy networking stack, i.e. [`wire::TakerToMaker`] and
+//! [`wire::MakerToTaker`], encoded as length-prefixed JSON.
+//!
+//! Substreams are always opened by the taker:
+//!
+//! - A substream of the order, rollover or settlement protocol carries all messages about a single
+//!   run of that protocol for a single order.
+//! - A substream of the offer protocol is used by the maker to push its current offers to the
+//!   taker for as long as the substream is open.
+//!
+//! The libp2p connection only authenticates the [`PeerId`] of the taker, whereas CFDs refer to its
+//! [`Identity`]. The first message on every substream is hence a [`Hello`], through which the taker
+//! proves to be in control of the secret key of its [`Identity`].
+
+use crate::wire;
+use crate::wire::taker_to_maker;
+use anyhow::Context as _;
+use anyhow::Result;
+use asynchronous_codec::Decoder;
+use asynchronous_codec::Encoder;
+use asynchronous_codec::Framed;
+use asynchronous_codec::LengthCodec;
+use bytes::Bytes;
+use bytes::BytesMut;
+use futures::stream::SplitSink;
+use futures::stream::SplitStream;
+use futures::AsyncReadExt;
+use futures::AsyncWriteExt;
+use futures::StreamExt;
+use hkdf::Hkdf;
+use libp2p_core::PeerId;
+use model::Identity;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::Sha256;
+use std::marker::PhantomData;
+use std::time::Duration;
+use subtle::ConstantTimeEq;
+use tokio_tasks::Tasks;
+use xtra::async_trait;
+use xtra::message_channel::MessageChannel;
+use xtra_libp2p::NewInboundSubstream;
+use xtra_libp2p::Substream;
+use xtra_productivity::xtra_productivity;
+use xtras::SendAsyncSafe;
+
+pub const OFFER_PROTOCOL: &str = "/itchysats/offer/1.0.0";
+pub const ORDER_PROTOCOL: &str = "/itchysats/order/1.0.0";
+/// Rollover proposed with [`wire::TakerToMaker::ProposeRollover`].
+pub const ROLLOVER_PROTOCOL: &str = "/itchysats/rollover/1.0.0";
+/// Rollover proposed with [`wire::TakerToMaker::ProposeRolloverV2`].
+pub const ROLLOVER_V2_PROTOCOL: &str = "/itchysats/rollover/2.0.0";
+pub const SETTLEMENT_PROTOCOL: &str = "/itchysats/settlement/1.0.0";
+
+/// How long we wait for the [`Hello`] of the taker after a substream was opened.
+const HELLO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The largest [`Hello`] we are willing to read, a serialized one is around 200 bytes.
+const MAX_HELLO_LEN: usize = 1024;
+
+pub type Read<D, E> = SplitStream<Framed<Substream, JsonCodec<D, E>>>;
+pub type Write<D, E> = SplitSink<Framed<Substream, JsonCodec<D, E>>, E>;
+
+/// Split a substream into a typed sink and stream of messages.
+pub fn split<D, E>(stream: Substream) -> (Write<D, E>, Read<D, E>)
+where
+    D: DeserializeOwned,
+    E: Serialize,
+{
+    Framed::new(stream, JsonCodec::new()).split()
+}
+
+/// The protocol which is started by sending the given message to the maker.
+///
+/// Returns `None` for messages which continue a protocol that is already running and for
+/// messages which only exist on the legacy networking stack.
+pub fn protocol_started_by(msg: &wire::TakerToMaker) -> Option<&'static str> {
+    match msg {
+        wire::TakerToMaker::TakeOrder { .. } => Some(ORDER_PROTOCOL),
+        wire::TakerToMaker::ProposeRollover { .. } => Some(ROLLOVER_PROTOCOL),
+        wire::TakerToMaker::ProposeRolloverV2 { .. } => Some(ROLLOVER_V2_PROTOCOL),
+        wire::TakerToMaker::Settlement {
+            msg: taker_to_maker::Settlement::Propose { .. },
+            ..
+        } => Some(SETTLEMENT_PROTOCOL),
+        wire::TakerToMaker::Hello(_)
+        | wire::TakerToMaker::HelloV2 { .. }
+        | wire::TakerToMaker::Protocol { .. }
+        | wire::TakerToMaker::RolloverProtocol { .. }
+        | wire::TakerToMaker::DeclineRollover { .. }
+        | wire::TakerToMaker::Settlement { .. }
+        | wire::TakerToMaker::Unknown => None,
+    }
+}
+
+/// The first message on every substream, sent by the taker.
+///
+/// The proof is derived from the Diffie-Hellman secret shared by the [`Identity`] of the taker and
+/// the one of the maker, hence only those two can compute it. It commits to the protocol and the
+/// peers of the substream, so it cannot be replayed on another substream.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Hello {
+    identity: Identity,
+    proof: [u8; 32],
+}
+
+impl Hello {
+    pub fn new(
+        identity_sk: &x25519_dalek::StaticSecret,
+        maker: Identity,
+        protocol: &str,
+        taker_peer: PeerId,
+        maker_peer: PeerId,
+    ) -> Self {
+        let shared_secret = identity_sk.diffie_hellman(&maker.pk());
+
+        Self {
+            identity: Identity::new(x25519_dalek::PublicKey::from(identity_sk)),
+            proof: identity_proof(shared_secret, protocol, taker_peer, maker_peer),
+        }
+    }
+
+    /// Verify the proof of the taker, returning its [`Identity`] on success.
+    pub fn verify(
+        &self,
+        identity_sk: &x25519_dalek::StaticSecret,
+        protocol: &str,
+        taker_peer: PeerId,
+        maker_peer: PeerId,
+    ) -> Result<Identity> {
+        let shared_secret = identity_sk.diffie_hellman(&self.identity.pk());
+        let expected = identity_proof(shared_secret, protocol, taker_peer, maker_peer);
+
+        anyhow::ensure!(
+            bool::from(self.proof.ct_eq(&expected)),
+            "Peer {taker_peer} failed to prove identity {}",
+            self.identity
+        );
+
+        Ok(self.identity)
+    }
+
+    pub async fn send(&self, stream: &mut Substream) -> Result<()> {
+        let bytes = serde_json::to_vec(self).context("Failed to serialize Hello")?;
+
+        stream
+            .write_all(&(bytes.len() as u64).to_be_bytes())
+            .await?;
+        stream.write_all(&bytes).await?;
+        stream.flush().await?;
+
+        Ok(())
+    }
+
+    pub async fn receive(stream: &mut Substream) -> Result<Self> {
+        let mut len = [0u8; 8];
+        stream.read_exact(&mut len).await?;
+        let len = u64::from_be_bytes(len) as usize;
+
+        anyhow::ensure!(
+            len <= MAX_HELLO_LEN,
+            "Hello of {len} bytes exceeds maximum of {MAX_HELLO_LEN} bytes"
+        );
+
+        let mut bytes = vec![0u8; len];
+        stream.read_exact(&mut bytes).await?;
+
+        let hello = serde_json::from_slice(&bytes).context("Failed to deserialize Hello")?;
+
+        Ok(hello)
+    }
+}
+
+fn identity_proof(
+    shared_secret: x25519_dalek::SharedSecret,
+    protocol: &str,
+    taker_peer: PeerId,
+    maker_peer: PeerId,
+) -> [u8; 32] {
+    let mut info = b"ITCHYSATS_LIBP2P_IDENTITY_PROOF".to_vec();
+    info.extend_from_slice(protocol.as_bytes());
+    info.extend_from_slice(&taker_peer.to_bytes());
+    info.extend_from_slice(&maker_peer.to_bytes());
+
+    let mut proof = [0u8; 32];
+
+    Hkdf::<Sha256>::new(None, shared_secret.as_bytes())
+        .expand(&info, &mut proof)
+        .expect("okm array is of correct length");
+
+    proof
+}
+
+/// A substream of one of the trading protocols, opened by an authenticated taker.
+pub struct AuthenticatedSubstream {
+    pub protocol: &'static str,
+    pub peer: PeerId,
+    pub taker: Identity,
+    pub stream: Substream,
+}
+
+/// Handles the inbound substreams of a single trading protocol.
+///
+/// Substreams are only handed over once the taker proved its [`Identity`] through [`Hello`].
+pub struct InboundHandler {
+    protocol: &'static str,
+    identity_sk: x25519_dalek::StaticSecret,
+    peer_id: PeerId,
+    authenticated: Box<dyn MessageChannel<AuthenticatedSubstream>>,
+    tasks: Tasks,
+}
+
+impl InboundHandler {
+    pub fn new(
+        protocol: &'static str,
+        identity_sk: x25519_dalek::StaticSecret,
+        peer_id: PeerId,
+        authenticated: &(impl MessageChannel<AuthenticatedSubstream> + 'static),
+    ) -> Self {
+        Self {
+            protocol,
+            identity_sk,
+            peer_id,
+            authenticated: authenticated.clone_channel(),
+            tasks: Tasks::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl xtra::Actor for InboundHandler {
+    type Stop = ();
+
+    async fn stopped(self) -> Self::Stop {}
+}
+
+#[xtra_productivity(message_impl = false)]
+impl InboundHandler {
+    async fn handle(&mut self, msg: NewInboundSubstream) {
+        let NewInboundSubstream { peer, mut stream } = msg;
+        let protocol = self.protocol;
+        let identity_sk = self.identity_sk.clone();
+        let peer_id = self.peer_id;
+        let authenticated = self.authenticated.clone_channel();
+
+        self.tasks.add_fallible(
+            async move {
+                let hello = tokio::time::timeout(HELLO_TIMEOUT, Hello::receive(&mut stream))
+                    .await
+                    .context("No Hello from taker within 10 seconds")??;
+                let taker = hello.verify(&identity_sk, protocol, peer, peer_id)?;
+
+                let _ = authenticated
+                    .send_async_safe(AuthenticatedSubstream {
+                        protocol,
+                        peer,
+                        taker,
+                        stream,
+                    })
+                    .await;
+
+                anyhow::Ok(())
+            },
+            move |e| async move {
+                tracing::debug!(%peer, %protocol, "Failed to authenticate inbound substream: {e:#}")
+            },
+        );
+    }
+}
+
+pub struct JsonCodec<D, E> {
+    _type: PhantomData<(D, E)>,
+    inner: LengthCodec,
+}
+
+impl<D, E> JsonCodec<D, E> {
+    pub fn new() -> Self {
+        Self {
+            _type: PhantomData,
+            inner: LengthCodec,
+        }
+    }
+}
+
+impl<D, E> Default for JsonCodec<D, E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D, E> Decoder for JsonCodec<D, E>
+where
+    D: DeserializeOwned,
+{
+    type Item = D;
+    type Error = anyhow::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let bytes = match self.inner.decode(src)? {
+            None => return Ok(None),
+            Some(bytes) => bytes,
+        };
+
+        let item = serde_json::from_slice(&bytes)?;
+
+        Ok(Some(item))
+    }
+}
+
+impl<D, E> Encoder for JsonCodec<D, E>
+where
+    E: Serialize,
+{
+    type Item = E;
+    type Error = anyhow::Error;
+
+    fn encode(&mut self, item: Self::Item, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let bytes = serde_json::to_vec(&item)?;
+
+        self.inner.encode(Bytes::from(bytes), dst)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hello_proves_identity_of_taker() {
+        let (taker_sk, maker_sk) = (secret(), secret());
+        let (taker_peer, maker_peer) = (PeerId::random(), PeerId::random());
+
+        let hello = Hello::new(
+            &taker_sk,
+            identity(&maker_sk),
+            ORDER_PROTOCOL,
+            taker_peer,
+            maker_peer,
+        );
+        let taker = hello
+            .verify(&maker_sk, ORDER_PROTOCOL, taker_peer, maker_peer)
+            .unwrap();
+
+        assert_eq!(taker, identity(&taker_sk));
+    }
+
+    #[test]
+    fn hello_cannot_be_replayed_by_other_peer() {
+        let (taker_sk, maker_sk) = (secret(), secret());
+        let (taker_peer, maker_peer) = (PeerId::random(), PeerId::random());
+
+        let hello = Hello::new(
+            &taker_sk,
+            identity(&maker_sk),
+            ORDER_PROTOCOL,
+            taker_peer,
+            maker_peer,
+        );
+
+        assert!(hello
+            .verify(&maker_sk, ORDER_PROTOCOL, PeerId::random(), maker_peer)
+            .is_err());
+        assert!(hello
+            .verify(&maker_sk, ROLLOVER_PROTOCOL, taker_peer, maker_peer)
+            .is_err());
+    }
+
+    #[test]
+    fn hello_for_other_maker_is_rejected() {
+        let (taker_sk, maker_sk) = (secret(), secret());
+        let (taker_peer, maker_peer) = (PeerId::random(), PeerId::random());
+
+        let hello = Hello::new(
+            &taker_sk,
+            identity(&secret()),
+            ORDER_PROTOCOL,
+            taker_peer,
+            maker_peer,
+        );
+
+        assert!(hello
+            .verify(&maker_sk, ORDER_PROTOCOL, taker_peer, maker_peer)
+            .is_err());
+    }
+
+    fn secret() -> x25519_dalek::StaticSecret {
+        x25519_dalek::StaticSecret::from(rand::random::<[u8; 32]>())
+    }
+
+    fn identity(sk: &x25519_dalek::StaticSecret) -> Identity {
+        Identity::new(x25519_dalek::PublicKey::from(sk))
+    }
+}
@@ -7,6 +7,8 @@ use crate::oracle;
 use crate::process_manager;
 use crate::projection;
 use crate::rollover_maker;
+use crate::setup_contract::RolloverCheckpoint;
+use crate::setup_contract::SetupCheckpoint;
 use crate::setup_maker;
 use crate::wallet;
 use crate::wire;
@@ -16,6 +18,7 @@ use anyhow::Context;
 use anyhow::Result;
 use async_trait::async_trait;
 use bdk::bitcoin::secp256k1::schnorrsig;
+use model::olivia::Announcement;
 use model::olivia::BitMexPriceEventId;
 use model::Cfd;
 use model::FundingRate;
@@ -70,6 +73,13 @@ pub struct RejectSettlement {
     pub order_id: OrderId,
 }
 
+/// Reply to the settlement proposal of the taker with a different price.
+#[derive(Clone, Copy)]
+pub struct CounterSettlement {
+    pub order_id: OrderId,
+    pub price: Price,
+}
+
 #[derive(Clone, Copy)]
 pub struct AcceptRollover {
     pub order_id: OrderId,
@@ -157,6 +167,12 @@ pub struct FromTaker {
     pub msg: wire::TakerToMaker,
 }
 
+/// Message sent to ourselves to resume a contract setup which was interrupted by a restart.
+struct ResumeContractSetup {
+    checkpoint: SetupCheckpoint,
+    announcement: Announcement,
+}
+
 /// Proposed rollover
 #[derive(Debug, Clone, PartialEq)]
 struct RolloverProposal {
@@ -180,6 +196,7 @@ pub struct Actor<O, T, W> {
     oracle: xtra::Address<O>,
     connected_takers: HashSet<Identity>,
     n_payouts: usize,
+    rollover_policy: rollover_maker::Policy,
     tasks: Tasks,
 }
 
@@ -195,6 +212,7 @@ impl<O, T, W> Actor<O, T, W> {
         takers: xtra::Address<T>,
         oracle: xtra::Address<O>,
         n_payouts: usize,
+        rollover_policy: rollover_maker::Policy,
     ) -> Self {
         Self {
             db: db.clone(),
@@ -210,6 +228,7 @@ impl<O, T, W> Actor<O, T, W> {
             setup_actors: AddressMap::default(),
             oracle,
             n_payouts,
+            rollover_policy,
             connected_takers: HashSet::new(),
             settlement_actors: AddressMap::default(),
             tasks: Tasks::default(),
@@ -281,6 +300,7 @@ where
             &self.takers,
             self.db.clone(),
             version,
+            self.rollover_policy,
         )
         .create(None)
         .spawn(&mut self.tasks);
@@ -289,6 +309,40 @@ where
 
         Ok(())
     }
+
+    /// Resume the rollovers which were interrupted by a restart.
+    async fn resume_rollovers(&mut self) -> Result<()> {
+        for checkpoint in RolloverCheckpoint::load_all(&self.db).await? {
+            let order_id = checkpoint.order_id;
+
+            let taker_id = match self.db.load_open_cfd::<Cfd>(order_id, ()).await {
+                Ok(cfd) => cfd.counterparty_network_identity(),
+                Err(e) => {
+                    tracing::warn!(%order_id, "Discarding rollover checkpoint: {e:#}");
+                    RolloverCheckpoint::delete(&self.db, order_id).await?;
+                    continue;
+                }
+            };
+
+            let rollover_actor_addr = rollover_maker::Actor::resume(
+                checkpoint,
+                &self.takers,
+                taker_id,
+                self.oracle_pk,
+                &self.oracle,
+                self.process_manager.clone(),
+                &self.takers,
+                self.db.clone(),
+                self.rollover_policy,
+            )
+            .create(None)
+            .spawn(&mut self.tasks);
+
+            self.rollover_actors.insert(order_id, rollover_actor_addr);
+        }
+
+        Ok(())
+    }
 }
 
 impl<O, T, W> Actor<O, T, W>
@@ -296,7 +350,8 @@ where
     O: xtra::Handler<oracle::GetAnnouncement> + xtra::Handler<oracle::MonitorAttestation>,
     T: xtra::Handler<maker_inc_connections::ConfirmOrder>
         + xtra::Handler<maker_inc_connections::TakerMessage>
-        + xtra::Handler<maker_inc_connections::BroadcastOffers>,
+        + xtra::Handler<maker_inc_connections::BroadcastOffers>
+        + xtra::Handler<maker_inc_connections::RegisterSetup>,
     W: xtra::Handler<wallet::Sign> + xtra::Handler<wallet::BuildPartyParams>,
 {
     async fn handle_take_order(
@@ -374,7 +429,7 @@ where
             (self.oracle_pk, announcement),
             &self.wallet,
             &self.wallet,
-            (&self.takers, &self.takers, taker_id),
+            (&self.takers, &self.takers, &self.takers, taker_id),
         )
         .create(None)
         .spawn(&mut self.tasks);
@@ -493,6 +548,27 @@ impl<O, T, W> Actor<O, T, W> {
         }
     }
 
+    async fn handle_counter_settlement(&mut self, msg: CounterSettlement) -> Result<()> {
+        let CounterSettlement { order_id, price } = msg;
+
+        match self
+            .settlement_actors
+            .send_async(&order_id, collab_settlement_maker::CounterOffer { price })
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(NotConnected(e)) => {
+                self.executor
+                    .execute(order_id, |cfd| {
+                        Ok(cfd.fail_collaborative_settlement(anyhow!(e)))
+                    })
+                    .await?;
+
+                bail!("Counter-offer failed: No settlement in progress for order {order_id}")
+            }
+        }
+    }
+
     async fn handle_accept_rollover(&mut self, msg: AcceptRollover) -> Result<()> {
         let current_offers = self
             .current_offers
@@ -587,9 +663,47 @@ where
         + xtra::Handler<maker_inc_connections::TakerMessage>
         + xtra::Handler<maker_inc_connections::BroadcastOffers>
         + xtra::Handler<maker_inc_connections::settlement::Response>
-        + xtra::Handler<maker_inc_connections::RegisterRollover>,
+        + xtra::Handler<maker_inc_connections::RegisterRollover>
+        + xtra::Handler<maker_inc_connections::RegisterSetup>,
     W: xtra::Handler<wallet::Sign> + xtra::Handler<wallet::BuildPartyParams>,
 {
+    async fn handle_resume_contract_setup(&mut self, msg: ResumeContractSetup) -> Result<()> {
+        let ResumeContractSetup {
+            checkpoint,
+            announcement,
+        } = msg;
+        let order_id = checkpoint.order_id;
+
+        let disconnected = self
+            .setup_actors
+            .get_disconnected(order_id)
+            .with_context(|| {
+                format!("Contract setup for order {order_id} is already in progress")
+            })?;
+
+        let taker_id = self
+            .db
+            .load_open_cfd::<Cfd>(order_id, ())
+            .await?
+            .counterparty_network_identity();
+
+        let addr = setup_maker::Actor::resume(
+            self.db.clone(),
+            self.process_manager.clone(),
+            checkpoint,
+            (self.oracle_pk, announcement),
+            &self.wallet,
+            &self.wallet,
+            (&self.takers, &self.takers, &self.takers, taker_id),
+        )
+        .create(None)
+        .spawn(&mut self.tasks);
+
+        disconnected.insert(addr);
+
+        Ok(())
+    }
+
     async fn handle_new_order(&mut self, msg: OfferParams) -> Result<()> {
         // 1. Update actor state to current order
         self.current_offers
@@ -650,7 +764,9 @@ where
                 }
             }
             wire::TakerToMaker::Settlement {
-                msg: wire::taker_to_maker::Settlement::Initiate { .. },
+                msg:
+                    wire::taker_to_maker::Settlement::Initiate { .. }
+                    | wire::taker_to_maker::Settlement::RejectCounterOffer,
                 ..
             } => {
                 unreachable!("Handled within `collab_settlement_maker::Actor");
@@ -692,6 +808,7 @@ where
                 }
             }
             wire::TakerToMaker::RolloverProtocol { .. }
+            | wire::TakerToMaker::DeclineRollover { .. }
             | wire::TakerToMaker::Protocol { .. }
             | wire::TakerToMaker::Hello(_)
             | wire::TakerToMaker::HelloV2 { .. }
@@ -705,8 +822,65 @@ where
 }
 
 #[async_trait]
-impl<O: Send + 'static, T: Send + 'static, W: Send + 'static> xtra::Actor for Actor<O, T, W> {
+impl<O, T, W> xtra::Actor for Actor<O, T, W>
+where
+    O: xtra::Handler<oracle::GetAnnouncement> + xtra::Handler<oracle::MonitorAttestation>,
+    T: xtra::Handler<maker_inc_connections::ConfirmOrder>
+        + xtra::Handler<maker_inc_connections::TakerMessage>
+        + xtra::Handler<maker_inc_connections::BroadcastOffers>
+        + xtra::Handler<maker_inc_connections::settlement::Response>
+        + xtra::Handler<maker_inc_connections::RegisterRollover>
+        + xtra::Handler<maker_inc_connections::RegisterSetup>,
+    W: xtra::Handler<wallet::Sign> + xtra::Handler<wallet::BuildPartyParams>,
+{
     type Stop = ();
 
+    async fn started(&mut self, ctx: &mut xtra::Context<Self>) {
+        if let Err(e) = self.resume_rollovers().await {
+            tracing::error!("Failed to resume rollovers: {e:#}");
+        }
+
+        let this = ctx.address().expect("we are alive");
+        let db = self.db.clone();
+        let oracle = self.oracle.clone();
+        let executor = self.executor.clone();
+
+        // Contract setups are resumed in the background because the oracle actor might not have
+        // fetched the announcements yet
+        self.tasks.add_fallible(
+            async move {
+                for checkpoint in SetupCheckpoint::load_all(&db).await? {
+                    let order_id = checkpoint.order_id;
+                    let oracle_event_id = checkpoint.terms.oracle_event_id;
+
+                    match oracle::wait_for_announcement(&oracle, oracle_event_id).await {
+                        Ok(announcement) => {
+                            if let Err(e) = this
+                                .send(ResumeContractSetup {
+                                    checkpoint,
+                                    announcement,
+                                })
+                                .await?
+                            {
+                                tracing::warn!(%order_id, "Failed to resume contract setup: {e:#}");
+                            }
+                        }
+                        Err(e) => {
+                            executor
+                                .execute(order_id, |cfd| Ok(cfd.fail_contract_setup(e)))
+                                .await?;
+                            SetupCheckpoint::delete(&db, order_id).await?;
+                        }
+                    }
+                }
+
+                anyhow::Ok(())
+            },
+            |e| async move {
+                tracing::error!("Failed to resume contract setups: {e:#}");
+            },
+        );
+    }
+
     async fn stopped(self) -> Self::Stop {}
 }
@@ -1,5 +1,8 @@
+use crate::access_control;
+use crate::access_control::AccessControl;
 use crate::collab_settlement_maker;
 use crate::future_ext::FutureExt;
+use crate::libp2p_wire;
 use crate::maker_cfd;
 use crate::noise;
 use crate::noise::TransportStateExt;
@@ -15,12 +18,16 @@ use async_trait::async_trait;
 use futures::SinkExt;
 use futures::StreamExt;
 use futures::TryStreamExt;
+use libp2p_core::PeerId;
+use maia::secp256k1_zkp::Signature;
 use model::Identity;
 use model::MakerOffers;
 use model::OrderId;
+use model::SettlementProposal;
 use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::time::Duration;
+use std::time::Instant;
 use tokio::net::TcpListener;
 use tokio::net::TcpStream;
 use tokio_tasks::Tasks;
@@ -72,6 +79,14 @@ pub mod settlement {
             address: xtra::Address<collab_settlement_maker::Actor>,
         },
         Reject,
+        CounterOffer {
+            proposal: SettlementProposal,
+            address: xtra::Address<collab_settlement_maker::Actor>,
+        },
+        /// Hand our signature of the settlement transaction to the taker.
+        Finalize {
+            sig_maker: Signature,
+        },
     }
 }
 
@@ -80,13 +95,38 @@ pub struct TakerMessage {
     pub msg: wire::MakerToTaker,
 }
 
+/// Message sent from a `setup_maker::Actor` which resumes a contract
+/// setup after a restart, so that the `maker_inc_connections::Actor`
+/// forwards the contract setup messages from the taker to it again.
+pub struct RegisterSetup {
+    pub order_id: OrderId,
+    pub address: xtra::Address<setup_maker::Actor>,
+}
+
 pub struct RegisterRollover {
     pub order_id: OrderId,
     pub address: xtra::Address<rollover_maker::Actor>,
 }
 
+/// Replace the policy deciding which takers are accepted.
+///
+/// Connections and substreams of takers which are no longer accepted are closed.
+pub struct SetAccessPolicy(pub access_control::Policy);
+
+/// Get the peer IDs of the connected takers which opened a libp2p substream.
+pub struct GetTakerPeers;
+
 pub struct Actor {
     connections: HashMap<Identity, Connection>,
+    /// Substreams carrying the protocol currently running for an order, opened by its taker.
+    order_substreams: HashMap<OrderId, TakerSubstream>,
+    /// Takers receiving our offers through the libp2p offer protocol.
+    offer_subscribers: HashMap<Identity, TakerSubstream>,
+    current_offers: Option<MakerOffers>,
+    next_substream_id: u64,
+    /// The peer IDs of the connected takers, learned from their libp2p substreams.
+    taker_peers: HashMap<Identity, PeerId>,
+    access_control: AccessControl,
     taker_connected_channel: Box<dyn MessageChannel<maker_cfd::TakerConnected>>,
     taker_disconnected_channel: Box<dyn MessageChannel<maker_cfd::TakerDisconnected>>,
     taker_msg_channel: Box<dyn MessageChannel<maker_cfd::FromTaker>>,
@@ -168,6 +208,34 @@ impl Connection {
     }
 }
 
+/// A libp2p substream opened by a taker.
+struct TakerSubstream {
+    id: u64,
+    taker: Identity,
+    peer: PeerId,
+    write: libp2p_wire::Write<wire::TakerToMaker, wire::MakerToTaker>,
+}
+
+impl TakerSubstream {
+    async fn send(&mut self, msg: wire::MakerToTaker) -> Result<()> {
+        let msg_str = msg.name();
+        let taker_id = self.taker;
+
+        P2P_MESSAGES_SENT
+            .with(&HashMap::from([(MESSAGE_LABEL, msg_str)]))
+            .inc();
+
+        tracing::trace!(target: "wire", %taker_id, msg_name = msg_str, "Sending over libp2p");
+
+        self.write
+            .send(msg)
+            .await
+            .with_context(|| format!("Failed to send msg {msg_str} to taker {taker_id}"))?;
+
+        Ok(())
+    }
+}
+
 impl Drop for Connection {
     fn drop(&mut self) {
         let taker_id = self.taker;
@@ -184,11 +252,18 @@ impl Actor {
         noise_priv_key: x25519_dalek::StaticSecret,
         heartbeat_interval: Duration,
         p2p_socket: SocketAddr,
+        access_policy: access_control::Policy,
     ) -> Self {
         NUM_CONNECTIONS_GAUGE.reset();
 
         Self {
             connections: HashMap::new(),
+            order_substreams: HashMap::new(),
+            offer_subscribers: HashMap::new(),
+            current_offers: None,
+            next_substream_id: 0,
+            taker_peers: HashMap::new(),
+            access_control: AccessControl::new(access_policy),
             taker_connected_channel: taker_connected_channel.clone_channel(),
             taker_disconnected_channel: taker_disconnected_channel.clone_channel(),
             taker_msg_channel: taker_msg_channel.clone_channel(),
@@ -203,6 +278,8 @@ impl Actor {
     }
 
     async fn drop_taker_connection(&mut self, taker_id: &Identity) {
+        self.taker_peers.remove(taker_id);
+
         if let Some(connection) = self.connections.remove(taker_id) {
             let _: Result<(), xtra::Disconnected> = self
                 .taker_disconnected_channel
@@ -226,11 +303,34 @@ impl Actor {
         }
     }
 
+    /// Send a message to the taker.
+    ///
+    /// Messages about an order are sent over the libp2p substream of the order if the taker opened
+    /// one, otherwise they are sent over the legacy connection.
     async fn send_to_taker(
         &mut self,
         taker_id: &Identity,
         msg: wire::MakerToTaker,
     ) -> Result<(), NoConnection> {
+        let substream = msg
+            .order_id()
+            .and_then(|order_id| self.order_substreams.get_mut(&order_id))
+            .filter(|substream| substream.taker == *taker_id);
+
+        if let Some(substream) = substream {
+            if let Err(e) = substream.send(msg).await {
+                tracing::debug!("{e:#}");
+
+                let id = substream.id;
+                self.order_substreams
+                    .retain(|_, substream| substream.id != id);
+
+                return Err(NoConnection(*taker_id));
+            }
+
+            return Ok(());
+        }
+
         let conn = self
             .connections
             .get_mut(taker_id)
@@ -278,6 +378,15 @@ impl Actor {
 
                 match new_connection {
                     Ok((stream, address)) => {
+                        match this.send(CheckAddress(address)).await {
+                            Ok(Ok(())) => {}
+                            Ok(Err(rejection)) => {
+                                tracing::debug!(%address, "Rejecting incoming connection: {rejection}");
+                                continue;
+                            }
+                            Err(_) => return,
+                        }
+
                         let upgrade = upgrade(stream, noise_priv_key.clone(), this.clone());
 
                         tasks
@@ -300,6 +409,8 @@ impl Actor {
 
 struct SendHeartbeat(Identity);
 
+struct CheckAddress(SocketAddr);
+
 #[derive(Debug, thiserror::Error, Clone, Copy)]
 #[error("No connection to taker {0}")]
 pub struct NoConnection(Identity);
@@ -308,10 +419,36 @@ pub struct NoConnection(Identity);
 impl Actor {
     async fn handle_broadcast_order(&mut self, msg: BroadcastOffers) {
         let offers = msg.0;
+        self.current_offers = offers;
+
+        let mut broken_subscriptions = Vec::new();
+
+        for (id, subscriber) in &mut self.offer_subscribers {
+            if let Err(e) = subscriber
+                .send(wire::MakerToTaker::CurrentOffers(offers))
+                .await
+            {
+                tracing::warn!("{:#}", e);
+                broken_subscriptions.push(*id);
+
+                continue;
+            }
+
+            tracing::trace!(taker_id = %id, "Sent new offers over libp2p: {:?}", offers);
+        }
+
+        for id in broken_subscriptions {
+            self.offer_subscribers.remove(&id);
+        }
 
         let mut broken_connections = Vec::with_capacity(self.connections.len());
 
         for (id, conn) in &mut self.connections {
+            // Takers subscribed through libp2p already received the offers
+            if self.offer_subscribers.contains_key(id) {
+                continue;
+            }
+
             if let Err(e) = conn.send(wire::MakerToTaker::CurrentOffers(offers)).await {
                 tracing::warn!("{:#}", e);
                 broken_connections.push(*id);
@@ -359,6 +496,19 @@ impl Actor {
                 wire::maker_to_taker::Settlement::Confirm
             }
             settlement::Decision::Reject => wire::maker_to_taker::Settlement::Reject,
+            settlement::Decision::CounterOffer { proposal, address } => {
+                self.settlement_actors.insert(msg.order_id, address);
+
+                wire::maker_to_taker::Settlement::CounterOffer {
+                    timestamp: proposal.timestamp,
+                    taker: proposal.taker,
+                    maker: proposal.maker,
+                    price: proposal.price,
+                }
+            }
+            settlement::Decision::Finalize { sig_maker } => {
+                wire::maker_to_taker::Settlement::Finalize { sig_maker }
+            }
         };
 
         self.send_to_taker(
@@ -409,6 +559,17 @@ impl Actor {
             return;
         }
 
+        if let Err(rejection) =
+            self.access_control
+                .check_connection(identity, self.connections.len(), Instant::now())
+        {
+            tracing::debug!(taker_id = %identity, taker_address = %address, "Rejecting connection: {rejection}");
+            REJECTED_CONNECTIONS
+                .with(&HashMap::from([(REASON_LABEL, rejection.label())]))
+                .inc();
+            return;
+        }
+
         let _: Result<(), xtra::Disconnected> = self
             .taker_connected_channel
             .send_async_safe(maker_cfd::TakerConnected { id: identity })
@@ -482,6 +643,185 @@ impl Actor {
     async fn handle_rollover_proposed(&mut self, msg: RegisterRollover) {
         self.rollover_actors.insert(msg.order_id, msg.address);
     }
+
+    async fn handle_register_setup(&mut self, msg: RegisterSetup) {
+        self.setup_actors.insert(msg.order_id, msg.address);
+    }
+
+    async fn handle_authenticated_substream(
+        &mut self,
+        msg: libp2p_wire::AuthenticatedSubstream,
+        ctx: &mut xtra::Context<Self>,
+    ) {
+        let libp2p_wire::AuthenticatedSubstream {
+            protocol,
+            peer,
+            taker,
+            stream,
+        } = msg;
+        let this = ctx.address().expect("we are alive");
+
+        if let Err(rejection) = self.access_control.check_taker(taker, Some(peer)) {
+            tracing::debug!(taker_id = %taker, %peer, %protocol, "Rejecting substream: {rejection}");
+            REJECTED_CONNECTIONS
+                .with(&HashMap::from([(REASON_LABEL, rejection.label())]))
+                .inc();
+            return;
+        }
+
+        let id = self.next_substream_id;
+        self.next_substream_id += 1;
+
+        tracing::debug!(taker_id = %taker, %peer, %protocol, "Taker opened substream");
+
+        self.taker_peers.insert(taker, peer);
+
+        let (write, mut read) = libp2p_wire::split(stream);
+        let mut substream = TakerSubstream {
+            id,
+            taker,
+            peer,
+            write,
+        };
+
+        if protocol == libp2p_wire::OFFER_PROTOCOL {
+            if let Err(e) = substream
+                .send(wire::MakerToTaker::CurrentOffers(self.current_offers))
+                .await
+            {
+                tracing::debug!("{e:#}");
+                return;
+            }
+
+            self.offer_subscribers.insert(taker, substream);
+
+            // The taker does not send anything on this substream, we only detect when it goes away
+            self.tasks.add(async move {
+                while let Some(Ok(_)) = read.next().await {}
+
+                let _ = this.send(SubstreamClosed { id }).await;
+            });
+
+            return;
+        }
+
+        self.tasks.add(async move {
+            let result = async {
+                let first_message = read
+                    .try_next()
+                    .timeout(Duration::from_secs(10))
+                    .await
+                    .context("No message from taker within 10 seconds")?
+                    .context("Failed to read first message on substream")?
+                    .context("Substream closed before first message")?;
+
+                let order_id = first_message
+                    .order_id()
+                    .filter(|_| libp2p_wire::protocol_started_by(&first_message) == Some(protocol))
+                    .with_context(|| {
+                        format!(
+                            "Message {} does not start protocol {protocol}",
+                            first_message.name()
+                        )
+                    })?;
+
+                this.send(RegisterSubstream {
+                    order_id,
+                    substream,
+                })
+                .await?;
+
+                let mut msg = first_message;
+
+                loop {
+                    this.send(maker_cfd::FromTaker {
+                        taker_id: taker,
+                        msg,
+                    })
+                    .await?;
+
+                    msg = match read.try_next().await? {
+                        Some(msg) => msg,
+                        None => return anyhow::Ok(()),
+                    };
+
+                    anyhow::ensure!(
+                        msg.order_id() == Some(order_id),
+                        "Message {} on substream of order {order_id} refers to another order",
+                        msg.name()
+                    );
+                }
+            };
+
+            if let Err(e) = result.await {
+                tracing::debug!(taker_id = %taker, %protocol, "Substream failed: {e:#}");
+            }
+
+            let _ = this.send(SubstreamClosed { id }).await;
+        });
+    }
+
+    async fn handle_register_substream(&mut self, msg: RegisterSubstream) {
+        self.order_substreams.insert(msg.order_id, msg.substream);
+    }
+
+    async fn handle_substream_closed(&mut self, msg: SubstreamClosed) {
+        self.order_substreams
+            .retain(|_, substream| substream.id != msg.id);
+        self.offer_subscribers
+            .retain(|_, substream| substream.id != msg.id);
+    }
+
+    async fn handle_check_address(
+        &mut self,
+        msg: CheckAddress,
+    ) -> Result<(), access_control::Rejection> {
+        let CheckAddress(address) = msg;
+
+        self.access_control
+            .check_address(address.ip(), Instant::now())
+            .map_err(|rejection| {
+                REJECTED_CONNECTIONS
+                    .with(&HashMap::from([(REASON_LABEL, rejection.label())]))
+                    .inc();
+
+                rejection
+            })
+    }
+
+    async fn handle_get_taker_peers(&mut self, _: GetTakerPeers) -> HashMap<Identity, PeerId> {
+        self.taker_peers.clone()
+    }
+
+    async fn handle_set_access_policy(&mut self, msg: SetAccessPolicy) {
+        let SetAccessPolicy(policy) = msg;
+
+        tracing::info!(?policy, "Updating access policy");
+        self.access_control.set_policy(policy);
+
+        let access_control = &self.access_control;
+        let is_rejected = |substream: &TakerSubstream| {
+            access_control
+                .check_taker(substream.taker, Some(substream.peer))
+                .is_err()
+        };
+        self.order_substreams
+            .retain(|_, substream| !is_rejected(substream));
+        self.offer_subscribers
+            .retain(|_, substream| !is_rejected(substream));
+
+        let rejected_takers = self
+            .connections
+            .keys()
+            .copied()
+            .filter(|taker| access_control.check_taker(*taker, None).is_err())
+            .collect::<Vec<_>>();
+
+        for taker_id in rejected_takers {
+            tracing::info!(%taker_id, "Disconnecting taker rejected by access policy");
+            self.drop_taker_connection(&taker_id).await;
+        }
+    }
 }
 
 #[xtra_productivity(message_impl = false)]
@@ -507,6 +847,15 @@ impl Actor {
                     tracing::warn!(%order_id, "No active rollover actor");
                 }
             }
+            DeclineRollover { order_id } => {
+                if let Err(NotConnected(_)) = self
+                    .rollover_actors
+                    .send_async(&order_id, rollover_maker::RolloverDeclined)
+                    .await
+                {
+                    tracing::warn!(%order_id, "No active rollover actor");
+                }
+            }
             Settlement {
                 order_id,
                 msg: taker_to_maker::Settlement::Initiate { sig_taker },
@@ -519,6 +868,18 @@ impl Actor {
                     tracing::warn!(%order_id, "No active settlement actor");
                 }
             }
+            Settlement {
+                order_id,
+                msg: taker_to_maker::Settlement::RejectCounterOffer,
+            } => {
+                if let Err(NotConnected(_)) = self
+                    .settlement_actors
+                    .send_async(&order_id, collab_settlement_maker::CounterOfferRejected)
+                    .await
+                {
+                    tracing::warn!(%order_id, "No active settlement actor");
+                }
+            }
             TakeOrder { .. }
             | ProposeRollover { .. }
             | ProposeRolloverV2 { .. }
@@ -630,6 +991,15 @@ struct ConnectionReady {
     daemon_version: String,
 }
 
+struct RegisterSubstream {
+    order_id: OrderId,
+    substream: TakerSubstream,
+}
+
+struct SubstreamClosed {
+    id: u64,
+}
+
 struct ReadFail {
     taker_id: Identity,
     error: anyhow::Error,
@@ -663,6 +1033,17 @@ static NUM_CONNECTIONS_GAUGE: conquer_once::Lazy<prometheus::IntGaugeVec> =
     });
 
 const MESSAGE_LABEL: &str = "message";
+const REASON_LABEL: &str = "reason";
+
+static REJECTED_CONNECTIONS: conquer_once::Lazy<prometheus::IntCounterVec> =
+    conquer_once::Lazy::new(|| {
+        prometheus::register_int_counter_vec!(
+            "p2p_rejected_connections_total",
+            "The number of connections and substreams rejected by the access policy.",
+            &[REASON_LABEL]
+        )
+        .unwrap()
+    });
 
 static P2P_MESSAGES_SENT: conquer_once::Lazy<prometheus::IntCounterVec> =
     conquer_once::Lazy::new(|| {
@@ -299,7 +299,9 @@ impl Cfd {
             | CollaborativeSettlementStarted { .. }
             | CollaborativeSettlementRejected
             | CollaborativeSettlementFailed
-            | CollaborativeSettlementProposalAccepted => self,
+            | CollaborativeSettlementProposalAccepted
+            | CollaborativeSettlementCounterOffered { .. }
+            | CollaborativeSettlementCounterOfferAccepted => self,
             RevokeConfirmed => {
                 tracing::error!("Revoked logic not implemented");
                 self
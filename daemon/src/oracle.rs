@@ -17,6 +17,7 @@ use time::Duration;
 use time::OffsetDateTime;
 use time::Time;
 use tokio_tasks::Tasks;
+use xtra::prelude::MessageChannel;
 use xtra_productivity::xtra_productivity;
 use xtras::SendInterval;
 
@@ -294,6 +295,31 @@ pub fn next_announcement_after(timestamp: OffsetDateTime) -> BitMexPriceEventId
     BitMexPriceEventId::with_20_digits(adjusted)
 }
 
+/// Get an announcement from the oracle actor, waiting for it to be fetched if necessary.
+///
+/// Right after startup the oracle actor has not fetched any announcements yet.
+pub async fn wait_for_announcement(
+    oracle: &dyn MessageChannel<GetAnnouncement>,
+    id: BitMexPriceEventId,
+) -> Result<olivia::Announcement> {
+    const MAX_WAIT: std::time::Duration = std::time::Duration::from_secs(60);
+    const RETRY_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+    let started_at = tokio::time::Instant::now();
+
+    loop {
+        match oracle
+            .send(GetAnnouncement(id))
+            .await
+            .context("Oracle actor disconnected")?
+        {
+            Ok(announcement) => return Ok(announcement),
+            Err(e) if started_at.elapsed() >= MAX_WAIT => return Err(e.into()),
+            Err(_) => tokio::time::sleep(RETRY_INTERVAL).await,
+        }
+    }
+}
+
 fn ceil_to_next_hour(original: OffsetDateTime) -> OffsetDateTime {
     let timestamp = original.add(1.hours());
     let exact_hour = Time::from_hms(timestamp.hour(), 0, 0).expect(
@@ -9,26 +9,89 @@ use crate::oracle;
 use crate::projection;
 use anyhow::Result;
 use async_trait::async_trait;
+use bdk::bitcoin::Transaction;
+use futures::StreamExt;
 use model::CfdEvent;
 use model::EventKind;
+use model::OrderId;
 use model::Role;
+use model::Timestamp;
+use std::time::Duration;
+use tokio_tasks::Tasks;
 use xtra::prelude::MessageChannel;
 use xtra_productivity::xtra_productivity;
 use xtras::SendAsyncSafe;
 
+/// How long the taker waits for the maker to publish the collaborative settlement transaction
+/// before publishing it itself, and how long both wait before trying again if publishing failed.
+///
+/// Publishing a transaction which is already in the mempool or on chain is harmless, hence the
+/// taker publishes regardless of whether the maker did.
+pub const COLLAB_SETTLEMENT_BROADCAST_DELAY: Duration = Duration::from_secs(60);
+
 pub struct Actor {
     db: db::Connection,
     role: Role,
+    /// See [`COLLAB_SETTLEMENT_BROADCAST_DELAY`].
+    settlement_broadcast_delay: Duration,
     cfds_changed: Box<dyn MessageChannel<projection::CfdChanged>>,
     try_broadcast_transaction: Box<dyn MessageChannel<TryBroadcastTransaction>>,
     start_monitoring: Box<dyn MessageChannel<StartMonitoring>>,
     monitor_cet_finality: Box<dyn MessageChannel<MonitorCetFinality>>,
     monitor_collaborative_settlement: Box<dyn MessageChannel<MonitorCollaborativeSettlement>>,
     monitor_attestation: Box<dyn MessageChannel<oracle::MonitorAttestation>>,
+    tasks: Tasks,
 }
 
 pub struct Event(CfdEvent);
 
+/// Read-model of the CFD for the process manager.
+#[derive(Clone)]
+struct Cfd {
+    id: OrderId,
+    /// The collaborative settlement transaction which is not yet confirmed and when the
+    /// settlement was completed.
+    pending_settlement: Option<(Transaction, Timestamp)>,
+    version: u32,
+}
+
+impl db::CfdAggregate for Cfd {
+    type CtorArgs = ();
+
+    fn new(_: Self::CtorArgs, cfd: db::Cfd) -> Self {
+        Self {
+            id: cfd.id,
+            pending_settlement: None,
+            version: 0,
+        }
+    }
+
+    fn apply(mut self, event: CfdEvent) -> Self {
+        self.version += 1;
+
+        use EventKind::*;
+        match event.event {
+            CollaborativeSettlementCompleted { spend_tx, .. } => Self {
+                pending_settlement: Some((spend_tx, event.timestamp)),
+                ..self
+            },
+            // Once the settlement is confirmed or the contract is closed on chain otherwise, there
+            // is no point in publishing the settlement transaction anymore.
+            CollaborativeSettlementConfirmed | CommitConfirmed | CetConfirmed | RefundConfirmed => {
+                Self {
+                    pending_settlement: None,
+                    ..self
+                }
+            }
+            _ => self,
+        }
+    }
+
+    fn version(&self) -> u32 {
+        self.version
+    }
+}
+
 impl Event {
     pub fn new(event: CfdEvent) -> Self {
         Self(event)
@@ -40,6 +103,7 @@ impl Actor {
     pub fn new(
         db: db::Connection,
         role: Role,
+        settlement_broadcast_delay: Duration,
         cfds_changed: &(impl MessageChannel<projection::CfdChanged> + 'static),
         try_broadcast_transaction: &(impl MessageChannel<TryBroadcastTransaction> + 'static),
         start_monitoring: &(impl MessageChannel<StartMonitoring> + 'static),
@@ -51,12 +115,14 @@ impl Actor {
         Self {
             db,
             role,
+            settlement_broadcast_delay,
             cfds_changed: cfds_changed.clone_channel(),
             try_broadcast_transaction: try_broadcast_transaction.clone_channel(),
             start_monitoring: start_monitoring.clone_channel(),
             monitor_cet_finality: monitor_cet.clone_channel(),
             monitor_collaborative_settlement: monitor_collaborative_settlement.clone_channel(),
             monitor_attestation: monitor_attestation.clone_channel(),
+            tasks: Tasks::default(),
         }
     }
 }
@@ -99,20 +165,7 @@ impl Actor {
             } => {
                 let txid = spend_tx.txid();
 
-                match self.role {
-                    Role::Maker => {
-                        self.try_broadcast_transaction
-                            .send_async_safe(TryBroadcastTransaction {
-                                tx: spend_tx,
-                                kind: TransactionKind::CollaborativeClose,
-                            })
-                            .await?;
-                    }
-                    Role::Taker => {
-                        // TODO: Publish the tx once the collaborative settlement is symmetric,
-                        // allowing the taker to publish as well.
-                    }
-                };
+                self.broadcast_settlement(event.id, spend_tx, event.timestamp);
 
                 self.monitor_collaborative_settlement
                     .send_async_safe(MonitorCollaborativeSettlement {
@@ -215,6 +268,8 @@ impl Actor {
             | RolloverRejected
             | RolloverFailed
             | CollaborativeSettlementProposalAccepted
+            | CollaborativeSettlementCounterOffered { .. }
+            | CollaborativeSettlementCounterOfferAccepted
             | LockConfirmed
             | LockConfirmedAfterFinality
             | CommitConfirmed
@@ -235,9 +290,106 @@ impl Actor {
     }
 }
 
+impl Actor {
+    /// Publish the collaborative settlement transaction once it is due.
+    ///
+    /// Publishing is retried until it succeeds or the CFD does not wait for the settlement
+    /// transaction anymore.
+    fn broadcast_settlement(
+        &mut self,
+        order_id: OrderId,
+        tx: Transaction,
+        completed_at: Timestamp,
+    ) {
+        let delay = match self.role {
+            Role::Maker => Duration::ZERO,
+            // The transaction lacks the signature of the maker if they did not send it to us, in
+            // which case only the maker can publish it.
+            Role::Taker if is_fully_signed(&tx) => self.settlement_broadcast_delay,
+            Role::Taker => return,
+        };
+
+        // After a restart, the settlement might have been completed a while ago already
+        let elapsed = Timestamp::now().seconds() - completed_at.seconds();
+        let delay = delay.saturating_sub(Duration::from_secs(elapsed.try_into().unwrap_or(0)));
+
+        let retry_interval = self.settlement_broadcast_delay;
+        let db = self.db.clone();
+        let try_broadcast_transaction = self.try_broadcast_transaction.clone_channel();
+
+        self.tasks.add_fallible(
+            async move {
+                tokio::time::sleep(delay).await;
+
+                loop {
+                    let result = try_broadcast_transaction
+                        .send(TryBroadcastTransaction {
+                            tx: tx.clone(),
+                            kind: TransactionKind::CollaborativeClose,
+                        })
+                        .await?;
+
+                    match result {
+                        Ok(()) => return anyhow::Ok(()),
+                        Err(e) => {
+                            tracing::warn!(
+                                %order_id,
+                                "Failed to publish collaborative settlement transaction, retrying in {}s: {e:#}",
+                                retry_interval.as_secs()
+                            );
+                        }
+                    }
+
+                    tokio::time::sleep(retry_interval).await;
+
+                    match db.load_open_cfd::<Cfd>(order_id, ()).await {
+                        Ok(Cfd {
+                            pending_settlement: Some(_),
+                            ..
+                        }) => {}
+                        Ok(_) | Err(db::Error::OpenCfdNotFound) => return Ok(()),
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+            },
+            move |e| async move {
+                tracing::warn!(%order_id, "Stopped publishing collaborative settlement transaction: {e:#}");
+            },
+        );
+    }
+}
+
 #[async_trait]
 impl xtra::Actor for Actor {
     type Stop = ();
 
+    async fn started(&mut self, _: &mut xtra::Context<Self>) {
+        // Settlements completed before a restart still have to be published
+        let mut stream = self.db.load_all_open_cfds::<Cfd>(());
+        let mut pending = Vec::new();
+
+        while let Some(cfd) = stream.next().await {
+            match cfd {
+                Ok(Cfd {
+                    id,
+                    pending_settlement: Some(settlement),
+                    ..
+                }) => pending.push((id, settlement)),
+                Ok(_) => {}
+                Err(e) => tracing::warn!("Failed to load CFD from database: {e:#}"),
+            }
+        }
+        drop(stream);
+
+        for (order_id, (tx, completed_at)) in pending {
+            self.broadcast_settlement(order_id, tx, completed_at);
+        }
+    }
+
     async fn stopped(self) -> Self::Stop {}
 }
+
+/// Whether every input of the transaction carries a witness.
+fn is_fully_signed(tx: &Transaction) -> bool {
+    tx.input.iter().all(|input| !input.witness.is_empty())
+}
@@ -79,6 +79,8 @@ pub struct Actor {
 pub struct Feeds {
     pub quote: watch::Receiver<Option<Quote>>,
     pub offers: watch::Receiver<MakerOffers>,
+    /// The offers of all makers we are connected to (taker only).
+    pub all_offers: watch::Receiver<Vec<CfdOrder>>,
     pub connected_takers: watch::Receiver<Vec<model::Identity>>,
     pub cfds: watch::Receiver<Option<Vec<Cfd>>>,
 }
@@ -87,13 +89,14 @@ impl Actor {
     pub fn new(
         db: db::Connection,
         network: Network,
-        price_feed: &(impl MessageChannel<xtra_bitmex_price_feed::LatestQuote> + 'static),
+        price_feed: Box<dyn MessageChannel<xtra_bitmex_price_feed::LatestQuote>>,
     ) -> (Self, Feeds) {
         let (tx_cfds, rx_cfds) = watch::channel(None);
         let (tx_order, rx_order) = watch::channel(MakerOffers {
             long: None,
             short: None,
         });
+        let (tx_all_offers, rx_all_offers) = watch::channel(Vec::new());
         let (tx_quote, rx_quote) = watch::channel(None);
         let (tx_connected_takers, rx_connected_takers) = watch::channel(Vec::new());
 
@@ -102,16 +105,18 @@ impl Actor {
             tx: Tx {
                 cfds: tx_cfds,
                 order: tx_order,
+                all_offers: tx_all_offers,
                 quote: tx_quote,
                 connected_takers: tx_connected_takers,
             },
             state: State::new(network),
-            price_feed: price_feed.clone_channel(),
+            price_feed,
             tasks: Tasks::default(),
         };
         let feeds = Feeds {
             cfds: rx_cfds,
             offers: rx_order,
+            all_offers: rx_all_offers,
             quote: rx_quote,
             connected_takers: rx_connected_takers,
         };
@@ -276,6 +281,10 @@ impl Aggregated {
                     Role::Taker => CfdState::OutgoingSettlementProposal,
                 },
                 ProtocolNegotiationState::Accepted => CfdState::IncomingSettlementProposal,
+                ProtocolNegotiationState::CounterOffered => match role {
+                    Role::Maker => CfdState::OutgoingSettlementProposal,
+                    Role::Taker => CfdState::IncomingSettlementProposal,
+                },
             };
         };
         if let Some(rollover_state) = self.rollover_state {
@@ -285,6 +294,9 @@ impl Aggregated {
                     Role::Taker => CfdState::OutgoingRolloverProposal,
                 },
                 ProtocolNegotiationState::Accepted => CfdState::RolloverSetup,
+                ProtocolNegotiationState::CounterOffered => {
+                    unreachable!("rollovers cannot be counter-offered")
+                }
             };
         };
         self.state
@@ -309,6 +321,8 @@ enum ProtocolNegotiationState {
     Started,
     /// Other party has agreed to proceed with the protocol
     Accepted,
+    /// The maker replied with a counter-offer the taker has to decide on
+    CounterOffered,
 }
 
 impl Cfd {
@@ -444,6 +458,14 @@ impl Cfd {
                 self.aggregated.settlement_state = Some(ProtocolNegotiationState::Accepted);
                 self.pending_settlement_proposal_price = None;
             }
+            CollaborativeSettlementCounterOffered { proposal } => {
+                self.aggregated.settlement_state = Some(ProtocolNegotiationState::CounterOffered);
+                self.pending_settlement_proposal_price = Some(proposal.price);
+            }
+            CollaborativeSettlementCounterOfferAccepted => {
+                self.aggregated.settlement_state = Some(ProtocolNegotiationState::Accepted);
+                self.pending_settlement_proposal_price = None;
+            }
             CollaborativeSettlementCompleted {
                 spend_tx,
                 script,
@@ -666,7 +688,14 @@ impl Cfd {
             (CfdState::IncomingSettlementProposal, Role::Maker) => {
                 HashSet::from([CfdAction::AcceptSettlement, CfdAction::RejectSettlement])
             }
-            (CfdState::IncomingSettlementProposal, Role::Taker) => HashSet::new(),
+            (CfdState::IncomingSettlementProposal, Role::Taker) => {
+                match self.aggregated.settlement_state {
+                    Some(ProtocolNegotiationState::CounterOffered) => {
+                        HashSet::from([CfdAction::AcceptSettlement, CfdAction::RejectSettlement])
+                    }
+                    _ => HashSet::new(),
+                }
+            }
             (CfdState::OutgoingSettlementProposal, _) => HashSet::new(),
             (CfdState::IncomingRolloverProposal, Role::Maker) => {
                 HashSet::from([CfdAction::AcceptRollover, CfdAction::RejectRollover])
@@ -746,6 +775,7 @@ impl Cfd {
 struct Tx {
     cfds: watch::Sender<Option<Vec<Cfd>>>,
     pub order: watch::Sender<MakerOffers>,
+    pub all_offers: watch::Sender<Vec<CfdOrder>>,
     pub quote: watch::Sender<Option<Quote>>,
     // TODO: Use this channel to communicate maker status as well with generic
     // ID of connected counterparties
@@ -779,37 +809,74 @@ impl Tx {
     fn send_order_update(&self, offers: Option<model::MakerOffers>) {
         let (long, short) = match offers {
             None => (None, None),
-            Some(offers) => {
-                let projection_long =
-                    offers
-                        .long
-                        .and_then(|long| match TryInto::<CfdOrder>::try_into(long) {
-                            Ok(projection_long) => Some(projection_long),
-                            Err(e) => {
-                                tracing::warn!("Unable to convert long order: {e:#}");
-                                None
-                            }
-                        });
-
-                let projection_short =
-                    offers
-                        .short
-                        .and_then(|short| match TryInto::<CfdOrder>::try_into(short) {
-                            Ok(projection_short) => Some(projection_short),
-                            Err(e) => {
-                                tracing::warn!("Unable to convert short order: {e:#}");
-                                None
-                            }
-                        });
-
-                (projection_long, projection_short)
-            }
+            Some(offers) => (
+                to_cfd_order(offers.long, None),
+                to_cfd_order(offers.short, None),
+            ),
         };
 
         let projection_offers = MakerOffers { long, short };
 
         let _ = self.order.send(projection_offers);
     }
+
+    /// Publish the offers of all makers.
+    ///
+    /// For backwards compatibility with clients that only know about a single maker, the best
+    /// offer on either side is published as the current offers as well: The highest price for
+    /// the maker's long offers and the lowest price for the maker's short offers.
+    fn send_all_offers_update(&self, offers: HashMap<model::Identity, model::MakerOffers>) {
+        let (longs, shorts): (Vec<_>, Vec<_>) = offers
+            .into_iter()
+            .sorted_by_key(|(maker, _)| maker.to_string())
+            .map(|(maker, offers)| {
+                (
+                    to_cfd_order(offers.long, Some(maker)),
+                    to_cfd_order(offers.short, Some(maker)),
+                )
+            })
+            .unzip();
+        let longs = longs.into_iter().flatten().collect::<Vec<_>>();
+        let shorts = shorts.into_iter().flatten().collect::<Vec<_>>();
+
+        let best_long = longs
+            .iter()
+            .max_by(|a, b| {
+                a.price
+                    .partial_cmp(&b.price)
+                    .expect("prices to be comparable")
+            })
+            .cloned();
+        let best_short = shorts
+            .iter()
+            .min_by(|a, b| {
+                a.price
+                    .partial_cmp(&b.price)
+                    .expect("prices to be comparable")
+            })
+            .cloned();
+
+        let _ = self.order.send(MakerOffers {
+            long: best_long,
+            short: best_short,
+        });
+        let _ = self
+            .all_offers
+            .send(longs.into_iter().chain(shorts).collect());
+    }
+}
+
+fn to_cfd_order(order: Option<Order>, maker: Option<model::Identity>) -> Option<CfdOrder> {
+    let order = order?;
+    let position = order.position_maker;
+
+    match CfdOrder::try_from(order) {
+        Ok(cfd_order) => Some(CfdOrder { maker, ..cfd_order }),
+        Err(e) => {
+            tracing::warn!(?position, "Unable to convert order: {e:#}");
+            None
+        }
+    }
 }
 
 /// Internal struct to keep state in one place
@@ -1055,6 +1122,10 @@ impl Actor {
         self.tx.send_order_update(msg.0);
     }
 
+    fn handle(&mut self, msg: Update<HashMap<model::Identity, model::MakerOffers>>) {
+        self.tx.send_all_offers_update(msg.0);
+    }
+
     fn handle(&mut self, msg: Update<Option<xtra_bitmex_price_feed::Quote>>) {
         self.state.update_quote(msg.0);
         self.tx.send_quote_update(msg.0);
@@ -1126,6 +1197,16 @@ impl From<xtra_bitmex_price_feed::Quote> for Quote {
     }
 }
 
+impl Quote {
+    pub fn bid(&self) -> Decimal {
+        self.bid
+    }
+
+    pub fn ask(&self) -> Decimal {
+        self.ask
+    }
+}
+
 /// Maker offers represents the offers as cerated by the maker
 #[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct MakerOffers {
@@ -1139,6 +1220,10 @@ pub struct MakerOffers {
 pub struct CfdOrder {
     pub id: OrderId,
 
+    /// The maker who published this offer, only set on the taker side
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub maker: Option<model::Identity>,
+
     pub trading_pair: TradingPair,
 
     #[serde(rename = "position")]
@@ -1249,6 +1334,7 @@ impl TryFrom<Order> for CfdOrder {
 
         Ok(Self {
             id: order.id,
+            maker: None,
             trading_pair: order.trading_pair,
             position_maker: order.position_maker,
             price: order.price,
@@ -1314,7 +1400,7 @@ pub enum CfdAction {
     RejectRollover,
 }
 
-mod round_to_two_dp {
+pub(crate) mod round_to_two_dp {
     use super::*;
     use serde::Serializer;
 
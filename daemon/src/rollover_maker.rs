@@ -5,6 +5,8 @@ use crate::oracle;
 use crate::process_manager;
 use crate::schnorrsig;
 use crate::setup_contract;
+use crate::setup_contract::RolloverCheckpoint;
+use crate::setup_contract::RolloverTerms;
 use crate::wire;
 use anyhow::Context as _;
 use anyhow::Result;
@@ -12,6 +14,8 @@ use futures::channel::mpsc;
 use futures::channel::mpsc::UnboundedSender;
 use futures::future;
 use futures::SinkExt;
+use model::olivia::Announcement;
+use model::Cfd;
 use model::Dlc;
 use model::FundingFee;
 use model::FundingRate;
@@ -39,6 +43,39 @@ pub struct AcceptRollover {
 #[derive(Clone, Copy)]
 pub struct RejectRollover;
 
+/// Message sent from the `maker_inc_connections::Actor` to notify that the taker declined the
+/// terms of the rollover.
+#[derive(Clone, Copy)]
+pub struct RolloverDeclined;
+
+/// How the maker picks the terms of a rollover.
+///
+/// The default renegotiates the funding rate and transaction fee rate with the current offers
+/// and keeps the number of payouts.
+#[derive(Debug, Clone, Copy)]
+pub struct Policy {
+    /// Charge the funding rate of the current offers instead of the one the CFD was opened with.
+    pub renegotiate_funding_rate: bool,
+    /// Build the new CETs with the transaction fee rate of the current offers instead of the one
+    /// the CFD was opened with.
+    pub renegotiate_tx_fee_rate: bool,
+    /// Build the new CETs with this number of payouts.
+    ///
+    /// Takers up to v0.4.12 cannot renegotiate the number of payouts and always use
+    /// [`crate::N_PAYOUTS`], rollovers with them fail if this is set to a different value.
+    pub n_payouts: Option<usize>,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            renegotiate_funding_rate: true,
+            renegotiate_tx_fee_rate: true,
+            n_payouts: None,
+        }
+    }
+}
+
 pub struct ProtocolMsg(pub wire::RolloverMsg);
 
 /// Message sent from the spawned task to `rollover_taker::Actor` to
@@ -64,8 +101,12 @@ pub struct Actor {
     oracle_actor: Box<dyn MessageChannel<oracle::GetAnnouncement>>,
     register: Box<dyn MessageChannel<maker_inc_connections::RegisterRollover>>,
     tasks: Tasks,
+    db: db::Connection,
     executor: command::Executor,
     version: RolloverVersion,
+    policy: Policy,
+    /// The checkpoint of a rollover which was interrupted by a restart.
+    resume: Option<RolloverCheckpoint>,
 }
 
 impl Actor {
@@ -81,6 +122,7 @@ impl Actor {
         register: &(impl MessageChannel<maker_inc_connections::RegisterRollover> + 'static),
         db: db::Connection,
         version: RolloverVersion,
+        policy: Policy,
     ) -> Self {
         Self {
             order_id,
@@ -91,9 +133,51 @@ impl Actor {
             sent_from_taker: None,
             oracle_actor: oracle_actor.clone_channel(),
             register: register.clone_channel(),
-            executor: command::Executor::new(db, process_manager),
+            executor: command::Executor::new(db.clone(), process_manager),
+            db,
             tasks: Tasks::default(),
             version,
+            policy,
+            resume: None,
+        }
+    }
+
+    /// Resume a rollover which was interrupted by a restart.
+    #[allow(clippy::too_many_arguments)]
+    pub fn resume(
+        checkpoint: RolloverCheckpoint,
+        send_to_taker_actor: &(impl MessageChannel<maker_inc_connections::TakerMessage> + 'static),
+        taker_id: Identity,
+        oracle_pk: schnorrsig::PublicKey,
+        oracle_actor: &(impl MessageChannel<oracle::GetAnnouncement> + 'static),
+        process_manager: xtra::Address<process_manager::Actor>,
+        register: &(impl MessageChannel<maker_inc_connections::RegisterRollover> + 'static),
+        db: db::Connection,
+        policy: Policy,
+    ) -> Self {
+        let params = checkpoint.terms.params;
+
+        Self {
+            order_id: checkpoint.order_id,
+            n_payouts: params.n_payouts,
+            send_to_taker_actor: send_to_taker_actor.clone_channel(),
+            taker_id,
+            oracle_pk,
+            sent_from_taker: None,
+            oracle_actor: oracle_actor.clone_channel(),
+            register: register.clone_channel(),
+            executor: command::Executor::new(db.clone(), process_manager),
+            db,
+            tasks: Tasks::default(),
+            version: params.version,
+            policy,
+            resume: Some(checkpoint),
+        }
+    }
+
+    async fn delete_checkpoint(&self) {
+        if let Err(e) = RolloverCheckpoint::delete(&self.db, self.order_id).await {
+            tracing::warn!(order_id = %self.order_id, "Failed to delete rollover checkpoint: {e:#}");
         }
     }
 
@@ -112,6 +196,7 @@ impl Actor {
         {
             tracing::warn!(order_id = %self.order_id, "{:#}", e)
         }
+        self.delete_checkpoint().await;
 
         ctx.stop();
     }
@@ -136,6 +221,7 @@ impl Actor {
         {
             tracing::warn!(order_id = %self.order_id, "{:#}", e)
         }
+        self.delete_checkpoint().await;
 
         ctx.stop();
     }
@@ -157,18 +243,39 @@ impl Actor {
 
         self.sent_from_taker = Some(sender);
 
-        let (rollover_params, dlc, position, interval, funding_rate) = self
+        let policy = self.policy;
+        let n_payouts = policy.n_payouts.unwrap_or(self.n_payouts);
+
+        let (rollover_params, dlc, position, interval, tx_fee_rate, funding_rate) = self
             .executor
             .execute(self.order_id, |cfd| {
-                let funding_rate = match cfd.position() {
-                    Position::Long => long_funding_rate,
-                    Position::Short => short_funding_rate,
+                let funding_rate = match (policy.renegotiate_funding_rate, cfd.position()) {
+                    (true, Position::Long) => long_funding_rate,
+                    (true, Position::Short) => short_funding_rate,
+                    (false, _) => cfd.initial_funding_rate(),
+                };
+                let tx_fee_rate = if policy.renegotiate_tx_fee_rate {
+                    tx_fee_rate
+                } else {
+                    cfd.initial_tx_fee_rate()
                 };
 
-                let (event, params, dlc, position, interval) =
-                    cfd.accept_rollover_proposal(tx_fee_rate, funding_rate, self.version)?;
-
-                Ok((event, params, dlc, position, interval, funding_rate))
+                let (event, params, dlc, position, interval) = cfd.accept_rollover_proposal(
+                    tx_fee_rate,
+                    funding_rate,
+                    n_payouts,
+                    self.version,
+                )?;
+
+                Ok((
+                    event,
+                    params,
+                    dlc,
+                    position,
+                    interval,
+                    tx_fee_rate,
+                    funding_rate,
+                ))
             })
             .await?;
 
@@ -185,6 +292,7 @@ impl Actor {
                     oracle_event_id,
                     tx_fee_rate,
                     funding_rate,
+                    n_payouts: policy.n_payouts,
                 },
             })
             .await
@@ -198,7 +306,73 @@ impl Actor {
             .context("Oracle actor disconnected")?
             .context("Failed to get announcement")?;
 
-        let funding_fee = *rollover_params.funding_fee();
+        let checkpoint = RolloverCheckpoint::new(
+            order_id,
+            RolloverTerms {
+                oracle_event_id,
+                params: rollover_params,
+            },
+        );
+
+        self.run_rollover(receiver, announcement, position, dlc, checkpoint, ctx);
+
+        Ok(())
+    }
+
+    async fn resume_rollover(
+        &mut self,
+        checkpoint: RolloverCheckpoint,
+        ctx: &mut xtra::Context<Self>,
+    ) -> Result<()> {
+        let order_id = self.order_id;
+        tracing::info!(%order_id, "Resuming rollover");
+
+        let resumed = match self.db.load_open_cfd::<Cfd>(order_id, ()).await {
+            Ok(cfd) => cfd.resume_rollover(),
+            Err(e) => Err(e),
+        };
+        let (dlc, position) = match resumed {
+            Ok(resumed) => resumed,
+            Err(e) => {
+                tracing::warn!(%order_id, "Discarding rollover checkpoint: {e:#}");
+                self.delete_checkpoint().await;
+
+                ctx.stop();
+                return Ok(());
+            }
+        };
+
+        let (sender, receiver) = mpsc::unbounded();
+        self.sent_from_taker = Some(sender);
+
+        self.register
+            .send(maker_inc_connections::RegisterRollover {
+                order_id,
+                address: ctx.address().expect("self to be alive"),
+            })
+            .await?;
+
+        let announcement =
+            oracle::wait_for_announcement(&*self.oracle_actor, checkpoint.terms.oracle_event_id)
+                .await?;
+
+        self.run_rollover(receiver, announcement, position, dlc, checkpoint, ctx);
+
+        Ok(())
+    }
+
+    fn run_rollover(
+        &mut self,
+        receiver: mpsc::UnboundedReceiver<wire::RolloverMsg>,
+        announcement: Announcement,
+        position: Position,
+        dlc: Dlc,
+        checkpoint: RolloverCheckpoint,
+        ctx: &mut xtra::Context<Self>,
+    ) {
+        let order_id = self.order_id;
+        let taker_id = self.taker_id;
+        let funding_fee = *checkpoint.terms.params.funding_fee();
 
         let rollover_fut = setup_contract::roll_over(
             self.send_to_taker_actor.sink().with(move |msg| {
@@ -209,11 +383,10 @@ impl Actor {
             }),
             receiver,
             (self.oracle_pk, announcement),
-            rollover_params,
             Role::Maker,
             position,
             dlc,
-            self.n_payouts,
+            (self.db.clone(), checkpoint),
         );
 
         let this = ctx.address().expect("self to be alive");
@@ -225,8 +398,6 @@ impl Actor {
                     Err(source) => this.send(RolloverFailed { error: source }).await,
                 };
         });
-
-        Ok(())
     }
 
     async fn reject(&mut self, ctx: &mut xtra::Context<Self>) -> Result<()> {
@@ -264,6 +435,14 @@ impl Actor {
 impl xtra::Actor for Actor {
     type Stop = ();
     async fn started(&mut self, ctx: &mut xtra::Context<Self>) {
+        if let Some(checkpoint) = self.resume.take() {
+            if let Err(source) = self.resume_rollover(checkpoint, ctx).await {
+                self.emit_fail(source, ctx).await;
+            }
+
+            return;
+        }
+
         let order_id = self.order_id;
         let taker_id = self.taker_id;
 
@@ -324,6 +503,17 @@ impl Actor {
         };
     }
 
+    async fn handle_rollover_declined(
+        &mut self,
+        _msg: RolloverDeclined,
+        ctx: &mut xtra::Context<Self>,
+    ) {
+        tracing::info!(order_id = %self.order_id, "Taker declined rollover terms");
+
+        self.emit_reject(anyhow::format_err!("Taker declined rollover terms"), ctx)
+            .await;
+    }
+
     async fn handle_protocol_msg(&mut self, msg: ProtocolMsg, ctx: &mut xtra::Context<Self>) {
         if let Err(error) = self.forward_protocol_msg(msg).await {
             self.emit_fail(error, ctx).await;
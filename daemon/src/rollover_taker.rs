@@ -4,8 +4,11 @@ use crate::db;
 use crate::oracle;
 use crate::process_manager;
 use crate::setup_contract;
+use crate::setup_contract::RolloverCheckpoint;
+use crate::setup_contract::RolloverTerms;
 use crate::wire;
 use anyhow::anyhow;
+use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
 use async_trait::async_trait;
@@ -14,12 +17,16 @@ use futures::channel::mpsc::UnboundedSender;
 use futures::future;
 use futures::SinkExt;
 use maia::secp256k1_zkp::schnorrsig;
+use model::olivia::Announcement;
 use model::olivia::BitMexPriceEventId;
+use model::Cfd;
 use model::Dlc;
 use model::FundingFee;
 use model::FundingRate;
 use model::OrderId;
+use model::Position;
 use model::Role;
+use model::RolloverVersion;
 use model::Timestamp;
 use model::TxFeeRate;
 use std::time::Duration;
@@ -33,15 +40,73 @@ use xtras::address_map::IPromiseIamReturningStopAllFromStopping;
 /// The maximum amount of time we give the maker to send us a response.
 const MAKER_RESPONSE_TIMEOUT: Duration = Duration::from_secs(30);
 
+/// Which terms the taker accepts when the maker confirms a rollover.
+///
+/// The maker may renegotiate the funding rate, the transaction fee rate of the new CETs and the
+/// number of payouts in every rollover. Rollovers with terms outside of these limits are declined.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Policy {
+    /// The maximum funding rate, regardless of which party pays it.
+    pub max_funding_rate: Option<FundingRate>,
+    pub max_tx_fee_rate: Option<TxFeeRate>,
+    pub min_n_payouts: Option<usize>,
+    pub max_n_payouts: Option<usize>,
+}
+
+impl Policy {
+    fn check(
+        &self,
+        tx_fee_rate: TxFeeRate,
+        funding_rate: FundingRate,
+        n_payouts: usize,
+    ) -> Result<()> {
+        if let Some(max) = self.max_funding_rate {
+            if funding_rate.to_decimal().abs() > max.to_decimal().abs() {
+                bail!(
+                    "Funding rate {} exceeds maximum of {}",
+                    funding_rate.to_decimal(),
+                    max.to_decimal()
+                );
+            }
+        }
+
+        if let Some(max) = self.max_tx_fee_rate {
+            if tx_fee_rate.to_u32() > max.to_u32() {
+                bail!("Transaction fee rate {tx_fee_rate} exceeds maximum of {max}");
+            }
+        }
+
+        if let Some(min) = self.min_n_payouts {
+            if n_payouts < min {
+                bail!("{n_payouts} payouts are less than the minimum of {min}");
+            }
+        }
+
+        if let Some(max) = self.max_n_payouts {
+            if n_payouts > max {
+                bail!("{n_payouts} payouts exceed the maximum of {max}");
+            }
+        }
+
+        Ok(())
+    }
+}
+
 pub struct Actor {
     id: OrderId,
     n_payouts: usize,
+    policy: Policy,
     oracle_pk: schnorrsig::PublicKey,
     maker: xtra::Address<connection::Actor>,
     get_announcement: Box<dyn MessageChannel<oracle::GetAnnouncement>>,
     rollover_msg_sender: Option<UnboundedSender<wire::RolloverMsg>>,
+    /// The rollover protocol version negotiated with the maker when proposing.
+    version: RolloverVersion,
+    db: db::Connection,
     executor: command::Executor,
     tasks: Tasks,
+    /// The checkpoint of a rollover which was interrupted by a restart.
+    resume: Option<RolloverCheckpoint>,
 }
 
 impl Actor {
@@ -49,6 +114,7 @@ impl Actor {
     pub fn new(
         id: OrderId,
         n_payouts: usize,
+        policy: Policy,
         oracle_pk: schnorrsig::PublicKey,
         maker: xtra::Address<connection::Actor>,
         get_announcement: &(impl MessageChannel<oracle::GetAnnouncement> + 'static),
@@ -58,12 +124,94 @@ impl Actor {
         Self {
             id,
             n_payouts,
+            policy,
+            oracle_pk,
+            maker,
+            get_announcement: get_announcement.clone_channel(),
+            rollover_msg_sender: None,
+            version: RolloverVersion::V2,
+            tasks: Tasks::default(),
+            executor: command::Executor::new(db.clone(), process_manager),
+            db,
+            resume: None,
+        }
+    }
+
+    /// Resume a rollover which was interrupted by a restart.
+    pub fn resume(
+        checkpoint: RolloverCheckpoint,
+        policy: Policy,
+        oracle_pk: schnorrsig::PublicKey,
+        maker: xtra::Address<connection::Actor>,
+        get_announcement: &(impl MessageChannel<oracle::GetAnnouncement> + 'static),
+        process_manager: xtra::Address<process_manager::Actor>,
+        db: db::Connection,
+    ) -> Self {
+        let params = checkpoint.terms.params;
+
+        Self {
+            id: checkpoint.order_id,
+            n_payouts: params.n_payouts,
+            policy,
             oracle_pk,
             maker,
             get_announcement: get_announcement.clone_channel(),
             rollover_msg_sender: None,
+            version: params.version,
             tasks: Tasks::default(),
-            executor: command::Executor::new(db, process_manager),
+            executor: command::Executor::new(db.clone(), process_manager),
+            db,
+            resume: Some(checkpoint),
+        }
+    }
+
+    async fn resume_rollover(
+        &mut self,
+        checkpoint: RolloverCheckpoint,
+        ctx: &mut xtra::Context<Self>,
+    ) -> Result<()> {
+        let order_id = self.id;
+        tracing::info!(%order_id, "Resuming rollover");
+
+        let resumed = match self.db.load_open_cfd::<Cfd>(order_id, ()).await {
+            Ok(cfd) => cfd.resume_rollover(),
+            Err(e) => Err(e),
+        };
+        let (dlc, position) = match resumed {
+            Ok(resumed) => resumed,
+            Err(e) => {
+                tracing::warn!(%order_id, "Discarding rollover checkpoint: {e:#}");
+                self.delete_checkpoint().await;
+
+                ctx.stop();
+                return Ok(());
+            }
+        };
+
+        let (sender, receiver) = mpsc::unbounded::<wire::RolloverMsg>();
+        self.rollover_msg_sender = Some(sender);
+
+        self.maker
+            .send(connection::RegisterRollover {
+                order_id,
+                address: ctx.address().expect("self to be alive"),
+            })
+            .await?;
+
+        let announcement = oracle::wait_for_announcement(
+            &*self.get_announcement,
+            checkpoint.terms.oracle_event_id,
+        )
+        .await?;
+
+        self.run_rollover(receiver, announcement, position, dlc, checkpoint, ctx);
+
+        Ok(())
+    }
+
+    async fn delete_checkpoint(&self) {
+        if let Err(e) = RolloverCheckpoint::delete(&self.db, self.id).await {
+            tracing::warn!(order_id = %self.id, "Failed to delete rollover checkpoint: {e:#}");
         }
     }
 
@@ -74,7 +222,8 @@ impl Actor {
 
         tracing::trace!(order_id=%self.id, "Proposing rollover");
 
-        self.maker
+        self.version = self
+            .maker
             .send(connection::ProposeRollover {
                 order_id: self.id,
                 timestamp: Timestamp::now(),
@@ -95,13 +244,16 @@ impl Actor {
             oracle_event_id,
             tx_fee_rate,
             funding_rate,
+            n_payouts,
         }: RolloverAccepted = msg;
         let order_id = self.id;
+        let version = self.version;
+        let n_payouts = n_payouts.unwrap_or(self.n_payouts);
 
         let (rollover_params, dlc, position) = self
             .executor
             .execute(self.id, |cfd| {
-                cfd.handle_rollover_accepted_taker(tx_fee_rate, funding_rate)
+                cfd.handle_rollover_accepted_taker(tx_fee_rate, funding_rate, n_payouts, version)
             })
             .await?;
 
@@ -119,7 +271,30 @@ impl Actor {
         // the spawned rollover task
         self.rollover_msg_sender = Some(sender);
 
-        let funding_fee = *rollover_params.funding_fee();
+        let checkpoint = RolloverCheckpoint::new(
+            order_id,
+            RolloverTerms {
+                oracle_event_id,
+                params: rollover_params,
+            },
+        );
+
+        self.run_rollover(receiver, announcement, position, dlc, checkpoint, ctx);
+
+        Ok(())
+    }
+
+    fn run_rollover(
+        &mut self,
+        receiver: mpsc::UnboundedReceiver<wire::RolloverMsg>,
+        announcement: Announcement,
+        position: Position,
+        dlc: Dlc,
+        checkpoint: RolloverCheckpoint,
+        ctx: &mut xtra::Context<Self>,
+    ) {
+        let order_id = self.id;
+        let funding_fee = *checkpoint.terms.params.funding_fee();
 
         let rollover_fut = setup_contract::roll_over(
             xtra::message_channel::MessageChannel::sink(&self.maker).with(move |msg| {
@@ -127,11 +302,10 @@ impl Actor {
             }),
             receiver,
             (self.oracle_pk, announcement),
-            rollover_params,
             Role::Taker,
             position,
             dlc,
-            self.n_payouts,
+            (self.db.clone(), checkpoint),
         );
 
         let this = ctx.address().expect("self to be alive");
@@ -144,8 +318,6 @@ impl Actor {
                     Err(error) => this.send(RolloverFailed { error }).await,
                 };
         });
-
-        Ok(())
     }
 
     async fn forward_protocol_msg(&mut self, msg: wire::RolloverMsg) -> Result<()> {
@@ -173,10 +345,29 @@ impl Actor {
         if let Err(e) = result {
             tracing::warn!(order_id = %self.id, "Failed to complete rollover: {:#}", e)
         }
+        self.delete_checkpoint().await;
 
         ctx.stop();
     }
 
+    /// Tell the maker that we do not accept the terms of the rollover.
+    async fn decline(&mut self, reason: anyhow::Error, ctx: &mut xtra::Context<Self>) {
+        let order_id = self.id;
+
+        tracing::info!(%order_id, "Declining rollover: {reason:#}");
+
+        if let Err(e) = self
+            .maker
+            .send(wire::TakerToMaker::DeclineRollover { order_id })
+            .await
+        {
+            tracing::warn!(%order_id, "Failed to decline rollover: {e:#}");
+        }
+
+        self.emit_reject(reason.context("Declined rollover terms"), ctx)
+            .await;
+    }
+
     async fn emit_reject(&mut self, reason: anyhow::Error, ctx: &mut xtra::Context<Self>) {
         if let Err(e) = self
             .executor
@@ -197,6 +388,7 @@ impl Actor {
         {
             tracing::warn!(order_id = %self.id, "{:#}", e)
         }
+        self.delete_checkpoint().await;
 
         ctx.stop();
     }
@@ -211,6 +403,14 @@ impl Actor {
 impl xtra::Actor for Actor {
     type Stop = ();
     async fn started(&mut self, ctx: &mut xtra::Context<Self>) {
+        if let Some(checkpoint) = self.resume.take() {
+            if let Err(error) = self.resume_rollover(checkpoint, ctx).await {
+                self.emit_fail(error, ctx).await;
+            }
+
+            return;
+        }
+
         let this = ctx.address().expect("self to be alive");
 
         if let Err(error) = self.propose(this).await {
@@ -251,6 +451,15 @@ impl Actor {
         msg: RolloverAccepted,
         ctx: &mut xtra::Context<Self>,
     ) {
+        let n_payouts = msg.n_payouts.unwrap_or(self.n_payouts);
+        if let Err(reason) = self
+            .policy
+            .check(msg.tx_fee_rate, msg.funding_rate, n_payouts)
+        {
+            self.decline(reason, ctx).await;
+            return;
+        }
+
         if let Err(error) = self.handle_confirmed(msg, ctx).await {
             self.emit_fail(error, ctx).await;
         }
@@ -320,6 +529,8 @@ pub struct RolloverAccepted {
     pub oracle_event_id: BitMexPriceEventId,
     pub tx_fee_rate: TxFeeRate,
     pub funding_rate: FundingRate,
+    /// `None` if the maker does not renegotiate the number of payouts.
+    pub n_payouts: Option<usize>,
 }
 
 /// Message sent from the `connection::Actor` to the
@@ -348,3 +559,45 @@ struct RolloverFailed {
 struct MakerResponseTimeoutReached {
     timeout: Duration,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn default_policy_accepts_any_terms() {
+        let policy = Policy::default();
+
+        let result = policy.check(
+            TxFeeRate::new(100),
+            FundingRate::new(dec!(-0.5)).unwrap(),
+            1000,
+        );
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn policy_declines_terms_outside_of_limits() {
+        let policy = Policy {
+            max_funding_rate: Some(FundingRate::new(dec!(0.001)).unwrap()),
+            max_tx_fee_rate: Some(TxFeeRate::new(10)),
+            min_n_payouts: Some(100),
+            max_n_payouts: Some(200),
+        };
+        let funding_rate = FundingRate::new(dec!(-0.0005)).unwrap();
+
+        assert!(policy.check(TxFeeRate::new(10), funding_rate, 200).is_ok());
+        assert!(policy
+            .check(
+                TxFeeRate::new(10),
+                FundingRate::new(dec!(-0.002)).unwrap(),
+                200
+            )
+            .is_err());
+        assert!(policy.check(TxFeeRate::new(11), funding_rate, 200).is_err());
+        assert!(policy.check(TxFeeRate::new(10), funding_rate, 99).is_err());
+        assert!(policy.check(TxFeeRate::new(10), funding_rate, 201).is_err());
+    }
+}
@@ -52,6 +52,26 @@ pub trait Seed {
         P::from(password)
     }
 
+    fn derive_backup_key<K: From<[u8; 32]>>(&self) -> K {
+        let mut key = [0u8; 32];
+
+        Hkdf::<Sha256>::new(None, &self.seed())
+            .expand(b"BACKUP_ENCRYPTION_KEY", &mut key)
+            .expect("okm array is of correct length");
+
+        K::from(key)
+    }
+
+    fn derive_db_encryption_key<K: From<[u8; 32]>>(&self) -> K {
+        let mut key = [0u8; 32];
+
+        Hkdf::<Sha256>::new(None, &self.seed())
+            .expand(b"DB_ENCRYPTION_KEY", &mut key)
+            .expect("okm array is of correct length");
+
+        K::from(key)
+    }
+
     fn derive_identity(&self) -> (x25519_dalek::PublicKey, x25519_dalek::StaticSecret) {
         let mut secret = [0u8; 32];
 
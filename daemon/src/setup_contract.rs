@@ -1,3 +1,4 @@
+use crate::db;
 use crate::future_ext::FutureExt;
 use crate::transaction_ext::TransactionExt;
 use crate::wallet;
@@ -14,6 +15,7 @@ use crate::wire::SetupMsg;
 use anyhow::Context;
 use anyhow::Result;
 use bdk::bitcoin::secp256k1::schnorrsig;
+use bdk::bitcoin::secp256k1::SecretKey;
 use bdk::bitcoin::secp256k1::Signature;
 use bdk::bitcoin::secp256k1::SECP256K1;
 use bdk::bitcoin::util::psbt::PartiallySignedTransaction;
@@ -41,76 +43,107 @@ use maia::PartyParams;
 use maia::PunishParams;
 use model::calculate_payouts;
 use model::olivia;
+use model::olivia::BitMexPriceEventId;
 use model::Cet;
 use model::Dlc;
+use model::OrderId;
 use model::Position;
 use model::RevokedCommit;
 use model::Role;
 use model::RolloverParams;
 use model::RolloverVersion;
 use model::SetupParams;
+use model::Timestamp;
 use model::CET_TIMELOCK;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde::Serialize;
 use std::collections::HashMap;
 use std::iter::FromIterator;
 use std::ops::RangeInclusive;
 use std::time::Duration;
 use xtra::prelude::MessageChannel;
 
-/// How long contract setup protocol waits for the next message before giving up
+/// How long contract setup protocol waits for the next message before re-sending our latest
+/// messages
 ///
 /// 120s are currently needed to ensure that we can outlive times when the maker/taker are under
-/// heavy message load. Failed contract setups are annoying compared to failed rollovers so we allow
-/// more time to see them less often.
+/// heavy message load.
 const CONTRACT_SETUP_MSG_TIMEOUT: Duration = Duration::from_secs(120);
 
-/// How long rollover protocol waits for the next message before giving up
+/// How long contract setup protocol may take, including resuming after the connection dropped,
+/// before giving up
 ///
-/// 60s timeout are acceptable here because rollovers are automatically retried; a few failed
-/// rollovers are not a big deal.
+/// Failed contract setups are annoying compared to failed rollovers so we allow more time to see
+/// them less often.
+const CONTRACT_SETUP_DEADLINE: Duration = Duration::from_secs(10 * 60);
+
+/// How long rollover protocol waits for the next message before re-sending our latest messages
 const ROLLOVER_MSG_TIMEOUT: Duration = Duration::from_secs(60);
 
+/// How long rollover protocol may take, including resuming after the connection dropped, before
+/// giving up
+///
+/// 5 minutes are acceptable here because rollovers are automatically retried; a few failed
+/// rollovers are not a big deal.
+const ROLLOVER_DEADLINE: Duration = Duration::from_secs(5 * 60);
+
 /// Given an initial set of parameters, sets up the CFD contract with
 /// the other party.
 #[allow(clippy::too_many_arguments)]
 pub async fn new(
-    mut sink: impl Sink<SetupMsg, Error = anyhow::Error> + Unpin,
-    mut stream: impl FusedStream<Item = SetupMsg> + Unpin,
+    sink: impl Sink<SetupMsg, Error = anyhow::Error> + Unpin,
+    stream: impl FusedStream<Item = SetupMsg> + Unpin,
     (oracle_pk, announcement): (schnorrsig::PublicKey, olivia::Announcement),
     setup_params: SetupParams,
     build_party_params_channel: Box<dyn MessageChannel<wallet::BuildPartyParams>>,
     sign_channel: Box<dyn MessageChannel<wallet::Sign>>,
     role: Role,
     position: Position,
-    n_payouts: usize,
+    (db, checkpoint): (db::Connection, SetupCheckpoint),
 ) -> Result<Dlc> {
-    let (sk, pk) = keypair::new(&mut rand::thread_rng());
-    let (rev_sk, rev_pk) = keypair::new(&mut rand::thread_rng());
-    let (publish_sk, publish_pk) = keypair::new(&mut rand::thread_rng());
-
-    let own_params = build_party_params_channel
-        .send(wallet::BuildPartyParams {
-            amount: setup_params.margin,
-            identity_pk: pk,
-            fee_rate: setup_params.tx_fee_rate,
-        })
-        .await
-        .context("Failed to send message to wallet actor")?
-        .context("Failed to build party params")?;
+    let n_payouts = checkpoint.terms.n_payouts;
+
+    let mut checkpoints = Checkpoints::new(
+        sink,
+        stream,
+        (db, checkpoint),
+        CONTRACT_SETUP_MSG_TIMEOUT,
+        CONTRACT_SETUP_DEADLINE,
+    );
 
-    let own_punish = PunishParams {
-        revocation_pk: rev_pk,
-        publish_pk,
+    let Keys {
+        identity: sk,
+        revocation: rev_sk,
+        publish: publish_sk,
+    } = checkpoints.keys();
+
+    // Our party params are only built once, building them again after a restart would select
+    // different UTXOs for the lock transaction
+    let own_msg0 = match checkpoints.sent(0) {
+        Some(msg0) => msg0.clone(),
+        None => {
+            let own_params = build_party_params_channel
+                .send(wallet::BuildPartyParams {
+                    amount: setup_params.margin,
+                    identity_pk: public_key(&sk),
+                    fee_rate: setup_params.tx_fee_rate,
+                })
+                .await
+                .context("Failed to send message to wallet actor")?
+                .context("Failed to build party params")?;
+
+            let own_punish = PunishParams {
+                revocation_pk: public_key(&rev_sk),
+                publish_pk: public_key(&publish_sk),
+            };
+
+            SetupMsg::Msg0(Msg0::from((own_params, own_punish)))
+        }
     };
 
-    sink.send(SetupMsg::Msg0(Msg0::from((own_params.clone(), own_punish))))
-        .await
-        .context("Failed to send Msg0")?;
-    let msg0 = stream
-        .select_next_some()
-        .timeout(CONTRACT_SETUP_MSG_TIMEOUT)
-        .await
-        .with_context(|| format_expect_msg_within("Msg0", CONTRACT_SETUP_MSG_TIMEOUT))?
-        .try_into_msg0()?;
+    let (own_params, own_punish) = checkpoints.send(own_msg0).await?.try_into_msg0()?.into();
+    let msg0 = checkpoints.recv("Msg0").await?.try_into_msg0()?;
 
     tracing::info!("Exchanged setup parameters");
 
@@ -165,16 +198,10 @@ pub async fn new(
 
     tracing::info!("Created CFD transactions");
 
-    sink.send(SetupMsg::Msg1(Msg1::from(own_cfd_txs.clone())))
-        .await
-        .context("Failed to send Msg1")?;
-
-    let msg1 = stream
-        .select_next_some()
-        .timeout(CONTRACT_SETUP_MSG_TIMEOUT)
-        .await
-        .with_context(|| format_expect_msg_within("Msg1", CONTRACT_SETUP_MSG_TIMEOUT))?
-        .try_into_msg1()?;
+    checkpoints
+        .send(SetupMsg::Msg1(Msg1::from(own_cfd_txs.clone())))
+        .await?;
+    let msg1 = checkpoints.recv("Msg1").await?.try_into_msg1()?;
 
     tracing::info!("Exchanged CFD transactions");
 
@@ -243,22 +270,26 @@ pub async fn new(
 
     tracing::info!("Verified all signatures");
 
-    let mut signed_lock_tx = sign_channel
-        .send(wallet::Sign { psbt: lock_tx })
-        .await
-        .context("Failed to send message to wallet actor")?
-        .context("Failed to sign transaction")?;
-    sink.send(SetupMsg::Msg2(Msg2 {
-        signed_lock: signed_lock_tx.clone(),
-    }))
-    .await
-    .context("Failed to send Msg2")?;
-    let msg2 = stream
-        .select_next_some()
-        .timeout(CONTRACT_SETUP_MSG_TIMEOUT)
-        .await
-        .with_context(|| format_expect_msg_within("Msg2", CONTRACT_SETUP_MSG_TIMEOUT))?
-        .try_into_msg2()?;
+    let own_msg2 = match checkpoints.sent(2) {
+        Some(msg2) => msg2.clone(),
+        None => {
+            let signed_lock_tx = sign_channel
+                .send(wallet::Sign { psbt: lock_tx })
+                .await
+                .context("Failed to send message to wallet actor")?
+                .context("Failed to sign transaction")?;
+
+            SetupMsg::Msg2(Msg2 {
+                signed_lock: signed_lock_tx,
+            })
+        }
+    };
+    let mut signed_lock_tx = checkpoints
+        .send(own_msg2)
+        .await?
+        .try_into_msg2()?
+        .signed_lock;
+    let msg2 = checkpoints.recv("Msg2").await?.try_into_msg2()?;
     signed_lock_tx
         .merge(msg2.signed_lock)
         .context("Failed to merge lock PSBTs")?;
@@ -318,15 +349,8 @@ pub async fn new(
 
     // TODO: Remove send- and receiving ACK messages once we are able to handle incomplete DLC
     // monitoring
-    sink.send(SetupMsg::Msg3(Msg3))
-        .await
-        .context("Failed to send Msg3")?;
-    let _ = stream
-        .select_next_some()
-        .timeout(CONTRACT_SETUP_MSG_TIMEOUT)
-        .await
-        .with_context(|| format_expect_msg_within("Msg3", CONTRACT_SETUP_MSG_TIMEOUT))?
-        .try_into_msg3()?;
+    checkpoints.send(SetupMsg::Msg3(Msg3)).await?;
+    let _ = checkpoints.recv("Msg3").await?.try_into_msg3()?;
 
     Ok(Dlc {
         identity: sk,
@@ -349,40 +373,48 @@ pub async fn new(
     })
 }
 
-#[allow(clippy::too_many_arguments)]
 pub async fn roll_over(
-    mut sink: impl Sink<RolloverMsg, Error = anyhow::Error> + Unpin,
-    mut stream: impl FusedStream<Item = RolloverMsg> + Unpin,
+    sink: impl Sink<RolloverMsg, Error = anyhow::Error> + Unpin,
+    stream: impl FusedStream<Item = RolloverMsg> + Unpin,
     (oracle_pk, announcement): (schnorrsig::PublicKey, olivia::Announcement),
-    rollover_params: RolloverParams,
     our_role: Role,
     our_position: Position,
     dlc: Dlc,
-    n_payouts: usize,
+    (db, checkpoint): (db::Connection, RolloverCheckpoint),
 ) -> Result<Dlc> {
+    let rollover_params = checkpoint.terms.params;
+
     let sk = dlc.identity;
-    let pk = PublicKey::new(secp256k1_zkp::PublicKey::from_secret_key(SECP256K1, &sk));
+    let pk = public_key(&sk);
+
+    let mut checkpoints = Checkpoints::new(
+        sink,
+        stream,
+        (db, checkpoint),
+        ROLLOVER_MSG_TIMEOUT,
+        ROLLOVER_DEADLINE,
+    );
 
-    let (rev_sk, rev_pk) = keypair::new(&mut rand::thread_rng());
-    let (publish_sk, publish_pk) = keypair::new(&mut rand::thread_rng());
+    let Keys {
+        revocation: rev_sk,
+        publish: publish_sk,
+        ..
+    } = checkpoints.keys();
+    let rev_pk = public_key(&rev_sk);
+    let publish_pk = public_key(&publish_sk);
 
     let own_punish = PunishParams {
         revocation_pk: rev_pk,
         publish_pk,
     };
 
-    sink.send(RolloverMsg::Msg0(RolloverMsg0 {
-        revocation_pk: rev_pk,
-        publish_pk,
-    }))
-    .await
-    .context("Failed to send Msg0")?;
-    let msg0 = stream
-        .select_next_some()
-        .timeout(ROLLOVER_MSG_TIMEOUT)
-        .await
-        .with_context(|| format_expect_msg_within("Msg0", ROLLOVER_MSG_TIMEOUT))?
-        .try_into_msg0()?;
+    checkpoints
+        .send(RolloverMsg::Msg0(RolloverMsg0 {
+            revocation_pk: rev_pk,
+            publish_pk,
+        }))
+        .await?;
+    let msg0 = checkpoints.recv("Msg0").await?.try_into_msg0()?;
 
     let complete_fee = match rollover_params.version {
         RolloverVersion::V1 => {
@@ -416,7 +448,7 @@ pub async fn roll_over(
             rollover_params.quantity,
             rollover_params.long_leverage,
             rollover_params.short_leverage,
-            n_payouts,
+            rollover_params.n_payouts,
             complete_fee,
         )?,
     )]);
@@ -475,16 +507,13 @@ pub async fn roll_over(
     .await?
     .context("Failed to create new CFD transactions")?;
 
-    sink.send(RolloverMsg::Msg1(RolloverMsg1::from(own_cfd_txs.clone())))
-        .await
-        .context("Failed to send Msg1")?;
-
-    let msg1 = stream
-        .select_next_some()
-        .timeout(ROLLOVER_MSG_TIMEOUT)
-        .await
-        .with_context(|| format_expect_msg_within("Msg1", ROLLOVER_MSG_TIMEOUT))?
+    // Our adaptor signature of the commit transaction is needed to punish the counterparty, hence
+    // we keep the one we actually sent
+    let own_msg1 = checkpoints
+        .send(RolloverMsg::Msg1(RolloverMsg1::from(own_cfd_txs.clone())))
+        .await?
         .try_into_msg1()?;
+    let msg1 = checkpoints.recv("Msg1").await?.try_into_msg1()?;
 
     let lock_amount = taker_lock_amount + maker_lock_amount;
 
@@ -606,18 +635,12 @@ pub async fn roll_over(
         .collect::<Result<HashMap<_, _>>>()?;
 
     // reveal revocation secrets to the other party
-    sink.send(RolloverMsg::Msg2(RolloverMsg2 {
-        revocation_sk: dlc.revocation,
-    }))
-    .await
-    .context("Failed to send Msg2")?;
-
-    let msg2 = stream
-        .select_next_some()
-        .timeout(ROLLOVER_MSG_TIMEOUT)
-        .await
-        .with_context(|| format_expect_msg_within("Msg2", ROLLOVER_MSG_TIMEOUT))?
-        .try_into_msg2()?;
+    checkpoints
+        .send(RolloverMsg::Msg2(RolloverMsg2 {
+            revocation_sk: dlc.revocation,
+        }))
+        .await?;
+    let msg2 = checkpoints.recv("Msg2").await?.try_into_msg2()?;
     let revocation_sk_theirs = msg2.revocation_sk;
 
     {
@@ -633,7 +656,7 @@ pub async fn roll_over(
 
     let mut revoked_commit = dlc.revoked_commit;
     revoked_commit.push(RevokedCommit {
-        encsig_ours: own_cfd_txs.commit.1,
+        encsig_ours: own_msg1.commit,
         revocation_sk_theirs,
         publication_pk_theirs: dlc.publish_pk_counterparty,
         txid: dlc.commit.0.txid(),
@@ -642,15 +665,8 @@ pub async fn roll_over(
 
     // TODO: Remove send- and receiving ACK messages once we are able to handle incomplete DLC
     // monitoring
-    sink.send(RolloverMsg::Msg3(RolloverMsg3))
-        .await
-        .context("Failed to send Msg3")?;
-    let _ = stream
-        .select_next_some()
-        .timeout(ROLLOVER_MSG_TIMEOUT)
-        .await
-        .with_context(|| format_expect_msg_within("Msg3", ROLLOVER_MSG_TIMEOUT))?
-        .try_into_msg3()?;
+    checkpoints.send(RolloverMsg::Msg3(RolloverMsg3)).await?;
+    let _ = checkpoints.recv("Msg3").await?.try_into_msg3()?;
 
     Ok(Dlc {
         identity: sk,
@@ -825,3 +841,450 @@ fn format_expect_msg_within(msg: &str, timeout: Duration) -> String {
 
     format!("Expected {msg} within {seconds} seconds")
 }
+
+fn public_key(sk: &SecretKey) -> PublicKey {
+    PublicKey::new(secp256k1_zkp::PublicKey::from_secret_key(SECP256K1, sk))
+}
+
+/// A message of a protocol in which both parties send one message per step.
+pub trait ProtocolMsg: Clone + Serialize + DeserializeOwned {
+    /// The name under which the checkpoints of the protocol are stored.
+    const PROTOCOL: &'static str;
+
+    /// The step of the protocol this message belongs to, starting at 0 for `Msg0`.
+    fn step(&self) -> usize;
+}
+
+impl ProtocolMsg for SetupMsg {
+    const PROTOCOL: &'static str = "contract_setup";
+
+    fn step(&self) -> usize {
+        match self {
+            SetupMsg::Msg0(_) => 0,
+            SetupMsg::Msg1(_) => 1,
+            SetupMsg::Msg2(_) => 2,
+            SetupMsg::Msg3(_) => 3,
+        }
+    }
+}
+
+impl ProtocolMsg for RolloverMsg {
+    const PROTOCOL: &'static str = "rollover";
+
+    fn step(&self) -> usize {
+        match self {
+            RolloverMsg::Msg0(_) => 0,
+            RolloverMsg::Msg1(_) => 1,
+            RolloverMsg::Msg2(_) => 2,
+            RolloverMsg::Msg3(_) => 3,
+        }
+    }
+}
+
+pub type SetupCheckpoint = Checkpoint<SetupTerms, SetupMsg>;
+pub type RolloverCheckpoint = Checkpoint<RolloverTerms, RolloverMsg>;
+
+/// The terms of a contract setup which cannot be derived from the CFD.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SetupTerms {
+    pub oracle_event_id: BitMexPriceEventId,
+    pub n_payouts: usize,
+}
+
+/// The terms of a rollover, agreed upon when the rollover was accepted.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct RolloverTerms {
+    pub oracle_event_id: BitMexPriceEventId,
+    pub params: RolloverParams,
+}
+
+/// The state of a run of a protocol.
+///
+/// It is stored in the database whenever we send or receive a message, so that the protocol can be
+/// resumed with the same keys and messages after a restart. The checkpoint has to be deleted once
+/// the protocol finished, regardless of whether it succeeded.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Checkpoint<T, M> {
+    pub order_id: OrderId,
+    pub terms: T,
+    keys: Keys,
+    sent: Vec<M>,
+    received: Vec<M>,
+    started_at: Timestamp,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct Keys {
+    /// Only used in contract setup, rollovers keep the identity of the DLC.
+    identity: SecretKey,
+    revocation: SecretKey,
+    publish: SecretKey,
+}
+
+impl<T, M> Checkpoint<T, M>
+where
+    T: Serialize + DeserializeOwned,
+    M: ProtocolMsg,
+{
+    /// Start a new run of the protocol with fresh keys.
+    pub fn new(order_id: OrderId, terms: T) -> Self {
+        Self {
+            order_id,
+            terms,
+            keys: Keys {
+                identity: keypair::new(&mut rand::thread_rng()).0,
+                revocation: keypair::new(&mut rand::thread_rng()).0,
+                publish: keypair::new(&mut rand::thread_rng()).0,
+            },
+            sent: Vec::new(),
+            received: Vec::new(),
+            started_at: Timestamp::now(),
+        }
+    }
+
+    /// Load the checkpoints of all runs of the protocol which were interrupted by a shutdown.
+    pub async fn load_all(db: &db::Connection) -> Result<Vec<Self>> {
+        db.load_protocol_checkpoints(M::PROTOCOL).await
+    }
+
+    pub async fn delete(db: &db::Connection, order_id: OrderId) -> Result<()> {
+        db.delete_protocol_checkpoint(order_id, M::PROTOCOL).await
+    }
+
+    async fn store(&self, db: &db::Connection) -> Result<()> {
+        db.store_protocol_checkpoint(self.order_id, M::PROTOCOL, self)
+            .await
+            .context("Failed to store checkpoint")
+    }
+}
+
+/// Exchanges the messages of a protocol, resuming it if messages got lost.
+///
+/// In every step, each party first sends its message and then waits for the message of the
+/// counterparty. All messages we sent and received are kept as a [`Checkpoint`] in the database.
+/// Hence, if the connection drops and messages get lost, the protocol does not fail but resumes
+/// once the connection is re-established. If we restart, the protocol is run again from the
+/// checkpoint: messages we already exchanged are not exchanged again, neither are UTXOs
+/// re-selected nor new keys generated.
+///
+/// Since a party only advances after receiving the message of the current step, the parties are
+/// at most one step apart. If we do not receive a message within the message timeout, we thus
+/// re-send our messages of the current and the previous step. A message of the next step is kept
+/// until we get there, messages of past steps are duplicates caused by re-sending and are ignored.
+/// The protocol fails once the deadline passes, counting from when it was first started.
+struct Checkpoints<Si, St, T, M> {
+    sink: Si,
+    stream: St,
+    db: db::Connection,
+    checkpoint: Checkpoint<T, M>,
+    /// The number of messages we sent in this run of the protocol.
+    step: usize,
+    /// A message of the counterparty which arrived before we reached its step.
+    early: Option<M>,
+    msg_timeout: Duration,
+    deadline: Duration,
+    started_at: tokio::time::Instant,
+}
+
+impl<Si, St, T, M> Checkpoints<Si, St, T, M>
+where
+    Si: Sink<M, Error = anyhow::Error> + Unpin,
+    St: FusedStream<Item = M> + Unpin,
+    T: Serialize + DeserializeOwned,
+    M: ProtocolMsg,
+{
+    fn new(
+        sink: Si,
+        stream: St,
+        (db, checkpoint): (db::Connection, Checkpoint<T, M>),
+        msg_timeout: Duration,
+        deadline: Duration,
+    ) -> Self {
+        let elapsed = (Timestamp::now().seconds() - checkpoint.started_at.seconds()).max(0);
+        let now = tokio::time::Instant::now();
+        let started_at = now
+            .checked_sub(Duration::from_secs(elapsed as u64))
+            .unwrap_or(now);
+
+        Self {
+            sink,
+            stream,
+            db,
+            checkpoint,
+            step: 0,
+            early: None,
+            msg_timeout,
+            deadline,
+            started_at,
+        }
+    }
+
+    fn keys(&self) -> Keys {
+        self.checkpoint.keys
+    }
+
+    /// Our message of the given step, if we sent it before a restart.
+    fn sent(&self, step: usize) -> Option<&M> {
+        self.checkpoint.sent.get(step)
+    }
+
+    /// Send our message of the next step.
+    ///
+    /// If we already sent a message of this step before a restart, that message is sent again
+    /// instead, so that the counterparty does not get two different messages for the same step.
+    /// Returns the message which was sent.
+    ///
+    /// Failing to send is not fatal, the message is re-sent if the counterparty does not respond.
+    async fn send(&mut self, msg: M) -> Result<M> {
+        debug_assert_eq!(msg.step(), self.step, "Steps must be sent in order");
+
+        let msg = match self.checkpoint.sent.get(self.step) {
+            Some(sent) => sent.clone(),
+            None => {
+                self.checkpoint.sent.push(msg.clone());
+                self.checkpoint.store(&self.db).await?;
+
+                msg
+            }
+        };
+        self.step += 1;
+
+        if let Err(e) = self.sink.send(msg.clone()).await {
+            tracing::debug!("Failed to send message, re-sending it later: {e:#}");
+        }
+
+        Ok(msg)
+    }
+
+    /// Receive the counterparty's message of the current step.
+    async fn recv(&mut self, name: &str) -> Result<M> {
+        let step = self
+            .step
+            .checked_sub(1)
+            .context("Cannot receive before sending")?;
+        let deadline = self.started_at + self.deadline;
+
+        if let Some(msg) = self.checkpoint.received.get(step) {
+            return Ok(msg.clone());
+        }
+
+        if let Some(msg) = self.early.take() {
+            if msg.step() == step {
+                return self.received(msg).await;
+            }
+        }
+
+        loop {
+            let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+            if remaining.is_zero() {
+                anyhow::bail!(format_expect_msg_within(name, self.deadline));
+            }
+
+            match self
+                .stream
+                .select_next_some()
+                .timeout(self.msg_timeout.min(remaining))
+                .await
+            {
+                Ok(msg) if msg.step() == step => return self.received(msg).await,
+                Ok(msg) if msg.step() == step + 1 => {
+                    self.early = Some(msg);
+                }
+                Ok(msg) => {
+                    tracing::trace!(
+                        "Ignoring message of step {} while waiting for {name}",
+                        msg.step()
+                    );
+                }
+                Err(_) => {
+                    tracing::debug!("Did not receive {name} in time, re-sending our messages");
+
+                    self.resend(step.saturating_sub(1)).await;
+                }
+            }
+        }
+    }
+
+    async fn received(&mut self, msg: M) -> Result<M> {
+        self.checkpoint.received.push(msg.clone());
+        self.checkpoint.store(&self.db).await?;
+
+        Ok(msg)
+    }
+
+    async fn resend(&mut self, from_step: usize) {
+        for msg in self.checkpoint.sent[from_step..self.step].to_vec() {
+            if let Err(e) = self.sink.send(msg).await {
+                tracing::debug!("Failed to re-send message: {e:#}");
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::channel::mpsc;
+    use futures::future;
+    use std::pin::Pin;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+    struct DummyMsg(usize);
+
+    impl ProtocolMsg for DummyMsg {
+        const PROTOCOL: &'static str = "dummy";
+
+        fn step(&self) -> usize {
+            self.0
+        }
+    }
+
+    const NUM_STEPS: usize = 4;
+
+    #[tokio::test]
+    async fn protocol_resumes_after_messages_got_lost() {
+        let (a_to_b, b_from_a) = mpsc::unbounded();
+        let (b_to_a, a_from_b) = mpsc::unbounded();
+
+        let a = Checkpoints::new(
+            lossy_sink(a_to_b, &[0, 2]),
+            a_from_b,
+            (
+                db::memory().await.unwrap(),
+                Checkpoint::new(OrderId::default(), ()),
+            ),
+            Duration::from_millis(20),
+            Duration::from_secs(10),
+        );
+        let b = Checkpoints::new(
+            lossy_sink(b_to_a, &[1, 2]),
+            b_from_a,
+            (
+                db::memory().await.unwrap(),
+                Checkpoint::new(OrderId::default(), ()),
+            ),
+            Duration::from_millis(20),
+            Duration::from_secs(10),
+        );
+
+        let (a, b) = future::join(run(a), run(b)).await;
+
+        assert_eq!(a.unwrap(), (0..NUM_STEPS).map(DummyMsg).collect::<Vec<_>>());
+        assert_eq!(b.unwrap(), (0..NUM_STEPS).map(DummyMsg).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn protocol_fails_after_deadline() {
+        let (a_to_b, _b_from_a) = mpsc::unbounded();
+        let (_b_to_a, a_from_b) = mpsc::unbounded::<DummyMsg>();
+
+        let mut a = Checkpoints::new(
+            lossy_sink(a_to_b, &[]),
+            a_from_b,
+            (
+                db::memory().await.unwrap(),
+                Checkpoint::new(OrderId::default(), ()),
+            ),
+            Duration::from_millis(20),
+            Duration::from_millis(100),
+        );
+
+        a.send(DummyMsg(0)).await.unwrap();
+        let result = a.recv("Msg0").await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn protocol_resumes_from_stored_checkpoint() {
+        let db = db::memory().await.unwrap();
+        let order_id = OrderId::default();
+
+        let (a_to_b, _b_from_a) = mpsc::unbounded();
+        let (b_to_a, a_from_b) = mpsc::unbounded();
+        let mut a = Checkpoints::new(
+            lossy_sink(a_to_b, &[]),
+            a_from_b,
+            (db.clone(), Checkpoint::new(order_id, ())),
+            Duration::from_millis(20),
+            Duration::from_secs(10),
+        );
+        a.send(DummyMsg(0)).await.unwrap();
+        b_to_a.unbounded_send(DummyMsg(0)).unwrap();
+        a.recv("Msg0").await.unwrap();
+        a.send(DummyMsg(1)).await.unwrap();
+        drop(a);
+
+        let mut checkpoints = DummyCheckpoint::load_all(&db).await.unwrap();
+        assert_eq!(checkpoints.len(), 1);
+        let checkpoint = checkpoints.remove(0);
+        assert_eq!(checkpoint.order_id, order_id);
+
+        let (a_to_b, b_from_a) = mpsc::unbounded();
+        let (b_to_a, a_from_b) = mpsc::unbounded();
+        let a = Checkpoints::new(
+            lossy_sink(a_to_b, &[]),
+            a_from_b,
+            (db.clone(), checkpoint),
+            Duration::from_millis(20),
+            Duration::from_secs(10),
+        );
+        let b = Checkpoints::new(
+            lossy_sink(b_to_a, &[]),
+            b_from_a,
+            (db::memory().await.unwrap(), Checkpoint::new(order_id, ())),
+            Duration::from_millis(20),
+            Duration::from_secs(10),
+        );
+
+        let (a, b) = future::join(run(a), run(b)).await;
+
+        assert_eq!(a.unwrap(), (0..NUM_STEPS).map(DummyMsg).collect::<Vec<_>>());
+        assert_eq!(b.unwrap(), (0..NUM_STEPS).map(DummyMsg).collect::<Vec<_>>());
+
+        DummyCheckpoint::delete(&db, order_id).await.unwrap();
+        assert!(DummyCheckpoint::load_all(&db).await.unwrap().is_empty());
+    }
+
+    type DummyCheckpoint = Checkpoint<(), DummyMsg>;
+
+    async fn run<Si, St>(
+        mut checkpoints: Checkpoints<Si, St, (), DummyMsg>,
+    ) -> Result<Vec<DummyMsg>>
+    where
+        Si: Sink<DummyMsg, Error = anyhow::Error> + Unpin,
+        St: FusedStream<Item = DummyMsg> + Unpin,
+    {
+        let mut received = Vec::new();
+
+        for step in 0..NUM_STEPS {
+            checkpoints.send(DummyMsg(step)).await?;
+            received.push(checkpoints.recv(&format!("Msg{step}")).await?);
+        }
+
+        Ok(received)
+    }
+
+    /// A sink which drops the first message of each of the given steps.
+    fn lossy_sink(
+        sender: mpsc::UnboundedSender<DummyMsg>,
+        lost_steps: &[usize],
+    ) -> Pin<Box<impl Sink<DummyMsg, Error = anyhow::Error>>> {
+        let lost_steps = lost_steps.to_vec();
+
+        Box::pin(futures::sink::unfold(
+            (sender, lost_steps),
+            |(sender, mut lost_steps), msg: DummyMsg| async move {
+                if let Some(i) = lost_steps.iter().position(|step| *step == msg.step()) {
+                    lost_steps.remove(i);
+                    return Ok((sender, lost_steps));
+                }
+
+                sender.unbounded_send(msg)?;
+
+                Ok((sender, lost_steps))
+            },
+        ))
+    }
+}
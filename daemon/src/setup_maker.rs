@@ -3,6 +3,8 @@ use crate::db;
 use crate::maker_inc_connections;
 use crate::process_manager;
 use crate::setup_contract;
+use crate::setup_contract::SetupCheckpoint;
+use crate::setup_contract::SetupTerms;
 use crate::wallet;
 use crate::wire;
 use anyhow::Context;
@@ -14,10 +16,14 @@ use futures::future;
 use futures::SinkExt;
 use maia::secp256k1_zkp::schnorrsig;
 use model::olivia::Announcement;
+use model::Cfd;
 use model::Dlc;
 use model::Identity;
 use model::Order;
+use model::OrderId;
+use model::Position;
 use model::Role;
+use model::SetupParams;
 use model::Usd;
 use tokio_tasks::Tasks;
 use xtra::prelude::MessageChannel;
@@ -27,8 +33,8 @@ use xtras::address_map::IPromiseIamReturningStopAllFromStopping;
 use xtras::LogFailure;
 
 pub struct Actor {
-    order: Order,
-    quantity: Usd,
+    order_id: OrderId,
+    start: Option<Start>,
     n_payouts: usize,
     oracle_pk: schnorrsig::PublicKey,
     announcement: Announcement,
@@ -39,9 +45,20 @@ pub struct Actor {
     taker_id: Identity,
     setup_msg_sender: Option<UnboundedSender<wire::SetupMsg>>,
     tasks: Tasks,
+    db: db::Connection,
+    register_setup: Box<dyn MessageChannel<maker_inc_connections::RegisterSetup>>,
     executor: command::Executor,
 }
 
+/// How the contract setup gets started.
+enum Start {
+    /// Check the taker's order and wait for the maker to decide on it.
+    TakeOrder { order: Order, quantity: Usd },
+    /// Run the protocol from the checkpoint of a contract setup which was interrupted by a
+    /// restart.
+    Resume(SetupCheckpoint),
+}
+
 impl Actor {
     #[allow(clippy::too_many_arguments)]
     pub fn new(
@@ -51,16 +68,18 @@ impl Actor {
         (oracle_pk, announcement): (schnorrsig::PublicKey, Announcement),
         build_party_params: &(impl MessageChannel<wallet::BuildPartyParams> + 'static),
         sign: &(impl MessageChannel<wallet::Sign> + 'static),
-        (taker, confirm_order, taker_id): (
+        (taker, confirm_order, register_setup, taker_id): (
             &(impl MessageChannel<maker_inc_connections::TakerMessage> + 'static),
             &(impl MessageChannel<maker_inc_connections::ConfirmOrder> + 'static),
+            &(impl MessageChannel<maker_inc_connections::RegisterSetup> + 'static),
             Identity,
         ),
     ) -> Self {
         Self {
-            executor: command::Executor::new(db, process_manager),
-            order,
-            quantity,
+            executor: command::Executor::new(db.clone(), process_manager),
+            db,
+            order_id: order.id,
+            start: Some(Start::TakeOrder { order, quantity }),
             n_payouts,
             oracle_pk,
             announcement,
@@ -68,6 +87,42 @@ impl Actor {
             sign: sign.clone_channel(),
             taker: taker.clone_channel(),
             confirm_order: confirm_order.clone_channel(),
+            register_setup: register_setup.clone_channel(),
+            taker_id,
+            setup_msg_sender: None,
+            tasks: Tasks::default(),
+        }
+    }
+
+    /// Resume a contract setup which was interrupted by a restart.
+    #[allow(clippy::too_many_arguments)]
+    pub fn resume(
+        db: db::Connection,
+        process_manager: xtra::Address<process_manager::Actor>,
+        checkpoint: SetupCheckpoint,
+        (oracle_pk, announcement): (schnorrsig::PublicKey, Announcement),
+        build_party_params: &(impl MessageChannel<wallet::BuildPartyParams> + 'static),
+        sign: &(impl MessageChannel<wallet::Sign> + 'static),
+        (taker, confirm_order, register_setup, taker_id): (
+            &(impl MessageChannel<maker_inc_connections::TakerMessage> + 'static),
+            &(impl MessageChannel<maker_inc_connections::ConfirmOrder> + 'static),
+            &(impl MessageChannel<maker_inc_connections::RegisterSetup> + 'static),
+            Identity,
+        ),
+    ) -> Self {
+        Self {
+            executor: command::Executor::new(db.clone(), process_manager),
+            db,
+            order_id: checkpoint.order_id,
+            n_payouts: checkpoint.terms.n_payouts,
+            start: Some(Start::Resume(checkpoint)),
+            oracle_pk,
+            announcement,
+            build_party_params: build_party_params.clone_channel(),
+            sign: sign.clone_channel(),
+            taker: taker.clone_channel(),
+            confirm_order: confirm_order.clone_channel(),
+            register_setup: register_setup.clone_channel(),
             taker_id,
             setup_msg_sender: None,
             tasks: Tasks::default(),
@@ -75,18 +130,80 @@ impl Actor {
     }
 
     async fn contract_setup(&mut self, this: xtra::Address<Self>) -> Result<()> {
-        let order_id = self.order.id;
+        let (setup_params, position) = self
+            .executor
+            .execute(self.order_id, |cfd| cfd.start_contract_setup())
+            .await?;
+
+        let checkpoint = SetupCheckpoint::new(
+            self.order_id,
+            SetupTerms {
+                oracle_event_id: self.announcement.id,
+                n_payouts: self.n_payouts,
+            },
+        );
+
+        self.run_contract_setup(setup_params, position, checkpoint, this);
+
+        Ok(())
+    }
+
+    async fn resume_contract_setup(
+        &mut self,
+        checkpoint: SetupCheckpoint,
+        ctx: &mut xtra::Context<Self>,
+    ) {
+        let order_id = self.order_id;
+        tracing::info!(%order_id, "Resuming contract setup");
+
+        let resumed = match self.db.load_open_cfd::<Cfd>(order_id, ()).await {
+            Ok(cfd) => cfd.resume_contract_setup(),
+            Err(e) => Err(e),
+        };
+        let (setup_params, position) = match resumed {
+            Ok(resumed) => resumed,
+            Err(e) => {
+                tracing::warn!(%order_id, "Discarding contract setup checkpoint: {e:#}");
+                self.delete_checkpoint().await;
+
+                ctx.stop();
+                return;
+            }
+        };
+
+        let this = ctx
+            .address()
+            .expect("actor to be able to give address to itself");
+        if let Err(e) = self
+            .register_setup
+            .send(maker_inc_connections::RegisterSetup {
+                order_id,
+                address: this.clone(),
+            })
+            .await
+        {
+            tracing::warn!(%order_id, "Stopping setup_maker actor: {e}");
+            ctx.stop();
+            return;
+        }
+
+        self.run_contract_setup(setup_params, position, checkpoint, this);
+    }
+
+    fn run_contract_setup(
+        &mut self,
+        setup_params: SetupParams,
+        position: Position,
+        checkpoint: SetupCheckpoint,
+        this: xtra::Address<Self>,
+    ) {
+        let order_id = self.order_id;
 
         let (sender, receiver) = mpsc::unbounded();
         // store the writing end to forward messages from the taker to
         // the spawned contract setup task
         self.setup_msg_sender = Some(sender);
 
-        let (setup_params, position) = self
-            .executor
-            .execute(order_id, |cfd| cfd.start_contract_setup())
-            .await?;
-
         let taker_id = setup_params.counterparty_identity();
 
         let contract_future = setup_contract::new(
@@ -103,7 +220,7 @@ impl Actor {
             self.sign.clone_channel(),
             Role::Maker,
             position,
-            self.n_payouts,
+            (self.db.clone(), checkpoint),
         );
 
         self.tasks.add(async move {
@@ -112,18 +229,23 @@ impl Actor {
                 Err(error) => this.send(SetupFailed { error }).await,
             };
         });
+    }
 
-        Ok(())
+    async fn delete_checkpoint(&self) {
+        if let Err(e) = SetupCheckpoint::delete(&self.db, self.order_id).await {
+            tracing::warn!(order_id = %self.order_id, "Failed to delete contract setup checkpoint: {e:#}");
+        }
     }
 
     async fn emit_complete(&mut self, dlc: Dlc, ctx: &mut xtra::Context<Self>) {
         if let Err(e) = self
             .executor
-            .execute(self.order.id, |cfd| cfd.complete_contract_setup(dlc))
+            .execute(self.order_id, |cfd| cfd.complete_contract_setup(dlc))
             .await
         {
             tracing::error!("Failed to execute `complete_contract_setup` command: {e:#}");
         }
+        self.delete_checkpoint().await;
 
         ctx.stop();
     }
@@ -131,7 +253,7 @@ impl Actor {
     async fn emit_reject(&mut self, reason: anyhow::Error, ctx: &mut xtra::Context<Self>) {
         if let Err(e) = self
             .executor
-            .execute(self.order.id, |cfd| cfd.reject_contract_setup(reason))
+            .execute(self.order_id, |cfd| cfd.reject_contract_setup(reason))
             .await
         {
             tracing::error!("Failed to execute `reject_contract_setup` command: {e:#}");
@@ -143,11 +265,12 @@ impl Actor {
     async fn emit_fail(&mut self, error: anyhow::Error, ctx: &mut xtra::Context<Self>) {
         if let Err(e) = self
             .executor
-            .execute(self.order.id, |cfd| Ok(cfd.fail_contract_setup(error)))
+            .execute(self.order_id, |cfd| Ok(cfd.fail_contract_setup(error)))
             .await
         {
             tracing::error!("Failed to execute `fail_contract_setup` command: {e:#}");
         }
+        self.delete_checkpoint().await;
 
         ctx.stop();
     }
@@ -166,7 +289,7 @@ impl Actor {
 #[xtra_productivity]
 impl Actor {
     fn handle(&mut self, _msg: Accepted, ctx: &mut xtra::Context<Self>) {
-        let order_id = self.order.id;
+        let order_id = self.order_id;
 
         if self.setup_msg_sender.is_some() {
             tracing::warn!(%order_id, "Contract setup already active");
@@ -210,7 +333,7 @@ impl Actor {
             .taker
             .send(maker_inc_connections::TakerMessage {
                 taker_id: self.taker_id,
-                msg: wire::MakerToTaker::RejectOrder(self.order.id),
+                msg: wire::MakerToTaker::RejectOrder(self.order_id),
             })
             .log_failure("Failed to reject order to taker")
             .await;
@@ -240,10 +363,17 @@ impl Actor {
 impl xtra::Actor for Actor {
     type Stop = ();
     async fn started(&mut self, ctx: &mut xtra::Context<Self>) {
-        let quantity = self.quantity;
-        if quantity < self.order.min_quantity || quantity > self.order.max_quantity {
-            let min = self.order.min_quantity;
-            let max = self.order.max_quantity;
+        let (order, quantity) = match self.start.take().expect("actor to be started once") {
+            Start::TakeOrder { order, quantity } => (order, quantity),
+            Start::Resume(checkpoint) => {
+                self.resume_contract_setup(checkpoint, ctx).await;
+                return;
+            }
+        };
+
+        if quantity < order.min_quantity || quantity > order.max_quantity {
+            let min = order.min_quantity;
+            let max = order.max_quantity;
 
             let reason =
                 format!("Order rejected: quantity {quantity} not in range [{min}, {max}]",);
@@ -253,7 +383,7 @@ impl xtra::Actor for Actor {
                 .taker
                 .send(maker_inc_connections::TakerMessage {
                     taker_id: self.taker_id,
-                    msg: wire::MakerToTaker::RejectOrder(self.order.id),
+                    msg: wire::MakerToTaker::RejectOrder(self.order_id),
                 })
                 .await;
 
@@ -3,6 +3,8 @@ use crate::connection;
 use crate::db;
 use crate::process_manager;
 use crate::setup_contract;
+use crate::setup_contract::SetupCheckpoint;
+use crate::setup_contract::SetupTerms;
 use crate::wallet;
 use crate::wire;
 use anyhow::anyhow;
@@ -15,9 +17,12 @@ use futures::future;
 use futures::SinkExt;
 use maia::secp256k1_zkp::schnorrsig;
 use model::olivia::Announcement;
+use model::Cfd;
 use model::Dlc;
 use model::OrderId;
+use model::Position;
 use model::Role;
+use model::SetupParams;
 use model::Usd;
 use std::time::Duration;
 use tokio_tasks::Tasks;
@@ -31,7 +36,7 @@ const MAKER_RESPONSE_TIMEOUT: Duration = Duration::from_secs(30);
 
 pub struct Actor {
     order_id: OrderId,
-    quantity: Usd,
+    start: Option<Start>,
     n_payouts: usize,
     oracle_pk: schnorrsig::PublicKey,
     announcement: Announcement,
@@ -40,9 +45,19 @@ pub struct Actor {
     maker: xtra::Address<connection::Actor>,
     setup_msg_sender: Option<UnboundedSender<wire::SetupMsg>>,
     tasks: Tasks,
+    db: db::Connection,
     executor: command::Executor,
 }
 
+/// How the contract setup gets started.
+enum Start {
+    /// Take the maker's order and run the protocol once the maker accepted.
+    TakeOrder { quantity: Usd },
+    /// Run the protocol from the checkpoint of a contract setup which was interrupted by a
+    /// restart.
+    Resume(SetupCheckpoint),
+}
+
 impl Actor {
     #[allow(clippy::too_many_arguments)]
     pub fn new(
@@ -56,7 +71,7 @@ impl Actor {
     ) -> Self {
         Self {
             order_id,
-            quantity,
+            start: Some(Start::TakeOrder { quantity }),
             n_payouts,
             oracle_pk,
             announcement,
@@ -65,44 +80,120 @@ impl Actor {
             maker,
             setup_msg_sender: None,
             tasks: Tasks::default(),
+            db: db.clone(),
             executor: command::Executor::new(db, process_manager),
         }
     }
 
-    /// Returns whether the maker has accepted our setup proposal.
-    fn is_accepted(&self) -> bool {
-        self.setup_msg_sender.is_some()
+    /// Resume a contract setup which was interrupted by a restart.
+    pub fn resume(
+        db: db::Connection,
+        process_manager: xtra::Address<process_manager::Actor>,
+        checkpoint: SetupCheckpoint,
+        (oracle_pk, announcement): (schnorrsig::PublicKey, Announcement),
+        build_party_params: &(impl MessageChannel<wallet::BuildPartyParams> + 'static),
+        sign: &(impl MessageChannel<wallet::Sign> + 'static),
+        maker: xtra::Address<connection::Actor>,
+    ) -> Self {
+        Self {
+            order_id: checkpoint.order_id,
+            n_payouts: checkpoint.terms.n_payouts,
+            start: Some(Start::Resume(checkpoint)),
+            oracle_pk,
+            announcement,
+            build_party_params: build_party_params.clone_channel(),
+            sign: sign.clone_channel(),
+            maker,
+            setup_msg_sender: None,
+            tasks: Tasks::default(),
+            db: db.clone(),
+            executor: command::Executor::new(db, process_manager),
+        }
     }
 
-    async fn forward_protocol_msg(&self, msg: wire::SetupMsg) -> Result<()> {
-        let mut sender = self
-            .setup_msg_sender
-            .clone()
-            .context("Cannot forward message to contract setup task")?;
-        sender.send(msg).await?;
+    async fn take_order(&mut self, quantity: Usd, ctx: &mut xtra::Context<Self>) {
+        let address = ctx
+            .address()
+            .expect("actor to be able to give address to itself");
 
-        Ok(())
+        let res = self
+            .maker
+            .send(connection::TakeOrder {
+                order_id: self.order_id,
+                quantity,
+                address,
+            })
+            .await;
+
+        if let Err(e) = res {
+            tracing::warn!(id = %self.order_id, "Stopping setup_taker actor: {e}");
+            ctx.stop()
+        }
+
+        let maker_response_timeout = {
+            let this = ctx.address().expect("self to be alive");
+            async move {
+                tokio::time::sleep(MAKER_RESPONSE_TIMEOUT).await;
+
+                let _ = this
+                    .send(MakerResponseTimeoutReached {
+                        timeout: MAKER_RESPONSE_TIMEOUT,
+                    })
+                    .await;
+            }
+        };
+
+        self.tasks.add(maker_response_timeout);
     }
-}
 
-#[xtra_productivity]
-impl Actor {
-    fn handle(&mut self, _: Accepted, ctx: &mut xtra::Context<Self>) {
+    async fn resume_contract_setup(
+        &mut self,
+        checkpoint: SetupCheckpoint,
+        ctx: &mut xtra::Context<Self>,
+    ) {
         let order_id = self.order_id;
-        tracing::info!(%order_id, "Order got accepted");
+        tracing::info!(%order_id, "Resuming contract setup");
 
-        let (setup_params, position) = match self
-            .executor
-            .execute(order_id, |cfd| cfd.start_contract_setup())
-            .await
-        {
-            Ok(contract_setup) => contract_setup,
+        let resumed = match self.db.load_open_cfd::<Cfd>(order_id, ()).await {
+            Ok(cfd) => cfd.resume_contract_setup(),
+            Err(e) => Err(e),
+        };
+        let (setup_params, position) = match resumed {
+            Ok(resumed) => resumed,
             Err(e) => {
-                tracing::error!("Failed to handle accepting contract setup: {e}");
+                tracing::warn!(%order_id, "Discarding contract setup checkpoint: {e:#}");
+                self.delete_checkpoint().await;
+
+                ctx.stop();
                 return;
             }
         };
 
+        let address = ctx
+            .address()
+            .expect("actor to be able to give address to itself");
+        if let Err(e) = self
+            .maker
+            .send(connection::RegisterSetup { order_id, address })
+            .await
+        {
+            tracing::warn!(%order_id, "Stopping setup_taker actor: {e}");
+            ctx.stop();
+            return;
+        }
+
+        self.run_contract_setup(setup_params, position, checkpoint, ctx);
+    }
+
+    fn run_contract_setup(
+        &mut self,
+        setup_params: SetupParams,
+        position: Position,
+        checkpoint: SetupCheckpoint,
+        ctx: &mut xtra::Context<Self>,
+    ) {
+        let order_id = self.order_id;
+
         let (sender, receiver) = mpsc::unbounded();
         // store the writing end to forward messages from the maker to
         // the spawned contract setup task
@@ -118,7 +209,7 @@ impl Actor {
             self.sign.clone_channel(),
             Role::Taker,
             position,
-            self.n_payouts,
+            (self.db.clone(), checkpoint),
         );
 
         let this = ctx.address().expect("self to be alive");
@@ -130,6 +221,57 @@ impl Actor {
         });
     }
 
+    async fn delete_checkpoint(&self) {
+        if let Err(e) = SetupCheckpoint::delete(&self.db, self.order_id).await {
+            tracing::warn!(order_id = %self.order_id, "Failed to delete contract setup checkpoint: {e:#}");
+        }
+    }
+
+    /// Returns whether the maker has accepted our setup proposal.
+    fn is_accepted(&self) -> bool {
+        self.setup_msg_sender.is_some()
+    }
+
+    async fn forward_protocol_msg(&self, msg: wire::SetupMsg) -> Result<()> {
+        let mut sender = self
+            .setup_msg_sender
+            .clone()
+            .context("Cannot forward message to contract setup task")?;
+        sender.send(msg).await?;
+
+        Ok(())
+    }
+}
+
+#[xtra_productivity]
+impl Actor {
+    fn handle(&mut self, _: Accepted, ctx: &mut xtra::Context<Self>) {
+        let order_id = self.order_id;
+        tracing::info!(%order_id, "Order got accepted");
+
+        let (setup_params, position) = match self
+            .executor
+            .execute(order_id, |cfd| cfd.start_contract_setup())
+            .await
+        {
+            Ok(contract_setup) => contract_setup,
+            Err(e) => {
+                tracing::error!("Failed to handle accepting contract setup: {e}");
+                return;
+            }
+        };
+
+        let checkpoint = SetupCheckpoint::new(
+            order_id,
+            SetupTerms {
+                oracle_event_id: self.announcement.id,
+                n_payouts: self.n_payouts,
+            },
+        );
+
+        self.run_contract_setup(setup_params, position, checkpoint, ctx);
+    }
+
     fn handle(&mut self, msg: Rejected, ctx: &mut xtra::Context<Self>) {
         let order_id = self.order_id;
         tracing::info!(%order_id, "Order got rejected");
@@ -165,6 +307,7 @@ impl Actor {
         {
             tracing::warn!("Failed to execute `complete_contract_setup` command: {e:#}");
         }
+        self.delete_checkpoint().await;
 
         ctx.stop();
     }
@@ -177,6 +320,7 @@ impl Actor {
         {
             tracing::warn!("Failed to execute `fail_contract_setup` command: {e:#}");
         }
+        self.delete_checkpoint().await;
 
         ctx.stop();
     }
@@ -214,38 +358,10 @@ impl Actor {
 impl xtra::Actor for Actor {
     type Stop = ();
     async fn started(&mut self, ctx: &mut xtra::Context<Self>) {
-        let address = ctx
-            .address()
-            .expect("actor to be able to give address to itself");
-
-        let res = self
-            .maker
-            .send(connection::TakeOrder {
-                order_id: self.order_id,
-                quantity: self.quantity,
-                address,
-            })
-            .await;
-
-        if let Err(e) = res {
-            tracing::warn!(id = %self.order_id, "Stopping setup_taker actor: {e}");
-            ctx.stop()
+        match self.start.take().expect("actor to be started once") {
+            Start::TakeOrder { quantity } => self.take_order(quantity, ctx).await,
+            Start::Resume(checkpoint) => self.resume_contract_setup(checkpoint, ctx).await,
         }
-
-        let maker_response_timeout = {
-            let this = ctx.address().expect("self to be alive");
-            async move {
-                tokio::time::sleep(MAKER_RESPONSE_TIMEOUT).await;
-
-                let _ = this
-                    .send(MakerResponseTimeoutReached {
-                        timeout: MAKER_RESPONSE_TIMEOUT,
-                    })
-                    .await;
-            }
-        };
-
-        self.tasks.add(maker_response_timeout);
     }
 
     async fn stopping(&mut self, _: &mut xtra::Context<Self>) -> KeepRunning {
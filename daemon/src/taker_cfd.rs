@@ -1,9 +1,11 @@
 use crate::collab_settlement_taker;
+use crate::command;
 use crate::connection;
 use crate::db;
 use crate::oracle;
 use crate::process_manager;
 use crate::projection;
+use crate::setup_contract::SetupCheckpoint;
 use crate::setup_taker;
 use crate::wallet;
 use anyhow::bail;
@@ -12,6 +14,7 @@ use anyhow::Result;
 use async_trait::async_trait;
 use bdk::bitcoin::secp256k1::schnorrsig;
 use model::market_closing_price;
+use model::olivia::Announcement;
 use model::Cfd;
 use model::Identity;
 use model::MakerOffers;
@@ -20,14 +23,22 @@ use model::Origin;
 use model::Price;
 use model::Role;
 use model::Usd;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
 use time::OffsetDateTime;
 use tokio_tasks::Tasks;
+use xtra::prelude::MessageChannel;
 use xtra::Actor as _;
+use xtra_bitmex_price_feed::LatestQuote;
 use xtra_productivity::xtra_productivity;
 use xtras::AddressMap;
 
+/// The offers of a single maker.
 #[derive(Clone, Copy)]
-pub struct CurrentMakerOffers(pub Option<MakerOffers>);
+pub struct CurrentMakerOffers {
+    pub maker: Identity,
+    pub offers: Option<MakerOffers>,
+}
 
 #[derive(Clone, Copy)]
 pub struct TakeOffer {
@@ -43,6 +54,22 @@ pub struct ProposeSettlement {
     pub quote_timestamp: String,
 }
 
+#[derive(Clone, Copy)]
+pub struct AcceptSettlementCounterOffer {
+    pub order_id: OrderId,
+}
+
+#[derive(Clone, Copy)]
+pub struct RejectSettlementCounterOffer {
+    pub order_id: OrderId,
+}
+
+/// Message sent to ourselves to resume a contract setup which was interrupted by a restart.
+struct ResumeContractSetup {
+    checkpoint: SetupCheckpoint,
+    announcement: Announcement,
+}
+
 pub struct Actor<O, W> {
     db: db::Connection,
     wallet: xtra::Address<W>,
@@ -54,9 +81,11 @@ pub struct Actor<O, W> {
     collab_settlement_actors: AddressMap<OrderId, collab_settlement_taker::Actor>,
     oracle_actor: xtra::Address<O>,
     n_payouts: usize,
+    settlement_counter_offer_tolerance: Option<Decimal>,
+    price_feed: Box<dyn MessageChannel<LatestQuote>>,
     tasks: Tasks,
-    current_maker_offers: Option<MakerOffers>,
-    maker_identity: Identity,
+    /// The latest offers of every maker we are connected to.
+    current_maker_offers: HashMap<Identity, MakerOffers>,
 }
 
 impl<O, W> Actor<O, W>
@@ -73,7 +102,8 @@ where
         conn_actor: xtra::Address<connection::Actor>,
         oracle_actor: xtra::Address<O>,
         n_payouts: usize,
-        maker_identity: Identity,
+        settlement_counter_offer_tolerance: Option<Decimal>,
+        price_feed: Box<dyn MessageChannel<LatestQuote>>,
     ) -> Self {
         Self {
             db,
@@ -84,11 +114,12 @@ where
             conn_actor,
             oracle_actor,
             n_payouts,
+            settlement_counter_offer_tolerance,
+            price_feed,
             setup_actors: AddressMap::default(),
             collab_settlement_actors: AddressMap::default(),
             tasks: Tasks::default(),
-            current_maker_offers: None,
-            maker_identity,
+            current_maker_offers: HashMap::new(),
         }
     }
 }
@@ -96,7 +127,9 @@ where
 #[xtra_productivity]
 impl<O, W> Actor<O, W> {
     async fn handle_current_offers(&mut self, msg: CurrentMakerOffers) -> Result<()> {
-        let takers_perspective_of_maker_offers = msg.0.map(|mut maker_offers| {
+        let CurrentMakerOffers { maker, offers } = msg;
+
+        let takers_perspective_of_maker_offers = offers.map(|mut maker_offers| {
             maker_offers.long = maker_offers.long.map(|mut long| {
                 long.origin = Origin::Theirs;
                 long
@@ -109,12 +142,15 @@ impl<O, W> Actor<O, W> {
             maker_offers
         });
 
-        self.current_maker_offers = takers_perspective_of_maker_offers;
+        tracing::trace!(%maker, "new maker offers {:?}", takers_perspective_of_maker_offers);
 
-        tracing::trace!("new maker offers {:?}", takers_perspective_of_maker_offers);
+        match takers_perspective_of_maker_offers {
+            Some(offers) => self.current_maker_offers.insert(maker, offers),
+            None => self.current_maker_offers.remove(&maker),
+        };
 
         self.projection_actor
-            .send(projection::Update(takers_perspective_of_maker_offers))
+            .send(projection::Update(self.current_maker_offers.clone()))
             .await?;
 
         Ok(())
@@ -143,6 +179,8 @@ impl<O, W> Actor<O, W> {
             order_id,
             proposal_closing_price,
             self.n_payouts,
+            self.settlement_counter_offer_tolerance,
+            self.price_feed.clone_channel(),
             self.conn_actor.clone(),
             self.process_manager_actor.clone(),
             self.db.clone(),
@@ -154,6 +192,34 @@ impl<O, W> Actor<O, W> {
 
         Ok(())
     }
+
+    async fn handle_accept_settlement_counter_offer(
+        &mut self,
+        msg: AcceptSettlementCounterOffer,
+    ) -> Result<()> {
+        let order_id = msg.order_id;
+
+        self.collab_settlement_actors
+            .send(&order_id, collab_settlement_taker::AcceptCounterOffer)
+            .await
+            .with_context(|| format!("No settlement in progress for order {order_id}"))?;
+
+        Ok(())
+    }
+
+    async fn handle_reject_settlement_counter_offer(
+        &mut self,
+        msg: RejectSettlementCounterOffer,
+    ) -> Result<()> {
+        let order_id = msg.order_id;
+
+        self.collab_settlement_actors
+            .send(&order_id, collab_settlement_taker::RejectCounterOffer)
+            .await
+            .with_context(|| format!("No settlement in progress for order {order_id}"))?;
+
+        Ok(())
+    }
 }
 
 #[xtra_productivity]
@@ -172,20 +238,27 @@ where
                 format!("Contract setup for order {order_id} is already in progress")
             })?;
 
-        let (order_to_take, maker_offers) = self
+        if self.current_maker_offers.is_empty() {
+            bail!("No maker offers available to take");
+        }
+
+        let (maker, order_to_take, maker_offers) = self
             .current_maker_offers
-            .context("No maker offers available to take")?
-            .take_order(order_id);
+            .iter()
+            .find_map(|(maker, offers)| {
+                let (order_to_take, maker_offers) = offers.take_order(order_id);
 
-        let order_to_take = order_to_take.context("Order to take could not be found in current maker offers, you might have an outdated offer")?;
+                order_to_take.map(|order_to_take| (*maker, order_to_take, maker_offers))
+            })
+            .context("Order to take could not be found in current maker offers, you might have an outdated offer")?;
 
         // The offer we are instructed to take is removed from the
         // set of available offers immediately so that we don't attempt
         // to take it more than once
         {
-            self.current_maker_offers.replace(maker_offers);
+            self.current_maker_offers.insert(maker, maker_offers);
             self.projection_actor
-                .send(projection::Update(self.current_maker_offers))
+                .send(projection::Update(self.current_maker_offers.clone()))
                 .await?;
         }
 
@@ -193,12 +266,12 @@ where
             bail!("The maker's offer appears to be outdated, refusing to take offer",);
         }
 
-        tracing::info!("Taking current order: {:?}", &order_to_take);
+        tracing::info!(%maker, "Taking current order: {:?}", &order_to_take);
 
         // We create the cfd here without any events yet, only static data
         // Once the contract setup completes (rejected / accepted / failed) the first event will be
         // recorded
-        let cfd = Cfd::from_order(order_to_take, quantity, self.maker_identity, Role::Taker);
+        let cfd = Cfd::from_order(order_to_take, quantity, maker, Role::Taker);
 
         self.db.insert_cfd(&cfd).await?;
         self.projection_actor
@@ -228,11 +301,89 @@ where
 
         Ok(())
     }
+
+    async fn handle_resume_contract_setup(&mut self, msg: ResumeContractSetup) -> Result<()> {
+        let ResumeContractSetup {
+            checkpoint,
+            announcement,
+        } = msg;
+        let order_id = checkpoint.order_id;
+
+        let disconnected = self
+            .setup_actors
+            .get_disconnected(order_id)
+            .with_context(|| {
+                format!("Contract setup for order {order_id} is already in progress")
+            })?;
+
+        let addr = setup_taker::Actor::resume(
+            self.db.clone(),
+            self.process_manager_actor.clone(),
+            checkpoint,
+            (self.oracle_pk, announcement),
+            &self.wallet,
+            &self.wallet,
+            self.conn_actor.clone(),
+        )
+        .create(None)
+        .spawn(&mut self.tasks);
+
+        disconnected.insert(addr);
+
+        Ok(())
+    }
 }
 
 #[async_trait]
-impl<O: 'static, W: 'static> xtra::Actor for Actor<O, W> {
+impl<O, W> xtra::Actor for Actor<O, W>
+where
+    O: xtra::Handler<oracle::GetAnnouncement> + xtra::Handler<oracle::MonitorAttestation>,
+    W: xtra::Handler<wallet::BuildPartyParams> + xtra::Handler<wallet::Sign>,
+{
     type Stop = ();
 
+    async fn started(&mut self, ctx: &mut xtra::Context<Self>) {
+        let this = ctx.address().expect("we are alive");
+        let db = self.db.clone();
+        let oracle = self.oracle_actor.clone();
+        let executor = command::Executor::new(db.clone(), self.process_manager_actor.clone());
+
+        // Contract setups are resumed in the background because the oracle actor might not have
+        // fetched the announcements yet
+        self.tasks.add_fallible(
+            async move {
+                for checkpoint in SetupCheckpoint::load_all(&db).await? {
+                    let order_id = checkpoint.order_id;
+                    let oracle_event_id = checkpoint.terms.oracle_event_id;
+
+                    match oracle::wait_for_announcement(&oracle, oracle_event_id).await {
+                        Ok(announcement) => {
+                            if let Err(e) = this
+                                .send(ResumeContractSetup {
+                                    checkpoint,
+                                    announcement,
+                                })
+                                .await?
+                            {
+                                tracing::warn!(%order_id, "Failed to resume contract setup: {e:#}");
+                            }
+                        }
+                        Err(e) => {
+                            executor
+                                .execute(order_id, |cfd| Ok(cfd.fail_contract_setup(e)))
+                                .await?;
+                            SetupCheckpoint::delete(&db, order_id).await?;
+                        }
+                    }
+                }
+
+                anyhow::Ok(())
+            },
+            |e| async move {
+                tracing::error!("Failed to resume contract setups: {e:#}");
+            },
+        );
+    }
+
     async fn stopped(self) -> Self::Stop {}
 }
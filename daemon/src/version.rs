@@ -1,3 +1,61 @@
+use crate::libp2p_wire;
+use model::RolloverVersion;
+use xtra_libp2p::PeerInfo;
+
+/// The agent advertised by the maker through the libp2p identify protocol.
+pub const MAKER_AGENT: &str = "itchysats-maker";
+
+/// The agent advertised by the taker through the libp2p identify protocol.
+pub const TAKER_AGENT: &str = "itchysats-taker";
+
 pub fn version() -> &'static str {
     env!("VERGEN_GIT_SEMVER_LIGHTWEIGHT")
 }
+
+/// The rollover protocol version to use with a peer, based on the protocols it advertised.
+///
+/// Peers which did not identify themselves (yet) or do not roll over via libp2p at all are
+/// reached over the legacy connection, which supports [`RolloverVersion::V2`] since 0.4.8.
+pub fn rollover_version(peer_info: Option<&PeerInfo>) -> RolloverVersion {
+    match peer_info {
+        Some(info) if info.supports(libp2p_wire::ROLLOVER_V2_PROTOCOL) => RolloverVersion::V2,
+        Some(info) if info.supports(libp2p_wire::ROLLOVER_PROTOCOL) => RolloverVersion::V1,
+        _ => RolloverVersion::V2,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rollover_version_depends_on_advertised_protocols() {
+        assert!(matches!(rollover_version(None), RolloverVersion::V2));
+        assert!(matches!(
+            rollover_version(Some(&peer_info(&[
+                libp2p_wire::ROLLOVER_PROTOCOL,
+                libp2p_wire::ROLLOVER_V2_PROTOCOL
+            ]))),
+            RolloverVersion::V2
+        ));
+        assert!(matches!(
+            rollover_version(Some(&peer_info(&[libp2p_wire::ROLLOVER_PROTOCOL]))),
+            RolloverVersion::V1
+        ));
+        assert!(matches!(
+            rollover_version(Some(&peer_info(&[libp2p_wire::OFFER_PROTOCOL]))),
+            RolloverVersion::V2
+        ));
+    }
+
+    fn peer_info(protocols: &[&str]) -> PeerInfo {
+        PeerInfo {
+            version: "0.4.12".to_owned(),
+            agent: MAKER_AGENT.to_owned(),
+            protocols: protocols
+                .iter()
+                .map(|protocol| protocol.to_string())
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,325 @@
+//! Notify external systems about the lifecycle of CFDs via HTTP webhooks.
+//!
+//! Notable events are written to the outbox in the same database transaction as the event itself,
+//! once for every configured webhook. The [`Actor`] delivers due notifications as JSON `POST`
+//! requests and retries failed deliveries with exponential backoff until they succeed or the
+//! webhook is removed, hence every notification is delivered at least once. Receivers can
+//! recognise duplicates by the [`DELIVERY_HEADER`].
+//!
+//! The body of every request is signed with HMAC-SHA256, keyed with the secret of the webhook. The
+//! hex-encoded signature is sent in the [`SIGNATURE_HEADER`].
+
+use crate::db;
+use anyhow::Context;
+use anyhow::Result;
+use async_trait::async_trait;
+use hmac::Hmac;
+use hmac::Mac;
+use model::CfdEvent;
+use model::OrderId;
+use model::Timestamp;
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::Sha256;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio_tasks::Tasks;
+use xtra_productivity::xtra_productivity;
+use xtras::SendInterval;
+
+pub const SIGNATURE_HEADER: &str = "X-Itchysats-Signature";
+pub const DELIVERY_HEADER: &str = "X-Itchysats-Delivery";
+
+/// How often we look for notifications which are due for delivery.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long we wait for a webhook to respond.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The maximum number of notifications delivered at once.
+const BATCH_SIZE: u32 = 100;
+
+/// The delay before retrying a failed delivery for the first time.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(10);
+
+/// The maximum delay between two delivery attempts.
+///
+/// Failed deliveries are never dropped, a webhook which is down keeps being retried at this
+/// interval.
+const MAX_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
+/// The events external systems are notified about.
+const NOTIFIED_EVENTS: &[&str] = &[
+    "ContractSetupCompleted",
+    "ContractSetupFailed",
+    "RolloverCompleted",
+    "RolloverRejected",
+    "RolloverFailed",
+    "CollaborativeSettlementStarted",
+    "CollaborativeSettlementCounterOffered",
+    "CollaborativeSettlementCompleted",
+    "CollaborativeSettlementRejected",
+    "CollaborativeSettlementFailed",
+    "CollaborativeSettlementConfirmed",
+    "CommitConfirmed",
+    "OracleAttestedPriorCetTimelock",
+    "OracleAttestedPostCetTimelock",
+    "CetConfirmed",
+    "RefundConfirmed",
+];
+
+/// A webhook as it is configured via CLI or API.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct NewWebhook {
+    pub url: String,
+    /// The key the body of every request is signed with.
+    pub secret: String,
+}
+
+impl NewWebhook {
+    pub fn validate(&self) -> Result<()> {
+        let url = reqwest::Url::parse(&self.url)
+            .with_context(|| format!("Invalid webhook URL: {}", self.url))?;
+
+        if !matches!(url.scheme(), "http" | "https") {
+            anyhow::bail!("Webhook URL must use http or https: {url}");
+        }
+
+        if self.secret.is_empty() {
+            anyhow::bail!("Webhook secret must not be empty");
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for NewWebhook {
+    type Err = anyhow::Error;
+
+    /// Parses a webhook in the form `<URL>,<SECRET>`.
+    fn from_str(s: &str) -> Result<Self> {
+        let (url, secret) = s
+            .rsplit_once(',')
+            .context("Expected webhook in the form <URL>,<SECRET>")?;
+
+        let webhook = NewWebhook {
+            url: url.to_owned(),
+            secret: secret.to_owned(),
+        };
+        webhook.validate()?;
+
+        Ok(webhook)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, sqlx::FromRow)]
+pub struct Webhook {
+    pub id: i64,
+    pub url: String,
+    #[serde(skip_serializing)]
+    pub secret: String,
+}
+
+/// The JSON body POSTed to webhooks.
+///
+/// Deliberately does not contain the data of the event, as some events carry key material.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Notification {
+    pub order_id: OrderId,
+    pub event: String,
+    pub timestamp: Timestamp,
+}
+
+impl Notification {
+    /// The notification about the given event, if webhooks are notified about it.
+    pub fn from_event(event: &CfdEvent) -> Option<Self> {
+        let name = event.event.to_string();
+
+        if !NOTIFIED_EVENTS.contains(&name.as_str()) {
+            return None;
+        }
+
+        Some(Self {
+            order_id: event.id,
+            event: name,
+            timestamp: event.timestamp,
+        })
+    }
+}
+
+/// A notification in the outbox, waiting to be delivered to a webhook.
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct Delivery {
+    pub id: i64,
+    pub url: String,
+    pub secret: String,
+    pub payload: String,
+    pub attempts: u32,
+}
+
+/// Sign the body of a request with the secret of a webhook.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// The delay before the next delivery attempt after `attempts` failed attempts.
+fn backoff(attempts: u32) -> Duration {
+    INITIAL_BACKOFF
+        .checked_mul(2u32.saturating_pow(attempts.saturating_sub(1)))
+        .unwrap_or(MAX_BACKOFF)
+        .min(MAX_BACKOFF)
+}
+
+/// An actor delivering the notifications in the outbox to the webhooks.
+pub struct Actor {
+    db: db::Connection,
+    client: reqwest::Client,
+    tasks: Tasks,
+}
+
+impl Actor {
+    pub fn new(db: db::Connection) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .context("Failed to build HTTP client")?;
+
+        Ok(Self {
+            db,
+            client,
+            tasks: Tasks::default(),
+        })
+    }
+
+    async fn deliver(&self, delivery: &Delivery) -> Result<()> {
+        let response = self
+            .client
+            .post(&delivery.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(
+                SIGNATURE_HEADER,
+                sign(&delivery.secret, delivery.payload.as_bytes()),
+            )
+            .header(DELIVERY_HEADER, delivery.id)
+            .body(delivery.payload.clone())
+            .send()
+            .await
+            .context("Failed to send request")?;
+
+        let status = response.status();
+        if !status.is_success() {
+            anyhow::bail!("Webhook responded with {status}");
+        }
+
+        Ok(())
+    }
+}
+
+/// Private message to deliver the notifications which are due.
+struct Deliver;
+
+#[xtra_productivity]
+impl Actor {
+    async fn handle(&mut self, _: Deliver) {
+        let now = Timestamp::now();
+
+        let deliveries = match self.db.load_due_webhook_deliveries(now, BATCH_SIZE).await {
+            Ok(deliveries) => deliveries,
+            Err(e) => {
+                tracing::warn!("Failed to load webhook deliveries: {e:#}");
+                return;
+            }
+        };
+
+        for delivery in deliveries {
+            let id = delivery.id;
+            let url = delivery.url.as_str();
+
+            let result = match self.deliver(&delivery).await {
+                Ok(()) => {
+                    tracing::debug!(%id, %url, "Delivered webhook notification");
+                    self.db.delete_webhook_delivery(id).await
+                }
+                Err(e) => {
+                    let attempts = delivery.attempts.saturating_add(1);
+                    let delay = backoff(attempts);
+
+                    if delay < MAX_BACKOFF {
+                        tracing::debug!(%id, %url, %attempts, "Failed to deliver webhook notification, retrying in {}s: {e:#}", delay.as_secs());
+                    } else {
+                        tracing::warn!(%id, %url, %attempts, "Failed to deliver webhook notification, retrying in {}s: {e:#}", delay.as_secs());
+                    }
+
+                    let next_attempt_at = Timestamp::new(now.seconds() + delay.as_secs() as i64);
+                    self.db
+                        .reschedule_webhook_delivery(id, attempts, next_attempt_at)
+                        .await
+                }
+            };
+
+            if let Err(e) = result {
+                tracing::warn!(%id, "Failed to update webhook delivery: {e:#}");
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl xtra::Actor for Actor {
+    type Stop = ();
+
+    async fn started(&mut self, ctx: &mut xtra::Context<Self>) {
+        let this = ctx.address().expect("we just started");
+
+        self.tasks
+            .add(this.send_interval(POLL_INTERVAL, || Deliver));
+    }
+
+    async fn stopped(self) -> Self::Stop {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_webhook_from_cli() {
+        let webhook = "https://example.com/hook?a=b,s3cr3t"
+            .parse::<NewWebhook>()
+            .unwrap();
+
+        assert_eq!(
+            webhook,
+            NewWebhook {
+                url: "https://example.com/hook?a=b".to_owned(),
+                secret: "s3cr3t".to_owned()
+            }
+        );
+        assert!("https://example.com/hook".parse::<NewWebhook>().is_err());
+        assert!("ftp://example.com,s3cr3t".parse::<NewWebhook>().is_err());
+    }
+
+    #[test]
+    fn signature_is_hmac_sha256_of_body() {
+        // Test vector 2 of RFC 4231
+        let signature = sign("Jefe", b"what do ya want for nothing?");
+
+        assert_eq!(
+            signature,
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        assert_eq!(backoff(1), INITIAL_BACKOFF);
+        assert_eq!(backoff(2), INITIAL_BACKOFF * 2);
+        assert_eq!(backoff(3), INITIAL_BACKOFF * 4);
+        assert_eq!(backoff(30), MAX_BACKOFF);
+        assert_eq!(backoff(u32::MAX), MAX_BACKOFF);
+    }
+}
@@ -79,6 +79,8 @@ pub mod taker_to_maker {
         Initiate {
             sig_taker: Signature,
         },
+        /// The taker does not accept the counter-offer of the maker.
+        RejectCounterOffer,
     }
 }
 
@@ -112,6 +114,10 @@ pub enum TakerToMaker {
         order_id: OrderId,
         msg: RolloverMsg,
     },
+    /// The taker does not accept the terms the maker confirmed the rollover with.
+    DeclineRollover {
+        order_id: OrderId,
+    },
     Settlement {
         order_id: OrderId,
         msg: taker_to_maker::Settlement,
@@ -138,15 +144,35 @@ impl TakerToMaker {
                 RolloverMsg::Msg2(_) => "TakerToMaker::RolloverProtocol::Msg2",
                 RolloverMsg::Msg3(_) => "TakerToMaker::RolloverProtocol::Msg3",
             },
+            TakerToMaker::DeclineRollover { .. } => "TakerToMaker::DeclineRollover",
             TakerToMaker::Settlement { msg, .. } => match msg {
                 taker_to_maker::Settlement::Propose { .. } => "TakerToMaker::Settlement::Propose",
                 taker_to_maker::Settlement::Initiate { .. } => "TakerToMaker::Settlement::Initiate",
+                taker_to_maker::Settlement::RejectCounterOffer => {
+                    "TakerToMaker::Settlement::RejectCounterOffer"
+                }
             },
             TakerToMaker::Hello(_) => "TakerToMaker::Hello",
             TakerToMaker::HelloV2 { .. } => "TakerToMaker::HelloV2",
             TakerToMaker::Unknown => "TakerToMaker::Unknown",
         }
     }
+
+    /// The order this message refers to.
+    ///
+    /// Returns `None` for messages which are not related to a particular order, such as `Hello`.
+    pub fn order_id(&self) -> Option<OrderId> {
+        match self {
+            TakerToMaker::TakeOrder { order_id, .. }
+            | TakerToMaker::ProposeRollover { order_id, .. }
+            | TakerToMaker::ProposeRolloverV2 { order_id, .. }
+            | TakerToMaker::Protocol { order_id, .. }
+            | TakerToMaker::RolloverProtocol { order_id, .. }
+            | TakerToMaker::DeclineRollover { order_id }
+            | TakerToMaker::Settlement { order_id, .. } => Some(*order_id),
+            TakerToMaker::Hello(_) | TakerToMaker::HelloV2 { .. } | TakerToMaker::Unknown => None,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -174,6 +200,11 @@ pub enum MakerToTaker {
         oracle_event_id: BitMexPriceEventId,
         tx_fee_rate: TxFeeRate,
         funding_rate: FundingRate,
+        /// The number of payouts of the new CETs.
+        ///
+        /// Not sent by makers up to v0.4.12, which always use [`crate::N_PAYOUTS`].
+        #[serde(default)]
+        n_payouts: Option<usize>,
     },
     RejectRollover(OrderId),
     Settlement {
@@ -233,10 +264,36 @@ impl MakerToTaker {
             MakerToTaker::Settlement { msg, .. } => match msg {
                 maker_to_taker::Settlement::Confirm => "MakerToTaker::Settlement::Confirm",
                 maker_to_taker::Settlement::Reject => "MakerToTaker::Settlement::Reject",
+                maker_to_taker::Settlement::CounterOffer { .. } => {
+                    "MakerToTaker::Settlement::CounterOffer"
+                }
+                maker_to_taker::Settlement::Finalize { .. } => "MakerToTaker::Settlement::Finalize",
             },
             MakerToTaker::Unknown => "MakerToTaker::Unknown",
         }
     }
+
+    /// The order this message refers to.
+    ///
+    /// Returns `None` for messages which are not related to a particular order, such as
+    /// `Heartbeat` or `CurrentOffers`.
+    pub fn order_id(&self) -> Option<OrderId> {
+        match self {
+            MakerToTaker::ConfirmOrder(order_id)
+            | MakerToTaker::RejectOrder(order_id)
+            | MakerToTaker::InvalidOrderId(order_id)
+            | MakerToTaker::RejectRollover(order_id)
+            | MakerToTaker::Protocol { order_id, .. }
+            | MakerToTaker::RolloverProtocol { order_id, .. }
+            | MakerToTaker::ConfirmRollover { order_id, .. }
+            | MakerToTaker::Settlement { order_id, .. } => Some(*order_id),
+            MakerToTaker::Hello(_)
+            | MakerToTaker::Heartbeat
+            | MakerToTaker::CurrentOrder(_)
+            | MakerToTaker::CurrentOffers(_)
+            | MakerToTaker::Unknown => None,
+        }
+    }
 }
 
 pub mod maker_to_taker {
@@ -247,6 +304,23 @@ pub mod maker_to_taker {
     pub enum Settlement {
         Confirm,
         Reject,
+        /// The maker proposes to settle at a different price instead.
+        ///
+        /// The taker either accepts by initiating the settlement or replies with
+        /// [`taker_to_maker::Settlement::RejectCounterOffer`].
+        CounterOffer {
+            timestamp: Timestamp,
+            #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+            taker: Amount,
+            #[serde(with = "::bdk::bitcoin::util::amount::serde::as_btc")]
+            maker: Amount,
+            price: Price,
+        },
+        /// The signature of the maker on the settlement transaction, sent after receiving the
+        /// signature of the taker so that either party can publish the transaction.
+        Finalize {
+            sig_maker: Signature,
+        },
     }
 }
 
@@ -325,7 +399,7 @@ where
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 #[serde(tag = "type", content = "payload")]
 pub enum SetupMsg {
     /// Message enabling setting up lock and based on that commit, refund and cets
@@ -388,7 +462,7 @@ impl SetupMsg {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Msg0 {
     pub lock_psbt: PartiallySignedTransaction, // TODO: Use binary representation
     pub identity_pk: PublicKey,
@@ -449,7 +523,7 @@ impl From<Msg0> for (PartyParams, PunishParams) {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Msg1 {
     pub commit: EcdsaAdaptorSignature,
     pub cets: HashMap<String, Vec<(RangeInclusive<u64>, EcdsaAdaptorSignature)>>,
@@ -480,7 +554,7 @@ impl From<CfdTransactions> for Msg1 {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Msg2 {
     pub signed_lock: PartiallySignedTransaction, // TODO: Use binary representation
 }
@@ -488,7 +562,7 @@ pub struct Msg2 {
 #[derive(Serialize, Deserialize, Clone, Copy)]
 pub struct Msg3;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 #[serde(tag = "type", content = "payload")]
 pub enum RolloverMsg {
     Msg0(RolloverMsg0),
@@ -537,7 +611,7 @@ impl RolloverMsg {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct RolloverMsg1 {
     pub commit: EcdsaAdaptorSignature,
     pub cets: HashMap<String, Vec<(RangeInclusive<u64>, EcdsaAdaptorSignature)>>,
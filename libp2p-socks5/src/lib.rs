@@ -0,0 +1,239 @@
+//! A libp2p [`Transport`] which dials TCP connections through a SOCKS5 proxy.
+//!
+//! Running the proxy as part of a local Tor daemon hides our IP address from the peers we connect
+//! to and allows dialing onion services. Host names are resolved by the proxy, hence they do not
+//! leak through local DNS queries either.
+//!
+//! The transport can only dial. Listening on an onion service is done by configuring the Tor
+//! daemon to forward the onion service to a regular TCP listener.
+
+use data_encoding::BASE32;
+use futures::future::BoxFuture;
+use futures::future::Ready;
+use futures::stream::Pending;
+use futures::FutureExt;
+use libp2p_core::multiaddr::Protocol;
+use libp2p_core::transport::ListenerEvent;
+use libp2p_core::transport::TransportError;
+use libp2p_core::Multiaddr;
+use libp2p_core::Transport;
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio_util::compat::Compat;
+use tokio_util::compat::TokioAsyncReadCompatExt;
+
+const VERSION: u8 = 0x05;
+const NO_AUTHENTICATION: u8 = 0x00;
+const CONNECT: u8 = 0x01;
+const RESERVED: u8 = 0x00;
+const SUCCEEDED: u8 = 0x00;
+
+const ADDRESS_TYPE_IPV4: u8 = 0x01;
+const ADDRESS_TYPE_DOMAIN: u8 = 0x03;
+const ADDRESS_TYPE_IPV6: u8 = 0x04;
+
+/// A TCP transport dialing all connections through the SOCKS5 proxy at the given address.
+///
+/// Supports `/ip4`, `/ip6`, `/dns`, `/dns4` and `/dns6` addresses followed by `/tcp`, as well as
+/// `/onion3` addresses. A trailing `/p2p` protocol is ignored.
+#[derive(Debug, Clone, Copy)]
+pub struct Socks5TokioTcpConfig {
+    proxy: SocketAddr,
+}
+
+impl Socks5TokioTcpConfig {
+    pub fn new(proxy: SocketAddr) -> Self {
+        Self { proxy }
+    }
+}
+
+impl Transport for Socks5TokioTcpConfig {
+    type Output = Compat<TcpStream>;
+    type Error = io::Error;
+    type Listener = Pending<Result<ListenerEvent<Self::ListenerUpgrade, Self::Error>, Self::Error>>;
+    type ListenerUpgrade = Ready<Result<Self::Output, Self::Error>>;
+    type Dial = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>> {
+        Err(TransportError::MultiaddrNotSupported(addr))
+    }
+
+    fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        let target = match Target::from_multiaddr(&addr) {
+            Some(target) => target,
+            None => return Err(TransportError::MultiaddrNotSupported(addr)),
+        };
+
+        Ok(async move {
+            let stream = connect(self.proxy, &target).await?;
+
+            Ok(stream.compat())
+        }
+        .boxed())
+    }
+
+    fn dial_as_listener(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        self.dial(addr)
+    }
+
+    fn address_translation(&self, _: &Multiaddr, _: &Multiaddr) -> Option<Multiaddr> {
+        None
+    }
+}
+
+/// The address we ask the proxy to connect to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    Socket(SocketAddr),
+    /// A host name to be resolved by the proxy, e.g. an onion address.
+    Domain(String, u16),
+}
+
+impl Target {
+    pub fn from_multiaddr(addr: &Multiaddr) -> Option<Self> {
+        let mut protocols = addr.iter();
+
+        let target = match (protocols.next()?, protocols.next()) {
+            (Protocol::Ip4(ip), Some(Protocol::Tcp(port))) => {
+                Target::Socket(SocketAddr::new(ip.into(), port))
+            }
+            (Protocol::Ip6(ip), Some(Protocol::Tcp(port))) => {
+                Target::Socket(SocketAddr::new(ip.into(), port))
+            }
+            (
+                Protocol::Dns(host) | Protocol::Dns4(host) | Protocol::Dns6(host),
+                Some(Protocol::Tcp(port)),
+            ) => Target::Domain(host.into_owned(), port),
+            (Protocol::Onion3(onion), next) => {
+                let host = format!("{}.onion", BASE32.encode(onion.hash()).to_lowercase());
+                let target = Target::Domain(host, onion.port());
+
+                return match next {
+                    None | Some(Protocol::P2p(_)) => Some(target),
+                    Some(_) => None,
+                };
+            }
+            _ => return None,
+        };
+
+        match protocols.next() {
+            None | Some(Protocol::P2p(_)) => Some(target),
+            Some(_) => None,
+        }
+    }
+
+    fn encode(&self, buffer: &mut Vec<u8>) -> io::Result<()> {
+        match self {
+            Target::Socket(SocketAddr::V4(addr)) => {
+                buffer.push(ADDRESS_TYPE_IPV4);
+                buffer.extend_from_slice(&addr.ip().octets());
+                buffer.extend_from_slice(&addr.port().to_be_bytes());
+            }
+            Target::Socket(SocketAddr::V6(addr)) => {
+                buffer.push(ADDRESS_TYPE_IPV6);
+                buffer.extend_from_slice(&addr.ip().octets());
+                buffer.extend_from_slice(&addr.port().to_be_bytes());
+            }
+            Target::Domain(host, port) => {
+                let len = u8::try_from(host.len()).map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Host name {host} is too long"),
+                    )
+                })?;
+
+                buffer.push(ADDRESS_TYPE_DOMAIN);
+                buffer.push(len);
+                buffer.extend_from_slice(host.as_bytes());
+                buffer.extend_from_slice(&port.to_be_bytes());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Socket(addr) => write!(f, "{addr}"),
+            Target::Domain(host, port) => write!(f, "{host}:{port}"),
+        }
+    }
+}
+
+/// Open a TCP connection to the target through the SOCKS5 proxy at the given address.
+pub async fn connect(proxy: SocketAddr, target: &Target) -> io::Result<TcpStream> {
+    let mut stream = TcpStream::connect(proxy).await?;
+
+    stream.write_all(&[VERSION, 1, NO_AUTHENTICATION]).await?;
+
+    let mut response = [0u8; 2];
+    stream.read_exact(&mut response).await?;
+
+    if response != [VERSION, NO_AUTHENTICATION] {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("SOCKS5 proxy {proxy} requires authentication"),
+        ));
+    }
+
+    let mut request = vec![VERSION, CONNECT, RESERVED];
+    target.encode(&mut request)?;
+    stream.write_all(&request).await?;
+
+    let mut response = [0u8; 4];
+    stream.read_exact(&mut response).await?;
+
+    if response[0] != VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Unexpected SOCKS version {}", response[0]),
+        ));
+    }
+
+    if response[1] != SUCCEEDED {
+        return Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!(
+                "SOCKS5 proxy {proxy} failed to connect to {target}: {}",
+                reply_message(response[1])
+            ),
+        ));
+    }
+
+    // The address the proxy bound to is of no use to us, but we have to consume it
+    let address_len = match response[3] {
+        ADDRESS_TYPE_IPV4 => 4,
+        ADDRESS_TYPE_IPV6 => 16,
+        ADDRESS_TYPE_DOMAIN => stream.read_u8().await? as usize,
+        other => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unknown SOCKS5 address type {other}"),
+            ))
+        }
+    };
+    let mut bound_address = vec![0u8; address_len + 2];
+    stream.read_exact(&mut bound_address).await?;
+
+    Ok(stream)
+}
+
+fn reply_message(reply: u8) -> &'static str {
+    match reply {
+        0x01 => "general failure",
+        0x02 => "connection not allowed by ruleset",
+        0x03 => "network unreachable",
+        0x04 => "host unreachable",
+        0x05 => "connection refused",
+        0x06 => "TTL expired",
+        0x07 => "command not supported",
+        0x08 => "address type not supported",
+        _ => "unknown error",
+    }
+}
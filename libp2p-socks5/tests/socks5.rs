@@ -0,0 +1,237 @@
+use libp2p_core::identity::Keypair;
+use libp2p_core::multiaddr::Protocol;
+use libp2p_core::Multiaddr;
+use libp2p_core::PeerId;
+use libp2p_core::Transport;
+use libp2p_socks5::Socks5TokioTcpConfig;
+use libp2p_socks5::Target;
+use libp2p_tcp::TokioTcpConfig;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use xtra::spawn::TokioGlobalSpawnExt;
+use xtra::Actor as _;
+use xtra::Address;
+use xtra_libp2p::Connect;
+use xtra_libp2p::Endpoint;
+use xtra_libp2p::GetConnectionStats;
+use xtra_libp2p::ListenOn;
+
+#[tokio::test]
+async fn endpoint_connects_through_socks5_proxy() {
+    let (listener_peer_id, listener_address) = listening_endpoint().await;
+    let (proxy, mut requested_targets) = socks5_proxy(move |_| listener_address).await;
+    let dialer = dialing_endpoint(proxy);
+
+    dialer
+        .send(Connect(
+            format!(
+                "/ip4/127.0.0.1/tcp/{}/p2p/{listener_peer_id}",
+                listener_address.port()
+            )
+            .parse()
+            .unwrap(),
+        ))
+        .await
+        .unwrap()
+        .unwrap();
+
+    wait_until_connected(&dialer, listener_peer_id).await;
+    assert_eq!(
+        requested_targets.recv().await.unwrap(),
+        Target::Socket(listener_address)
+    );
+}
+
+#[tokio::test]
+async fn host_names_are_resolved_by_the_proxy() {
+    let (listener_peer_id, listener_address) = listening_endpoint().await;
+    let (proxy, mut requested_targets) = socks5_proxy(move |_| listener_address).await;
+    let dialer = dialing_endpoint(proxy);
+
+    dialer
+        .send(Connect(
+            format!("/dns/maker.example/tcp/10001/p2p/{listener_peer_id}")
+                .parse()
+                .unwrap(),
+        ))
+        .await
+        .unwrap()
+        .unwrap();
+
+    wait_until_connected(&dialer, listener_peer_id).await;
+    assert_eq!(
+        requested_targets.recv().await.unwrap(),
+        Target::Domain("maker.example".to_owned(), 10001)
+    );
+}
+
+#[tokio::test]
+async fn onion_addresses_are_dialed_through_the_proxy() {
+    let (listener_peer_id, listener_address) = listening_endpoint().await;
+    let (proxy, mut requested_targets) = socks5_proxy(move |_| listener_address).await;
+    let dialer = dialing_endpoint(proxy);
+
+    let onion = "vww6ybal4bd7szmgncyruucpgfkqahzddi37ktceo3ah7ngmcopnpyyd";
+    let address = format!("/onion3/{onion}:10001")
+        .parse::<Multiaddr>()
+        .unwrap()
+        .with(Protocol::P2p(listener_peer_id.into()));
+
+    dialer.send(Connect(address)).await.unwrap().unwrap();
+
+    wait_until_connected(&dialer, listener_peer_id).await;
+    assert_eq!(
+        requested_targets.recv().await.unwrap(),
+        Target::Domain(format!("{onion}.onion"), 10001)
+    );
+}
+
+#[test]
+fn cannot_listen() {
+    let transport = Socks5TokioTcpConfig::new("127.0.0.1:9050".parse().unwrap());
+
+    assert!(transport
+        .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+        .is_err());
+}
+
+#[test]
+fn unsupported_addresses_are_rejected() {
+    for address in [
+        "/ip4/127.0.0.1/udp/10000",
+        "/memory/10000",
+        "/ip4/127.0.0.1",
+    ] {
+        assert_eq!(
+            Target::from_multiaddr(&address.parse().unwrap()),
+            None,
+            "{address} should not be supported"
+        );
+    }
+}
+
+async fn listening_endpoint() -> (PeerId, SocketAddr) {
+    let id = Keypair::generate_ed25519();
+    let peer_id = id.public().to_peer_id();
+
+    let endpoint = Endpoint::new(TokioTcpConfig::new(), id, Duration::from_secs(20), [])
+        .create(None)
+        .spawn_global();
+
+    // Find a free port, there is no way to ask the endpoint for the port it bound to
+    let port = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    endpoint
+        .send(ListenOn(
+            format!("/ip4/127.0.0.1/tcp/{port}").parse().unwrap(),
+        ))
+        .await
+        .unwrap();
+
+    (peer_id, SocketAddr::from(([127, 0, 0, 1], port)))
+}
+
+fn dialing_endpoint(proxy: SocketAddr) -> Address<Endpoint> {
+    Endpoint::new(
+        Socks5TokioTcpConfig::new(proxy),
+        Keypair::generate_ed25519(),
+        Duration::from_secs(20),
+        [],
+    )
+    .create(None)
+    .spawn_global()
+}
+
+async fn wait_until_connected(endpoint: &Address<Endpoint>, peer: PeerId) {
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while !endpoint
+            .send(GetConnectionStats)
+            .await
+            .unwrap()
+            .connected_peers
+            .contains(&peer)
+        {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("to connect within 10 seconds");
+}
+
+/// A minimal SOCKS5 proxy, only supporting the `CONNECT` command without authentication.
+///
+/// Every requested target is reported on the returned channel and connected to the address
+/// returned by `resolve`, which allows us to pretend that host names and onion addresses exist.
+async fn socks5_proxy(
+    resolve: impl Fn(&Target) -> SocketAddr + Send + Sync + Copy + 'static,
+) -> (SocketAddr, mpsc::UnboundedReceiver<Target>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let (sender, receiver) = mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let sender = sender.clone();
+
+            tokio::spawn(async move {
+                let _ = serve(stream, resolve, sender).await;
+            });
+        }
+    });
+
+    (address, receiver)
+}
+
+async fn serve(
+    mut stream: TcpStream,
+    resolve: impl Fn(&Target) -> SocketAddr,
+    requested_targets: mpsc::UnboundedSender<Target>,
+) -> std::io::Result<()> {
+    let mut greeting = [0u8; 2];
+    stream.read_exact(&mut greeting).await?;
+    let mut methods = vec![0u8; greeting[1] as usize];
+    stream.read_exact(&mut methods).await?;
+    stream.write_all(&[0x05, 0x00]).await?;
+
+    let mut request = [0u8; 4];
+    stream.read_exact(&mut request).await?;
+    let target = match request[3] {
+        0x01 => {
+            let mut ip = [0u8; 4];
+            stream.read_exact(&mut ip).await?;
+            let port = stream.read_u16().await?;
+
+            Target::Socket(SocketAddr::from((ip, port)))
+        }
+        0x03 => {
+            let len = stream.read_u8().await?;
+            let mut host = vec![0u8; len as usize];
+            stream.read_exact(&mut host).await?;
+            let port = stream.read_u16().await?;
+
+            Target::Domain(String::from_utf8(host).unwrap(), port)
+        }
+        other => panic!("Unexpected address type {other}"),
+    };
+
+    let mut upstream = TcpStream::connect(resolve(&target)).await?;
+    let _ = requested_targets.send(target);
+
+    stream
+        .write_all(&[0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+        .await?;
+
+    tokio::io::copy_bidirectional(&mut stream, &mut upstream).await?;
+
+    Ok(())
+}
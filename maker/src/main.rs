@@ -2,30 +2,40 @@ use anyhow::Context;
 use anyhow::Result;
 use clap::Parser;
 use clap::Subcommand;
+use daemon::access_control;
+use daemon::api_token;
+use daemon::backup;
 use daemon::bdk;
 use daemon::bdk::bitcoin;
 use daemon::bdk::bitcoin::Amount;
 use daemon::bdk::FeeRate;
+use daemon::candles;
 use daemon::db;
+use daemon::discovery;
 use daemon::monitor;
 use daemon::oracle;
 use daemon::projection;
+use daemon::rollover_maker;
 use daemon::seed::RandomSeed;
 use daemon::seed::Seed;
 use daemon::wallet;
+use daemon::webhook;
 use daemon::MakerActorSystem;
 use daemon::HEARTBEAT_INTERVAL;
 use daemon::N_PAYOUTS;
+use libp2p_core::Multiaddr;
 use model::olivia;
+use model::Identity;
 use model::SETTLEMENT_INTERVAL;
 use shared_bin::fairings;
 use shared_bin::logger;
 use shared_bin::logger::LevelFilter;
+use shared_bin::price_feed;
+use shared_bin::price_feed::PriceFeed;
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use tokio_tasks::Tasks;
 use xtra::Actor;
-use xtras::supervisor;
 
 mod routes;
 
@@ -35,6 +45,13 @@ struct Opts {
     #[clap(long, default_value = "9999")]
     p2p_port: u16,
 
+    /// The port to additionally listen on for libp2p connections over WebSocket.
+    ///
+    /// Allows takers to connect through proxies which only permit HTTP(S) traffic. The legacy
+    /// connection is not available over WebSocket.
+    #[clap(long)]
+    p2p_ws_port: Option<u16>,
+
     /// The IP address to listen on for the HTTP API.
     #[clap(long, default_value = "127.0.0.1:8001")]
     http_address: SocketAddr,
@@ -51,10 +68,104 @@ struct Opts {
     #[clap(short, long, default_value = "Debug")]
     log_level: LevelFilter,
 
+    #[clap(flatten)]
+    price_feed: price_feed::Opts,
+
+    /// Register at the rendezvous node with the given address, so takers can discover us.
+    ///
+    /// The address has to end with the peer ID of the rendezvous node, e.g.
+    /// `/ip4/1.2.3.4/tcp/10000/p2p/12D3KooW...`.
+    #[clap(long, requires = "external_address")]
+    rendezvous: Option<Multiaddr>,
+
+    /// The address under which takers can reach our p2p port, published at the rendezvous node.
+    #[clap(long)]
+    external_address: Option<SocketAddr>,
+
+    /// The onion address under which takers can reach our p2p port, e.g. `/onion3/<ONION>:10001`.
+    ///
+    /// The onion service has to forward this port to our p2p port and the port below to our
+    /// legacy port. The address is published at the rendezvous node in addition to the external
+    /// address.
+    #[clap(long, requires = "rendezvous")]
+    onion_service: Option<Multiaddr>,
+
+    /// Only accept the given takers, identified by their identity or peer ID.
+    ///
+    /// Can be specified multiple times. If not specified, all takers which are not banned are
+    /// accepted. The lists can be changed at runtime via `PUT /api/access-policy`, which is stored
+    /// in the database. The takers given here are applied on top of the stored lists, but are not
+    /// stored themselves.
+    #[clap(long = "allow-taker", multiple_occurrences = true)]
+    allowed_takers: Vec<access_control::TakerId>,
+
+    /// Never accept the given takers, identified by their identity or peer ID.
+    ///
+    /// Can be specified multiple times.
+    #[clap(long = "ban-taker", multiple_occurrences = true)]
+    banned_takers: Vec<access_control::TakerId>,
+
+    /// The maximum number of takers connected at the same time.
+    #[clap(long)]
+    max_takers: Option<usize>,
+
+    /// The maximum number of connection attempts per minute from a single IP address.
+    #[clap(long)]
+    max_connections_per_ip: Option<usize>,
+
+    /// The maximum number of connection attempts per minute from a single taker.
+    #[clap(long)]
+    max_connections_per_identity: Option<usize>,
+
+    /// Roll over CFDs with the funding rate they were opened with instead of the one of the
+    /// current offers.
+    #[clap(long)]
+    rollover_keep_funding_rate: bool,
+
+    /// Build the CETs of rolled over CFDs with the transaction fee rate they were opened with
+    /// instead of the one of the current offers.
+    #[clap(long)]
+    rollover_keep_tx_fee_rate: bool,
+
+    /// Build the CETs of rolled over CFDs with the given number of payouts.
+    ///
+    /// Takers up to version 0.4.12 do not support this and fail to roll over.
+    #[clap(long)]
+    rollover_n_payouts: Option<usize>,
+
+    /// Notify the given webhook about the lifecycle of CFDs, given as `<URL>,<SECRET>`.
+    ///
+    /// Can be specified multiple times. Webhooks can also be managed at runtime via
+    /// `/api/webhooks`.
+    #[clap(long = "webhook", multiple_occurrences = true)]
+    webhooks: Vec<webhook::NewWebhook>,
+
     #[clap(subcommand)]
     network: Network,
 }
 
+impl Opts {
+    fn access_policy(&self) -> access_control::Policy {
+        access_control::Policy {
+            allowlist: self.allowed_takers.iter().copied().collect(),
+            banlist: self.banned_takers.iter().copied().collect(),
+            limits: access_control::Limits {
+                max_takers: self.max_takers,
+                max_connections_per_ip: self.max_connections_per_ip,
+                max_connections_per_identity: self.max_connections_per_identity,
+            },
+        }
+    }
+
+    fn rollover_policy(&self) -> rollover_maker::Policy {
+        rollover_maker::Policy {
+            renegotiate_funding_rate: !self.rollover_keep_funding_rate,
+            renegotiate_tx_fee_rate: !self.rollover_keep_tx_fee_rate,
+            n_payouts: self.rollover_n_payouts,
+        }
+    }
+}
+
 #[derive(Parser)]
 enum Network {
     /// Run on mainnet.
@@ -64,7 +175,7 @@ enum Network {
         electrum: String,
 
         #[clap(subcommand)]
-        withdraw: Option<Withdraw>,
+        command: Option<Command>,
     },
     /// Run on testnet.
     Testnet {
@@ -73,7 +184,7 @@ enum Network {
         electrum: String,
 
         #[clap(subcommand)]
-        withdraw: Option<Withdraw>,
+        command: Option<Command>,
     },
     /// Run on signet
     Signet {
@@ -82,12 +193,13 @@ enum Network {
         electrum: String,
 
         #[clap(subcommand)]
-        withdraw: Option<Withdraw>,
+        command: Option<Command>,
     },
 }
 
 #[derive(Subcommand)]
-enum Withdraw {
+enum Command {
+    /// Withdraw Bitcoin from the wallet.
     Withdraw {
         /// Optionally specify the amount of Bitcoin to be withdrawn. If not specified the wallet
         /// will be drained. Amount is to be specified with denomination, e.g. "0.1 BTC"
@@ -101,6 +213,40 @@ enum Withdraw {
         #[clap(long)]
         address: bdk::bitcoin::Address,
     },
+    /// Write an encrypted backup of all open CFDs to the given file.
+    ///
+    /// Can be run while the maker is running. The backup can only be restored with the same seed.
+    Backup {
+        #[clap(long)]
+        output: PathBuf,
+    },
+    /// Restore an encrypted backup of open CFDs into a new database.
+    ///
+    /// The seed the backup was created with has to be in the data directory already.
+    Restore {
+        #[clap(long)]
+        input: PathBuf,
+    },
+    /// Create an API token and print its secret.
+    ///
+    /// The token is passed as `Authorization: Bearer <TOKEN>` header and only grants access to the
+    /// routes its scopes allow. The secret is only printed once.
+    CreateToken {
+        /// A unique name to recognise the token by.
+        #[clap(long)]
+        name: String,
+        /// The scopes of the token, one of read, trade, withdraw or admin. Can be given multiple
+        /// times.
+        #[clap(long = "scope", required = true)]
+        scopes: Vec<api_token::Scope>,
+    },
+    /// List all API tokens with their scopes.
+    ListTokens,
+    /// Revoke the API token with the given name.
+    RevokeToken {
+        #[clap(long)]
+        name: String,
+    },
 }
 
 impl Network {
@@ -128,11 +274,11 @@ impl Network {
         }
     }
 
-    fn withdraw(&self) -> &Option<Withdraw> {
+    fn command(&self) -> &Option<Command> {
         match self {
-            Network::Mainnet { withdraw, .. } => withdraw,
-            Network::Testnet { withdraw, .. } => withdraw,
-            Network::Signet { withdraw, .. } => withdraw,
+            Network::Mainnet { command, .. } => command,
+            Network::Testnet { command, .. } => command,
+            Network::Signet { command, .. } => command,
         }
     }
 }
@@ -160,7 +306,98 @@ async fn main() -> Result<()> {
         tokio::fs::create_dir_all(&data_dir).await?;
     }
 
-    let seed = RandomSeed::initialize(&data_dir.join("maker_seed")).await?;
+    let seed_file = data_dir.join("maker_seed");
+    if matches!(opts.network.command(), Some(Command::Restore { .. })) && !seed_file.exists() {
+        anyhow::bail!(
+            "No seed found at {}, copy the seed the backup was created with to the data directory",
+            seed_file.display()
+        );
+    }
+    let seed = RandomSeed::initialize(&seed_file).await?;
+    let backup_key = seed.derive_backup_key::<backup::Key>();
+    let db_key = seed.derive_db_encryption_key::<db::EncryptionKey>();
+
+    let db_path = data_dir.join("maker.sqlite");
+
+    match opts.network.command() {
+        Some(Command::Backup { output }) => {
+            let db = db::connect(db_path.clone())
+                .await?
+                .encrypt_at_rest(db_key)
+                .await?;
+            let backup = backup::create(&db, &backup_key).await?;
+
+            tokio::fs::write(output, backup)
+                .await
+                .with_context(|| format!("Failed to write backup to {}", output.display()))?;
+            tracing::info!("Wrote backup of open CFDs to {}", output.display());
+
+            return Ok(());
+        }
+        Some(Command::Restore { input }) => {
+            let backup = tokio::fs::read(input)
+                .await
+                .with_context(|| format!("Failed to read backup from {}", input.display()))?;
+
+            let db = db::connect(db_path.clone())
+                .await?
+                .encrypt_at_rest(db_key)
+                .await?;
+            backup::restore(&db, &backup_key, &backup).await?;
+
+            return Ok(());
+        }
+        Some(Command::CreateToken { name, scopes }) => {
+            let db = db::connect(db_path.clone())
+                .await?
+                .encrypt_at_rest(db_key)
+                .await?;
+            let token = api_token::create(
+                &db,
+                api_token::NewToken {
+                    name: name.clone(),
+                    scopes: scopes.clone(),
+                },
+            )
+            .await?;
+
+            println!("{}", token.secret);
+
+            return Ok(());
+        }
+        Some(Command::ListTokens) => {
+            let db = db::connect(db_path.clone())
+                .await?
+                .encrypt_at_rest(db_key)
+                .await?;
+
+            for token in db.load_api_tokens().await? {
+                let scopes = token
+                    .scopes
+                    .iter()
+                    .map(|scope| scope.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",");
+                println!("{}\t{scopes}\t{}", token.name, token.created_at.seconds());
+            }
+
+            return Ok(());
+        }
+        Some(Command::RevokeToken { name }) => {
+            let db = db::connect(db_path.clone())
+                .await?
+                .encrypt_at_rest(db_key)
+                .await?;
+
+            if !db.delete_api_token(name).await? {
+                anyhow::bail!("No API token named {name}");
+            }
+            tracing::info!("Revoked API token {name}");
+
+            return Ok(());
+        }
+        Some(Command::Withdraw { .. }) | None => {}
+    }
 
     let bitcoin_network = opts.network.bitcoin_network();
     let ext_priv_key = seed.derive_extended_priv_key(bitcoin_network)?;
@@ -171,11 +408,11 @@ async fn main() -> Result<()> {
 
     let wallet = wallet.create(None).spawn(&mut tasks);
 
-    if let Some(Withdraw::Withdraw {
+    if let Some(Command::Withdraw {
         amount,
         address,
         fee,
-    }) = opts.network.withdraw()
+    }) = opts.network.command()
     {
         wallet
             .send(wallet::Withdraw {
@@ -194,6 +431,8 @@ async fn main() -> Result<()> {
     let identities = seed.derive_identities();
 
     let peer_id = identities.peer_id();
+    let libp2p_identity = identities.libp2p.clone();
+    let maker_identity = Identity::new(identities.identity_pk);
     let hex_pk = hex::encode(identities.identity_pk.to_bytes());
     tracing::info!("Authentication details: username='{auth_username}' password='{auth_password}'");
     tracing::info!("Connection details: maker_id='{hex_pk}', peer_id='{peer_id}'");
@@ -205,8 +444,19 @@ async fn main() -> Result<()> {
 
     let p2p_port = opts.p2p_port;
     let p2p_socket = format!("0.0.0.0:{p2p_port}").parse::<SocketAddr>().unwrap();
+    let p2p_ws_socket = opts
+        .p2p_ws_port
+        .map(|port| SocketAddr::from(([0, 0, 0, 0], port)));
 
-    let db = db::connect(data_dir.join("maker.sqlite")).await?;
+    let db = db::connect(db_path).await?.encrypt_at_rest(db_key).await?;
+
+    for webhook in &opts.webhooks {
+        db.upsert_webhook(webhook)
+            .await
+            .with_context(|| format!("Failed to add webhook {}", webhook.url))?;
+    }
+
+    let access_policy = db.load_access_policy().await?.unwrap_or_default();
 
     // Create actors
 
@@ -225,26 +475,48 @@ async fn main() -> Result<()> {
         },
         SETTLEMENT_INTERVAL,
         N_PAYOUTS,
+        opts.rollover_policy(),
         projection_actor.clone(),
         identities,
         HEARTBEAT_INTERVAL,
         p2p_socket,
+        p2p_ws_socket,
+        access_policy,
+        opts.access_policy(),
     )?;
 
-    let (supervisor, price_feed) =
-        supervisor::Actor::with_policy(xtra_bitmex_price_feed::Actor::default, |e| match e {
-            xtra_bitmex_price_feed::Error::FailedToParseQuote { .. }
-            | xtra_bitmex_price_feed::Error::Failed { .. }
-            | xtra_bitmex_price_feed::Error::Unspecified
-            | xtra_bitmex_price_feed::Error::StreamEnded => true, // always restart price feed actor
-        });
-
-    let _supervisor_address = supervisor.create(None).spawn(&mut tasks);
+    let price_feed = PriceFeed::new(&opts.price_feed, &mut tasks)?;
 
     let (proj_actor, projection_feeds) =
-        projection::Actor::new(db.clone(), bitcoin_network, &price_feed);
+        projection::Actor::new(db.clone(), bitcoin_network, price_feed.channel());
     tasks.add(projection_context.run(proj_actor));
 
+    let _candles_actor = candles::Actor::new(db.clone(), price_feed.channel())
+        .create(None)
+        .spawn(&mut tasks);
+
+    let _webhook_actor = webhook::Actor::new(db.clone())?
+        .create(None)
+        .spawn(&mut tasks);
+
+    let _rendezvous_client = match (opts.rendezvous.clone(), opts.external_address) {
+        (Some(rendezvous_node), Some(external_address)) => {
+            tracing::info!(%rendezvous_node, %external_address, onion_service = ?opts.onion_service, "Registering at rendezvous node");
+
+            Some(discovery::register_maker(
+                maker.endpoint.clone(),
+                libp2p_identity,
+                rendezvous_node,
+                maker_identity,
+                external_address,
+                opts.onion_service.clone(),
+                projection_feeds.offers.clone(),
+                &mut tasks,
+            )?)
+        }
+        _ => None,
+    };
+
     rocket::custom(figment)
         .manage(projection_feeds)
         .manage(wallet_feed_receiver)
@@ -252,17 +524,34 @@ async fn main() -> Result<()> {
         .manage(auth_username)
         .manage(auth_password)
         .manage(bitcoin_network)
+        .manage(db.clone())
+        .manage(backup_key)
         .mount(
             "/api",
             rocket::routes![
                 routes::maker_feed,
                 routes::put_offer_params,
                 routes::post_cfd_action,
+                routes::post_settlement_counter_offer,
                 routes::get_health_check,
                 routes::post_withdraw_request,
                 routes::get_cfds,
+                routes::get_cfd_history,
                 routes::get_takers,
+                routes::get_access_policy,
+                routes::put_access_policy,
                 routes::get_metrics,
+                routes::get_quote_history,
+                routes::get_event_stream,
+                routes::get_events,
+                routes::get_backup,
+                routes::get_closed_cfds_export,
+                routes::get_webhooks,
+                routes::post_webhook,
+                routes::delete_webhook,
+                routes::get_tokens,
+                routes::post_token,
+                routes::delete_token,
             ],
         )
         .register("/api", rocket::catchers![rocket_basicauth::unauthorized])
@@ -1,12 +1,19 @@
 use anyhow::Result;
+use daemon::access_control;
+use daemon::accounting;
+use daemon::api_token;
+use daemon::backup;
 use daemon::bdk;
 use daemon::bdk::bitcoin::Network;
 use daemon::bdk::blockchain::ElectrumBlockchain;
+use daemon::candles;
+use daemon::db;
 use daemon::oracle;
 use daemon::projection::Cfd;
 use daemon::projection::CfdAction;
 use daemon::projection::Feeds;
 use daemon::wallet;
+use daemon::webhook;
 use daemon::MakerActorSystem;
 use http_api_problem::HttpApiProblem;
 use http_api_problem::StatusCode;
@@ -15,6 +22,7 @@ use model::Identity;
 use model::OpeningFee;
 use model::OrderId;
 use model::Price;
+use model::Timestamp;
 use model::TxFeeRate;
 use model::Usd;
 use model::WalletInfo;
@@ -29,6 +37,12 @@ use rocket_basicauth::Authenticated;
 use rust_embed::RustEmbed;
 use rust_embed_rocket::EmbeddedFileExt;
 use serde::Deserialize;
+use serde::Serialize;
+use shared_bin::auth;
+use shared_bin::events;
+use shared_bin::history;
+use shared_bin::quote_history;
+use shared_bin::ConnectionQuality;
 use shared_bin::ToSseEvent;
 use std::borrow::Cow;
 use std::path::PathBuf;
@@ -43,7 +57,7 @@ pub type Maker = MakerActorSystem<oracle::Actor, wallet::Actor<ElectrumBlockchai
 pub async fn maker_feed(
     rx: &State<Feeds>,
     rx_wallet: &State<watch::Receiver<Option<WalletInfo>>>,
-    _auth: Authenticated,
+    _auth: auth::Read,
 ) -> EventStream![] {
     let rx = rx.inner();
     let mut rx_cfds = rx.cfds.clone();
@@ -122,7 +136,7 @@ pub struct CfdNewOfferParamsRequest {
 pub async fn put_offer_params(
     offer_params: Json<CfdNewOfferParamsRequest>,
     maker: &State<Maker>,
-    _auth: Authenticated,
+    _auth: auth::Trade,
 ) -> Result<(), HttpApiProblem> {
     maker
         .set_offer_params(
@@ -150,7 +164,7 @@ pub async fn post_cfd_action(
     id: Uuid,
     action: String,
     maker: &State<Maker>,
-    _auth: Authenticated,
+    _auth: auth::Trade,
 ) -> Result<(), HttpApiProblem> {
     let id = OrderId::from(id);
     let action = action.parse().map_err(|_| {
@@ -183,6 +197,35 @@ pub async fn post_cfd_action(
     Ok(())
 }
 
+/// The maker POSTs this to reply to a settlement proposal with a different price
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct SettlementCounterOffer {
+    pub price: Price,
+}
+
+#[rocket::post("/cfd/<id>/settlement/counter-offer", data = "<counter_offer>")]
+pub async fn post_settlement_counter_offer(
+    id: Uuid,
+    counter_offer: Json<SettlementCounterOffer>,
+    maker: &State<Maker>,
+    _auth: auth::Trade,
+) -> Result<(), HttpApiProblem> {
+    let id = OrderId::from(id);
+
+    maker
+        .counter_settlement(id, counter_offer.price)
+        .await
+        .map_err(|e| {
+            tracing::warn!(order_id=%id, "Countering settlement proposal failed: {e:#}");
+
+            HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+                .title("Countering settlement proposal failed")
+                .detail(format!("{e:#}"))
+        })?;
+
+    Ok(())
+}
+
 #[rocket::get("/alive")]
 pub fn get_health_check() {}
 
@@ -215,7 +258,7 @@ pub async fn post_withdraw_request(
     withdraw_request: Json<WithdrawRequest>,
     maker: &State<Maker>,
     network: &State<Network>,
-    _auth: Authenticated,
+    _auth: auth::Withdraw,
 ) -> Result<String, HttpApiProblem> {
     let amount =
         (withdraw_request.amount != bdk::bitcoin::Amount::ZERO).then(|| withdraw_request.amount);
@@ -246,7 +289,7 @@ pub async fn post_withdraw_request(
 #[rocket::get("/cfds")]
 pub async fn get_cfds<'r>(
     rx: &State<Feeds>,
-    _auth: Authenticated,
+    _auth: auth::Read,
 ) -> Result<Json<Vec<Cfd>>, HttpApiProblem> {
     let rx = rx.inner();
     let rx_cfds = rx.cfds.clone();
@@ -260,20 +303,391 @@ pub async fn get_cfds<'r>(
     }
 }
 
+/// Load a page of all open and closed CFDs matching the filter, newest first.
+///
+/// Pass the `next_cursor` of a page as `cursor` to load the next one.
+#[rocket::get("/cfds/history?<state>&<position>&<counterparty>&<from>&<to>&<cursor>&<limit>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn get_cfd_history(
+    state: Option<String>,
+    position: Option<String>,
+    counterparty: Option<String>,
+    from: Option<i64>,
+    to: Option<i64>,
+    cursor: Option<String>,
+    limit: Option<u32>,
+    db: &State<db::Connection>,
+    _auth: auth::Read,
+) -> Result<Json<history::Page>, HttpApiProblem> {
+    let filter = history::parse_filter(
+        state.as_deref(),
+        position.as_deref(),
+        counterparty.as_deref(),
+        from,
+        to,
+    )
+    .map_err(|e| {
+        HttpApiProblem::new(StatusCode::BAD_REQUEST)
+            .title("Invalid filter")
+            .detail(format!("{e:#}"))
+    })?;
+    let after = cursor
+        .as_deref()
+        .map(history::parse_cursor)
+        .transpose()
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::BAD_REQUEST)
+                .title("Invalid cursor")
+                .detail(format!("{e:#}"))
+        })?;
+    let limit = limit.unwrap_or(history::DEFAULT_PAGE_SIZE);
+
+    let page = history::load_page(db, filter, after, limit)
+        .await
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+                .title("Failed to load CFDs")
+                .detail(format!("{e:#}"))
+        })?;
+
+    Ok(Json(page))
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectedTaker {
+    identity: Identity,
+    /// Only known if the taker uses the libp2p protocols.
+    peer_id: Option<String>,
+    connection_quality: Option<ConnectionQuality>,
+}
+
 #[rocket::get("/takers")]
 pub async fn get_takers<'r>(
     rx: &State<Feeds>,
-    _auth: Authenticated,
-) -> Result<Json<Vec<Identity>>, HttpApiProblem> {
+    maker: &State<Maker>,
+    _auth: auth::Read,
+) -> Result<Json<Vec<ConnectedTaker>>, HttpApiProblem> {
     let rx = rx.inner();
     let rx_connected_takers = rx.connected_takers.clone();
     let takers = rx_connected_takers.borrow().clone();
 
+    let mut qualities = maker.taker_connection_qualities().await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Failed to load connection quality of takers")
+            .detail(format!("{e:#}"))
+    })?;
+
+    let takers = takers
+        .into_iter()
+        .map(|identity| {
+            let (peer_id, connection_quality) = match qualities.remove(&identity) {
+                Some((peer_id, quality)) => (
+                    Some(peer_id.to_string()),
+                    Some(ConnectionQuality::from(&quality)),
+                ),
+                None => (None, None),
+            };
+
+            ConnectedTaker {
+                identity,
+                peer_id,
+                connection_quality,
+            }
+        })
+        .collect();
+
     Ok(Json(takers))
 }
 
+/// Load the stored allowlist, banlist and connection limits for takers.
+///
+/// The takers and limits given on the command line are not part of the stored policy.
+#[rocket::get("/access-policy")]
+pub async fn get_access_policy(
+    maker: &State<Maker>,
+    _auth: auth::Read,
+) -> Result<Json<access_control::Policy>, HttpApiProblem> {
+    let policy = maker.access_policy().await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Failed to load access policy")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(Json(policy))
+}
+
+/// Replace the stored allowlist, banlist and connection limits for takers.
+///
+/// The takers and limits given on the command line still apply on top of the new policy. Connected
+/// takers which are no longer accepted are disconnected.
+#[rocket::put("/access-policy", data = "<policy>")]
+pub async fn put_access_policy(
+    policy: Json<access_control::Policy>,
+    maker: &State<Maker>,
+    _auth: auth::Admin,
+) -> Result<(), HttpApiProblem> {
+    maker
+        .set_access_policy(policy.into_inner())
+        .await
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+                .title("Updating access policy failed")
+                .detail(format!("{e:#}"))
+        })?;
+
+    Ok(())
+}
+
+#[rocket::get("/quotes/history?<interval>&<from>")]
+pub async fn get_quote_history(
+    interval: String,
+    from: Option<i64>,
+    db: &State<db::Connection>,
+    _auth: auth::Read,
+) -> Result<Json<candles::History>, HttpApiProblem> {
+    let interval = quote_history::parse_interval(&interval)
+        .map_err(|e| HttpApiProblem::new(StatusCode::BAD_REQUEST).detail(format!("{e:#}")))?;
+
+    let history = quote_history::load(db, interval, from).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Failed to load price history")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(Json(history))
+}
+
+/// Stream the events of all open CFDs as they are stored in the database.
+///
+/// Starts after the cursor given as `after` or as `Last-Event-ID` header, or from the beginning.
+/// Key material and signed transactions are stripped from the data of every event.
+#[rocket::get("/events?<after>", format = "text/event-stream")]
+pub async fn get_event_stream(
+    after: Option<i64>,
+    last_event_id: events::LastEventId,
+    db: &State<db::Connection>,
+    _auth: auth::Read,
+) -> EventStream![] {
+    let after = after.or(last_event_id.0).unwrap_or(0);
+
+    events::stream(db.inner().clone(), after)
+}
+
+/// Load a page of the events of all open CFDs as they are stored in the database.
+///
+/// Key material and signed transactions are stripped from the data of every event.
+#[rocket::get("/events?<after>&<limit>", rank = 2)]
+pub async fn get_events(
+    after: Option<i64>,
+    limit: Option<u32>,
+    db: &State<db::Connection>,
+    _auth: auth::Read,
+) -> Result<Json<events::Page>, HttpApiProblem> {
+    let after = after.unwrap_or(0);
+    let limit = limit.unwrap_or(events::DEFAULT_PAGE_SIZE);
+
+    let page = events::load_page(db, after, limit).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Failed to load events")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(Json(page))
+}
+
+/// Download an encrypted backup of all open CFDs.
+///
+/// The backup can only be restored with the same seed, using the `restore` command.
+#[rocket::get("/backup")]
+pub async fn get_backup(
+    db: &State<db::Connection>,
+    key: &State<backup::Key>,
+    _auth: auth::Admin,
+) -> Result<(ContentType, Vec<u8>), HttpApiProblem> {
+    let backup = backup::create(db, key).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Failed to create backup")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok((ContentType::Binary, backup))
+}
+
+/// Export the CFDs which were closed within the given time range for accounting.
+///
+/// `from` and `to` are UNIX timestamps in seconds and default to everything closed so far. The
+/// format is either `csv` (default) or `json`.
+#[rocket::get("/cfds/closed/export?<format>&<from>&<to>")]
+pub async fn get_closed_cfds_export(
+    format: Option<String>,
+    from: Option<i64>,
+    to: Option<i64>,
+    db: &State<db::Connection>,
+    _auth: auth::Read,
+) -> Result<(ContentType, String), HttpApiProblem> {
+    let format = format
+        .as_deref()
+        .unwrap_or("csv")
+        .parse::<accounting::Format>()
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::BAD_REQUEST)
+                .detail(format!("{e:#}, expected one of csv, json"))
+        })?;
+    let from = from
+        .map(Timestamp::new)
+        .unwrap_or_else(|| Timestamp::new(0));
+    let to = to.map(Timestamp::new).unwrap_or_else(Timestamp::now);
+
+    let entries = accounting::load_entries(db, from, to).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Failed to load closed CFDs")
+            .detail(format!("{e:#}"))
+    })?;
+
+    let export = match format {
+        accounting::Format::Csv => (ContentType::CSV, accounting::to_csv(&entries)),
+        accounting::Format::Json => {
+            let json = rocket::serde::json::to_string(&entries).map_err(|e| {
+                HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+                    .title("Failed to serialize closed CFDs")
+                    .detail(format!("{e:#}"))
+            })?;
+
+            (ContentType::JSON, json)
+        }
+    };
+
+    Ok(export)
+}
+
+#[rocket::get("/webhooks")]
+pub async fn get_webhooks(
+    db: &State<db::Connection>,
+    _auth: auth::Admin,
+) -> Result<Json<Vec<webhook::Webhook>>, HttpApiProblem> {
+    let webhooks = db.load_webhooks().await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Failed to load webhooks")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(Json(webhooks))
+}
+
+/// Add a webhook which is notified about the lifecycle of CFDs.
+///
+/// If a webhook with the same URL exists already, its secret is replaced.
+#[rocket::post("/webhooks", data = "<webhook>")]
+pub async fn post_webhook(
+    webhook: Json<webhook::NewWebhook>,
+    db: &State<db::Connection>,
+    _auth: auth::Admin,
+) -> Result<Json<webhook::Webhook>, HttpApiProblem> {
+    let webhook = webhook.into_inner();
+    webhook.validate().map_err(|e| {
+        HttpApiProblem::new(StatusCode::BAD_REQUEST)
+            .title("Invalid webhook")
+            .detail(format!("{e:#}"))
+    })?;
+
+    let id = db.upsert_webhook(&webhook).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Failed to add webhook")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(Json(webhook::Webhook {
+        id,
+        url: webhook.url,
+        secret: webhook.secret,
+    }))
+}
+
+/// Remove a webhook, discarding its pending notifications.
+#[rocket::delete("/webhooks/<id>")]
+pub async fn delete_webhook(
+    id: i64,
+    db: &State<db::Connection>,
+    _auth: auth::Admin,
+) -> Result<(), HttpApiProblem> {
+    let deleted = db.delete_webhook(id).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Failed to remove webhook")
+            .detail(format!("{e:#}"))
+    })?;
+
+    if !deleted {
+        return Err(HttpApiProblem::new(StatusCode::NOT_FOUND)
+            .title("Webhook not found")
+            .detail(format!("No webhook with ID {id}")));
+    }
+
+    Ok(())
+}
+
+#[rocket::get("/tokens")]
+pub async fn get_tokens(
+    db: &State<db::Connection>,
+    _auth: auth::Admin,
+) -> Result<Json<Vec<api_token::Token>>, HttpApiProblem> {
+    let tokens = db.load_api_tokens().await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Failed to load API tokens")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(Json(tokens))
+}
+
+/// Create an API token with the given scopes.
+///
+/// The secret of the token is only part of this response and cannot be retrieved later.
+#[rocket::post("/tokens", data = "<token>")]
+pub async fn post_token(
+    token: Json<api_token::NewToken>,
+    db: &State<db::Connection>,
+    _auth: auth::Admin,
+) -> Result<Json<api_token::CreatedToken>, HttpApiProblem> {
+    let token = token.into_inner();
+    token.validate().map_err(|e| {
+        HttpApiProblem::new(StatusCode::BAD_REQUEST)
+            .title("Invalid API token")
+            .detail(format!("{e:#}"))
+    })?;
+
+    let token = api_token::create(db, token).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Failed to create API token")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(Json(token))
+}
+
+/// Revoke the API token with the given name.
+#[rocket::delete("/tokens/<name>")]
+pub async fn delete_token(
+    name: String,
+    db: &State<db::Connection>,
+    _auth: auth::Admin,
+) -> Result<(), HttpApiProblem> {
+    let deleted = db.delete_api_token(&name).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Failed to revoke API token")
+            .detail(format!("{e:#}"))
+    })?;
+
+    if !deleted {
+        return Err(HttpApiProblem::new(StatusCode::NOT_FOUND)
+            .title("API token not found")
+            .detail(format!("No API token named {name}")));
+    }
+
+    Ok(())
+}
+
 #[rocket::get("/metrics")]
-pub async fn get_metrics<'r>(_auth: Authenticated) -> Result<String, HttpApiProblem> {
+pub async fn get_metrics<'r>(_auth: auth::Read) -> Result<String, HttpApiProblem> {
     let metrics = prometheus::TextEncoder::new()
         .encode_to_string(&prometheus::gather())
         .map_err(|e| {
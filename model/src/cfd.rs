@@ -436,6 +436,11 @@ pub enum EventKind {
         proposal: SettlementProposal,
     },
     CollaborativeSettlementProposalAccepted,
+    /// The maker replied to the settlement proposal of the taker with a different price.
+    CollaborativeSettlementCounterOffered {
+        proposal: SettlementProposal,
+    },
+    CollaborativeSettlementCounterOfferAccepted,
     CollaborativeSettlementCompleted {
         #[serde(with = "hex_transaction")]
         spend_tx: Transaction,
@@ -497,11 +502,14 @@ pub enum EventKind {
 impl EventKind {
     pub const CONTRACT_SETUP_COMPLETED_EVENT: &'static str = "ContractSetupCompleted";
     pub const ROLLOVER_COMPLETED_EVENT: &'static str = "RolloverCompleted";
+    pub const COLLABORATIVE_SETTLEMENT_COMPLETED: &'static str = "CollaborativeSettlementCompleted";
     pub const COLLABORATIVE_SETTLEMENT_CONFIRMED: &'static str = "CollaborativeSettlementConfirmed";
     pub const CET_CONFIRMED: &'static str = "CetConfirmed";
     pub const REFUND_CONFIRMED: &'static str = "RefundConfirmed";
     pub const CONTRACT_SETUP_FAILED: &'static str = "ContractSetupFailed";
     pub const OFFER_REJECTED: &'static str = "OfferRejected";
+    pub const ORACLE_ATTESTED_PRIOR_CET_TIMELOCK: &'static str = "OracleAttestedPriorCetTimelock";
+    pub const ORACLE_ATTESTED_POST_CET_TIMELOCK: &'static str = "OracleAttestedPostCetTimelock";
 
     pub fn to_json(&self) -> (String, String) {
         let value = serde_json::to_value(self).expect("serialization to always work");
@@ -543,6 +551,10 @@ impl fmt::Display for EventKind {
             RolloverFailed => "RolloverFailed",
             CollaborativeSettlementStarted { .. } => "CollaborativeSettlementStarted",
             CollaborativeSettlementProposalAccepted => "CollaborativeSettlementProposalAccepted",
+            CollaborativeSettlementCounterOffered { .. } => "CollaborativeSettlementCounterOffered",
+            CollaborativeSettlementCounterOfferAccepted => {
+                "CollaborativeSettlementCounterOfferAccepted"
+            }
             CollaborativeSettlementCompleted { .. } => "CollaborativeSettlementCompleted",
             CollaborativeSettlementRejected => "CollaborativeSettlementRejected",
             CollaborativeSettlementFailed => "CollaborativeSettlementFailed",
@@ -594,7 +606,9 @@ fn from_json_inner(name: String, data: String) -> Result<EventKind> {
 /// we apply the event to the aggregate producing a new aggregate (representing the latest state
 /// `version`). To bring a cfd into a certain state version we load all events from the
 /// database and apply them in order (order by version).
-#[derive(Clone, Debug, PartialEq)]
+///
+/// The aggregate can be serialized to be stored as snapshot, avoiding to apply all events again.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Cfd {
     version: u32,
 
@@ -620,6 +634,7 @@ pub struct Cfd {
     ///
     /// There is not guarantee that the transaction is confirmed if this is set to `Some`.
     /// However, if this is set to `Some`, there is no need to re-emit it as part of another event.
+    #[serde(with = "hex_transaction::opt")]
     cet: Option<Transaction>,
 
     /// Holds the decrypted commit transaction if we have previously emitted it as part of an
@@ -627,9 +642,12 @@ pub struct Cfd {
     ///
     /// There is not guarantee that the transaction is confirmed if this is set to `Some`.
     /// However, if this is set to `Some`, there is no need to re-emit it as part of another event.
+    #[serde(with = "hex_transaction::opt")]
     commit_tx: Option<Transaction>,
 
+    #[serde(with = "hex_transaction::opt")]
     collaborative_settlement_spend_tx: Option<Transaction>,
+    #[serde(with = "hex_transaction::opt")]
     refund_tx: Option<Transaction>,
 
     lock_finality: bool,
@@ -858,27 +876,37 @@ impl Cfd {
             bail!("Start contract not allowed in version {}", self.version)
         }
 
-        let margin = self.margin();
-        let counterparty_margin = self.counterparty_margin();
-
         Ok((
             CfdEvent::new(self.id(), EventKind::ContractSetupStarted),
-            SetupParams::new(
-                margin,
-                counterparty_margin,
-                self.counterparty_network_identity,
-                self.initial_price,
-                self.quantity,
-                self.long_leverage,
-                self.short_leverage,
-                self.refund_timelock_in_blocks(),
-                self.initial_tx_fee_rate(),
-                self.fee_account,
-            )?,
+            self.setup_params()?,
             self.position,
         ))
     }
 
+    /// The parameters of the contract setup in progress, to resume it after a restart.
+    pub fn resume_contract_setup(&self) -> Result<(SetupParams, Position)> {
+        if !self.during_contract_setup {
+            bail!("The CFD is not in contract setup");
+        }
+
+        Ok((self.setup_params()?, self.position))
+    }
+
+    fn setup_params(&self) -> Result<SetupParams> {
+        SetupParams::new(
+            self.margin(),
+            self.counterparty_margin(),
+            self.counterparty_network_identity,
+            self.initial_price,
+            self.quantity,
+            self.long_leverage,
+            self.short_leverage,
+            self.refund_timelock_in_blocks(),
+            self.initial_tx_fee_rate(),
+            self.fee_account,
+        )
+    }
+
     pub fn start_rollover(&self) -> Result<CfdEvent> {
         if self.during_rollover {
             bail!("The CFD is already being rolled over")
@@ -893,6 +921,7 @@ impl Cfd {
         self,
         tx_fee_rate: TxFeeRate,
         funding_rate: FundingRate,
+        n_payouts: usize,
         version: rollover::Version,
     ) -> Result<(CfdEvent, RolloverParams, Dlc, Position, Duration)> {
         if !self.during_rollover {
@@ -922,6 +951,8 @@ impl Cfd {
             rollover_version = %version,
             %hours_to_charge,
             funding_fee = %funding_fee.compute_relative(self.position),
+            %tx_fee_rate,
+            %n_payouts,
             "Accepting rollover proposal"
         );
 
@@ -936,6 +967,7 @@ impl Cfd {
                 tx_fee_rate,
                 self.fee_account,
                 funding_fee,
+                n_payouts,
                 version,
             ),
             self.dlc.clone().context("No DLC present")?,
@@ -944,10 +976,24 @@ impl Cfd {
         ))
     }
 
+    /// The DLC which is being rolled over, to resume the rollover after a restart.
+    ///
+    /// The terms of the rollover are not part of the CFD, they have to be stored alongside the
+    /// state of the protocol.
+    pub fn resume_rollover(&self) -> Result<(Dlc, Position)> {
+        if !self.during_rollover {
+            bail!("The CFD is not rolling over");
+        }
+
+        Ok((self.dlc.clone().context("No DLC present")?, self.position))
+    }
+
     pub fn handle_rollover_accepted_taker(
         &self,
         tx_fee_rate: TxFeeRate,
         funding_rate: FundingRate,
+        n_payouts: usize,
+        version: rollover::Version,
     ) -> Result<(CfdEvent, RolloverParams, Dlc, Position)> {
         if !self.during_rollover {
             bail!("The CFD is not rolling over");
@@ -959,7 +1005,10 @@ impl Cfd {
 
         self.can_rollover()?;
 
-        let hours_to_charge = self.hours_to_extend_in_rollover()?;
+        let hours_to_charge = match version {
+            rollover::Version::V1 => 1,
+            rollover::Version::V2 => self.hours_to_extend_in_rollover()?,
+        };
         let funding_fee = FundingFee::calculate(
             self.initial_price,
             self.quantity,
@@ -980,18 +1029,23 @@ impl Cfd {
                 tx_fee_rate,
                 self.fee_account,
                 funding_fee,
-                rollover::Version::V2,
+                n_payouts,
+                version,
             ),
             self.dlc.clone().context("No DLC present")?,
             self.position,
         ))
     }
 
+    /// Sign the collaborative settlement transaction with the signature of the taker.
+    ///
+    /// Returns the signature of the maker as well, so that the taker can finalize the transaction
+    /// too.
     pub fn sign_collaborative_settlement_maker(
         &self,
         proposal: SettlementProposal,
         sig_taker: Signature,
-    ) -> Result<CollaborativeSettlement> {
+    ) -> Result<(CollaborativeSettlement, Signature)> {
         debug_assert_eq!(
             self.role,
             Role::Maker,
@@ -1011,7 +1065,7 @@ impl Cfd {
         let script_pk = dlc.script_pubkey_for(Role::Maker);
 
         let settlement = CollaborativeSettlement::new(spend_tx, script_pk, proposal.price)?;
-        Ok(settlement)
+        Ok((settlement, sig_maker))
     }
 
     pub fn propose_collaborative_settlement(
@@ -1026,30 +1080,13 @@ impl Cfd {
             "Failed to propose collaborative settlement"
         );
 
-        let payout_curve = calculate_payouts(
-            self.position,
-            self.role,
-            self.initial_price,
-            self.quantity,
-            self.long_leverage,
-            self.short_leverage,
-            n_payouts,
-            self.fee_account.settle(),
-        )?;
-
-        let payout = {
-            let current_price = current_price.try_into_u64()?;
-            payout_curve
-                .iter()
-                .find(|&x| x.digits().range().contains(&current_price))
-                .context("find current price on the payout curve")?
-        };
+        let (taker, maker) = self.settlement_amounts(current_price, n_payouts)?;
 
         let proposal = SettlementProposal {
             order_id: self.id,
             timestamp: Timestamp::now(),
-            taker: *payout.taker_amount(),
-            maker: *payout.maker_amount(),
+            taker,
+            maker,
             price: current_price,
         };
 
@@ -1076,8 +1113,88 @@ impl Cfd {
         );
 
         // Validate that the amounts sent by the taker are sane according to the payout curve
+        let (taker, maker) = self.settlement_amounts(proposal.price, n_payouts)?;
+
+        if proposal.maker != maker || proposal.taker != taker {
+            bail!("The settlement amounts sent by the taker are not according to the agreed payout curve. Expected taker {} and maker {} but received taker {} and maker {}", taker, maker, proposal.taker, proposal.maker);
+        }
+
+        Ok(CfdEvent::new(
+            self.id,
+            EventKind::CollaborativeSettlementStarted { proposal },
+        ))
+    }
+
+    /// Reply to the settlement proposal of the taker with a different price.
+    pub fn counter_collaborative_settlement_proposal(
+        self,
+        price: Price,
+        n_payouts: usize,
+    ) -> Result<(CfdEvent, SettlementProposal)> {
+        anyhow::ensure!(
+            self.role == Role::Maker && self.settlement_proposal.is_some(),
+            "Can only counter a pending settlement proposal as maker"
+        );
+
+        let (taker, maker) = self.settlement_amounts(price, n_payouts)?;
+
+        let counter_offer = SettlementProposal {
+            order_id: self.id,
+            timestamp: Timestamp::now(),
+            taker,
+            maker,
+            price,
+        };
+
+        Ok((
+            self.event(EventKind::CollaborativeSettlementCounterOffered {
+                proposal: counter_offer,
+            }),
+            counter_offer,
+        ))
+    }
+
+    pub fn receive_collaborative_settlement_counter_offer(
+        self,
+        counter_offer: SettlementProposal,
+        n_payouts: usize,
+    ) -> Result<CfdEvent> {
+        anyhow::ensure!(
+            self.role == Role::Taker
+                && self.settlement_proposal.is_some()
+                && counter_offer.order_id == self.id,
+            "Failed to receive settlement counter-offer"
+        );
+
+        // Validate that the amounts sent by the maker are sane according to the payout curve
+        let (taker, maker) = self.settlement_amounts(counter_offer.price, n_payouts)?;
+
+        if counter_offer.maker != maker || counter_offer.taker != taker {
+            bail!("The settlement amounts sent by the maker are not according to the agreed payout curve. Expected taker {} and maker {} but received taker {} and maker {}", taker, maker, counter_offer.taker, counter_offer.maker);
+        }
+
+        Ok(
+            self.event(EventKind::CollaborativeSettlementCounterOffered {
+                proposal: counter_offer,
+            }),
+        )
+    }
+
+    pub fn accept_collaborative_settlement_counter_offer(
+        self,
+        counter_offer: &SettlementProposal,
+    ) -> Result<CfdEvent> {
+        anyhow::ensure!(
+            self.role == Role::Taker && self.settlement_proposal.as_ref() == Some(counter_offer),
+            "Can only accept the pending settlement counter-offer as taker"
+        );
+
+        Ok(self.event(EventKind::CollaborativeSettlementCounterOfferAccepted))
+    }
 
-        let payout_curve_long = calculate_payouts(
+    /// The amounts the taker and the maker get paid when settling at the given price.
+    fn settlement_amounts(&self, price: Price, n_payouts: usize) -> Result<(Amount, Amount)> {
+        let payout_curve = calculate_payouts(
             self.position,
             self.role,
             self.initial_price,
@@ -1088,22 +1205,13 @@ impl Cfd {
             self.fee_account.settle(),
         )?;
 
-        let payout = {
-            let proposal_price = proposal.price.try_into_u64()?;
-            payout_curve_long
-                .iter()
-                .find(|&x| x.digits().range().contains(&proposal_price))
-                .context("find current price on the payout curve")?
-        };
-
-        if proposal.maker != *payout.maker_amount() || proposal.taker != *payout.taker_amount() {
-            bail!("The settlement amounts sent by the taker are not according to the agreed payout curve. Expected taker {} and maker {} but received taker {} and maker {}", payout.taker_amount(), payout.maker_amount(), proposal.taker, proposal.maker);
-        }
+        let price = price.try_into_u64()?;
+        let payout = payout_curve
+            .iter()
+            .find(|&x| x.digits().range().contains(&price))
+            .context("find current price on the payout curve")?;
 
-        Ok(CfdEvent::new(
-            self.id,
-            EventKind::CollaborativeSettlementStarted { proposal },
-        ))
+        Ok((*payout.taker_amount(), *payout.maker_amount()))
     }
 
     pub fn accept_collaborative_settlement_proposal(
@@ -1412,6 +1520,35 @@ impl Cfd {
         Ok((tx, sig, script_pk))
     }
 
+    /// Finalize the collaborative settlement transaction with the signature of the maker.
+    ///
+    /// This gives the taker a transaction it can publish itself.
+    pub fn finalize_collaborative_settlement_taker(
+        &self,
+        proposal: &SettlementProposal,
+        sig_maker: Signature,
+    ) -> Result<CollaborativeSettlement> {
+        debug_assert_eq!(
+            self.role,
+            Role::Taker,
+            "Only the taker can finalize collaborative settlement"
+        );
+
+        let dlc = self
+            .dlc
+            .as_ref()
+            .context("Collaborative close without DLC")?;
+
+        let (tx, sig_taker, lock_amount) = dlc.close_transaction(proposal)?;
+
+        let spend_tx = dlc
+            .finalize_spend_transaction(tx, sig_taker, sig_maker, lock_amount)
+            .context("Failed to finalize collaborative settlement transaction")?;
+        let script_pk = dlc.script_pubkey_for(Role::Taker);
+
+        CollaborativeSettlement::new(spend_tx, script_pk, proposal.price)
+    }
+
     /// Number of hours that the time-to-live of the contract will be
     /// extended by with the next rollover.
     ///
@@ -1498,6 +1635,10 @@ impl Cfd {
                 self.settlement_proposal = Some(proposal)
             }
             CollaborativeSettlementProposalAccepted { .. } => {}
+            CollaborativeSettlementCounterOffered { proposal } => {
+                self.settlement_proposal = Some(proposal)
+            }
+            CollaborativeSettlementCounterOfferAccepted => {}
             CollaborativeSettlementCompleted { spend_tx, .. } => {
                 self.settlement_proposal = None;
                 self.collaborative_settlement_spend_tx = Some(spend_tx);
@@ -2557,6 +2698,19 @@ mod tests {
         assert_eq!(cannot_roll_over, NoRolloverReason::TooRecent)
     }
 
+    #[test]
+    fn rolled_over_cfd_can_be_restored_from_snapshot() {
+        let cfd = Cfd::dummy_taker_long()
+            .dummy_open(dummy_event_id())
+            .dummy_rollovers(100, dec!(0.001), 2);
+
+        let snapshot = serde_json::to_string(&cfd).unwrap();
+        let restored = serde_json::from_str::<Cfd>(&snapshot).unwrap();
+
+        assert_eq!(restored, cfd);
+        assert_eq!(restored.version(), cfd.version());
+    }
+
     #[test]
     fn given_cfd_out_of_bounds_expiry_then_no_rollover() {
         // --|----|-------------------------------------------------|--> time
@@ -2642,6 +2796,25 @@ mod tests {
         assert_eq!(funding_fee.rate, funding_rate);
     }
 
+    #[test]
+    fn contract_setup_and_rollover_can_only_be_resumed_while_in_progress() {
+        let cfd = Cfd::dummy_taker_long();
+        assert!(cfd.resume_contract_setup().is_err());
+
+        let (event, setup_params, _) = cfd.start_contract_setup().unwrap();
+        let cfd = cfd.apply(event);
+        let (resumed_params, _) = cfd.resume_contract_setup().unwrap();
+        assert_eq!(resumed_params.margin, setup_params.margin);
+
+        let cfd = Cfd::dummy_taker_long().dummy_open(dummy_event_id());
+        assert!(cfd.resume_contract_setup().is_err());
+        assert!(cfd.resume_rollover().is_err());
+
+        let cfd = cfd.dummy_start_rollover();
+        let (dlc, _) = cfd.resume_rollover().unwrap();
+        assert_eq!(Some(dlc), cfd.dlc);
+    }
+
     #[test]
     fn given_collab_settlement_then_cannot_start_rollover() {
         let quantity = Usd::new(dec!(10));
@@ -2773,6 +2946,51 @@ mod tests {
         );
     }
 
+    #[test]
+    fn taker_and_maker_end_up_with_same_signed_settlement_transaction() {
+        let quantity = Usd::new(dec!(10));
+        let opening_price = Price::new(dec!(10000)).unwrap();
+        let order_id = OrderId::default();
+
+        let taker_keys = keypair::new(&mut thread_rng());
+        let maker_keys = keypair::new(&mut thread_rng());
+
+        let taker_long = Cfd::dummy_taker_long()
+            .with_id(order_id)
+            .with_quantity(quantity)
+            .with_opening_price(opening_price)
+            .dummy_open(dummy_event_id())
+            .with_lock(taker_keys, maker_keys);
+
+        let maker_short = Cfd::dummy_maker_short()
+            .with_id(order_id)
+            .with_quantity(quantity)
+            .with_opening_price(opening_price)
+            .dummy_open(dummy_event_id())
+            .with_lock(taker_keys, maker_keys);
+
+        let (_, proposal) = taker_long
+            .propose_collaborative_settlement(opening_price, N_PAYOUTS)
+            .unwrap();
+        let (_, taker_sig, _) = taker_long
+            .sign_collaborative_settlement_taker(&proposal)
+            .unwrap();
+
+        let (maker_settlement, maker_sig) = maker_short
+            .sign_collaborative_settlement_maker(proposal, taker_sig)
+            .unwrap();
+        let taker_settlement = taker_long
+            .finalize_collaborative_settlement_taker(&proposal, maker_sig)
+            .unwrap();
+
+        assert_eq!(taker_settlement.tx, maker_settlement.tx);
+        assert!(taker_settlement
+            .tx
+            .input
+            .iter()
+            .all(|input| !input.witness.is_empty()));
+    }
+
     #[test]
     fn given_collab_settlement_then_cannot_force_close() {
         let quantity = Usd::new(dec!(10));
@@ -2888,6 +3106,47 @@ mod tests {
         assert!(result_maker.is_err(), "When having commit tx available we should not be able to trigger collaborative settlement");
     }
 
+    #[test]
+    fn taker_only_accepts_counter_offer_according_to_payout_curve() {
+        let taker_long = Cfd::dummy_taker_long().dummy_open(dummy_event_id());
+        let maker_short = Cfd::dummy_maker_short().dummy_open(dummy_event_id());
+
+        let (propose, proposal) = taker_long
+            .clone()
+            .propose_collaborative_settlement(Price::new(dec!(10000)).unwrap(), N_PAYOUTS)
+            .unwrap();
+        let taker_long = taker_long.apply(propose);
+        let receive = maker_short
+            .clone()
+            .receive_collaborative_settlement_proposal(proposal, N_PAYOUTS)
+            .unwrap();
+        let maker_short = maker_short.apply(receive);
+
+        let (_, counter_offer) = maker_short
+            .counter_collaborative_settlement_proposal(Price::new(dec!(9000)).unwrap(), N_PAYOUTS)
+            .unwrap();
+        let tampered_counter_offer = SettlementProposal {
+            taker: counter_offer.taker - Amount::ONE_SAT,
+            maker: counter_offer.maker + Amount::ONE_SAT,
+            ..counter_offer
+        };
+
+        let valid = taker_long
+            .clone()
+            .receive_collaborative_settlement_counter_offer(counter_offer, N_PAYOUTS)
+            .unwrap();
+        let invalid = taker_long
+            .receive_collaborative_settlement_counter_offer(tampered_counter_offer, N_PAYOUTS);
+
+        assert_eq!(
+            valid.event,
+            EventKind::CollaborativeSettlementCounterOffered {
+                proposal: counter_offer
+            }
+        );
+        assert!(invalid.is_err());
+    }
+
     #[test]
     fn given_no_rollover_then_no_rollover_fee() {
         let quantity = Usd::new(dec!(10));
@@ -3595,7 +3854,7 @@ mod tests {
                 .unwrap();
             events.push(accept);
 
-            let settlement = cfd
+            let (settlement, _) = cfd
                 .sign_collaborative_settlement_maker(proposal, taker_signature)
                 .unwrap();
             let script_pubkey = settlement.script_pubkey.clone();
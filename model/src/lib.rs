@@ -772,8 +772,9 @@ pub enum FeeFlow {
 /// The balance being positive means we owe this amount to the other party.
 /// The balance being negative means that the other party owes this amount to us.
 /// The counterparty fee-account balance is always the inverse of the balance.
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct FeeAccount {
+    #[serde(with = "::bdk::bitcoin::util::amount::serde::as_sat")]
     balance: SignedAmount,
     position: Position,
     role: Role,
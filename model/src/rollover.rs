@@ -4,8 +4,10 @@ use crate::Leverage;
 use crate::Price;
 use crate::TxFeeRate;
 use crate::Usd;
+use serde::Deserialize;
+use serde::Serialize;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum Version {
     /// Version one of the rollover protocol
     ///
@@ -29,7 +31,7 @@ impl std::fmt::Display for Version {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct RolloverParams {
     pub price: Price,
     pub quantity: Usd,
@@ -39,6 +41,8 @@ pub struct RolloverParams {
     pub fee_rate: TxFeeRate,
     pub fee_account: FeeAccount,
     pub current_fee: FundingFee,
+    /// The number of payouts of the CETs of the new DLC.
+    pub n_payouts: usize,
     pub version: Version,
 }
 
@@ -53,6 +57,7 @@ impl RolloverParams {
         fee_rate: TxFeeRate,
         fee_account: FeeAccount,
         current_fee: FundingFee,
+        n_payouts: usize,
         version: Version,
     ) -> Self {
         Self {
@@ -64,6 +69,7 @@ impl RolloverParams {
             fee_rate,
             fee_account,
             current_fee,
+            n_payouts,
             version,
         }
     }
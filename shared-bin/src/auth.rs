@@ -0,0 +1,101 @@
+//! Request guards which authorize access to the HTTP API.
+//!
+//! A request is either authenticated via basic auth with the password derived from the seed, which
+//! grants every scope, or with an API token passed as `Authorization: Bearer <TOKEN>`, which only
+//! grants the scopes of the token. Every route requires the guard of the scope it needs.
+
+use daemon::api_token;
+use daemon::api_token::Scope;
+use daemon::db;
+use rocket::http::Status;
+use rocket::outcome::try_outcome;
+use rocket::request::FromRequest;
+use rocket::request::Outcome;
+use rocket::Request;
+use rocket::State;
+use rocket_basicauth::Authenticated;
+
+#[derive(Debug)]
+pub enum Error {
+    /// No bearer token was given and basic auth failed.
+    BasicAuth(rocket_basicauth::Error),
+    UnknownToken,
+    /// The token is valid but does not have the required scope.
+    MissingScope(Scope),
+    /// The database was not configured in Rocket's state.
+    MissingDatabase,
+    Database(anyhow::Error),
+}
+
+async fn authorize(req: &Request<'_>, required: Scope) -> Outcome<(), Error> {
+    let token = req
+        .headers()
+        .get_one("Authorization")
+        .and_then(|header| header.strip_prefix("Bearer "));
+
+    let token = match token {
+        Some(token) => token,
+        None => {
+            return req
+                .guard::<Authenticated>()
+                .await
+                .map(|_| ())
+                .map_failure(|(status, e)| (status, Error::BasicAuth(e)))
+        }
+    };
+
+    let db = try_outcome!(req
+        .guard::<&State<db::Connection>>()
+        .await
+        .map_failure(|(status, _)| (status, Error::MissingDatabase)));
+
+    let scopes = match db.load_api_token_scopes(&api_token::hash(token)).await {
+        Ok(Some(scopes)) => scopes,
+        Ok(None) => return Outcome::Failure((Status::Unauthorized, Error::UnknownToken)),
+        Err(e) => return Outcome::Failure((Status::InternalServerError, Error::Database(e))),
+    };
+
+    if !scopes.iter().any(|scope| scope.grants(required)) {
+        return Outcome::Failure((Status::Forbidden, Error::MissingScope(required)));
+    }
+
+    Outcome::Success(())
+}
+
+macro_rules! scope_guard {
+    ($(#[$meta:meta])* $guard:ident, $scope:expr) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy)]
+        pub struct $guard;
+
+        #[rocket::async_trait]
+        impl<'r> FromRequest<'r> for $guard {
+            type Error = Error;
+
+            async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+                authorize(req, $scope).await.map(|()| $guard)
+            }
+        }
+    };
+}
+
+scope_guard!(
+    /// Requires the [`Scope::Read`] scope, which every token has.
+    Read,
+    Scope::Read
+);
+scope_guard!(
+    /// Requires the [`Scope::Trade`] scope.
+    Trade,
+    Scope::Trade
+);
+scope_guard!(
+    /// Requires the [`Scope::Withdraw`] scope.
+    Withdraw,
+    Scope::Withdraw
+);
+scope_guard!(
+    /// Requires the [`Scope::Admin`] scope.
+    Admin,
+    Scope::Admin
+);
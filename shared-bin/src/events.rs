@@ -0,0 +1,206 @@
+//! Expose the events of CFDs to external consumers.
+//!
+//! In contrast to the feeds, which only contain the projection of every CFD, these are the events
+//! as they are stored in the database. This allows external systems to rebuild the state of CFDs
+//! independently of the projection.
+//!
+//! The data of every event is reduced to what is safe to pass on, see [`public_data`]. Key
+//! material is left out and signed transactions are replaced by their txid, so that consumers can
+//! neither spend the funds of a CFD nor publish one of its transactions.
+//!
+//! Every event has a cursor, which increases monotonically. Consumers can resume from the cursor
+//! of the last event they processed, either by passing it as `after` or, for the event stream, as
+//! the `Last-Event-ID` header. Events of CFDs which have been closed are no longer available.
+
+use anyhow::Context;
+use anyhow::Result;
+use daemon::db;
+use model::Dlc;
+use model::EventKind;
+use model::OrderId;
+use model::Timestamp;
+use rocket::request::FromRequest;
+use rocket::request::Outcome;
+use rocket::response::stream::Event;
+use rocket::response::stream::EventStream;
+use rocket::serde::json::json;
+use rocket::serde::json::Value;
+use rocket::Request;
+use serde::Serialize;
+use std::convert::Infallible;
+use std::time::Duration;
+
+pub const DEFAULT_PAGE_SIZE: u32 = 100;
+pub const MAX_PAGE_SIZE: u32 = 1000;
+
+/// How often the event stream looks for new events once it caught up.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CfdEvent {
+    pub cursor: i64,
+    pub order_id: OrderId,
+    pub name: String,
+    pub data: Value,
+    pub timestamp: Timestamp,
+}
+
+impl TryFrom<db::StoredEvent> for CfdEvent {
+    type Error = anyhow::Error;
+
+    fn try_from(event: db::StoredEvent) -> Result<Self> {
+        let kind = EventKind::from_json(event.name.clone(), event.data)
+            .with_context(|| format!("Failed to parse data of event {}", event.cursor))?;
+
+        Ok(Self {
+            cursor: event.cursor,
+            order_id: event.order_id,
+            name: event.name,
+            data: public_data(&kind),
+            timestamp: event.timestamp,
+        })
+    }
+}
+
+/// The data of an event which is safe to pass on.
+///
+/// Every event is listed explicitly, so that new events have to be classified before they are
+/// exposed.
+pub fn public_data(event: &EventKind) -> Value {
+    use EventKind::*;
+    match event {
+        ContractSetupCompleted { dlc } => json!({ "dlc": public_dlc(dlc) }),
+        RolloverCompleted { dlc, funding_fee } => json!({
+            "dlc": public_dlc(dlc),
+            "funding_fee": funding_fee,
+        }),
+        CollaborativeSettlementStarted { proposal }
+        | CollaborativeSettlementCounterOffered { proposal } => json!({ "proposal": proposal }),
+        CollaborativeSettlementCompleted {
+            spend_tx, price, ..
+        } => json!({
+            "spend_txid": spend_tx.txid(),
+            "price": price,
+        }),
+        CetTimelockExpiredPostOracleAttestation { cet } => json!({ "cet_txid": cet.txid() }),
+        RefundTimelockExpired { refund_tx } => json!({ "refund_txid": refund_tx.txid() }),
+        OracleAttestedPriorCetTimelock {
+            timelocked_cet,
+            commit_tx,
+            price,
+        } => json!({
+            "cet_txid": timelocked_cet.txid(),
+            "commit_txid": commit_tx.as_ref().map(|tx| tx.txid()),
+            "price": price,
+        }),
+        OracleAttestedPostCetTimelock { cet, price } => json!({
+            "cet_txid": cet.txid(),
+            "price": price,
+        }),
+        ManualCommit { tx } => json!({ "commit_txid": tx.txid() }),
+        ContractSetupStarted
+        | ContractSetupFailed
+        | OfferRejected
+        | RolloverStarted
+        | RolloverAccepted
+        | RolloverRejected
+        | RolloverFailed
+        | CollaborativeSettlementProposalAccepted
+        | CollaborativeSettlementCounterOfferAccepted
+        | CollaborativeSettlementRejected
+        | CollaborativeSettlementFailed
+        | LockConfirmed
+        | LockConfirmedAfterFinality
+        | CommitConfirmed
+        | CetConfirmed
+        | RefundConfirmed
+        | RevokeConfirmed
+        | CollaborativeSettlementConfirmed
+        | CetTimelockExpiredPriorOracleAttestation => Value::Null,
+    }
+}
+
+/// The parts of a DLC which are public, i.e. everything but the secret keys and signatures.
+fn public_dlc(dlc: &Dlc) -> Value {
+    json!({
+        "lock_txid": dlc.lock.0.txid(),
+        "commit_txid": dlc.commit.0.txid(),
+        "refund_txid": dlc.refund.0.txid(),
+        "maker_address": dlc.maker_address,
+        "taker_address": dlc.taker_address,
+        "maker_lock_amount": dlc.maker_lock_amount.as_sat(),
+        "taker_lock_amount": dlc.taker_lock_amount.as_sat(),
+        "settlement_event_id": dlc.settlement_event_id,
+        "refund_timelock": dlc.refund_timelock,
+    })
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Page {
+    pub events: Vec<CfdEvent>,
+    /// The cursor to pass as `after` to load the next page.
+    pub next_cursor: i64,
+}
+
+/// Load up to `limit` events after the given cursor.
+pub async fn load_page(db: &db::Connection, after: i64, limit: u32) -> Result<Page> {
+    let events = db
+        .load_events_after(after, limit.min(MAX_PAGE_SIZE))
+        .await?
+        .into_iter()
+        .map(CfdEvent::try_from)
+        .collect::<Result<Vec<_>>>()?;
+
+    let next_cursor = events.last().map_or(after, |event| event.cursor);
+
+    Ok(Page {
+        events,
+        next_cursor,
+    })
+}
+
+/// Stream all events after the given cursor, followed by every event appended from now on.
+pub fn stream(db: db::Connection, mut after: i64) -> EventStream![] {
+    EventStream! {
+        loop {
+            let page = match load_page(&db, after, MAX_PAGE_SIZE).await {
+                Ok(page) => page,
+                Err(e) => {
+                    tracing::warn!("Failed to load events: {e:#}");
+                    rocket::tokio::time::sleep(POLL_INTERVAL).await;
+                    continue;
+                }
+            };
+
+            let caught_up = page.events.len() < MAX_PAGE_SIZE as usize;
+
+            for event in page.events {
+                yield Event::json(&event)
+                    .event("event")
+                    .id(event.cursor.to_string());
+            }
+            after = page.next_cursor;
+
+            if caught_up {
+                rocket::tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        }
+    }
+}
+
+/// The cursor of the last event an event stream consumer received before reconnecting.
+pub struct LastEventId(pub Option<i64>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for LastEventId {
+    type Error = Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let cursor = request
+            .headers()
+            .get_one("Last-Event-ID")
+            .and_then(|id| id.parse().ok());
+
+        Outcome::Success(LastEventId(cursor))
+    }
+}
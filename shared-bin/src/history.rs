@@ -0,0 +1,122 @@
+//! History of all CFDs, open as well as closed, loaded page by page from the database.
+//!
+//! In contrast to the feed, which only contains the CFDs the projection knows about, the history
+//! includes the details of how closed CFDs were settled. CFDs are ordered newest first and every
+//! page contains the cursor to load the next one. Cursors stay valid when CFDs are closed.
+
+use anyhow::Context;
+use anyhow::Result;
+use daemon::accounting;
+use daemon::db;
+use model::Identity;
+use model::Leverage;
+use model::OrderId;
+use model::Position;
+use model::Price;
+use model::Role;
+use model::Timestamp;
+use model::Usd;
+use serde::Serialize;
+
+pub const DEFAULT_PAGE_SIZE: u32 = 100;
+pub const MAX_PAGE_SIZE: u32 = 1000;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Cfd {
+    pub order_id: OrderId,
+    pub state: db::HistoryState,
+    pub position: Position,
+    pub role: Role,
+    pub counterparty: Identity,
+    pub quantity_usd: Usd,
+    pub leverage_taker: Leverage,
+    pub initial_price: Price,
+    pub created_at: Timestamp,
+    /// How the CFD was closed, only set if it is closed.
+    pub closed: Option<accounting::Entry>,
+}
+
+impl From<db::HistoricCfd> for Cfd {
+    fn from(cfd: db::HistoricCfd) -> Self {
+        Self {
+            order_id: cfd.order_id,
+            state: cfd.state,
+            position: cfd.position,
+            role: cfd.role,
+            counterparty: cfd.counterparty,
+            quantity_usd: cfd.quantity_usd,
+            leverage_taker: cfd.taker_leverage,
+            initial_price: cfd.initial_price,
+            created_at: cfd.created_at,
+            closed: cfd.closed.map(accounting::Entry::from),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Page {
+    pub cfds: Vec<Cfd>,
+    /// The cursor to pass as `cursor` to load the next page, `None` once there are no more CFDs.
+    pub next_cursor: Option<String>,
+}
+
+/// Parse the filter from the query parameters of a request.
+///
+/// `state` is one of `open`, `failed` or `closed`, `position` is either `long` or `short` and
+/// `from` and `to` are UNIX timestamps in seconds.
+pub fn parse_filter(
+    state: Option<&str>,
+    position: Option<&str>,
+    counterparty: Option<&str>,
+    from: Option<i64>,
+    to: Option<i64>,
+) -> Result<db::CfdFilter> {
+    let state = state.map(str::parse::<db::HistoryState>).transpose()?;
+    let position = position
+        .map(|position| match position {
+            "long" => Ok(Position::Long),
+            "short" => Ok(Position::Short),
+            unknown => anyhow::bail!("Unknown position: {unknown}"),
+        })
+        .transpose()?;
+    let counterparty = counterparty
+        .map(str::parse::<Identity>)
+        .transpose()
+        .context("Invalid counterparty")?;
+
+    Ok(db::CfdFilter {
+        state,
+        position,
+        counterparty,
+        from: from.map(Timestamp::new),
+        to: to.map(Timestamp::new),
+    })
+}
+
+/// Parse the cursor returned as `next_cursor` by a previous request.
+pub fn parse_cursor(cursor: &str) -> Result<db::HistoryCursor> {
+    cursor
+        .parse()
+        .with_context(|| format!("Invalid cursor: {cursor}"))
+}
+
+/// Load up to `limit` CFDs matching the filter, after the given cursor.
+pub async fn load_page(
+    db: &db::Connection,
+    filter: db::CfdFilter,
+    after: Option<db::HistoryCursor>,
+    limit: u32,
+) -> Result<Page> {
+    let limit = limit.clamp(1, MAX_PAGE_SIZE);
+    let cfds = db.load_cfd_history(filter, after, limit).await?;
+
+    let next_cursor = match cfds.last() {
+        Some(cfd) if cfds.len() == limit as usize => Some(cfd.cursor().to_string()),
+        _ => None,
+    };
+
+    Ok(Page {
+        cfds: cfds.into_iter().map(Cfd::from).collect(),
+        next_cursor,
+    })
+}
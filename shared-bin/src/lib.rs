@@ -1,5 +1,10 @@
+pub mod auth;
+pub mod events;
 pub mod fairings;
+pub mod history;
 pub mod logger;
+pub mod price_feed;
+pub mod quote_history;
 mod to_sse_event;
 
 pub use crate::to_sse_event::*;
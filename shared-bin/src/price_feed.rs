@@ -0,0 +1,107 @@
+use anyhow::Result;
+use clap::Args;
+use daemon::MAX_QUOTE_AGE;
+use std::path::PathBuf;
+use tokio_tasks::Tasks;
+use xtra::message_channel::MessageChannel;
+use xtra::Actor as _;
+use xtra::Address;
+use xtra_bitmex_price_feed::LatestQuote;
+use xtras::supervisor;
+
+#[derive(Args, Clone)]
+pub struct Opts {
+    /// Record all messages received from BitMex to the given file.
+    ///
+    /// While recording, BitMex is used as the only price source so that a replay reproduces
+    /// exactly the quotes the daemon acted upon.
+    #[clap(long, conflicts_with = "replay_price_feed")]
+    pub record_price_feed: Option<PathBuf>,
+
+    /// Play back a file recorded with `--record-price-feed` instead of connecting to any exchange.
+    #[clap(long)]
+    pub replay_price_feed: Option<PathBuf>,
+
+    /// Speed-up factor for `--replay-price-feed`, e.g. `60` plays back one hour of quotes per
+    /// minute.
+    #[clap(long, default_value = "1")]
+    pub replay_speed: f64,
+}
+
+/// The price feed selected on the command line.
+pub enum PriceFeed {
+    /// The median of all supported exchanges.
+    Aggregated {
+        actor: Address<xtra_price_feed::Actor>,
+        _supervisor: Address<supervisor::Actor<xtra_price_feed::Actor, xtra_price_feed::Error>>,
+    },
+    /// BitMex only, recording every message.
+    Recording {
+        actor: Address<xtra_bitmex_price_feed::Actor>,
+        _supervisor: Address<
+            supervisor::Actor<xtra_bitmex_price_feed::Actor, xtra_bitmex_price_feed::Error>,
+        >,
+    },
+    /// Playback of a previous recording.
+    Replay {
+        actor: Address<xtra_bitmex_price_feed::replay::Actor>,
+    },
+}
+
+impl PriceFeed {
+    pub fn new(opts: &Opts, tasks: &mut Tasks) -> Result<Self> {
+        let price_feed = match (&opts.record_price_feed, &opts.replay_price_feed) {
+            (_, Some(recording)) => {
+                let actor = xtra_bitmex_price_feed::replay::Actor::new(
+                    recording.clone(),
+                    opts.replay_speed,
+                )?
+                .create(None)
+                .spawn(tasks);
+
+                PriceFeed::Replay { actor }
+            }
+            (Some(recording), None) => {
+                tracing::info!(recording = %recording.display(), "Recording price feed");
+
+                let recording = recording.clone();
+                let (supervisor, actor) = supervisor::Actor::with_policy(
+                    move || xtra_bitmex_price_feed::Actor::with_recording(recording.clone()),
+                    |_: &xtra_bitmex_price_feed::Error| true, // always restart price feed actor
+                );
+
+                PriceFeed::Recording {
+                    actor,
+                    _supervisor: supervisor.create(None).spawn(tasks),
+                }
+            }
+            (None, None) => {
+                let (supervisor, actor) = supervisor::Actor::with_policy(
+                    || xtra_price_feed::Actor::all_exchanges(MAX_QUOTE_AGE),
+                    |e| match e {
+                        xtra_price_feed::Error::Unspecified => true, // always restart price feed actor
+                    },
+                );
+
+                PriceFeed::Aggregated {
+                    actor,
+                    _supervisor: supervisor.create(None).spawn(tasks),
+                }
+            }
+        };
+
+        Ok(price_feed)
+    }
+
+    pub fn channel(&self) -> Box<dyn MessageChannel<LatestQuote>> {
+        match self {
+            PriceFeed::Aggregated { actor, .. } => {
+                MessageChannel::<LatestQuote>::clone_channel(actor)
+            }
+            PriceFeed::Recording { actor, .. } => {
+                MessageChannel::<LatestQuote>::clone_channel(actor)
+            }
+            PriceFeed::Replay { actor } => MessageChannel::<LatestQuote>::clone_channel(actor),
+        }
+    }
+}
@@ -0,0 +1,29 @@
+//! History of quotes, aggregated into candles of a fixed interval.
+
+use anyhow::anyhow;
+use anyhow::Result;
+use daemon::candles;
+use daemon::db;
+use model::Timestamp;
+
+/// Parse the interval of the candles, either `1m` or `1h`.
+pub fn parse_interval(interval: &str) -> Result<candles::Interval> {
+    interval
+        .parse()
+        .map_err(|_| anyhow!("Invalid interval: {interval}, expected one of 1m, 1h"))
+}
+
+/// Load the candles of the given interval since `from`.
+///
+/// Defaults to everything we still retain for the interval.
+pub async fn load(
+    db: &db::Connection,
+    interval: candles::Interval,
+    from: Option<i64>,
+) -> Result<candles::History> {
+    let from = from.map(Timestamp::new).unwrap_or_else(|| {
+        Timestamp::new(Timestamp::now().seconds() - interval.retention().whole_seconds())
+    });
+
+    candles::load_history(db, interval, from).await
+}
@@ -3,11 +3,14 @@ use crate::ConnectionCloseReason::TakerVersionOutdated;
 use daemon::bdk::bitcoin::Amount;
 use daemon::connection;
 use daemon::projection::Cfd;
+use daemon::projection::CfdOrder;
 use daemon::projection::Quote;
+use daemon::xtra_libp2p_ping;
 use model::Identity;
 use model::Timestamp;
 use rocket::response::stream::Event;
 use serde::Serialize;
+use std::collections::HashMap;
 
 pub trait ToSseEvent {
     fn to_sse_event(&self) -> Event;
@@ -57,9 +60,9 @@ pub enum ConnectionCloseReason {
     TakerVersionOutdated,
 }
 
-impl ToSseEvent for connection::ConnectionStatus {
-    fn to_sse_event(&self) -> Event {
-        let connected = match self {
+impl From<&connection::ConnectionStatus> for ConnectionStatus {
+    fn from(status: &connection::ConnectionStatus) -> Self {
+        match status {
             connection::ConnectionStatus::Online => ConnectionStatus {
                 online: true,
                 connection_close_reason: None,
@@ -79,9 +82,80 @@ impl ToSseEvent for connection::ConnectionStatus {
                     }
                 }),
             },
-        };
+        }
+    }
+}
+
+impl ToSseEvent for connection::ConnectionStatus {
+    fn to_sse_event(&self) -> Event {
+        Event::json(&ConnectionStatus::from(self)).event("maker_status")
+    }
+}
+
+/// The quality of a libp2p connection, as measured by the ping protocol.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionQuality {
+    connected: bool,
+    latest_latency_ms: Option<u128>,
+    mean_latency_ms: Option<u128>,
+    /// The most recent latency measurements, oldest first.
+    latency_history_ms: Vec<u128>,
+    uptime_secs: u64,
+    reconnects: u32,
+}
+
+impl From<&xtra_libp2p_ping::ConnectionQuality> for ConnectionQuality {
+    fn from(quality: &xtra_libp2p_ping::ConnectionQuality) -> Self {
+        ConnectionQuality {
+            connected: quality.connected,
+            latest_latency_ms: quality.latest_latency().map(|latency| latency.as_millis()),
+            mean_latency_ms: quality.mean_latency().map(|latency| latency.as_millis()),
+            latency_history_ms: quality
+                .latencies
+                .iter()
+                .map(|latency| latency.as_millis())
+                .collect(),
+            uptime_secs: quality.uptime.as_secs(),
+            reconnects: quality.reconnects,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MakerConnectionStatus {
+    maker: Identity,
+    #[serde(flatten)]
+    status: ConnectionStatus,
+    /// Only known if we have been connected to the maker's libp2p endpoint recently.
+    connection_quality: Option<ConnectionQuality>,
+}
+
+/// The connection status of every maker, together with the quality of the libp2p connection.
+pub struct MakerStatuses {
+    pub statuses: HashMap<Identity, connection::ConnectionStatus>,
+    pub qualities: HashMap<Identity, xtra_libp2p_ping::ConnectionQuality>,
+}
 
-        Event::json(&connected).event("maker_status")
+impl ToSseEvent for MakerStatuses {
+    fn to_sse_event(&self) -> Event {
+        let mut statuses = self
+            .statuses
+            .iter()
+            .map(|(maker, status)| MakerConnectionStatus {
+                maker: *maker,
+                status: status.into(),
+                connection_quality: self.qualities.get(maker).map(ConnectionQuality::from),
+            })
+            .collect::<Vec<_>>();
+        statuses.sort_by_key(|status| status.maker.to_string());
+
+        Event::json(&statuses).event("maker_statuses")
+    }
+}
+
+impl ToSseEvent for Vec<CfdOrder> {
+    fn to_sse_event(&self) -> Event {
+        Event::json(&self).event("offers")
     }
 }
 
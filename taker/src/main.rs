@@ -3,33 +3,51 @@ use anyhow::Context;
 use anyhow::Result;
 use clap::Parser;
 use clap::Subcommand;
+use daemon::api_token;
+use daemon::backup;
 use daemon::bdk::bitcoin;
 use daemon::bdk::bitcoin::Address;
 use daemon::bdk::bitcoin::Amount;
 use daemon::bdk::FeeRate;
+use daemon::candles;
 use daemon::connection::connect;
+use daemon::connection::MakerAddress;
 use daemon::db;
+use daemon::discovery;
+use daemon::libp2p_utils::create_connect_multiaddr;
 use daemon::libp2p_utils::create_connect_tcp_multiaddr;
+use daemon::libp2p_utils::legacy_networking_from_libp2p;
 use daemon::libp2p_utils::libp2p_socket_from_legacy_networking;
 use daemon::monitor;
 use daemon::oracle;
+use daemon::process_manager;
 use daemon::projection;
+use daemon::rollover_taker;
 use daemon::seed::RandomSeed;
 use daemon::seed::Seed;
 use daemon::seed::UmbrelSeed;
 use daemon::wallet;
+use daemon::webhook;
 use daemon::TakerActorSystem;
 use daemon::HEARTBEAT_INTERVAL;
 use daemon::N_PAYOUTS;
+use itertools::Itertools;
+use libp2p_core::multiaddr::Protocol;
+use libp2p_core::Multiaddr;
 use libp2p_core::PeerId;
 use model::olivia;
+use model::FundingRate;
 use model::Identity;
+use model::TxFeeRate;
 use model::SETTLEMENT_INTERVAL;
 use rocket::fairing::AdHoc;
 use rocket::fairing::Fairing;
+use rust_decimal::Decimal;
 use shared_bin::fairings;
 use shared_bin::logger;
 use shared_bin::logger::LevelFilter;
+use shared_bin::price_feed;
+use shared_bin::price_feed::PriceFeed;
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::time::Duration;
@@ -54,6 +72,9 @@ const TESTNET_MAKER_PEER_ID: &str = "12D3KooWEsK2X8Tp24XtyWh7DM65VfwXtNH2cmfs2Js
 struct Opts {
     /// The IP address or hostname of the other party (i.e. the maker).
     ///
+    /// Can also be the multiaddr of the maker's libp2p endpoint, e.g. an onion address like
+    /// `/onion3/<ONION>:10001`. The maker's legacy port is assumed to be the port below.
+    ///
     /// If not specified it defaults to the itchysats maker for the mainnet or testnet.
     #[clap(long)]
     maker: Option<String>,
@@ -70,6 +91,28 @@ struct Opts {
     #[clap(long)]
     maker_peer_id: Option<PeerId>,
 
+    /// Further makers to connect to, given as `<ADDRESS>,<MAKER_ID>,<MAKER_PEER_ID>`.
+    ///
+    /// Can be specified multiple times. The offers of all makers are shown and taking an offer
+    /// is routed to the maker who published it.
+    #[clap(long = "additional-maker", multiple_occurrences = true)]
+    additional_makers: Vec<MakerOpts>,
+
+    /// Discover further makers at the rendezvous node with the given address.
+    ///
+    /// The address has to end with the peer ID of the rendezvous node, e.g.
+    /// `/ip4/1.2.3.4/tcp/10000/p2p/12D3KooW...`. Makers act as rendezvous nodes, hence the
+    /// address of a maker can be used.
+    #[clap(long)]
+    rendezvous: Option<Multiaddr>,
+
+    /// Connect to makers and the rendezvous node through the SOCKS5 proxy at the given address.
+    ///
+    /// Point this to a Tor daemon (usually `127.0.0.1:9050`) to hide the IP address of the taker
+    /// and to connect to makers running as onion services. Host names are resolved by the proxy.
+    #[clap(long)]
+    socks5_proxy: Option<SocketAddr>,
+
     /// The IP address to listen on for the HTTP API.
     #[clap(long, default_value = "127.0.0.1:8000")]
     http_address: SocketAddr,
@@ -92,22 +135,73 @@ struct Opts {
     #[clap(long)]
     password: Option<rocket_basicauth::Password>,
 
+    #[clap(flatten)]
+    price_feed: price_feed::Opts,
+
     #[clap(subcommand)]
     network: Option<Network>,
 
     #[clap(short, long, parse(try_from_str = parse_umbrel_seed))]
     umbrel_seed: Option<[u8; 32]>,
+
+    /// Decline rollovers for which the maker charges a higher funding rate, regardless of who pays
+    /// it.
+    #[clap(long)]
+    max_rollover_funding_rate: Option<FundingRate>,
+
+    /// Decline rollovers for which the maker builds the CETs with a higher transaction fee rate in
+    /// sat/vbyte.
+    #[clap(long)]
+    max_rollover_tx_fee_rate: Option<TxFeeRate>,
+
+    /// Decline rollovers for which the maker builds the CETs with fewer payouts.
+    #[clap(long)]
+    min_rollover_payouts: Option<usize>,
+
+    /// Decline rollovers for which the maker builds the CETs with more payouts.
+    #[clap(long)]
+    max_rollover_payouts: Option<usize>,
+
+    /// Automatically accept settlement counter-offers of the maker whose price is at most this
+    /// many percent worse than the latest quote, e.g. "0.5". Counter-offers outside of this
+    /// tolerance, or received while no recent quote is available, have to be accepted manually.
+    #[clap(long)]
+    settlement_counter_offer_tolerance: Option<Decimal>,
+
+    /// Notify the given webhook about the lifecycle of CFDs, given as `<URL>,<SECRET>`.
+    ///
+    /// Can be specified multiple times. Webhooks can also be managed at runtime via
+    /// `/api/webhooks`.
+    #[clap(long = "webhook", multiple_occurrences = true)]
+    webhooks: Vec<webhook::NewWebhook>,
 }
 
 impl Opts {
+    fn rollover_policy(&self) -> rollover_taker::Policy {
+        rollover_taker::Policy {
+            max_funding_rate: self.max_rollover_funding_rate,
+            max_tx_fee_rate: self.max_rollover_tx_fee_rate,
+            min_n_payouts: self.min_rollover_payouts,
+            max_n_payouts: self.max_rollover_payouts,
+        }
+    }
+
     fn network(&self) -> Network {
         self.network.clone().unwrap_or_else(|| Network::Mainnet {
             electrum: MAINNET_ELECTRUM.to_string(),
-            withdraw: None,
+            command: None,
         })
     }
 
-    fn maker(&self) -> Result<(String, x25519_dalek::PublicKey, PeerId)> {
+    /// All makers to connect to, starting with the one given by `--maker`.
+    fn makers(&self) -> Result<Vec<MakerOpts>> {
+        let mut makers = vec![self.maker()?];
+        makers.extend(self.additional_makers.iter().cloned());
+
+        Ok(makers)
+    }
+
+    fn maker(&self) -> Result<MakerOpts> {
         let network = self.network();
 
         let maker_url = match self.maker.clone() {
@@ -141,7 +235,36 @@ impl Opts {
             },
         };
 
-        Ok((maker_url, maker_id, maker_peer_id))
+        Ok(MakerOpts {
+            url: maker_url,
+            id: maker_id,
+            peer_id: maker_peer_id,
+        })
+    }
+}
+
+#[derive(Clone)]
+struct MakerOpts {
+    /// The IP address or hostname of the maker, or the multiaddr of its libp2p endpoint.
+    url: String,
+    id: x25519_dalek::PublicKey,
+    peer_id: PeerId,
+}
+
+impl std::str::FromStr for MakerOpts {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (url, id, peer_id) = s
+            .split(',')
+            .collect_tuple()
+            .context("Expected maker as <ADDRESS>,<MAKER_ID>,<MAKER_PEER_ID>")?;
+
+        Ok(Self {
+            url: url.to_owned(),
+            id: parse_x25519_pubkey(id).context("Invalid maker id")?,
+            peer_id: peer_id.parse().context("Invalid maker peer id")?,
+        })
     }
 }
 
@@ -166,7 +289,7 @@ enum Network {
         electrum: String,
 
         #[clap(subcommand)]
-        withdraw: Option<Withdraw>,
+        command: Option<Command>,
     },
     /// Run on testnet
     Testnet {
@@ -175,7 +298,7 @@ enum Network {
         electrum: String,
 
         #[clap(subcommand)]
-        withdraw: Option<Withdraw>,
+        command: Option<Command>,
     },
     /// Run on signet
     Signet {
@@ -184,12 +307,13 @@ enum Network {
         electrum: String,
 
         #[clap(subcommand)]
-        withdraw: Option<Withdraw>,
+        command: Option<Command>,
     },
 }
 
 #[derive(Subcommand, Clone)]
-enum Withdraw {
+enum Command {
+    /// Withdraw Bitcoin from the wallet.
     Withdraw {
         /// Optionally specify the amount of Bitcoin to be withdrawn. If not specified the wallet
         /// will be drained. Amount is to be specified with denomination, e.g. "0.1 BTC"
@@ -203,6 +327,40 @@ enum Withdraw {
         #[clap(long)]
         address: Address,
     },
+    /// Write an encrypted backup of all open CFDs to the given file.
+    ///
+    /// Can be run while the taker is running. The backup can only be restored with the same seed.
+    Backup {
+        #[clap(long)]
+        output: PathBuf,
+    },
+    /// Restore an encrypted backup of open CFDs into a new database.
+    ///
+    /// The seed the backup was created with has to be in the data directory already.
+    Restore {
+        #[clap(long)]
+        input: PathBuf,
+    },
+    /// Create an API token and print its secret.
+    ///
+    /// The token is passed as `Authorization: Bearer <TOKEN>` header and only grants access to the
+    /// routes its scopes allow. The secret is only printed once.
+    CreateToken {
+        /// A unique name to recognise the token by.
+        #[clap(long)]
+        name: String,
+        /// The scopes of the token, one of read, trade, withdraw or admin. Can be given multiple
+        /// times.
+        #[clap(long = "scope", required = true)]
+        scopes: Vec<api_token::Scope>,
+    },
+    /// List all API tokens with their scopes.
+    ListTokens,
+    /// Revoke the API token with the given name.
+    RevokeToken {
+        #[clap(long)]
+        name: String,
+    },
 }
 
 impl Network {
@@ -230,11 +388,11 @@ impl Network {
         }
     }
 
-    fn withdraw(&self) -> &Option<Withdraw> {
+    fn command(&self) -> &Option<Command> {
         match self {
-            Network::Mainnet { withdraw, .. } => withdraw,
-            Network::Testnet { withdraw, .. } => withdraw,
-            Network::Signet { withdraw, .. } => withdraw,
+            Network::Mainnet { command, .. } => command,
+            Network::Testnet { command, .. } => command,
+            Network::Signet { command, .. } => command,
         }
     }
 }
@@ -244,7 +402,8 @@ async fn main() -> Result<()> {
     let opts = Opts::parse();
 
     let network = opts.network();
-    let (maker_url, maker_id, maker_peer_id) = opts.maker()?;
+    let rollover_policy = opts.rollover_policy();
+    let mut makers = opts.makers()?;
 
     logger::init(opts.log_level, opts.json).context("initialize logger")?;
     tracing::info!("Running version: {}", daemon::version::version());
@@ -254,6 +413,40 @@ async fn main() -> Result<()> {
         "CFDs created with this release will settle after {settlement_interval_hours} hours"
     );
 
+    if let Some(rendezvous_node) = opts.rendezvous.clone() {
+        match discovery::discover_makers(rendezvous_node.clone(), opts.socks5_proxy).await {
+            Ok(discovered) => {
+                for maker in discovered {
+                    let identity = maker.record.identity;
+
+                    if makers
+                        .iter()
+                        .any(|known| Identity::new(known.id) == identity)
+                    {
+                        continue;
+                    }
+
+                    tracing::info!(%identity, peer_id = %maker.peer_id, address = %maker.record.address, "Discovered maker");
+
+                    // Prefer the onion service of the maker if we can reach it
+                    let url = match (opts.socks5_proxy, maker.onion_address()) {
+                        (Some(_), Some(onion_address)) => onion_address.to_string(),
+                        _ => maker.record.address.to_string(),
+                    };
+
+                    makers.push(MakerOpts {
+                        url,
+                        id: identity.pk(),
+                        peer_id: maker.peer_id,
+                    });
+                }
+            }
+            Err(e) => {
+                tracing::warn!(%rendezvous_node, "Failed to discover makers: {e:#}");
+            }
+        }
+    }
+
     let data_dir = opts
         .data_dir
         .clone()
@@ -265,37 +458,129 @@ async fn main() -> Result<()> {
         tokio::fs::create_dir_all(&data_dir).await?;
     }
 
-    let maker_identity = Identity::new(maker_id);
-
     let bitcoin_network = network.bitcoin_network();
-    let (ext_priv_key, identities, web_password) = match opts.umbrel_seed {
+    let (ext_priv_key, identities, web_password, backup_key, db_key) = match opts.umbrel_seed {
         Some(seed_bytes) => {
             let seed = UmbrelSeed::from(seed_bytes);
             let ext_priv_key = seed.derive_extended_priv_key(bitcoin_network)?;
             let identities = seed.derive_identities();
             let web_password = opts.password.unwrap_or_else(|| seed.derive_auth_password());
-            (ext_priv_key, identities, web_password)
+            let backup_key = seed.derive_backup_key::<backup::Key>();
+            let db_key = seed.derive_db_encryption_key::<db::EncryptionKey>();
+            (ext_priv_key, identities, web_password, backup_key, db_key)
         }
         None => {
-            let seed = RandomSeed::initialize(&data_dir.join("taker_seed")).await?;
+            let seed_file = data_dir.join("taker_seed");
+            if matches!(network.command(), Some(Command::Restore { .. })) && !seed_file.exists() {
+                bail!(
+                    "No seed found at {}, copy the seed the backup was created with to the data directory",
+                    seed_file.display()
+                );
+            }
+
+            let seed = RandomSeed::initialize(&seed_file).await?;
             let ext_priv_key = seed.derive_extended_priv_key(bitcoin_network)?;
             let identities = seed.derive_identities();
             let web_password = opts.password.unwrap_or_else(|| seed.derive_auth_password());
-            (ext_priv_key, identities, web_password)
+            let backup_key = seed.derive_backup_key::<backup::Key>();
+            let db_key = seed.derive_db_encryption_key::<db::EncryptionKey>();
+            (ext_priv_key, identities, web_password, backup_key, db_key)
         }
     };
 
+    let db_path = data_dir.join("taker.sqlite");
+
+    match network.command() {
+        Some(Command::Backup { output }) => {
+            let db = db::connect(db_path.clone())
+                .await?
+                .encrypt_at_rest(db_key)
+                .await?;
+            let backup = backup::create(&db, &backup_key).await?;
+
+            tokio::fs::write(output, backup)
+                .await
+                .with_context(|| format!("Failed to write backup to {}", output.display()))?;
+            tracing::info!("Wrote backup of open CFDs to {}", output.display());
+
+            return Ok(());
+        }
+        Some(Command::Restore { input }) => {
+            let backup = tokio::fs::read(input)
+                .await
+                .with_context(|| format!("Failed to read backup from {}", input.display()))?;
+
+            let db = db::connect(db_path.clone())
+                .await?
+                .encrypt_at_rest(db_key)
+                .await?;
+            backup::restore(&db, &backup_key, &backup).await?;
+
+            return Ok(());
+        }
+        Some(Command::CreateToken { name, scopes }) => {
+            let db = db::connect(db_path.clone())
+                .await?
+                .encrypt_at_rest(db_key)
+                .await?;
+            let token = api_token::create(
+                &db,
+                api_token::NewToken {
+                    name: name.clone(),
+                    scopes: scopes.clone(),
+                },
+            )
+            .await?;
+
+            println!("{}", token.secret);
+
+            return Ok(());
+        }
+        Some(Command::ListTokens) => {
+            let db = db::connect(db_path.clone())
+                .await?
+                .encrypt_at_rest(db_key)
+                .await?;
+
+            for token in db.load_api_tokens().await? {
+                let scopes = token
+                    .scopes
+                    .iter()
+                    .map(|scope| scope.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",");
+                println!("{}\t{scopes}\t{}", token.name, token.created_at.seconds());
+            }
+
+            return Ok(());
+        }
+        Some(Command::RevokeToken { name }) => {
+            let db = db::connect(db_path.clone())
+                .await?
+                .encrypt_at_rest(db_key)
+                .await?;
+
+            if !db.delete_api_token(name).await? {
+                anyhow::bail!("No API token named {name}");
+            }
+            tracing::info!("Revoked API token {name}");
+
+            return Ok(());
+        }
+        Some(Command::Withdraw { .. }) | None => {}
+    }
+
     let mut tasks = Tasks::default();
 
     let (wallet, wallet_feed_receiver) = wallet::Actor::new(network.electrum(), ext_priv_key)?;
 
     let wallet = wallet.create(None).spawn(&mut tasks);
 
-    if let Some(Withdraw::Withdraw {
+    if let Some(Command::Withdraw {
         amount,
         address,
         fee,
-    }) = network.withdraw()
+    }) = network.command()
     {
         wallet
             .send(wallet::Withdraw {
@@ -316,22 +601,30 @@ async fn main() -> Result<()> {
         .merge(("port", opts.http_address.port()))
         .merge(("cli_colors", false));
 
-    let db = db::connect(data_dir.join("taker.sqlite")).await?;
+    let db = db::connect(db_path).await?.encrypt_at_rest(db_key).await?;
+
+    for webhook in &opts.webhooks {
+        db.upsert_webhook(webhook)
+            .await
+            .with_context(|| format!("Failed to add webhook {}", webhook.url))?;
+    }
 
     // Create actors
 
     let (projection_actor, projection_context) = xtra::Context::new(None);
 
-    let possible_addresses = resolve_maker_addresses(maker_url.as_str()).await?;
+    let mut maker_addresses = Vec::new();
+    let mut maker_multiaddrs = Vec::new();
+    for maker in makers {
+        let maker_identity = Identity::new(maker.id);
+        let (possible_addresses, maker_multiaddr) =
+            maker_addresses_from_url(&maker, opts.socks5_proxy).await?;
 
-    // Assume that the first resolved ipv4 address is good enough for libp2p.
-    let first_maker_address = possible_addresses
-        .iter()
-        .find(|x| x.is_ipv4())
-        .context("Could not resolve maker URL")?;
+        maker_addresses.push((maker_identity, possible_addresses));
+        maker_multiaddrs.push((maker_identity, maker_multiaddr));
+    }
 
-    let maker_libp2p_address = libp2p_socket_from_legacy_networking(first_maker_address);
-    let maker_multiaddr = create_connect_tcp_multiaddr(&maker_libp2p_address, maker_peer_id)?;
+    let price_feed = PriceFeed::new(&opts.price_feed, &mut tasks)?;
 
     let taker = TakerActorSystem::new(
         db.clone(),
@@ -345,31 +638,47 @@ async fn main() -> Result<()> {
                 monitor::Actor::new(db.clone(), electrum, executor)
             }
         },
-        xtra_bitmex_price_feed::Actor::default,
+        price_feed.channel(),
         N_PAYOUTS,
+        rollover_policy,
+        opts.settlement_counter_offer_tolerance,
         HEARTBEAT_INTERVAL,
         Duration::from_secs(10),
+        process_manager::COLLAB_SETTLEMENT_BROADCAST_DELAY,
         projection_actor.clone(),
-        Identity::new(maker_id),
-        maker_multiaddr,
+        maker_multiaddrs,
+        opts.socks5_proxy,
     )?;
 
     let (proj_actor, projection_feeds) =
-        projection::Actor::new(db.clone(), bitcoin_network, &taker.price_feed_actor);
+        projection::Actor::new(db.clone(), bitcoin_network, price_feed.channel());
     tasks.add(projection_context.run(proj_actor));
 
-    tasks.add(connect(
-        taker.maker_online_status_feed_receiver.clone(),
-        taker.connection_actor.clone(),
-        maker_identity,
-        possible_addresses,
-    ));
+    let _candles_actor = candles::Actor::new(db.clone(), price_feed.channel())
+        .create(None)
+        .spawn(&mut tasks);
+
+    let _webhook_actor = webhook::Actor::new(db.clone())?
+        .create(None)
+        .spawn(&mut tasks);
+
+    for (maker_identity, possible_addresses) in maker_addresses {
+        tasks.add(connect(
+            taker.makers_online_status_feed_receiver.clone(),
+            taker.connection_actor.clone(),
+            maker_identity,
+            possible_addresses,
+        ));
+    }
 
     rocket::custom(figment)
         .manage(projection_feeds)
         .manage(wallet_feed_receiver)
         .manage(bitcoin_network)
-        .manage(taker.maker_online_status_feed_receiver.clone())
+        .manage(db.clone())
+        .manage(backup_key)
+        .manage(taker.makers_online_status_feed_receiver.clone())
+        .manage(taker.makers_connection_quality_feed_receiver.clone())
         .manage(taker)
         .manage(auth_username)
         .manage(web_password)
@@ -381,6 +690,18 @@ async fn main() -> Result<()> {
                 routes::get_health_check,
                 routes::post_cfd_action,
                 routes::post_withdraw_request,
+                routes::get_quote_history,
+                routes::get_event_stream,
+                routes::get_events,
+                routes::get_cfd_history,
+                routes::get_backup,
+                routes::get_closed_cfds_export,
+                routes::get_webhooks,
+                routes::post_webhook,
+                routes::delete_webhook,
+                routes::get_tokens,
+                routes::post_token,
+                routes::delete_token,
             ],
         )
         .register("/api", rocket::catchers![rocket_basicauth::unauthorized])
@@ -397,6 +718,79 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// The addresses of the maker's legacy networking stack and the multiaddr of its libp2p endpoint.
+///
+/// Host names are only resolved locally if we do not connect through a proxy.
+async fn maker_addresses_from_url(
+    maker: &MakerOpts,
+    socks5_proxy: Option<SocketAddr>,
+) -> Result<(Vec<MakerAddress>, Multiaddr)> {
+    let (host, port, multiaddr) = if maker.url.starts_with('/') {
+        let mut multiaddr = maker
+            .url
+            .parse::<Multiaddr>()
+            .with_context(|| format!("Invalid maker multiaddr {}", maker.url))?;
+        let (host, port) = legacy_networking_from_libp2p(&multiaddr)?;
+
+        if !matches!(multiaddr.iter().last(), Some(Protocol::P2p(_))) {
+            multiaddr.push(Protocol::P2p(maker.peer_id.into()));
+        }
+
+        (host, port, Some(multiaddr))
+    } else {
+        let (host, port) = maker
+            .url
+            .rsplit_once(':')
+            .with_context(|| format!("Expected maker URL as <HOST>:<PORT>, got {}", maker.url))?;
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let port = port
+            .parse::<u16>()
+            .with_context(|| format!("Invalid port in maker URL {}", maker.url))?;
+
+        (host.to_owned(), port, None)
+    };
+
+    if socks5_proxy.is_some() {
+        let multiaddr = match multiaddr {
+            Some(multiaddr) => multiaddr,
+            None => create_connect_multiaddr(&host, port + 1, maker.peer_id)?,
+        };
+        let address = match host.parse() {
+            Ok(ip) => MakerAddress::Socket(SocketAddr::new(ip, port)),
+            Err(_) => MakerAddress::Host { host, port },
+        };
+
+        return Ok((vec![address], multiaddr));
+    }
+
+    if host.ends_with(".onion") {
+        bail!("Connecting to onion address {host} requires --socks5-proxy");
+    }
+
+    let possible_addresses = resolve_maker_addresses(&format!("{host}:{port}")).await?;
+
+    let multiaddr = match multiaddr {
+        Some(multiaddr) => multiaddr,
+        None => {
+            // Assume that the first resolved ipv4 address is good enough for libp2p.
+            let first_maker_address = possible_addresses
+                .iter()
+                .find(|x| x.is_ipv4())
+                .with_context(|| format!("Could not resolve maker URL {}", maker.url))?;
+
+            let maker_libp2p_address = libp2p_socket_from_legacy_networking(first_maker_address);
+            create_connect_tcp_multiaddr(&maker_libp2p_address, maker.peer_id)?
+        }
+    };
+
+    let possible_addresses = possible_addresses
+        .into_iter()
+        .map(MakerAddress::from)
+        .collect();
+
+    Ok((possible_addresses, multiaddr))
+}
+
 async fn resolve_maker_addresses(maker_addr: &str) -> Result<Vec<SocketAddr>> {
     let possible_addresses = tokio::net::lookup_host(maker_addr)
         .await?
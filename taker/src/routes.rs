@@ -1,16 +1,24 @@
+use daemon::accounting;
+use daemon::api_token;
+use daemon::backup;
 use daemon::bdk;
 use daemon::bdk::bitcoin::Amount;
 use daemon::bdk::bitcoin::Network;
 use daemon::bdk::blockchain::ElectrumBlockchain;
+use daemon::candles;
 use daemon::connection::ConnectionStatus;
+use daemon::db;
 use daemon::oracle;
 use daemon::projection;
 use daemon::projection::CfdAction;
 use daemon::projection::Feeds;
 use daemon::wallet;
+use daemon::webhook;
+use daemon::xtra_libp2p_ping::ConnectionQuality;
 use daemon::TakerActorSystem;
 use http_api_problem::HttpApiProblem;
 use http_api_problem::StatusCode;
+use model::Identity;
 use model::Leverage;
 use model::OrderId;
 use model::Price;
@@ -29,18 +37,20 @@ use rust_embed::RustEmbed;
 use rust_embed_rocket::EmbeddedFileExt;
 use serde::Deserialize;
 use serde::Serialize;
+use shared_bin::auth;
+use shared_bin::events;
+use shared_bin::history;
+use shared_bin::quote_history;
+use shared_bin::MakerStatuses;
 use shared_bin::ToSseEvent;
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::path::PathBuf;
 use tokio::select;
 use tokio::sync::watch;
 use uuid::Uuid;
 
-type Taker = TakerActorSystem<
-    oracle::Actor,
-    wallet::Actor<ElectrumBlockchain>,
-    xtra_bitmex_price_feed::Actor,
->;
+type Taker = TakerActorSystem<oracle::Actor, wallet::Actor<ElectrumBlockchain>>;
 
 const HEARTBEAT_INTERVAL_SECS: u64 = 5;
 
@@ -48,15 +58,18 @@ const HEARTBEAT_INTERVAL_SECS: u64 = 5;
 pub async fn feed(
     rx: &State<Feeds>,
     rx_wallet: &State<watch::Receiver<Option<WalletInfo>>>,
-    rx_maker_status: &State<watch::Receiver<ConnectionStatus>>,
-    _auth: Authenticated,
+    rx_maker_status: &State<watch::Receiver<HashMap<Identity, ConnectionStatus>>>,
+    rx_maker_quality: &State<watch::Receiver<HashMap<Identity, ConnectionQuality>>>,
+    _auth: auth::Read,
 ) -> EventStream![] {
     let rx = rx.inner();
     let mut rx_cfds = rx.cfds.clone();
     let mut rx_offers = rx.offers.clone();
+    let mut rx_all_offers = rx.all_offers.clone();
     let mut rx_quote = rx.quote.clone();
     let mut rx_wallet = rx_wallet.inner().clone();
     let mut rx_maker_status = rx_maker_status.inner().clone();
+    let mut rx_maker_quality = rx_maker_quality.inner().clone();
     let mut heartbeat =
         tokio::time::interval(std::time::Duration::from_secs(HEARTBEAT_INTERVAL_SECS));
 
@@ -64,13 +77,20 @@ pub async fn feed(
         let wallet_info = rx_wallet.borrow().clone();
         yield wallet_info.to_sse_event();
 
-        let maker_status = rx_maker_status.borrow().clone();
-        yield maker_status.to_sse_event();
+        let maker_statuses = rx_maker_status.borrow().clone();
+        yield ConnectionStatus::any_online(maker_statuses.values()).to_sse_event();
+        yield MakerStatuses {
+            statuses: maker_statuses,
+            qualities: rx_maker_quality.borrow().clone(),
+        }.to_sse_event();
 
         let offers = rx_offers.borrow().clone();
         yield Event::json(&offers.long).event("long_offer");
         yield Event::json(&offers.short).event("short_offer");
 
+        let all_offers = rx_all_offers.borrow().clone();
+        yield all_offers.to_sse_event();
+
         let quote = rx_quote.borrow().clone();
         yield quote.to_sse_event();
 
@@ -86,14 +106,28 @@ pub async fn feed(
                     yield wallet_info.to_sse_event();
                 },
                 Ok(()) = rx_maker_status.changed() => {
-                    let maker_status = rx_maker_status.borrow().clone();
-                    yield maker_status.to_sse_event();
+                    let maker_statuses = rx_maker_status.borrow().clone();
+                    yield ConnectionStatus::any_online(maker_statuses.values()).to_sse_event();
+                    yield MakerStatuses {
+                        statuses: maker_statuses,
+                        qualities: rx_maker_quality.borrow().clone(),
+                    }.to_sse_event();
+                },
+                Ok(()) = rx_maker_quality.changed() => {
+                    yield MakerStatuses {
+                        statuses: rx_maker_status.borrow().clone(),
+                        qualities: rx_maker_quality.borrow().clone(),
+                    }.to_sse_event();
                 },
                 Ok(()) = rx_offers.changed() => {
                     let offers = rx_offers.borrow().clone();
                     yield Event::json(&offers.long).event("long_offer");
                     yield Event::json(&offers.short).event("short_offer");
                 }
+                Ok(()) = rx_all_offers.changed() => {
+                    let all_offers = rx_all_offers.borrow().clone();
+                    yield all_offers.to_sse_event();
+                }
                 Ok(()) = rx_cfds.changed() => {
                     let cfds = rx_cfds.borrow().clone();
                     if let Some(cfds) = cfds {
@@ -137,7 +171,7 @@ pub struct CfdOrderRequest {
 pub async fn post_order_request(
     cfd_order_request: Json<CfdOrderRequest>,
     taker: &State<Taker>,
-    _auth: Authenticated,
+    _auth: auth::Trade,
 ) -> Result<(), HttpApiProblem> {
     taker
         .take_offer(cfd_order_request.order_id, cfd_order_request.quantity)
@@ -156,7 +190,7 @@ pub async fn post_cfd_action(
     id: Uuid,
     action: String,
     taker: &State<Taker>,
-    _auth: Authenticated,
+    _auth: auth::Trade,
 ) -> Result<(), HttpApiProblem> {
     let id = OrderId::from(id);
     let action = action.parse().map_err(|_| {
@@ -166,8 +200,6 @@ pub async fn post_cfd_action(
     let result = match action {
         CfdAction::AcceptOrder
         | CfdAction::RejectOrder
-        | CfdAction::AcceptSettlement
-        | CfdAction::RejectSettlement
         | CfdAction::AcceptRollover
         | CfdAction::RejectRollover => {
             return Err(HttpApiProblem::new(StatusCode::BAD_REQUEST)
@@ -175,6 +207,8 @@ pub async fn post_cfd_action(
         }
         CfdAction::Commit => taker.commit(id).await,
         CfdAction::Settle => taker.propose_settlement(id).await,
+        CfdAction::AcceptSettlement => taker.accept_settlement_counter_offer(id).await,
+        CfdAction::RejectSettlement => taker.reject_settlement_counter_offer(id).await,
     };
 
     result.map_err(|e| {
@@ -189,6 +223,304 @@ pub async fn post_cfd_action(
 #[rocket::get("/alive")]
 pub fn get_health_check() {}
 
+#[rocket::get("/quotes/history?<interval>&<from>")]
+pub async fn get_quote_history(
+    interval: String,
+    from: Option<i64>,
+    db: &State<db::Connection>,
+    _auth: auth::Read,
+) -> Result<Json<candles::History>, HttpApiProblem> {
+    let interval = quote_history::parse_interval(&interval)
+        .map_err(|e| HttpApiProblem::new(StatusCode::BAD_REQUEST).detail(format!("{e:#}")))?;
+
+    let history = quote_history::load(db, interval, from).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Failed to load price history")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(Json(history))
+}
+
+/// Stream the events of all open CFDs as they are stored in the database.
+///
+/// Starts after the cursor given as `after` or as `Last-Event-ID` header, or from the beginning.
+/// Key material and signed transactions are stripped from the data of every event.
+#[rocket::get("/events?<after>", format = "text/event-stream")]
+pub async fn get_event_stream(
+    after: Option<i64>,
+    last_event_id: events::LastEventId,
+    db: &State<db::Connection>,
+    _auth: auth::Read,
+) -> EventStream![] {
+    let after = after.or(last_event_id.0).unwrap_or(0);
+
+    events::stream(db.inner().clone(), after)
+}
+
+/// Load a page of the events of all open CFDs as they are stored in the database.
+///
+/// Key material and signed transactions are stripped from the data of every event.
+#[rocket::get("/events?<after>&<limit>", rank = 2)]
+pub async fn get_events(
+    after: Option<i64>,
+    limit: Option<u32>,
+    db: &State<db::Connection>,
+    _auth: auth::Read,
+) -> Result<Json<events::Page>, HttpApiProblem> {
+    let after = after.unwrap_or(0);
+    let limit = limit.unwrap_or(events::DEFAULT_PAGE_SIZE);
+
+    let page = events::load_page(db, after, limit).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Failed to load events")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(Json(page))
+}
+
+/// Load a page of all open and closed CFDs matching the filter, newest first.
+///
+/// Pass the `next_cursor` of a page as `cursor` to load the next one.
+#[rocket::get("/cfds/history?<state>&<position>&<counterparty>&<from>&<to>&<cursor>&<limit>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn get_cfd_history(
+    state: Option<String>,
+    position: Option<String>,
+    counterparty: Option<String>,
+    from: Option<i64>,
+    to: Option<i64>,
+    cursor: Option<String>,
+    limit: Option<u32>,
+    db: &State<db::Connection>,
+    _auth: auth::Read,
+) -> Result<Json<history::Page>, HttpApiProblem> {
+    let filter = history::parse_filter(
+        state.as_deref(),
+        position.as_deref(),
+        counterparty.as_deref(),
+        from,
+        to,
+    )
+    .map_err(|e| {
+        HttpApiProblem::new(StatusCode::BAD_REQUEST)
+            .title("Invalid filter")
+            .detail(format!("{e:#}"))
+    })?;
+    let after = cursor
+        .as_deref()
+        .map(history::parse_cursor)
+        .transpose()
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::BAD_REQUEST)
+                .title("Invalid cursor")
+                .detail(format!("{e:#}"))
+        })?;
+    let limit = limit.unwrap_or(history::DEFAULT_PAGE_SIZE);
+
+    let page = history::load_page(db, filter, after, limit)
+        .await
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+                .title("Failed to load CFDs")
+                .detail(format!("{e:#}"))
+        })?;
+
+    Ok(Json(page))
+}
+
+/// Download an encrypted backup of all open CFDs.
+///
+/// The backup can only be restored with the same seed, using the `restore` command.
+#[rocket::get("/backup")]
+pub async fn get_backup(
+    db: &State<db::Connection>,
+    key: &State<backup::Key>,
+    _auth: auth::Admin,
+) -> Result<(ContentType, Vec<u8>), HttpApiProblem> {
+    let backup = backup::create(db, key).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Failed to create backup")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok((ContentType::Binary, backup))
+}
+
+/// Export the CFDs which were closed within the given time range for accounting.
+///
+/// `from` and `to` are UNIX timestamps in seconds and default to everything closed so far. The
+/// format is either `csv` (default) or `json`.
+#[rocket::get("/cfds/closed/export?<format>&<from>&<to>")]
+pub async fn get_closed_cfds_export(
+    format: Option<String>,
+    from: Option<i64>,
+    to: Option<i64>,
+    db: &State<db::Connection>,
+    _auth: auth::Read,
+) -> Result<(ContentType, String), HttpApiProblem> {
+    let format = format
+        .as_deref()
+        .unwrap_or("csv")
+        .parse::<accounting::Format>()
+        .map_err(|e| {
+            HttpApiProblem::new(StatusCode::BAD_REQUEST)
+                .detail(format!("{e:#}, expected one of csv, json"))
+        })?;
+    let from = from
+        .map(Timestamp::new)
+        .unwrap_or_else(|| Timestamp::new(0));
+    let to = to.map(Timestamp::new).unwrap_or_else(Timestamp::now);
+
+    let entries = accounting::load_entries(db, from, to).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Failed to load closed CFDs")
+            .detail(format!("{e:#}"))
+    })?;
+
+    let export = match format {
+        accounting::Format::Csv => (ContentType::CSV, accounting::to_csv(&entries)),
+        accounting::Format::Json => {
+            let json = rocket::serde::json::to_string(&entries).map_err(|e| {
+                HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+                    .title("Failed to serialize closed CFDs")
+                    .detail(format!("{e:#}"))
+            })?;
+
+            (ContentType::JSON, json)
+        }
+    };
+
+    Ok(export)
+}
+
+#[rocket::get("/webhooks")]
+pub async fn get_webhooks(
+    db: &State<db::Connection>,
+    _auth: auth::Admin,
+) -> Result<Json<Vec<webhook::Webhook>>, HttpApiProblem> {
+    let webhooks = db.load_webhooks().await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Failed to load webhooks")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(Json(webhooks))
+}
+
+/// Add a webhook which is notified about the lifecycle of CFDs.
+///
+/// If a webhook with the same URL exists already, its secret is replaced.
+#[rocket::post("/webhooks", data = "<webhook>")]
+pub async fn post_webhook(
+    webhook: Json<webhook::NewWebhook>,
+    db: &State<db::Connection>,
+    _auth: auth::Admin,
+) -> Result<Json<webhook::Webhook>, HttpApiProblem> {
+    let webhook = webhook.into_inner();
+    webhook.validate().map_err(|e| {
+        HttpApiProblem::new(StatusCode::BAD_REQUEST)
+            .title("Invalid webhook")
+            .detail(format!("{e:#}"))
+    })?;
+
+    let id = db.upsert_webhook(&webhook).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Failed to add webhook")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(Json(webhook::Webhook {
+        id,
+        url: webhook.url,
+        secret: webhook.secret,
+    }))
+}
+
+/// Remove a webhook, discarding its pending notifications.
+#[rocket::delete("/webhooks/<id>")]
+pub async fn delete_webhook(
+    id: i64,
+    db: &State<db::Connection>,
+    _auth: auth::Admin,
+) -> Result<(), HttpApiProblem> {
+    let deleted = db.delete_webhook(id).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Failed to remove webhook")
+            .detail(format!("{e:#}"))
+    })?;
+
+    if !deleted {
+        return Err(HttpApiProblem::new(StatusCode::NOT_FOUND)
+            .title("Webhook not found")
+            .detail(format!("No webhook with ID {id}")));
+    }
+
+    Ok(())
+}
+
+#[rocket::get("/tokens")]
+pub async fn get_tokens(
+    db: &State<db::Connection>,
+    _auth: auth::Admin,
+) -> Result<Json<Vec<api_token::Token>>, HttpApiProblem> {
+    let tokens = db.load_api_tokens().await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Failed to load API tokens")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(Json(tokens))
+}
+
+/// Create an API token with the given scopes.
+///
+/// The secret of the token is only part of this response and cannot be retrieved later.
+#[rocket::post("/tokens", data = "<token>")]
+pub async fn post_token(
+    token: Json<api_token::NewToken>,
+    db: &State<db::Connection>,
+    _auth: auth::Admin,
+) -> Result<Json<api_token::CreatedToken>, HttpApiProblem> {
+    let token = token.into_inner();
+    token.validate().map_err(|e| {
+        HttpApiProblem::new(StatusCode::BAD_REQUEST)
+            .title("Invalid API token")
+            .detail(format!("{e:#}"))
+    })?;
+
+    let token = api_token::create(db, token).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Failed to create API token")
+            .detail(format!("{e:#}"))
+    })?;
+
+    Ok(Json(token))
+}
+
+/// Revoke the API token with the given name.
+#[rocket::delete("/tokens/<name>")]
+pub async fn delete_token(
+    name: String,
+    db: &State<db::Connection>,
+    _auth: auth::Admin,
+) -> Result<(), HttpApiProblem> {
+    let deleted = db.delete_api_token(&name).await.map_err(|e| {
+        HttpApiProblem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .title("Failed to revoke API token")
+            .detail(format!("{e:#}"))
+    })?;
+
+    if !deleted {
+        return Err(HttpApiProblem::new(StatusCode::NOT_FOUND)
+            .title("API token not found")
+            .detail(format!("No API token named {name}")));
+    }
+
+    Ok(())
+}
+
 #[derive(Debug, Clone, Copy, Deserialize)]
 pub struct MarginRequest {
     pub price: Price,
@@ -239,7 +571,7 @@ pub async fn post_withdraw_request(
     withdraw_request: Json<WithdrawRequest>,
     taker: &State<Taker>,
     network: &State<Network>,
-    _auth: Authenticated,
+    _auth: auth::Withdraw,
 ) -> Result<String, HttpApiProblem> {
     let amount =
         (withdraw_request.amount != bdk::bitcoin::Amount::ZERO).then(|| withdraw_request.amount);
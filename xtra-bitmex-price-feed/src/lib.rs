@@ -1,13 +1,18 @@
+use crate::recording::Recorder;
 use anyhow::Result;
 use async_trait::async_trait;
 use futures::TryStreamExt;
 use rust_decimal::Decimal;
 use std::fmt;
+use std::path::PathBuf;
 use time::OffsetDateTime;
 use tokio_tasks::Tasks;
 use xtra::Disconnected;
 use xtra_productivity::xtra_productivity;
 
+pub mod recording;
+pub mod replay;
+
 pub const QUOTE_INTERVAL_MINUTES: i64 = 1;
 
 #[derive(Default)]
@@ -15,22 +20,47 @@ pub struct Actor {
     tasks: Tasks,
     latest_quote: Option<Quote>,
 
+    /// If set, every message received from BitMex is appended to this file.
+    recording: Option<PathBuf>,
+
     /// Contains the reason we are stopping.
     stop_reason: Option<Error>,
 }
 
+impl Actor {
+    /// Record every message received from BitMex to the given file.
+    ///
+    /// The recording can be played back using the [`replay::Actor`].
+    pub fn with_recording(path: PathBuf) -> Self {
+        Self {
+            recording: Some(path),
+            ..Self::default()
+        }
+    }
+}
+
 #[async_trait]
 impl xtra::Actor for Actor {
     type Stop = Error;
 
     async fn started(&mut self, ctx: &mut xtra::Context<Self>) {
         let this = ctx.address().expect("we are alive");
+        let recording = self.recording.clone();
 
         self.tasks.add_fallible(
             {
                 let this = this.clone();
 
                 async move {
+                    let mut recorder = match recording {
+                        Some(path) => Some(
+                            Recorder::open(&path)
+                                .await
+                                .map_err(|e| Error::Recording { source: e })?,
+                        ),
+                        None => None,
+                    };
+
                     let mut stream = bitmex_stream::subscribe([format!(
                         "quoteBin{QUOTE_INTERVAL_MINUTES}m:XBTUSD"
                     )]);
@@ -40,7 +70,14 @@ impl xtra::Actor for Actor {
                         .await
                         .map_err(|e| Error::Failed { source: e })?
                     {
-                        let quote = Quote::from_str(&text)
+                        if let Some(recorder) = recorder.as_mut() {
+                            recorder
+                                .record(&text)
+                                .await
+                                .map_err(|e| Error::Recording { source: e })?;
+                        }
+
+                        let quote = Quote::from_bitmex_message(&text)
                             .map_err(|e| Error::FailedToParseQuote { source: e })?;
 
                         match quote {
@@ -99,6 +136,8 @@ pub enum Error {
     StreamEnded,
     #[error("Failed to parse quote")]
     FailedToParseQuote { source: anyhow::Error },
+    #[error("Failed to access recording")]
+    Recording { source: anyhow::Error },
     #[error("Stop reason was not specified")]
     Unspecified,
 }
@@ -134,7 +173,10 @@ impl fmt::Debug for Quote {
 }
 
 impl Quote {
-    fn from_str(text: &str) -> Result<Option<Self>> {
+    /// Parse a quote from a `quoteBin` table message of BitMex's websocket API.
+    ///
+    /// Returns `None` for all other messages, e.g. the initial subscription confirmation.
+    pub fn from_bitmex_message(text: &str) -> Result<Option<Self>> {
         let table_message = match serde_json::from_str::<wire::TableMessage>(text) {
             Ok(table_message) => table_message,
             Err(_) => {
@@ -201,7 +243,7 @@ mod tests {
 
     #[test]
     fn can_deserialize_quote_message() {
-        let quote = Quote::from_str(r#"{"table":"quoteBin1m","action":"insert","data":[{"timestamp":"2021-09-21T02:40:00.000Z","symbol":"XBTUSD","bidSize":50200,"bidPrice":42640.5,"askPrice":42641,"askSize":363600}]}"#).unwrap().unwrap();
+        let quote = Quote::from_bitmex_message(r#"{"table":"quoteBin1m","action":"insert","data":[{"timestamp":"2021-09-21T02:40:00.000Z","symbol":"XBTUSD","bidSize":50200,"bidPrice":42640.5,"askPrice":42641,"askSize":363600}]}"#).unwrap().unwrap();
 
         assert_eq!(quote.bid, dec!(42640.5));
         assert_eq!(quote.ask, dec!(42641));
@@ -0,0 +1,92 @@
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use std::path::Path;
+use time::OffsetDateTime;
+use tokio::io::AsyncWriteExt;
+
+/// A message received from BitMex's websocket API, together with the time it was received.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedMessage {
+    #[serde(with = "time::serde::rfc3339")]
+    pub received_at: OffsetDateTime,
+    pub message: String,
+}
+
+/// Appends messages to a recording file, one JSON object per line.
+pub struct Recorder {
+    file: tokio::fs::File,
+}
+
+impl Recorder {
+    /// Open the recording at the given path, creating it if it does not exist yet.
+    ///
+    /// Messages are appended to an existing recording.
+    pub async fn open(path: &Path) -> Result<Self> {
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await
+            .with_context(|| format!("Failed to open recording {}", path.display()))?;
+
+        Ok(Self { file })
+    }
+
+    /// Record a message as received right now.
+    pub async fn record(&mut self, message: &str) -> Result<()> {
+        let mut line = serde_json::to_string(&RecordedMessage {
+            received_at: OffsetDateTime::now_utc(),
+            message: message.to_owned(),
+        })?;
+        line.push('\n');
+
+        self.file.write_all(line.as_bytes()).await?;
+        self.file.flush().await?;
+
+        Ok(())
+    }
+}
+
+/// Load all messages of a recording, in the order they were received.
+pub async fn load(path: &Path) -> Result<Vec<RecordedMessage>> {
+    let content = tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("Failed to read recording {}", path.display()))?;
+
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(i, line)| {
+            serde_json::from_str(line)
+                .with_context(|| format!("Failed to parse line {} of recording", i + 1))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn recorded_messages_can_be_loaded() {
+        let path = std::env::temp_dir().join(format!(
+            "bitmex-recording-{}.jsonl",
+            OffsetDateTime::now_utc().unix_timestamp_nanos()
+        ));
+
+        let mut recorder = Recorder::open(&path).await.unwrap();
+        recorder.record("first").await.unwrap();
+        recorder.record("second").await.unwrap();
+
+        let messages = load(&path).await.unwrap();
+        tokio::fs::remove_file(&path).await.unwrap();
+
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].message, "first");
+        assert_eq!(messages[1].message, "second");
+        assert!(messages[0].received_at <= messages[1].received_at);
+    }
+}
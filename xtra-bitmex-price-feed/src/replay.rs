@@ -0,0 +1,143 @@
+use crate::recording;
+use crate::Error;
+use crate::LatestQuote;
+use crate::NewQuoteReceived;
+use crate::Quote;
+use anyhow::bail;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::path::PathBuf;
+use time::OffsetDateTime;
+use tokio_tasks::Tasks;
+use xtra::Disconnected;
+use xtra_productivity::xtra_productivity;
+
+/// A price feed which plays back a recording made with
+/// [`Actor::with_recording`](crate::Actor::with_recording) instead of connecting to BitMex.
+///
+/// All timestamps are shifted such that the first recorded message appears to be received at the
+/// time the replay starts. Hence, replayed quotes are as fresh as they were at the time of
+/// recording. Once the end of the recording is reached, the last quote is kept.
+pub struct Actor {
+    recording: PathBuf,
+    speed: f64,
+    tasks: Tasks,
+    latest_quote: Option<Quote>,
+
+    /// Contains the reason we are stopping.
+    stop_reason: Option<Error>,
+}
+
+impl Actor {
+    /// Replay the given recording.
+    ///
+    /// With a `speed` of `n`, `n` seconds of recorded time are played back per second, e.g. a
+    /// `speed` of `1.0` replays in real-time.
+    pub fn new(recording: PathBuf, speed: f64) -> Result<Self> {
+        if !speed.is_finite() || speed <= 0.0 {
+            bail!("Replay speed must be a positive number, got {speed}");
+        }
+
+        Ok(Self {
+            recording,
+            speed,
+            tasks: Tasks::default(),
+            latest_quote: None,
+            stop_reason: None,
+        })
+    }
+}
+
+#[async_trait]
+impl xtra::Actor for Actor {
+    type Stop = Error;
+
+    async fn started(&mut self, ctx: &mut xtra::Context<Self>) {
+        let this = ctx.address().expect("we are alive");
+
+        let path = self.recording.clone();
+        let speed = self.speed;
+
+        self.tasks.add_fallible(
+            {
+                let this = this.clone();
+
+                async move {
+                    let messages = recording::load(&path)
+                        .await
+                        .map_err(|e| Error::Recording { source: e })?;
+
+                    let first_received_at = match messages.first() {
+                        Some(message) => message.received_at,
+                        None => {
+                            tracing::warn!(recording = %path.display(), "Recording is empty");
+                            return Ok(());
+                        }
+                    };
+
+                    tracing::info!(recording = %path.display(), %speed, "Replaying price feed");
+
+                    let started_at = OffsetDateTime::now_utc();
+                    let replay_time = |recorded: OffsetDateTime| {
+                        let elapsed = recorded - first_received_at;
+                        started_at + time::Duration::seconds_f64(elapsed.as_seconds_f64() / speed)
+                    };
+
+                    for message in messages {
+                        let due = replay_time(message.received_at) - OffsetDateTime::now_utc();
+                        if let Ok(due) = std::time::Duration::try_from(due) {
+                            tokio::time::sleep(due).await;
+                        }
+
+                        let quote = Quote::from_bitmex_message(&message.message)
+                            .map_err(|e| Error::FailedToParseQuote { source: e })?;
+
+                        let quote = match quote {
+                            Some(quote) => Quote {
+                                timestamp: replay_time(quote.timestamp),
+                                ..quote
+                            },
+                            None => continue,
+                        };
+
+                        tracing::debug!(bid = %quote.bid, ask = %quote.ask, timestamp = %quote.timestamp, "Replaying quote");
+
+                        let is_our_address_disconnected =
+                            this.send(NewQuoteReceived(quote)).await.is_err();
+
+                        if is_our_address_disconnected {
+                            return Ok(());
+                        }
+                    }
+
+                    tracing::info!(recording = %path.display(), "Finished replaying price feed");
+
+                    Ok(())
+                }
+            },
+            |e: Error| async move {
+                let _: Result<(), Disconnected> = this.send(e).await;
+            },
+        );
+    }
+
+    async fn stopped(self) -> Self::Stop {
+        self.stop_reason.unwrap_or(Error::Unspecified)
+    }
+}
+
+#[xtra_productivity(message_impl = false)]
+impl Actor {
+    async fn handle(&mut self, msg: Error, ctx: &mut xtra::Context<Self>) {
+        self.stop_reason = Some(msg);
+        ctx.stop();
+    }
+
+    async fn handle(&mut self, msg: NewQuoteReceived) {
+        self.latest_quote = Some(msg.0);
+    }
+
+    async fn handle(&mut self, _: LatestQuote) -> Option<Quote> {
+        self.latest_quote
+    }
+}
@@ -1,8 +1,17 @@
 use conquer_once::Lazy;
 use prometheus::register_histogram;
+use prometheus::register_histogram_vec;
+use prometheus::register_int_counter_vec;
+use prometheus::register_int_gauge_vec;
 use prometheus::Histogram;
+use prometheus::HistogramVec;
+use prometheus::IntCounterVec;
+use prometheus::IntGaugeVec;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
 use std::time::Duration;
+use std::time::Instant;
 use tokio_tasks::Tasks;
 use xtra::async_trait;
 use xtra::Address;
@@ -20,6 +29,12 @@ use xtras::SendInterval;
 /// Using this indicates that we are wire-compatible with other libp2p/ipfs nodes.
 pub const PROTOCOL_NAME: &str = "/ipfs/ping/1.0.0";
 
+/// The number of latency measurements we keep per peer.
+pub const LATENCY_HISTORY_LEN: usize = 100;
+
+/// How long we keep the connection quality of a peer we are no longer connected to.
+const FORGET_DISCONNECTED_PEERS_AFTER: Duration = Duration::from_secs(24 * 60 * 60);
+
 /// An actor implementing the official ipfs/libp2p ping protocol.
 ///
 /// The ping protocol serves two purposes:
@@ -35,11 +50,15 @@ pub const PROTOCOL_NAME: &str = "/ipfs/ping/1.0.0";
 /// incoming pings even without a `ping_interval` set. This is useful if an application wants to
 /// allow other peers in the network to measure their latency but is not interested in measuring
 /// latencies itself or keeping connections alive otherwise.
+///
+/// Alongside the latencies, the actor tracks the quality of the connection to every peer, see
+/// [`ConnectionQuality`]. Connections are only observed once per `ping_interval`, hence a
+/// reconnect within a single interval goes unnoticed.
 pub struct Actor {
     endpoint: Address<Endpoint>,
     ping_interval: Option<Duration>,
     tasks: Tasks,
-    latencies: HashMap<PeerId, Duration>,
+    peers: HashMap<PeerId, PeerState>,
 }
 
 impl Actor {
@@ -48,7 +67,138 @@ impl Actor {
             endpoint,
             ping_interval,
             tasks: Tasks::default(),
-            latencies: HashMap::default(),
+            peers: HashMap::default(),
+        }
+    }
+
+    /// Update the connection state of all peers we know of, given the currently connected peers.
+    fn observe_connections(&mut self, connected_peers: &HashSet<PeerId>, now: Instant) {
+        for peer in connected_peers {
+            let state = self
+                .peers
+                .entry(*peer)
+                .or_insert_with(|| PeerState::new(now));
+
+            if state.connected_since.is_none() {
+                if state.connections > 0 {
+                    PEER_RECONNECTS
+                        .with_label_values(&[&peer.to_string()])
+                        .inc();
+                }
+
+                state.connected_since = Some(now);
+                state.connections += 1;
+            }
+        }
+
+        for (peer, state) in self.peers.iter_mut() {
+            if connected_peers.contains(peer) {
+                continue;
+            }
+
+            if let Some(connected_since) = state.connected_since.take() {
+                state.previous_uptime += now.duration_since(connected_since);
+                state.last_seen = now;
+            }
+        }
+
+        self.peers.retain(|peer, state| {
+            let forget = state.connected_since.is_none()
+                && now.duration_since(state.last_seen) > FORGET_DISCONNECTED_PEERS_AFTER;
+
+            if forget {
+                let peer = peer.to_string();
+                let _ = PEER_LATENCY_HISTOGRAM_BY_PEER.remove_label_values(&[&peer]);
+                let _ = PEER_RECONNECTS.remove_label_values(&[&peer]);
+                let _ = PEER_UPTIME.remove_label_values(&[&peer]);
+            }
+
+            !forget
+        });
+
+        for (peer, state) in &self.peers {
+            PEER_UPTIME
+                .with_label_values(&[&peer.to_string()])
+                .set(state.uptime(now).as_secs() as i64);
+        }
+    }
+}
+
+/// The quality of the connection to a peer, as observed by the ping protocol.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConnectionQuality {
+    /// Whether we are currently connected to the peer.
+    pub connected: bool,
+    /// The most recent latency measurements, oldest first.
+    ///
+    /// At most [`LATENCY_HISTORY_LEN`] measurements are kept.
+    pub latencies: Vec<Duration>,
+    /// For how long we have been connected to the peer in total, across all connections.
+    pub uptime: Duration,
+    /// How often the connection to the peer was re-established.
+    pub reconnects: u32,
+}
+
+impl ConnectionQuality {
+    /// The most recent latency measurement.
+    pub fn latest_latency(&self) -> Option<Duration> {
+        self.latencies.last().copied()
+    }
+
+    /// The mean of all latency measurements in the history.
+    pub fn mean_latency(&self) -> Option<Duration> {
+        let num_latencies = u32::try_from(self.latencies.len())
+            .ok()
+            .filter(|n| *n > 0)?;
+
+        Some(self.latencies.iter().sum::<Duration>() / num_latencies)
+    }
+}
+
+struct PeerState {
+    latencies: VecDeque<Duration>,
+    connected_since: Option<Instant>,
+    /// The uptime of all previous connections.
+    previous_uptime: Duration,
+    /// The number of connections we have observed.
+    connections: u32,
+    last_seen: Instant,
+}
+
+impl PeerState {
+    fn new(now: Instant) -> Self {
+        Self {
+            latencies: VecDeque::with_capacity(LATENCY_HISTORY_LEN),
+            connected_since: None,
+            previous_uptime: Duration::ZERO,
+            connections: 0,
+            last_seen: now,
+        }
+    }
+
+    fn uptime(&self, now: Instant) -> Duration {
+        let current_uptime = self
+            .connected_since
+            .map(|connected_since| now.duration_since(connected_since))
+            .unwrap_or_default();
+
+        self.previous_uptime + current_uptime
+    }
+
+    fn record_latency(&mut self, latency: Duration) {
+        if self.latencies.len() == LATENCY_HISTORY_LEN {
+            self.latencies.pop_front();
+        }
+
+        self.latencies.push_back(latency);
+    }
+
+    fn quality(&self, now: Instant) -> ConnectionQuality {
+        ConnectionQuality {
+            connected: self.connected_since.is_some(),
+            latencies: self.latencies.iter().copied().collect(),
+            uptime: self.uptime(now),
+            reconnects: self.connections.saturating_sub(1),
         }
     }
 }
@@ -82,6 +232,16 @@ struct RecordLatency {
 /// Primarily used for testing. May be exposed publicly at some point.
 struct GetLatency(pub PeerId);
 
+/// Get the [`ConnectionQuality`] of a peer.
+///
+/// Returns `None` if we have not been connected to the peer recently.
+#[derive(Clone, Copy, Debug)]
+pub struct GetConnectionQuality(pub PeerId);
+
+/// Get the [`ConnectionQuality`] of all peers we have been connected to recently.
+#[derive(Clone, Copy, Debug)]
+pub struct GetConnectionQualities;
+
 #[xtra_productivity]
 impl Actor {
     async fn handle(&mut self, _: Ping, ctx: &mut Context<Self>) {
@@ -93,7 +253,7 @@ impl Actor {
             }
         };
 
-        self.latencies.clear();
+        self.observe_connections(&connection_stats.connected_peers, Instant::now());
 
         for peer in connection_stats.connected_peers {
             let endpoint = self.endpoint.clone();
@@ -123,7 +283,9 @@ impl Actor {
     async fn handle(&mut self, msg: RecordLatency) {
         let RecordLatency { peer, latency } = msg;
 
-        self.latencies.insert(peer, latency);
+        if let Some(state) = self.peers.get_mut(&peer) {
+            state.record_latency(latency);
+        }
 
         let latency_milliseconds = latency.as_millis();
 
@@ -131,10 +293,35 @@ impl Actor {
 
         let latency_seconds = (latency_milliseconds as f64) / 1000.0;
         PEER_LATENCY_HISTOGRAM.observe(latency_seconds);
+        PEER_LATENCY_HISTOGRAM_BY_PEER
+            .with_label_values(&[&peer.to_string()])
+            .observe(latency_seconds);
     }
 
     async fn handle(&mut self, GetLatency(peer): GetLatency) -> Option<Duration> {
-        return self.latencies.get(&peer).copied();
+        return self
+            .peers
+            .get(&peer)
+            .filter(|state| state.connected_since.is_some())
+            .and_then(|state| state.latencies.back().copied());
+    }
+
+    async fn handle(
+        &mut self,
+        GetConnectionQuality(peer): GetConnectionQuality,
+    ) -> Option<ConnectionQuality> {
+        let now = Instant::now();
+
+        self.peers.get(&peer).map(|state| state.quality(now))
+    }
+
+    async fn handle(&mut self, _: GetConnectionQualities) -> HashMap<PeerId, ConnectionQuality> {
+        let now = Instant::now();
+
+        self.peers
+            .iter()
+            .map(|(peer, state)| (*peer, state.quality(now)))
+            .collect()
     }
 }
 
@@ -171,6 +358,42 @@ static PEER_LATENCY_HISTOGRAM: Lazy<Histogram> = Lazy::new(|| {
     .unwrap()
 });
 
+const PEER_LABEL: &str = "peer";
+
+/// A histogram tracking the latency to each of our peers.
+///
+/// Contrary to [`PEER_LATENCY_HISTOGRAM`], this metric is labelled by peer, which allows us to
+/// identify peers with flaky connections. To keep the number of label values bounded, the label
+/// values of a peer are removed once we forget about the peer, i.e. after it has been disconnected
+/// for a day.
+static PEER_LATENCY_HISTOGRAM_BY_PEER: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "p2p_peer_ping_latency_seconds",
+        "The latency of ping messages to each connected peer in seconds.",
+        &[PEER_LABEL],
+        vec![0.01, 0.025, 0.05, 0.1, 0.2, 0.5, 1.0, 2.0, 5.0]
+    )
+    .unwrap()
+});
+
+static PEER_RECONNECTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "p2p_peer_reconnects_total",
+        "The number of times the connection to a peer was re-established.",
+        &[PEER_LABEL]
+    )
+    .unwrap()
+});
+
+static PEER_UPTIME: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "p2p_peer_uptime_seconds",
+        "For how long we have been connected to a peer in total, across all connections.",
+        &[PEER_LABEL]
+    )
+    .unwrap()
+});
+
 /// The actual protocol functions for sending ping messages.
 ///
 /// Insbired by https://github.com/libp2p/rust-libp2p/blob/102509afe3a3b984e43a88dbe4de935fde36f319/protocols/ping/src/protocol.rs#L82-L113.
@@ -273,6 +496,38 @@ mod tests {
         assert!(!bob_to_alice_latency.is_zero());
     }
 
+    #[test]
+    fn uptime_and_reconnects_are_tracked() {
+        let (endpoint, _) = Context::new(None);
+        let mut actor = Actor::new(endpoint, None);
+        let peer = PeerId::random();
+        let start = Instant::now();
+
+        actor.observe_connections(&HashSet::from([peer]), start);
+        actor.observe_connections(&HashSet::new(), start + Duration::from_secs(10));
+        actor.observe_connections(&HashSet::from([peer]), start + Duration::from_secs(20));
+
+        let quality = actor.peers[&peer].quality(start + Duration::from_secs(25));
+
+        assert!(quality.connected);
+        assert_eq!(quality.reconnects, 1);
+        assert_eq!(quality.uptime, Duration::from_secs(15));
+    }
+
+    #[test]
+    fn latency_history_is_bounded() {
+        let mut state = PeerState::new(Instant::now());
+
+        for millis in 0..(LATENCY_HISTORY_LEN as u64 + 10) {
+            state.record_latency(Duration::from_millis(millis));
+        }
+
+        let quality = state.quality(Instant::now());
+
+        assert_eq!(quality.latencies.len(), LATENCY_HISTORY_LEN);
+        assert_eq!(quality.latencies[0], Duration::from_millis(10));
+    }
+
     fn create_endpoint_with_ping() -> (PeerId, Address<Actor>, Address<Endpoint>) {
         let (endpoint_address, endpoint_context) = Context::new(None);
 
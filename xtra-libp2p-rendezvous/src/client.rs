@@ -0,0 +1,341 @@
+use crate::protocol;
+use crate::protocol::Request;
+use crate::protocol::Response;
+use crate::Record;
+use crate::PROTOCOL_NAME;
+use anyhow::bail;
+use anyhow::Context as _;
+use anyhow::Result;
+use std::time::Duration;
+use tokio_tasks::Tasks;
+use xtra::async_trait;
+use xtra::Address;
+use xtra::Context;
+use xtra_libp2p::libp2p::identity::Keypair;
+use xtra_libp2p::libp2p::multiaddr::Protocol;
+use xtra_libp2p::libp2p::Multiaddr;
+use xtra_libp2p::libp2p::PeerId;
+use xtra_libp2p::Connect;
+use xtra_libp2p::Endpoint;
+use xtra_libp2p::GetConnectionStats;
+use xtra_libp2p::OpenSubstream;
+use xtra_productivity::xtra_productivity;
+use xtras::SendInterval;
+
+/// The time-to-live we ask the rendezvous node to keep our registration for.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// How long we wait for the connection to the rendezvous node to be established.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often we check whether the connection to the rendezvous node is established.
+const CONNECT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long we wait before retrying a failed registration for the first time.
+const INITIAL_RETRY_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The longest we wait between two attempts to register.
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+/// An actor implementing the client side of the rendezvous protocol.
+///
+/// Once a [`Register`] message was handled, the registration is refreshed after half of its
+/// time-to-live, hence we stay discoverable for as long as this actor is alive. Sending another
+/// [`Register`] message replaces the registration, e.g. to update the payload of our record.
+///
+/// The registration is kept even if registering fails, e.g. because the rendezvous node is not
+/// reachable yet. Failed attempts are retried with exponential backoff until they succeed.
+///
+/// Records returned in response to [`Discover`] are verified by this actor, i.e. the rendezvous
+/// node does not have to be trusted with the content of the records.
+pub struct Actor {
+    endpoint: Address<Endpoint>,
+    identity: Keypair,
+    rendezvous_node: Multiaddr,
+    rendezvous_peer: PeerId,
+    ttl: Duration,
+    registration: Option<Register>,
+    failed_attempts: u32,
+    retry_scheduled: bool,
+    tasks: Tasks,
+}
+
+impl Actor {
+    /// Construct a client for the rendezvous node reachable at the given address.
+    ///
+    /// The address must end with the `/p2p` protocol of the rendezvous node. The `identity` has to
+    /// be the one of the `endpoint`, otherwise the rendezvous node rejects our registrations.
+    pub fn new(
+        endpoint: Address<Endpoint>,
+        identity: Keypair,
+        rendezvous_node: Multiaddr,
+        ttl: Duration,
+    ) -> Result<Self> {
+        let rendezvous_peer = match rendezvous_node.iter().last() {
+            Some(Protocol::P2p(hash)) => PeerId::from_multihash(hash)
+                .map_err(|_| anyhow::anyhow!("Invalid peer ID in {rendezvous_node}"))?,
+            _ => bail!("Address {rendezvous_node} does not end with a peer ID"),
+        };
+
+        Ok(Self {
+            endpoint,
+            identity,
+            rendezvous_node,
+            rendezvous_peer,
+            ttl,
+            registration: None,
+            failed_attempts: 0,
+            retry_scheduled: false,
+            tasks: Tasks::default(),
+        })
+    }
+
+    async fn ensure_connected(&self) -> Result<()> {
+        if self.is_connected().await? {
+            return Ok(());
+        }
+
+        // Somebody else might be connecting already, hence we don't care if this fails
+        let _ = self
+            .endpoint
+            .send(Connect(self.rendezvous_node.clone()))
+            .await
+            .context("Endpoint actor is disconnected")?;
+
+        tokio::time::timeout(CONNECT_TIMEOUT, async {
+            while !self.is_connected().await? {
+                tokio::time::sleep(CONNECT_POLL_INTERVAL).await;
+            }
+
+            anyhow::Ok(())
+        })
+        .await
+        .with_context(|| format!("Failed to connect to {}", self.rendezvous_node))?
+    }
+
+    async fn is_connected(&self) -> Result<bool> {
+        let stats = self
+            .endpoint
+            .send(GetConnectionStats)
+            .await
+            .context("Endpoint actor is disconnected")?;
+
+        Ok(stats.connected_peers.contains(&self.rendezvous_peer))
+    }
+
+    async fn request(&self, request: Request) -> Result<Response> {
+        self.ensure_connected().await?;
+
+        let mut stream = self
+            .endpoint
+            .send(OpenSubstream::single_protocol(
+                self.rendezvous_peer,
+                PROTOCOL_NAME,
+            ))
+            .await
+            .context("Endpoint actor is disconnected")??;
+
+        protocol::write(&mut stream, &request).await?;
+        let response = protocol::read(&mut stream).await?;
+
+        Ok(response)
+    }
+
+    async fn register(&self, registration: &Register) -> Result<()> {
+        let record = Record::new(
+            self.identity.public().to_peer_id(),
+            registration.namespace.clone(),
+            registration.addresses.clone(),
+            &registration.payload,
+        )?
+        .sign(&self.identity)?;
+
+        let request = Request::Register {
+            record,
+            ttl: self.ttl.as_secs(),
+        };
+
+        match self.request(request).await? {
+            Response::Registered { ttl } => {
+                tracing::debug!(rendezvous = %self.rendezvous_peer, namespace = %registration.namespace, ttl_seconds = %ttl, "Registered at rendezvous node");
+
+                Ok(())
+            }
+            Response::Rejected { reason } => bail!("Registration rejected: {reason}"),
+            other => bail!("Unexpected response to registration: {other:?}"),
+        }
+    }
+
+    /// Register our current registration, if any, and schedule a retry if that fails.
+    async fn register_or_retry(&mut self, ctx: &mut Context<Self>) -> Result<()> {
+        let registration = match &self.registration {
+            Some(registration) => registration,
+            None => return Ok(()),
+        };
+
+        match self.register(registration).await {
+            Ok(()) => {
+                self.failed_attempts = 0;
+
+                Ok(())
+            }
+            Err(e) => {
+                self.failed_attempts = self.failed_attempts.saturating_add(1);
+                self.schedule_retry(ctx);
+
+                Err(e)
+            }
+        }
+    }
+
+    fn schedule_retry(&mut self, ctx: &mut Context<Self>) {
+        if self.retry_scheduled {
+            return;
+        }
+        self.retry_scheduled = true;
+
+        let this = ctx.address().expect("we are alive");
+        let delay = retry_backoff(self.failed_attempts);
+
+        self.tasks.add(async move {
+            tokio::time::sleep(delay).await;
+            let _ = this.send(RetryRegistration).await;
+        });
+    }
+}
+
+/// The delay before the next registration attempt after `attempts` failed attempts.
+fn retry_backoff(attempts: u32) -> Duration {
+    INITIAL_RETRY_BACKOFF
+        .checked_mul(2u32.saturating_pow(attempts.saturating_sub(1)))
+        .unwrap_or(MAX_RETRY_BACKOFF)
+        .min(MAX_RETRY_BACKOFF)
+}
+
+#[async_trait]
+impl xtra::Actor for Actor {
+    type Stop = ();
+
+    async fn started(&mut self, ctx: &mut Context<Self>) {
+        let this = ctx.address().expect("we just started");
+
+        self.tasks
+            .add(this.send_interval(self.ttl / 2, || RefreshRegistration));
+    }
+
+    async fn stopped(self) -> Self::Stop {}
+}
+
+/// Register a record about ourselves at the rendezvous node.
+#[derive(Debug, Clone)]
+pub struct Register {
+    pub namespace: String,
+    /// The addresses under which we can be reached.
+    pub addresses: Vec<Multiaddr>,
+    pub payload: serde_json::Value,
+}
+
+/// Remove our registration from the given namespace.
+#[derive(Debug, Clone)]
+pub struct Unregister {
+    pub namespace: String,
+}
+
+/// Ask the rendezvous node for the verified records of all peers in the given namespace.
+///
+/// Records which fail verification are dropped.
+#[derive(Debug, Clone)]
+pub struct Discover {
+    pub namespace: String,
+}
+
+/// Private message to refresh our registration before it expires.
+struct RefreshRegistration;
+
+/// Private message to retry a registration which failed.
+struct RetryRegistration;
+
+#[xtra_productivity]
+impl Actor {
+    async fn handle(&mut self, msg: Register, ctx: &mut Context<Self>) -> Result<()> {
+        self.registration = Some(msg);
+
+        self.register_or_retry(ctx).await
+    }
+
+    async fn handle(&mut self, msg: Unregister) -> Result<()> {
+        if matches!(&self.registration, Some(registration) if registration.namespace == msg.namespace)
+        {
+            self.registration = None;
+        }
+
+        match self
+            .request(Request::Unregister {
+                namespace: msg.namespace,
+            })
+            .await?
+        {
+            Response::Unregistered => Ok(()),
+            Response::Rejected { reason } => bail!("Unregistration rejected: {reason}"),
+            other => bail!("Unexpected response to unregistration: {other:?}"),
+        }
+    }
+
+    async fn handle(&mut self, msg: Discover) -> Result<Vec<Record>> {
+        let records = match self
+            .request(Request::Discover {
+                namespace: msg.namespace.clone(),
+            })
+            .await?
+        {
+            Response::Discovered { records } => records,
+            Response::Rejected { reason } => bail!("Discovery rejected: {reason}"),
+            other => bail!("Unexpected response to discovery: {other:?}"),
+        };
+
+        let records = records
+            .into_iter()
+            .filter_map(|record| match record.verify() {
+                Ok(record) if record.namespace == msg.namespace => Some(record),
+                Ok(record) => {
+                    tracing::warn!(peer = %record.peer_id, namespace = %record.namespace, "Ignoring record from other namespace");
+                    None
+                }
+                Err(e) => {
+                    tracing::warn!(rendezvous = %self.rendezvous_peer, "Ignoring invalid record: {e:#}");
+                    None
+                }
+            })
+            .collect();
+
+        Ok(records)
+    }
+
+    async fn handle(&mut self, _: RefreshRegistration, ctx: &mut Context<Self>) {
+        if let Err(e) = self.register_or_retry(ctx).await {
+            tracing::warn!(rendezvous = %self.rendezvous_peer, "Failed to refresh registration: {e:#}");
+        }
+    }
+
+    async fn handle(&mut self, _: RetryRegistration, ctx: &mut Context<Self>) {
+        self.retry_scheduled = false;
+
+        if let Err(e) = self.register_or_retry(ctx).await {
+            tracing::warn!(rendezvous = %self.rendezvous_peer, attempts = %self.failed_attempts, "Failed to retry registration: {e:#}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_backoff_doubles_up_to_max() {
+        assert_eq!(retry_backoff(1), INITIAL_RETRY_BACKOFF);
+        assert_eq!(retry_backoff(2), INITIAL_RETRY_BACKOFF * 2);
+        assert_eq!(retry_backoff(3), INITIAL_RETRY_BACKOFF * 4);
+        assert_eq!(retry_backoff(30), MAX_RETRY_BACKOFF);
+        assert_eq!(retry_backoff(u32::MAX), MAX_RETRY_BACKOFF);
+    }
+}
@@ -0,0 +1,11 @@
+pub use crate::record::Record;
+pub use crate::record::SignedRecord;
+pub use crate::record::VerificationError;
+
+pub mod client;
+mod protocol;
+mod record;
+pub mod server;
+
+/// The name of the rendezvous protocol.
+pub const PROTOCOL_NAME: &str = "/itchysats/rendezvous/1.0.0";
@@ -0,0 +1,81 @@
+//! The messages exchanged between a rendezvous node and its clients.
+//!
+//! Every substream carries exactly one request followed by one response. Messages are encoded as
+//! JSON, prefixed with their length as big-endian `u32`.
+
+use crate::SignedRecord;
+use anyhow::Context;
+use anyhow::Result;
+use futures::AsyncReadExt;
+use futures::AsyncWriteExt;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde::Serialize;
+use xtra_libp2p::Substream;
+
+/// The largest message we are willing to read.
+///
+/// Large enough for a few hundred records in a single [`Response::Discovered`].
+const MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "payload")]
+pub enum Request {
+    /// Register the record of the requesting peer for `ttl` seconds.
+    Register { record: SignedRecord, ttl: u64 },
+    /// Remove the record of the requesting peer from the given namespace.
+    Unregister { namespace: String },
+    /// Ask for the records of all peers registered in the given namespace.
+    Discover { namespace: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "payload")]
+pub enum Response {
+    /// The record was registered, possibly with a shorter `ttl` than requested.
+    Registered {
+        ttl: u64,
+    },
+    Unregistered,
+    Discovered {
+        records: Vec<SignedRecord>,
+    },
+    Rejected {
+        reason: String,
+    },
+}
+
+pub async fn write<T>(stream: &mut Substream, message: &T) -> Result<()>
+where
+    T: Serialize,
+{
+    let bytes = serde_json::to_vec(message).context("Failed to serialize message")?;
+    let len = u32::try_from(bytes.len()).context("Message too large")?;
+
+    stream.write_all(&len.to_be_bytes()).await?;
+    stream.write_all(&bytes).await?;
+    stream.flush().await?;
+
+    Ok(())
+}
+
+pub async fn read<T>(stream: &mut Substream) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len).await?;
+    let len = u32::from_be_bytes(len) as usize;
+
+    anyhow::ensure!(
+        len <= MAX_MESSAGE_SIZE,
+        "Message of {len} bytes exceeds maximum of {MAX_MESSAGE_SIZE} bytes"
+    );
+
+    let mut bytes = vec![0u8; len];
+    stream.read_exact(&mut bytes).await?;
+
+    let message = serde_json::from_slice(&bytes).context("Failed to deserialize message")?;
+
+    Ok(message)
+}
@@ -0,0 +1,214 @@
+use anyhow::Context;
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_with::serde_as;
+use serde_with::DisplayFromStr;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+use xtra_libp2p::libp2p::identity::Keypair;
+use xtra_libp2p::libp2p::identity::PublicKey;
+use xtra_libp2p::libp2p::Multiaddr;
+use xtra_libp2p::libp2p::PeerId;
+
+/// Prefixed to the record before signing to make sure a signature over a record cannot be
+/// mistaken for a signature in any other context.
+const SIGNATURE_DOMAIN: &[u8] = b"/itchysats/rendezvous/record";
+
+/// Describes how to reach a peer and what it offers.
+///
+/// A record is only ever exchanged as [`SignedRecord`], hence neither the rendezvous node nor
+/// anybody else can tamper with the record of a peer.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    #[serde_as(as = "DisplayFromStr")]
+    pub peer_id: PeerId,
+    pub namespace: String,
+    #[serde_as(as = "Vec<DisplayFromStr>")]
+    pub addresses: Vec<Multiaddr>,
+    /// Application specific data, e.g. a summary of what the peer offers.
+    pub payload: serde_json::Value,
+    /// Seconds since the unix epoch at which the record was created.
+    pub timestamp: u64,
+}
+
+impl Record {
+    pub fn new(
+        peer_id: PeerId,
+        namespace: impl Into<String>,
+        addresses: Vec<Multiaddr>,
+        payload: &impl Serialize,
+    ) -> Result<Self> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .context("System time is before the unix epoch")?
+            .as_secs();
+
+        Ok(Self {
+            peer_id,
+            namespace: namespace.into(),
+            addresses,
+            payload: serde_json::to_value(payload).context("Failed to serialize payload")?,
+            timestamp,
+        })
+    }
+
+    /// Deserialize the application specific payload of the record.
+    pub fn payload<T>(&self) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        let payload =
+            serde_json::from_value(self.payload.clone()).context("Failed to parse payload")?;
+
+        Ok(payload)
+    }
+
+    /// Sign the record with the keypair of the peer it describes.
+    pub fn sign(&self, keypair: &Keypair) -> Result<SignedRecord> {
+        let public_key = keypair.public();
+        anyhow::ensure!(
+            public_key.to_peer_id() == self.peer_id,
+            "Cannot sign record of {} with keypair of {}",
+            self.peer_id,
+            public_key.to_peer_id()
+        );
+
+        let record = serde_json::to_vec(self).context("Failed to serialize record")?;
+        let signature = keypair
+            .sign(&signing_message(&record))
+            .context("Failed to sign record")?;
+
+        Ok(SignedRecord {
+            public_key: public_key.to_protobuf_encoding(),
+            record,
+            signature,
+        })
+    }
+}
+
+/// A [`Record`] together with the signature of the peer it describes.
+///
+/// The record is kept in its serialized form, so the signature can be verified over the exact
+/// bytes that were signed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignedRecord {
+    #[serde(with = "hex")]
+    public_key: Vec<u8>,
+    #[serde(with = "hex")]
+    record: Vec<u8>,
+    #[serde(with = "hex")]
+    signature: Vec<u8>,
+}
+
+impl SignedRecord {
+    /// Verify the signature and return the contained record.
+    ///
+    /// Fails unless the record is signed by the peer it describes.
+    pub fn verify(&self) -> Result<Record, VerificationError> {
+        let public_key = PublicKey::from_protobuf_encoding(&self.public_key)
+            .map_err(|_| VerificationError::InvalidPublicKey)?;
+
+        if !public_key.verify(&signing_message(&self.record), &self.signature) {
+            return Err(VerificationError::InvalidSignature);
+        }
+
+        let record = serde_json::from_slice::<Record>(&self.record)
+            .map_err(|source| VerificationError::MalformedRecord { source })?;
+
+        let signer = public_key.to_peer_id();
+        if record.peer_id != signer {
+            return Err(VerificationError::PeerIdMismatch {
+                signer,
+                record: record.peer_id,
+            });
+        }
+
+        Ok(record)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum VerificationError {
+    #[error("Public key could not be decoded")]
+    InvalidPublicKey,
+    #[error("Signature does not match the record")]
+    InvalidSignature,
+    #[error("Record could not be decoded")]
+    MalformedRecord { source: serde_json::Error },
+    #[error("Record of {record} is signed by {signer}")]
+    PeerIdMismatch { signer: PeerId, record: PeerId },
+}
+
+fn signing_message(record: &[u8]) -> Vec<u8> {
+    [SIGNATURE_DOMAIN, record].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signed_record_can_be_verified() {
+        let keypair = Keypair::generate_ed25519();
+        let record = dummy_record(&keypair);
+
+        let verified = record.sign(&keypair).unwrap().verify().unwrap();
+
+        assert_eq!(verified, record);
+    }
+
+    #[test]
+    fn cannot_sign_record_of_other_peer() {
+        let record = dummy_record(&Keypair::generate_ed25519());
+
+        let result = record.sign(&Keypair::generate_ed25519());
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn tampered_record_fails_verification() {
+        let keypair = Keypair::generate_ed25519();
+        let mut signed = dummy_record(&keypair).sign(&keypair).unwrap();
+
+        let mut tampered = dummy_record(&keypair);
+        tampered.addresses = vec!["/ip4/6.6.6.6/tcp/666".parse().unwrap()];
+        signed.record = serde_json::to_vec(&tampered).unwrap();
+
+        assert!(matches!(
+            signed.verify(),
+            Err(VerificationError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn record_signed_by_other_peer_fails_verification() {
+        let keypair = Keypair::generate_ed25519();
+        let impostor = Keypair::generate_ed25519();
+
+        let record = serde_json::to_vec(&dummy_record(&keypair)).unwrap();
+        let signed = SignedRecord {
+            public_key: impostor.public().to_protobuf_encoding(),
+            signature: impostor.sign(&signing_message(&record)).unwrap(),
+            record,
+        };
+
+        assert!(matches!(
+            signed.verify(),
+            Err(VerificationError::PeerIdMismatch { .. })
+        ));
+    }
+
+    fn dummy_record(keypair: &Keypair) -> Record {
+        Record::new(
+            keypair.public().to_peer_id(),
+            "test",
+            vec!["/ip4/127.0.0.1/tcp/10000".parse().unwrap()],
+            &"payload",
+        )
+        .unwrap()
+    }
+}
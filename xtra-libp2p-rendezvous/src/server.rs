@@ -0,0 +1,159 @@
+use crate::protocol;
+use crate::protocol::Request;
+use crate::protocol::Response;
+use crate::SignedRecord;
+use std::collections::HashMap;
+use std::time::Duration;
+use std::time::Instant;
+use tokio_tasks::Tasks;
+use xtra::async_trait;
+use xtra::Context;
+use xtra_libp2p::libp2p::PeerId;
+use xtra_libp2p::NewInboundSubstream;
+use xtra_productivity::xtra_productivity;
+
+/// The longest time a registration is kept unless the server is configured otherwise.
+pub const DEFAULT_MAX_TTL: Duration = Duration::from_secs(2 * 60 * 60);
+
+/// An actor implementing the rendezvous node.
+///
+/// Peers register their [`SignedRecord`] under a namespace and can discover the records of all
+/// other peers registered under the same namespace. A peer can only register a record about
+/// itself, i.e. the record has to be signed by the peer that opened the substream.
+///
+/// Registrations expire after their time-to-live, hence peers have to refresh their registration
+/// regularly to remain discoverable.
+pub struct Actor {
+    max_ttl: Duration,
+    registrations: HashMap<(String, PeerId), Registration>,
+    tasks: Tasks,
+}
+
+struct Registration {
+    record: SignedRecord,
+    expires_at: Instant,
+}
+
+impl Actor {
+    pub fn new(max_ttl: Duration) -> Self {
+        Self {
+            max_ttl,
+            registrations: HashMap::default(),
+            tasks: Tasks::default(),
+        }
+    }
+
+    fn prune_expired(&mut self) {
+        let now = Instant::now();
+
+        self.registrations
+            .retain(|_, registration| registration.expires_at > now);
+    }
+
+    fn register(&mut self, peer: PeerId, record: SignedRecord, ttl: u64) -> Response {
+        let verified = match record.verify() {
+            Ok(verified) => verified,
+            Err(e) => {
+                return Response::Rejected {
+                    reason: format!("Invalid record: {e}"),
+                }
+            }
+        };
+
+        if verified.peer_id != peer {
+            return Response::Rejected {
+                reason: format!(
+                    "Peer {peer} cannot register the record of {}",
+                    verified.peer_id
+                ),
+            };
+        }
+
+        let ttl = Duration::from_secs(ttl).min(self.max_ttl);
+        if ttl.is_zero() {
+            return Response::Rejected {
+                reason: "Time-to-live must be positive".to_owned(),
+            };
+        }
+
+        tracing::debug!(%peer, namespace = %verified.namespace, ttl_seconds = %ttl.as_secs(), "Registered peer");
+
+        self.registrations.insert(
+            (verified.namespace, peer),
+            Registration {
+                record,
+                expires_at: Instant::now() + ttl,
+            },
+        );
+
+        Response::Registered { ttl: ttl.as_secs() }
+    }
+}
+
+impl Default for Actor {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_TTL)
+    }
+}
+
+#[async_trait]
+impl xtra::Actor for Actor {
+    type Stop = ();
+
+    async fn stopped(self) -> Self::Stop {}
+}
+
+/// Private message to process a request received on an inbound substream.
+struct HandleRequest {
+    peer: PeerId,
+    request: Request,
+}
+
+#[xtra_productivity]
+impl Actor {
+    async fn handle(&mut self, msg: HandleRequest) -> Response {
+        let HandleRequest { peer, request } = msg;
+
+        self.prune_expired();
+
+        match request {
+            Request::Register { record, ttl } => self.register(peer, record, ttl),
+            Request::Unregister { namespace } => {
+                self.registrations.remove(&(namespace, peer));
+
+                Response::Unregistered
+            }
+            Request::Discover { namespace } => {
+                let records = self
+                    .registrations
+                    .iter()
+                    .filter(|((registered_namespace, _), _)| *registered_namespace == namespace)
+                    .map(|(_, registration)| registration.record.clone())
+                    .collect();
+
+                Response::Discovered { records }
+            }
+        }
+    }
+}
+
+#[xtra_productivity(message_impl = false)]
+impl Actor {
+    async fn handle(&mut self, msg: NewInboundSubstream, ctx: &mut Context<Self>) {
+        let NewInboundSubstream { peer, mut stream } = msg;
+        let this = ctx.address().expect("we are alive");
+
+        self.tasks.add_fallible(
+            async move {
+                let request = protocol::read::<Request>(&mut stream).await?;
+                let response = this.send(HandleRequest { peer, request }).await?;
+                protocol::write(&mut stream, &response).await?;
+
+                anyhow::Ok(())
+            },
+            move |e| async move {
+                tracing::debug!(%peer, "Inbound rendezvous protocol failed: {e:#}")
+            },
+        );
+    }
+}
@@ -0,0 +1,242 @@
+use serde_json::json;
+use std::time::Duration;
+use xtra::message_channel::StrongMessageChannel;
+use xtra::spawn::TokioGlobalSpawnExt;
+use xtra::Actor as _;
+use xtra::Address;
+use xtra_libp2p::libp2p::identity::Keypair;
+use xtra_libp2p::libp2p::transport::MemoryTransport;
+use xtra_libp2p::libp2p::Multiaddr;
+use xtra_libp2p::libp2p::PeerId;
+use xtra_libp2p::Endpoint;
+use xtra_libp2p::ListenOn;
+use xtra_libp2p_rendezvous::client;
+use xtra_libp2p_rendezvous::client::Discover;
+use xtra_libp2p_rendezvous::client::Register;
+use xtra_libp2p_rendezvous::client::Unregister;
+use xtra_libp2p_rendezvous::server;
+use xtra_libp2p_rendezvous::PROTOCOL_NAME;
+
+#[tokio::test]
+async fn registered_peer_can_be_discovered() {
+    let rendezvous_node = rendezvous_node().await;
+    let (maker_peer_id, maker) = client(&rendezvous_node);
+    let (_, taker) = client(&rendezvous_node);
+
+    let maker_address = "/memory/10000".parse::<Multiaddr>().unwrap();
+    maker
+        .send(Register {
+            namespace: "makers".to_owned(),
+            addresses: vec![maker_address.clone()],
+            payload: json!({ "price": 40_000 }),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+    let records = taker
+        .send(Discover {
+            namespace: "makers".to_owned(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].peer_id, maker_peer_id);
+    assert_eq!(records[0].addresses, vec![maker_address]);
+    assert_eq!(records[0].payload, json!({ "price": 40_000 }));
+}
+
+#[tokio::test]
+async fn only_peers_of_requested_namespace_are_discovered() {
+    let rendezvous_node = rendezvous_node().await;
+    let (_, maker) = client(&rendezvous_node);
+    let (_, taker) = client(&rendezvous_node);
+
+    maker
+        .send(Register {
+            namespace: "makers".to_owned(),
+            addresses: vec![],
+            payload: json!(null),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+    let records = taker
+        .send(Discover {
+            namespace: "takers".to_owned(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert!(records.is_empty());
+}
+
+#[tokio::test]
+async fn registering_again_replaces_record() {
+    let rendezvous_node = rendezvous_node().await;
+    let (_, maker) = client(&rendezvous_node);
+    let (_, taker) = client(&rendezvous_node);
+
+    for price in [40_000, 41_000] {
+        maker
+            .send(Register {
+                namespace: "makers".to_owned(),
+                addresses: vec![],
+                payload: json!({ "price": price }),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    let records = taker
+        .send(Discover {
+            namespace: "makers".to_owned(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].payload, json!({ "price": 41_000 }));
+}
+
+#[tokio::test]
+async fn unregistered_peer_is_no_longer_discovered() {
+    let rendezvous_node = rendezvous_node().await;
+    let (_, maker) = client(&rendezvous_node);
+    let (_, taker) = client(&rendezvous_node);
+
+    maker
+        .send(Register {
+            namespace: "makers".to_owned(),
+            addresses: vec![],
+            payload: json!(null),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    maker
+        .send(Unregister {
+            namespace: "makers".to_owned(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+    let records = taker
+        .send(Discover {
+            namespace: "makers".to_owned(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert!(records.is_empty());
+}
+
+#[tokio::test]
+async fn failed_registration_is_retried() {
+    let id = Keypair::generate_ed25519();
+    let port = rand::random::<u16>();
+    let rendezvous_node = format!("/memory/{port}/p2p/{}", id.public().to_peer_id())
+        .parse::<Multiaddr>()
+        .unwrap();
+    let (maker_peer_id, maker) = client(&rendezvous_node);
+    let (_, taker) = client(&rendezvous_node);
+
+    let result = maker
+        .send(Register {
+            namespace: "makers".to_owned(),
+            addresses: vec![],
+            payload: json!(null),
+        })
+        .await
+        .unwrap();
+    assert!(result.is_err(), "rendezvous node is not listening yet");
+
+    spawn_rendezvous_node(id, port).await;
+
+    let records = tokio::time::timeout(Duration::from_secs(30), async {
+        loop {
+            let records = taker
+                .send(Discover {
+                    namespace: "makers".to_owned(),
+                })
+                .await
+                .unwrap()
+                .unwrap();
+
+            if !records.is_empty() {
+                return records;
+            }
+
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    })
+    .await
+    .expect("registration to be retried");
+
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].peer_id, maker_peer_id);
+}
+
+/// Spawn a rendezvous node listening on a random memory address.
+///
+/// Returns the address of the node, including its peer ID.
+async fn rendezvous_node() -> Multiaddr {
+    let id = Keypair::generate_ed25519();
+    let peer_id = id.public().to_peer_id();
+    let port = rand::random::<u16>();
+
+    spawn_rendezvous_node(id, port).await;
+
+    format!("/memory/{port}/p2p/{peer_id}").parse().unwrap()
+}
+
+async fn spawn_rendezvous_node(id: Keypair, port: u16) {
+    let server = server::Actor::default().create(None).spawn_global();
+    let (_, endpoint) = make_endpoint_with_identity(id, [(PROTOCOL_NAME, server.clone_channel())]);
+
+    endpoint
+        .send(ListenOn(format!("/memory/{port}").parse().unwrap()))
+        .await
+        .unwrap();
+}
+
+fn client(rendezvous_node: &Multiaddr) -> (PeerId, Address<client::Actor>) {
+    let id = Keypair::generate_ed25519();
+    let (peer_id, endpoint) = make_endpoint_with_identity(id.clone(), []);
+
+    let client = client::Actor::new(endpoint, id, rendezvous_node.clone(), client::DEFAULT_TTL)
+        .unwrap()
+        .create(None)
+        .spawn_global();
+
+    (peer_id, client)
+}
+
+fn make_endpoint_with_identity<const N: usize>(
+    id: Keypair,
+    substream_handlers: [(
+        &'static str,
+        Box<dyn StrongMessageChannel<xtra_libp2p::NewInboundSubstream>>,
+    ); N],
+) -> (PeerId, Address<Endpoint>) {
+    let peer_id = id.public().to_peer_id();
+
+    let endpoint = Endpoint::new(
+        MemoryTransport::default(),
+        id,
+        Duration::from_secs(20),
+        substream_handlers,
+    )
+    .create(None)
+    .spawn_global();
+
+    (peer_id, endpoint)
+}
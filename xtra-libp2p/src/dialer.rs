@@ -1,70 +1,109 @@
 use crate::multiaddress_ext::MultiaddrExt;
-use crate::Connect;
-use crate::ConnectionStats;
+use crate::AddAddress;
+use crate::ConnectionEvent;
+use crate::Dial;
 use crate::Endpoint;
-use crate::GetConnectionStats;
+use crate::Subscribe;
 use anyhow::anyhow;
 use async_trait::async_trait;
 use libp2p_core::Multiaddr;
+use libp2p_core::PeerId;
+use rand::Rng;
 use std::time::Duration;
 use tokio_tasks::Tasks;
+use xtra::message_channel::MessageChannel;
 use xtra::Address;
 use xtra_productivity::xtra_productivity;
-use xtras::SendInterval;
+use xtras::SendAsyncSafe;
 
-/// How often we check whether we are still connected
-pub const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// The delay before the first reconnection attempt.
+pub const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The maximum delay between two reconnection attempts.
+pub const MAX_BACKOFF: Duration = Duration::from_secs(60);
 
 /// xtra actor that takes care of dialing (connecting) to an Endpoint.
 ///
-/// Periodically polls Endpoint to check whether connection is still active.
-/// Should be used in conjunction with supervisor maintaining resilient connection.
+/// Adds the address to the address book of the Endpoint and subscribes to its connection events.
+/// Whenever the connection drops or dialing fails, it dials the peer again on all known
+/// addresses, backing off exponentially with jitter between consecutive failures.
+///
+/// The actor only stops if the Endpoint goes away or the address is invalid, hence a supervisor
+/// restarting it does not hot-loop if the peer is down.
 pub struct Actor {
-    tasks: Tasks,
     endpoint: Address<Endpoint>,
     connect_address: Multiaddr,
+    /// Number of consecutive failed attempts to connect.
+    failed_attempts: u32,
+    /// Holds the task waiting to reconnect, dropping it cancels the reconnect.
+    reconnect: Option<Tasks>,
     stop_reason: Option<Error>,
 }
 
 impl Actor {
     pub fn new(endpoint: Address<Endpoint>, connect_address: Multiaddr) -> Self {
         Self {
-            tasks: Tasks::default(),
             endpoint,
             connect_address,
+            failed_attempts: 0,
+            reconnect: None,
             stop_reason: None,
         }
     }
 
-    /// Returns error if we cannot access the Endpoint or if the peer is not
-    /// connected to the Endpoint.
-    async fn check_connection_active_in_endpoint(&self) -> Result<(), Error> {
-        let ConnectionStats {
-            connected_peers, ..
-        } = self
-            .endpoint
-            .send(GetConnectionStats)
+    fn peer(&self) -> Result<PeerId, Error> {
+        self.connect_address
+            .clone()
+            .extract_peer_id()
+            .ok_or(Error::InvalidPeerId)
+    }
+
+    async fn start(&mut self, this: Address<Self>) -> Result<(), Error> {
+        let peer = self.peer()?;
+
+        self.endpoint
+            .send(AddAddress(self.connect_address.clone()))
+            .await
+            .map_err(|_| Error::NoEndpoint)?
+            .map_err(|e| Error::Failed { source: anyhow!(e) })?;
+        self.endpoint
+            .send(Subscribe(MessageChannel::clone_channel(&this.downgrade())))
             .await
             .map_err(|_| Error::NoEndpoint)?;
 
-        connected_peers
-            .contains(
-                &self
-                    .connect_address
-                    .clone()
-                    .extract_peer_id()
-                    .ok_or(Error::InvalidPeerId)?,
-            )
-            .then(|| ())
-            .ok_or(Error::ConnectionDropped)
+        self.dial(peer, this).await
     }
 
-    async fn connect(&self) -> Result<(), Error> {
-        self.endpoint
-            .send(Connect(self.connect_address.clone()))
+    async fn dial(&mut self, peer: PeerId, this: Address<Self>) -> Result<(), Error> {
+        let result = self
+            .endpoint
+            .send(Dial(peer))
             .await
-            .map_err(|_| Error::NoEndpoint)?
-            .map_err(|e| Error::Failed { source: anyhow!(e) })
+            .map_err(|_| Error::NoEndpoint)?;
+
+        match result {
+            Ok(()) | Err(crate::Error::AlreadyConnected(_)) => {}
+            Err(e) => {
+                tracing::debug!(%peer, "Failed to dial: {e:#}");
+                self.schedule_reconnect(peer, this);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn schedule_reconnect(&mut self, peer: PeerId, this: Address<Self>) {
+        let delay = backoff(self.failed_attempts);
+        self.failed_attempts = self.failed_attempts.saturating_add(1);
+
+        tracing::debug!(%peer, "Reconnecting in {}s", delay.as_secs());
+
+        let mut tasks = Tasks::default();
+        tasks.add(async move {
+            tokio::time::sleep(delay).await;
+            let _ = this.send_async_safe(Reconnect).await;
+        });
+        self.reconnect = Some(tasks);
     }
 }
 
@@ -75,15 +114,10 @@ impl xtra::Actor for Actor {
     async fn started(&mut self, ctx: &mut xtra::Context<Self>) {
         let this = ctx.address().expect("we are alive");
 
-        if let Err(e) = self.connect().await {
+        if let Err(e) = self.start(this).await {
             self.stop_reason = Some(e);
             ctx.stop();
         }
-
-        // Only start checking the connection after it had enough time to be established
-        tokio::time::sleep(CHECK_INTERVAL).await;
-        self.tasks
-            .add(this.send_interval(CHECK_INTERVAL, || CheckConnection));
     }
 
     async fn stopped(self) -> Self::Stop {
@@ -98,22 +132,67 @@ impl Actor {
         ctx.stop();
     }
 
-    async fn handle(&mut self, _msg: CheckConnection, ctx: &mut xtra::Context<Self>) {
-        if let Err(e) = self.check_connection_active_in_endpoint().await {
+    async fn handle(&mut self, _: Reconnect, ctx: &mut xtra::Context<Self>) {
+        let this = ctx.address().expect("we are alive");
+        self.reconnect = None;
+
+        let result = match self.peer() {
+            Ok(peer) => self.dial(peer, this).await,
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = result {
             self.stop_reason = Some(e);
             ctx.stop();
         }
     }
 }
 
+#[xtra_productivity(message_impl = false)]
+impl Actor {
+    async fn handle(&mut self, msg: ConnectionEvent, ctx: &mut xtra::Context<Self>) {
+        let this = ctx.address().expect("we are alive");
+
+        let peer = match self.peer() {
+            Ok(peer) => peer,
+            Err(_) => return,
+        };
+
+        match msg {
+            ConnectionEvent::Connected(connected) if connected == peer => {
+                self.failed_attempts = 0;
+                self.reconnect = None;
+            }
+            ConnectionEvent::Disconnected(disconnected) if disconnected == peer => {
+                self.schedule_reconnect(peer, this);
+            }
+            ConnectionEvent::DialFailed(failed) if failed == peer => {
+                self.schedule_reconnect(peer, this);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The delay before the next reconnection attempt after `failed_attempts` consecutive failures.
+///
+/// Doubles with every failure up to [`MAX_BACKOFF`]. The actual delay is picked randomly from the
+/// upper half of that range so that many dialers do not reconnect in lockstep.
+fn backoff(failed_attempts: u32) -> Duration {
+    let max = INITIAL_BACKOFF
+        .checked_mul(2u32.saturating_pow(failed_attempts))
+        .unwrap_or(MAX_BACKOFF)
+        .min(MAX_BACKOFF);
+
+    rand::thread_rng().gen_range(max / 2..=max)
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("Dialer failed")]
     Failed { source: anyhow::Error },
     #[error("Endpoint actor is disconnected")]
     NoEndpoint,
-    #[error("Connection dropped from endpoint")]
-    ConnectionDropped,
     #[error("Invalid Peer Id")]
     InvalidPeerId,
     #[error("Stop reason was not specified")]
@@ -121,4 +200,21 @@ pub enum Error {
 }
 
 #[derive(Clone, Copy)]
-pub struct CheckConnection;
+struct Reconnect;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_grows_exponentially_up_to_max() {
+        for failed_attempts in 0..100 {
+            let max =
+                (INITIAL_BACKOFF * 2u32.saturating_pow(failed_attempts.min(16))).min(MAX_BACKOFF);
+            let delay = backoff(failed_attempts);
+
+            assert!(delay <= max, "{delay:?} > {max:?}");
+            assert!(delay >= max / 2, "{delay:?} < {:?}", max / 2);
+        }
+    }
+}
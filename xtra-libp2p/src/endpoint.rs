@@ -1,7 +1,10 @@
+use crate::identify;
+use crate::identify::PeerInfo;
 use crate::multiaddress_ext::MultiaddrExt as _;
 use crate::upgrade;
 use crate::Connection;
 use crate::Substream;
+use anyhow::anyhow;
 use anyhow::bail;
 use anyhow::Context as _;
 use anyhow::Result;
@@ -27,6 +30,7 @@ use std::marker::PhantomData;
 use std::time::Duration;
 use thiserror::Error;
 use tokio_tasks::Tasks;
+use xtra::message_channel::MessageChannel;
 use xtra::message_channel::StrongMessageChannel;
 use xtra_productivity::xtra_productivity;
 use xtras::SendAsyncSafe;
@@ -39,15 +43,24 @@ use xtras::SendAsyncSafe;
 /// be disconnected by sending [`Disconnect`]. Listening for incoming connections is done by sending
 /// a [`ListenOn`] message. To list the current state, send the [`GetConnectionStats`] message.
 ///
+/// The endpoint keeps an address book with all addresses it learned for a peer, either through
+/// [`Connect`] or [`AddAddress`]. Sending [`Dial`] tries all known addresses of a peer in order.
+///
+/// Actors can [`Subscribe`] to [`ConnectionEvent`]s to learn about established and dropped
+/// connections as well as failed dial attempts.
+///
 /// The combination of the above should make it possible to implement a fairly large number of
-/// policies. For example, to maintain a connection to an another endpoint, you can regularly check
-/// if the connection is still established by sending [`GetConnectionStats`] and react accordingly
-/// (f.e. sending [`Connect`] in case the connection has disappeared).
+/// policies. For example, to maintain a connection to an another endpoint, you can subscribe to
+/// [`ConnectionEvent`]s and send [`Dial`] in case the connection has disappeared. The
+/// [`dialer`](crate::dialer) does exactly that.
 ///
 /// Once a connection with a peer is established, both sides can open substreams on top of the
 /// connection. Any incoming substream will - assuming the protocol is supported by the endpoint -
 /// trigger a [`NewInboundSubstream`] message to the actor provided in the constructor.
 /// Opening a new substream can be achieved by sending the [`OpenSubstream`] message.
+///
+/// On every new connection, both sides exchange a [`PeerInfo`] through the [`identify`] protocol.
+/// The information about a connected peer can be retrieved by sending [`GetPeerInfo`].
 pub struct Endpoint {
     transport: Boxed<Connection>,
     tasks: Tasks,
@@ -57,6 +70,10 @@ pub struct Endpoint {
     listen_addresses: HashSet<Multiaddr>,
     inflight_connections: HashSet<PeerId>,
     connection_timeout: Duration,
+    local_info: PeerInfo,
+    peer_infos: HashMap<PeerId, PeerInfo>,
+    address_book: HashMap<PeerId, Vec<Multiaddr>>,
+    subscribers: Vec<Box<dyn MessageChannel<ConnectionEvent>>>,
 }
 
 /// Open a substream to the provided peer.
@@ -115,10 +132,40 @@ impl OpenSubstream<Multiple> {
 #[derive(Debug)]
 pub struct Connect(pub Multiaddr);
 
+/// Connect to the given peer, trying all addresses in the address book in order.
+///
+/// Will fail if we do not know any address of the peer or are already connected to it.
+#[derive(Clone, Copy, Debug)]
+pub struct Dial(pub PeerId);
+
 /// Disconnect from the given peer.
 #[derive(Clone, Copy, Debug)]
 pub struct Disconnect(pub PeerId);
 
+/// Add an address to the address book.
+///
+/// The address must contain a `/p2p` suffix.
+#[derive(Clone, Debug)]
+pub struct AddAddress(pub Multiaddr);
+
+/// Retrieve all known addresses of the given peer.
+#[derive(Clone, Copy, Debug)]
+pub struct GetAddresses(pub PeerId);
+
+/// Subscribe to [`ConnectionEvent`]s.
+pub struct Subscribe(pub Box<dyn MessageChannel<ConnectionEvent>>);
+
+/// Notifies subscribers about changes to the connection with a peer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionEvent {
+    /// A connection with the peer was established, no matter who dialed.
+    Connected(PeerId),
+    /// An established connection with the peer was closed or failed.
+    Disconnected(PeerId),
+    /// We failed to connect to the peer on all addresses we tried.
+    DialFailed(PeerId),
+}
+
 /// Listen on the provided [`Multiaddr`].
 ///
 /// For this to work, the [`Endpoint`] needs to be constructed with a compatible transport.
@@ -136,6 +183,12 @@ pub struct ConnectionStats {
     pub listen_addresses: HashSet<Multiaddr>,
 }
 
+/// Retrieve the [`PeerInfo`] the given peer sent us upon connecting.
+///
+/// Returns `None` if we are not connected to the peer or it has not identified itself (yet).
+#[derive(Clone, Copy, Debug)]
+pub struct GetPeerInfo(pub PeerId);
+
 /// Notifies an actor of a new, inbound substream from the given peer.
 #[derive(Debug)]
 pub struct NewInboundSubstream {
@@ -157,6 +210,8 @@ pub enum Error {
     NoPeerIdInAddress(Multiaddr),
     #[error("Either currently connecting or already connected to peer {0}")]
     AlreadyConnected(PeerId),
+    #[error("No known address for peer {0}")]
+    NoAddress(PeerId),
 }
 
 impl Endpoint {
@@ -164,6 +219,9 @@ impl Endpoint {
     ///
     /// An [`Endpoint`]s identity ([`PeerId`]) will be computed from the given [`Keypair`].
     ///
+    /// Besides the addresses supported by the given transport, the endpoint can listen on and dial
+    /// WebSocket addresses on top of it, e.g. `/ip4/127.0.0.1/tcp/10000/ws` for a TCP transport.
+    ///
     /// The `connection_timeout` is applied to:
     /// 1. Connection upgrades (i.e. noise handshake, yamux upgrade, etc)
     /// 2. Protocol negotiations
@@ -182,17 +240,29 @@ impl Endpoint {
     where
         T: Transport + Clone + Send + Sync + 'static,
         T::Output: AsyncRead + AsyncWrite + Unpin + Send + 'static,
-        T::Error: Send + Sync,
+        T::Error: Send + Sync + 'static,
         T::Listener: Send + 'static,
         T::Dial: Send + 'static,
         T::ListenerUpgrade: Send + 'static,
     {
+        let mut protocols = inbound_substream_handlers
+            .iter()
+            .map(|(proto, _)| *proto)
+            .collect::<Vec<_>>();
+        protocols.sort_unstable();
+
+        let local_info = PeerInfo {
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            agent: identify::DEFAULT_AGENT.to_owned(),
+            protocols: protocols.iter().map(|proto| proto.to_string()).collect(),
+        };
+
         let transport = upgrade::transport(
             transport,
             &identity,
-            inbound_substream_handlers
-                .iter()
-                .map(|(proto, _)| *proto)
+            protocols
+                .into_iter()
+                .chain([identify::PROTOCOL_NAME])
                 .collect(),
             connection_timeout,
         );
@@ -205,12 +275,33 @@ impl Endpoint {
             listen_addresses: HashSet::default(),
             inflight_connections: HashSet::default(),
             connection_timeout,
+            local_info,
+            peer_infos: HashMap::default(),
+            address_book: HashMap::default(),
+            subscribers: Vec::default(),
         }
     }
 
-    fn drop_connection(&mut self, peer: &PeerId) {
+    /// Configure the version and agent advertised to peers through the [`identify`] protocol.
+    ///
+    /// Defaults to the version of this library.
+    pub fn with_identify_info(
+        mut self,
+        version: impl Into<String>,
+        agent: impl Into<String>,
+    ) -> Self {
+        self.local_info.version = version.into();
+        self.local_info.agent = agent.into();
+
+        self
+    }
+
+    /// Drop the connection to the given peer, returning whether we were connected.
+    fn drop_connection(&mut self, peer: &PeerId) -> bool {
+        self.peer_infos.remove(peer);
+
         let (mut control, tasks) = match self.controls.remove(peer) {
-            None => return,
+            None => return false,
             Some(control) => control,
         };
 
@@ -219,6 +310,82 @@ impl Endpoint {
             let _ = control.close().await;
             drop(tasks);
         });
+
+        true
+    }
+
+    fn add_address(&mut self, peer: PeerId, address: Multiaddr) {
+        let addresses = self.address_book.entry(peer).or_default();
+
+        if !addresses.contains(&address) {
+            addresses.push(address);
+        }
+    }
+
+    /// Dial the given addresses of the peer in order until one of them succeeds.
+    fn dial(
+        &mut self,
+        peer: PeerId,
+        addresses: Vec<Multiaddr>,
+        this: xtra::Address<Self>,
+    ) -> Result<(), Error> {
+        if self.inflight_connections.contains(&peer) || self.controls.contains_key(&peer) {
+            return Err(Error::AlreadyConnected(peer));
+        }
+
+        self.inflight_connections.insert(peer);
+        self.tasks.add_fallible(
+            {
+                let transport = self.transport.clone();
+                let this = this.clone();
+
+                async move {
+                    let mut last_error = None;
+
+                    for address in addresses {
+                        let connection = match transport.clone().dial(address.clone()) {
+                            Ok(dial) => dial.await.map_err(anyhow::Error::from),
+                            Err(e) => Err(anyhow::Error::from(e)),
+                        };
+
+                        match connection {
+                            Ok((peer, control, incoming_substreams, worker)) => {
+                                let _ = this
+                                    .send_async_safe(NewConnection {
+                                        peer,
+                                        control,
+                                        incoming_substreams,
+                                        worker,
+                                    })
+                                    .await;
+
+                                return Ok(());
+                            }
+                            Err(e) => {
+                                tracing::debug!(%address, "Failed to dial: {e:#}");
+                                last_error = Some(e.context(format!("Failed to dial {address}")));
+                            }
+                        }
+                    }
+
+                    Err(last_error.unwrap_or_else(|| anyhow!("No address to dial")))
+                }
+            },
+            move |error| async move {
+                let _ = this.send(FailedToConnect { peer, error }).await;
+            },
+        );
+
+        Ok(())
+    }
+
+    async fn notify(&mut self, event: ConnectionEvent) {
+        self.subscribers
+            .retain(|subscriber| subscriber.is_connected());
+
+        for subscriber in self.subscribers.iter() {
+            let _ = subscriber.send_async_safe(event).await;
+        }
     }
 
     async fn open_substream(
@@ -265,6 +432,7 @@ impl Endpoint {
         tasks.add(worker);
         tasks.add_fallible(
             {
+                let local_info = self.local_info.clone();
                 let inbound_substream_channels = self
                     .inbound_substream_channels
                     .iter()
@@ -277,8 +445,10 @@ impl Endpoint {
                     .collect::<HashMap<_, _>>();
 
                 async move {
+                    let mut identify_tasks = Tasks::default();
+
                     loop {
-                        let (stream, protocol) = match incoming_substreams.try_next().await {
+                        let (mut stream, protocol) = match incoming_substreams.try_next().await {
                             Ok(Some(Ok((stream, protocol)))) => (stream, protocol),
                             Ok(Some(Err(upgrade::Error::NegotiationTimeoutReached))) => {
                                 tracing::debug!("Hit timeout while negotiating substream");
@@ -292,6 +462,17 @@ impl Endpoint {
                             Err(e) => bail!(e),
                         };
 
+                        if protocol == identify::PROTOCOL_NAME {
+                            let local_info = local_info.clone();
+                            identify_tasks.add_fallible(
+                                async move { identify::send(&mut stream, &local_info).await },
+                                move |e| async move {
+                                    tracing::debug!(%peer, "Failed to send identify info: {e:#}");
+                                },
+                            );
+                            continue;
+                        }
+
                         let channel = inbound_substream_channels
                             .get(&protocol)
                             .expect("Cannot negotiate a protocol that we don't support");
@@ -305,11 +486,63 @@ impl Endpoint {
                     }
                 }
             },
-            move |error| async move {
-                let _ = this.send(ExistingConnectionFailed { peer, error }).await;
+            {
+                let this = this.clone();
+                move |error| async move {
+                    let _ = this.send(ExistingConnectionFailed { peer, error }).await;
+                }
+            },
+        );
+        tasks.add_fallible(
+            {
+                let mut control = control.clone();
+                let timeout = self.connection_timeout;
+
+                async move {
+                    let stream = control.open_stream().await?;
+                    let (_, mut stream) = tokio::time::timeout(
+                        timeout,
+                        multistream_select::dialer_select_proto(
+                            stream,
+                            vec![identify::PROTOCOL_NAME],
+                            Version::V1,
+                        ),
+                    )
+                    .await
+                    .context("Timeout while negotiating identify protocol")??;
+                    let info = tokio::time::timeout(timeout, identify::recv(&mut stream))
+                        .await
+                        .context("Timeout while receiving identify info")??;
+
+                    this.send_async_safe(PeerIdentified { peer, info }).await?;
+
+                    anyhow::Ok(())
+                }
+            },
+            move |e| async move {
+                tracing::debug!(%peer, "Failed to identify peer: {e:#}");
             },
         );
         self.controls.insert(peer, (control, tasks));
+
+        self.notify(ConnectionEvent::Connected(peer)).await;
+    }
+
+    async fn handle(&mut self, msg: PeerIdentified) {
+        let PeerIdentified { peer, info } = msg;
+
+        // The connection may have been dropped in the meantime.
+        if !self.controls.contains_key(&peer) {
+            return;
+        }
+
+        tracing::debug!(%peer, version = %info.version, agent = %info.agent, "Peer identified");
+
+        self.peer_infos.insert(peer, info);
+    }
+
+    async fn handle(&mut self, msg: GetPeerInfo) -> Option<PeerInfo> {
+        self.peer_infos.get(&msg.0).cloned()
     }
 
     async fn handle(&mut self, msg: ListenerFailed) {
@@ -324,13 +557,17 @@ impl Endpoint {
 
         self.inflight_connections.remove(&peer);
         self.drop_connection(&peer);
+
+        self.notify(ConnectionEvent::DialFailed(peer)).await;
     }
 
     async fn handle(&mut self, msg: ExistingConnectionFailed) {
         tracing::debug!("Connection failed: {:#}", msg.error);
         let peer = msg.peer;
 
-        self.drop_connection(&peer);
+        if self.drop_connection(&peer) {
+            self.notify(ConnectionEvent::Disconnected(peer)).await;
+        }
     }
 
     async fn handle(&mut self, _: GetConnectionStats) -> ConnectionStats {
@@ -349,42 +586,50 @@ impl Endpoint {
             .extract_peer_id()
             .ok_or_else(|| Error::NoPeerIdInAddress(msg.0.clone()))?;
 
-        if self.inflight_connections.contains(&peer) || self.controls.contains_key(&peer) {
-            return Err(Error::AlreadyConnected(peer));
-        }
+        self.dial(peer, vec![msg.0.clone()], this)?;
+        self.add_address(peer, msg.0);
 
-        self.inflight_connections.insert(peer);
-        self.tasks.add_fallible(
-            {
-                let transport = self.transport.clone();
-                let this = this.clone();
+        Ok(())
+    }
 
-                async move {
-                    let (peer, control, incoming_substreams, worker) =
-                        transport.clone().dial(msg.0)?.await?;
-
-                    let _ = this
-                        .send_async_safe(NewConnection {
-                            peer,
-                            control,
-                            incoming_substreams,
-                            worker,
-                        })
-                        .await;
+    async fn handle(&mut self, msg: Dial, ctx: &mut xtra::Context<Self>) -> Result<(), Error> {
+        let this = ctx.address().expect("we are alive");
+        let peer = msg.0;
 
-                    anyhow::Ok(())
-                }
-            },
-            move |error| async move {
-                let _ = this.send(FailedToConnect { peer, error }).await;
-            },
-        );
+        let addresses = self
+            .address_book
+            .get(&peer)
+            .cloned()
+            .filter(|addresses| !addresses.is_empty())
+            .ok_or(Error::NoAddress(peer))?;
 
-        Ok(())
+        self.dial(peer, addresses, this)
     }
 
     async fn handle(&mut self, msg: Disconnect) {
-        self.drop_connection(&msg.0);
+        if self.drop_connection(&msg.0) {
+            self.notify(ConnectionEvent::Disconnected(msg.0)).await;
+        }
+    }
+
+    async fn handle(&mut self, msg: AddAddress) -> Result<(), Error> {
+        let peer = msg
+            .0
+            .clone()
+            .extract_peer_id()
+            .ok_or_else(|| Error::NoPeerIdInAddress(msg.0.clone()))?;
+
+        self.add_address(peer, msg.0);
+
+        Ok(())
+    }
+
+    async fn handle(&mut self, msg: GetAddresses) -> Vec<Multiaddr> {
+        self.address_book.get(&msg.0).cloned().unwrap_or_default()
+    }
+
+    async fn handle(&mut self, msg: Subscribe) {
+        self.subscribers.push(msg.0);
     }
 
     async fn handle(&mut self, msg: ListenOn, ctx: &mut xtra::Context<Self>) {
@@ -533,6 +778,12 @@ struct ExistingConnectionFailed {
     error: anyhow::Error,
 }
 
+#[derive(Debug)]
+struct PeerIdentified {
+    peer: PeerId,
+    info: PeerInfo,
+}
+
 struct NewListenAddress {
     listen_address: Multiaddr,
 }
@@ -554,3 +805,7 @@ struct NewConnection {
 impl xtra::Message for NewInboundSubstream {
     type Result = ();
 }
+
+impl xtra::Message for ConnectionEvent {
+    type Result = ();
+}
@@ -0,0 +1,151 @@
+//! A minimal identify protocol.
+//!
+//! Right after a connection is established, both endpoints open a substream with
+//! [`PROTOCOL_NAME`] to each other. The listening side of the substream answers with its
+//! [`PeerInfo`] and closes the substream.
+//!
+//! The message is encoded as a sequence of strings, each prefixed with its length as big-endian
+//! `u16`: version, agent, number of protocols (as `u16`), protocols.
+
+use anyhow::bail;
+use anyhow::Context as _;
+use anyhow::Result;
+use futures::AsyncRead;
+use futures::AsyncReadExt;
+use futures::AsyncWrite;
+use futures::AsyncWriteExt;
+
+pub const PROTOCOL_NAME: &str = "/xtra-libp2p/identify/1.0.0";
+
+/// The agent advertised if none is configured.
+pub const DEFAULT_AGENT: &str = concat!("xtra-libp2p/", env!("CARGO_PKG_VERSION"));
+
+const MAX_STRING_LEN: usize = 256;
+const MAX_PROTOCOLS: usize = 128;
+
+/// What a peer told us about itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerInfo {
+    /// The version of the application running on the peer.
+    pub version: String,
+    /// A free-form description of the application, e.g. its name.
+    pub agent: String,
+    /// The protocols the peer accepts inbound substreams for.
+    pub protocols: Vec<String>,
+}
+
+impl PeerInfo {
+    pub fn supports(&self, protocol: &str) -> bool {
+        self.protocols.iter().any(|p| p == protocol)
+    }
+}
+
+pub(crate) async fn send<S>(stream: &mut S, info: &PeerInfo) -> Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    if info.protocols.len() > MAX_PROTOCOLS {
+        bail!("Cannot advertise more than {MAX_PROTOCOLS} protocols");
+    }
+
+    let mut bytes = Vec::new();
+    write_string(&mut bytes, &info.version)?;
+    write_string(&mut bytes, &info.agent)?;
+    bytes.extend_from_slice(&(info.protocols.len() as u16).to_be_bytes());
+    for protocol in info.protocols.iter() {
+        write_string(&mut bytes, protocol)?;
+    }
+
+    stream.write_all(&bytes).await?;
+    stream.flush().await?;
+    stream.close().await?;
+
+    Ok(())
+}
+
+pub(crate) async fn recv<S>(stream: &mut S) -> Result<PeerInfo>
+where
+    S: AsyncRead + Unpin,
+{
+    let version = read_string(stream)
+        .await
+        .context("Failed to read version")?;
+    let agent = read_string(stream).await.context("Failed to read agent")?;
+
+    let num_protocols = read_u16(stream).await? as usize;
+    if num_protocols > MAX_PROTOCOLS {
+        bail!("Peer advertises {num_protocols} protocols, at most {MAX_PROTOCOLS} are allowed");
+    }
+
+    let mut protocols = Vec::with_capacity(num_protocols);
+    for _ in 0..num_protocols {
+        protocols.push(
+            read_string(stream)
+                .await
+                .context("Failed to read protocol")?,
+        );
+    }
+
+    Ok(PeerInfo {
+        version,
+        agent,
+        protocols,
+    })
+}
+
+fn write_string(bytes: &mut Vec<u8>, string: &str) -> Result<()> {
+    if string.len() > MAX_STRING_LEN {
+        bail!("String exceeds {MAX_STRING_LEN} bytes: {string}");
+    }
+
+    bytes.extend_from_slice(&(string.len() as u16).to_be_bytes());
+    bytes.extend_from_slice(string.as_bytes());
+
+    Ok(())
+}
+
+async fn read_string<S>(stream: &mut S) -> Result<String>
+where
+    S: AsyncRead + Unpin,
+{
+    let len = read_u16(stream).await? as usize;
+    if len > MAX_STRING_LEN {
+        bail!("String of {len} bytes exceeds {MAX_STRING_LEN} bytes");
+    }
+
+    let mut bytes = vec![0u8; len];
+    stream.read_exact(&mut bytes).await?;
+
+    Ok(String::from_utf8(bytes)?)
+}
+
+async fn read_u16<S>(stream: &mut S) -> Result<u16>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut bytes = [0u8; 2];
+    stream.read_exact(&mut bytes).await?;
+
+    Ok(u16::from_be_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn peer_info_roundtrip() {
+        let info = PeerInfo {
+            version: "0.4.8".to_owned(),
+            agent: "itchysats-maker".to_owned(),
+            protocols: vec!["/foo/1.0.0".to_owned(), "/bar/1.0.0".to_owned()],
+        };
+
+        let mut bytes = futures::io::Cursor::new(Vec::new());
+        send(&mut bytes, &info).await.unwrap();
+        bytes.set_position(0);
+        let received = recv(&mut bytes).await.unwrap();
+
+        assert_eq!(received, info);
+    }
+}
@@ -1,14 +1,22 @@
+pub use crate::endpoint::AddAddress;
 pub use crate::endpoint::Connect;
+pub use crate::endpoint::ConnectionEvent;
 pub use crate::endpoint::ConnectionStats;
+pub use crate::endpoint::Dial;
 pub use crate::endpoint::Disconnect;
 pub use crate::endpoint::Endpoint;
 pub use crate::endpoint::Error;
+pub use crate::endpoint::GetAddresses;
 pub use crate::endpoint::GetConnectionStats;
+pub use crate::endpoint::GetPeerInfo;
 pub use crate::endpoint::ListenOn;
 pub use crate::endpoint::Multiple;
 pub use crate::endpoint::NewInboundSubstream;
 pub use crate::endpoint::OpenSubstream;
 pub use crate::endpoint::Single;
+pub use crate::endpoint::Subscribe;
+pub use crate::identify::PeerInfo;
+pub use crate::multiaddress_ext::MultiaddrExt;
 pub use crate::substream::Substream;
 pub use libp2p_core as libp2p;
 pub use multistream_select::NegotiationError;
@@ -20,6 +28,7 @@ use libp2p_core::PeerId;
 
 pub mod dialer;
 mod endpoint;
+pub mod identify;
 pub mod listener;
 mod multiaddress_ext;
 mod substream;
@@ -10,11 +10,13 @@ use futures::StreamExt;
 use libp2p_core::identity::Keypair;
 use libp2p_core::transport::timeout::TransportTimeout;
 use libp2p_core::transport::Boxed;
+use libp2p_core::transport::OrTransport;
 use libp2p_core::upgrade;
 use libp2p_core::upgrade::Version;
 use libp2p_core::Endpoint;
 use libp2p_core::Transport;
 use libp2p_noise as noise;
+use libp2p_websocket::WsConfig;
 use multistream_select::NegotiationError;
 use std::time::Duration;
 use void::Void;
@@ -23,6 +25,7 @@ use yamux::Mode;
 /// Upgrades the given [`Transport`].
 ///
 /// We apply:
+/// - WebSocket framing for `/ws` addresses, using the given transport underneath
 /// - Noise encryption and authentication
 /// - PeerID verification for each connection
 /// - Yamux multiplexing
@@ -36,7 +39,7 @@ pub fn transport<T>(
 where
     T: Transport + Clone + Send + Sync + 'static,
     T::Output: AsyncRead + AsyncWrite + Unpin + Send + 'static,
-    T::Error: Send + Sync,
+    T::Error: Send + Sync + 'static,
     T::Listener: Send + 'static,
     T::Dial: Send + 'static,
     T::ListenerUpgrade: Send + 'static,
@@ -45,6 +48,8 @@ where
         .into_authentic(identity)
         .expect("ed25519 signing does not fail");
 
+    let transport = OrTransport::new(WsConfig::new(transport.clone()), transport);
+
     let authenticated = transport.and_then(|conn, endpoint| {
         upgrade::apply(
             conn,
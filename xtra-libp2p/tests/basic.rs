@@ -6,6 +6,8 @@ use futures::SinkExt;
 use futures::StreamExt;
 use libp2p_core::multiaddr::Protocol;
 use libp2p_core::Multiaddr;
+use libp2p_tcp::TokioTcpConfig;
+use rand::Rng;
 use std::collections::HashSet;
 use std::time::Duration;
 use tokio_tasks::Tasks;
@@ -13,16 +15,24 @@ use xtra::message_channel::StrongMessageChannel;
 use xtra::spawn::TokioGlobalSpawnExt;
 use xtra::Actor;
 use xtra::Address;
+use xtra_libp2p::dialer;
 use xtra_libp2p::libp2p::identity::Keypair;
 use xtra_libp2p::libp2p::transport::MemoryTransport;
 use xtra_libp2p::libp2p::PeerId;
+use xtra_libp2p::AddAddress;
 use xtra_libp2p::Connect;
+use xtra_libp2p::ConnectionEvent;
+use xtra_libp2p::Dial;
 use xtra_libp2p::Disconnect;
 use xtra_libp2p::Endpoint;
+use xtra_libp2p::GetAddresses;
 use xtra_libp2p::GetConnectionStats;
+use xtra_libp2p::GetPeerInfo;
 use xtra_libp2p::ListenOn;
 use xtra_libp2p::NewInboundSubstream;
 use xtra_libp2p::OpenSubstream;
+use xtra_libp2p::PeerInfo;
+use xtra_libp2p::Subscribe;
 use xtra_productivity::xtra_productivity;
 
 #[tokio::test]
@@ -51,6 +61,49 @@ async fn hello_world() {
     assert_eq!(string, "Hello Bob!");
 }
 
+#[tokio::test]
+async fn hello_world_over_websocket() {
+    let alice_hello_world_handler = HelloWorld::default().create(None).spawn_global();
+    let port = rand::thread_rng().gen_range(10_000..60_000);
+
+    let (alice_peer_id, alice) = make_tcp_endpoint([(
+        "/hello-world/1.0.0",
+        alice_hello_world_handler.clone_channel(),
+    )]);
+    let (_, bob) = make_tcp_endpoint([]);
+
+    alice
+        .send(ListenOn(
+            format!("/ip4/127.0.0.1/tcp/{port}/ws").parse().unwrap(),
+        ))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await; // Give the listener time to bind.
+
+    bob.send(Connect(
+        format!("/ip4/127.0.0.1/tcp/{port}/ws/p2p/{alice_peer_id}")
+            .parse()
+            .unwrap(),
+    ))
+    .await
+    .unwrap()
+    .unwrap();
+    wait_for_connection(&bob, alice_peer_id).await;
+
+    let bob_to_alice = bob
+        .send(OpenSubstream::single_protocol(
+            alice_peer_id,
+            "/hello-world/1.0.0",
+        ))
+        .await
+        .unwrap()
+        .unwrap();
+
+    let string = hello_world_dialer(bob_to_alice, "Bob").await.unwrap();
+
+    assert_eq!(string, "Hello Bob!");
+}
+
 #[tokio::test]
 async fn after_connect_see_each_other_as_connected() {
     let (alice_peer_id, bob_peer_id, alice, bob, _) = alice_and_bob([], []).await;
@@ -62,6 +115,38 @@ async fn after_connect_see_each_other_as_connected() {
     assert_eq!(bob_stats.connected_peers, HashSet::from([alice_peer_id]));
 }
 
+#[tokio::test]
+async fn after_connect_know_each_others_peer_info() {
+    let alice_hello_world_handler = HelloWorld::default().create(None).spawn_global();
+    let (alice_peer_id, bob_peer_id, alice, bob, _) = alice_and_bob(
+        [(
+            "/hello-world/1.0.0",
+            alice_hello_world_handler.clone_channel(),
+        )],
+        [],
+    )
+    .await;
+
+    let alice_info = wait_for_peer_info(&bob, alice_peer_id).await;
+    let bob_info = wait_for_peer_info(&alice, bob_peer_id).await;
+
+    assert_eq!(alice_info.agent, xtra_libp2p::identify::DEFAULT_AGENT);
+    assert_eq!(alice_info.protocols, vec!["/hello-world/1.0.0"]);
+    assert!(alice_info.supports("/hello-world/1.0.0"));
+    assert_eq!(bob_info.protocols, Vec::<String>::new());
+}
+
+#[tokio::test]
+async fn disconnect_forgets_peer_info() {
+    let (alice_peer_id, bob_peer_id, alice, bob, _) = alice_and_bob([], []).await;
+    wait_for_peer_info(&bob, alice_peer_id).await;
+
+    bob.send(Disconnect(alice_peer_id)).await.unwrap();
+
+    assert_eq!(bob.send(GetPeerInfo(alice_peer_id)).await.unwrap(), None);
+    assert_eq!(alice.send(GetPeerInfo(bob_peer_id)).await.unwrap(), None);
+}
+
 #[tokio::test]
 async fn disconnect_is_reflected_in_stats() {
     let (_, bob_peer_id, alice, bob, _) = alice_and_bob([], []).await;
@@ -106,6 +191,127 @@ async fn cannot_open_substream_for_unhandled_protocol() {
     ))
 }
 
+#[tokio::test]
+async fn dial_tries_all_known_addresses() {
+    let port = rand::random::<u16>();
+    let (alice_peer_id, alice) = make_endpoint([]);
+    let (_, bob) = make_endpoint([]);
+
+    alice
+        .send(ListenOn(format!("/memory/{port}").parse().unwrap()))
+        .await
+        .unwrap();
+
+    let unreachable = format!("/memory/{}/p2p/{alice_peer_id}", port.wrapping_add(1))
+        .parse::<Multiaddr>()
+        .unwrap();
+    let reachable = format!("/memory/{port}/p2p/{alice_peer_id}")
+        .parse::<Multiaddr>()
+        .unwrap();
+    bob.send(AddAddress(unreachable.clone()))
+        .await
+        .unwrap()
+        .unwrap();
+    bob.send(AddAddress(reachable.clone()))
+        .await
+        .unwrap()
+        .unwrap();
+
+    bob.send(Dial(alice_peer_id)).await.unwrap().unwrap();
+
+    wait_for_connection(&bob, alice_peer_id).await;
+    assert_eq!(
+        bob.send(GetAddresses(alice_peer_id)).await.unwrap(),
+        vec![unreachable, reachable]
+    );
+}
+
+#[tokio::test]
+async fn cannot_dial_peer_without_address() {
+    let (_, bob) = make_endpoint([]);
+    let peer_id = PeerId::random();
+
+    let error = bob.send(Dial(peer_id)).await.unwrap().unwrap_err();
+
+    assert!(matches!(
+        error,
+        xtra_libp2p::Error::NoAddress(peer) if peer == peer_id
+    ))
+}
+
+#[tokio::test]
+async fn subscribers_are_notified_about_connection_events() {
+    let port = rand::random::<u16>();
+    let (alice_peer_id, alice) = make_endpoint([]);
+    let (_, bob) = make_endpoint([]);
+    let events = ConnectionEvents::default().create(None).spawn_global();
+
+    bob.send(Subscribe(Box::new(events.clone()))).await.unwrap();
+    alice
+        .send(ListenOn(format!("/memory/{port}").parse().unwrap()))
+        .await
+        .unwrap();
+    bob.send(Connect(
+        format!("/memory/{port}/p2p/{alice_peer_id}")
+            .parse()
+            .unwrap(),
+    ))
+    .await
+    .unwrap()
+    .unwrap();
+    wait_for_connection(&bob, alice_peer_id).await;
+    bob.send(Disconnect(alice_peer_id)).await.unwrap();
+
+    assert_eq!(
+        events.send(GetEvents).await.unwrap(),
+        vec![
+            ConnectionEvent::Connected(alice_peer_id),
+            ConnectionEvent::Disconnected(alice_peer_id)
+        ]
+    );
+}
+
+#[tokio::test]
+async fn dialer_redials_after_connection_is_killed() {
+    let port = rand::random::<u16>();
+    let (alice_peer_id, alice) = make_endpoint([]);
+    let (bob_peer_id, bob) = make_endpoint([]);
+    let events = ConnectionEvents::default().create(None).spawn_global();
+
+    bob.send(Subscribe(Box::new(events.clone()))).await.unwrap();
+    alice
+        .send(ListenOn(format!("/memory/{port}").parse().unwrap()))
+        .await
+        .unwrap();
+    let _dialer = dialer::Actor::new(
+        bob.clone(),
+        format!("/memory/{port}/p2p/{alice_peer_id}")
+            .parse()
+            .unwrap(),
+    )
+    .create(None)
+    .spawn_global();
+    wait_for_connection(&bob, alice_peer_id).await;
+
+    alice.send(Disconnect(bob_peer_id)).await.unwrap();
+    assert!(!bob
+        .send(GetConnectionStats)
+        .await
+        .unwrap()
+        .connected_peers
+        .contains(&alice_peer_id));
+
+    wait_for_connection(&bob, alice_peer_id).await;
+    assert_eq!(
+        events.send(GetEvents).await.unwrap(),
+        vec![
+            ConnectionEvent::Connected(alice_peer_id),
+            ConnectionEvent::Disconnected(alice_peer_id),
+            ConnectionEvent::Connected(alice_peer_id)
+        ]
+    );
+}
+
 #[tokio::test]
 async fn cannot_connect_twice() {
     let (alice_peer_id, _bob_peer_id, _alice, bob, alice_listen) = alice_and_bob([], []).await;
@@ -226,6 +432,36 @@ async fn alice_and_bob<const AN: usize, const BN: usize>(
     (alice_peer_id, bob_peer_id, alice, bob, alice_listen)
 }
 
+async fn wait_for_connection(endpoint: &Address<Endpoint>, peer: PeerId) {
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while !endpoint
+            .send(GetConnectionStats)
+            .await
+            .unwrap()
+            .connected_peers
+            .contains(&peer)
+        {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("peer to be connected")
+}
+
+async fn wait_for_peer_info(endpoint: &Address<Endpoint>, peer: PeerId) -> PeerInfo {
+    tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            if let Some(info) = endpoint.send(GetPeerInfo(peer)).await.unwrap() {
+                return info;
+            }
+
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("peer to identify itself")
+}
+
 fn make_endpoint<const N: usize>(
     substream_handlers: [(
         &'static str,
@@ -247,6 +483,27 @@ fn make_endpoint<const N: usize>(
     (peer_id, endpoint)
 }
 
+fn make_tcp_endpoint<const N: usize>(
+    substream_handlers: [(
+        &'static str,
+        Box<dyn StrongMessageChannel<NewInboundSubstream>>,
+    ); N],
+) -> (PeerId, Address<Endpoint>) {
+    let id = Keypair::generate_ed25519();
+    let peer_id = id.public().to_peer_id();
+
+    let endpoint = Endpoint::new(
+        TokioTcpConfig::new(),
+        id,
+        Duration::from_secs(20),
+        substream_handlers,
+    )
+    .create(None)
+    .spawn_global();
+
+    (peer_id, endpoint)
+}
+
 #[derive(Default)]
 struct HelloWorld {
     tasks: Tasks,
@@ -271,6 +528,34 @@ impl Actor for HelloWorld {
     async fn stopped(self) -> Self::Stop {}
 }
 
+#[derive(Default)]
+struct ConnectionEvents {
+    events: Vec<ConnectionEvent>,
+}
+
+#[xtra_productivity(message_impl = false)]
+impl ConnectionEvents {
+    async fn handle(&mut self, msg: ConnectionEvent) {
+        self.events.push(msg);
+    }
+}
+
+#[xtra_productivity]
+impl ConnectionEvents {
+    async fn handle(&mut self, _: GetEvents) -> Vec<ConnectionEvent> {
+        self.events.clone()
+    }
+}
+
+#[async_trait]
+impl Actor for ConnectionEvents {
+    type Stop = ();
+
+    async fn stopped(self) -> Self::Stop {}
+}
+
+struct GetEvents;
+
 async fn hello_world_dialer(stream: xtra_libp2p::Substream, name: &'static str) -> Result<String> {
     let mut stream = asynchronous_codec::Framed::new(stream, asynchronous_codec::LengthCodec);
 
@@ -0,0 +1,48 @@
+use crate::PriceSource;
+use anyhow::Result;
+use xtra_bitmex_price_feed::Quote;
+use xtra_bitmex_price_feed::QUOTE_INTERVAL_MINUTES;
+
+const URL: &str = "wss://www.bitmex.com/realtime";
+
+/// Quotes for XBTUSD from BitMex's `quoteBin1m` topic.
+pub struct BitMex {
+    url: String,
+}
+
+impl BitMex {
+    pub fn new() -> Self {
+        Self::with_url(URL)
+    }
+
+    /// Connect to a different endpoint than BitMex's production API.
+    pub fn with_url(url: impl Into<String>) -> Self {
+        Self { url: url.into() }
+    }
+}
+
+impl Default for BitMex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PriceSource for BitMex {
+    fn name(&self) -> &'static str {
+        "bitmex"
+    }
+
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn subscriptions(&self) -> Vec<String> {
+        vec![format!(
+            r#"{{"op":"subscribe","args":["quoteBin{QUOTE_INTERVAL_MINUTES}m:XBTUSD"]}}"#
+        )]
+    }
+
+    fn parse(&self, text: &str) -> Result<Option<Quote>> {
+        Quote::from_bitmex_message(text)
+    }
+}
@@ -0,0 +1,119 @@
+use crate::PriceSource;
+use anyhow::Context;
+use anyhow::Result;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::str::FromStr;
+use time::OffsetDateTime;
+use xtra_bitmex_price_feed::Quote;
+
+const URL: &str = "wss://ws.bitstamp.net";
+
+/// Quotes for BTC/USD from the top of Bitstamp's `order_book_btcusd` channel.
+pub struct Bitstamp {
+    url: String,
+}
+
+impl Bitstamp {
+    pub fn new() -> Self {
+        Self::with_url(URL)
+    }
+
+    /// Connect to a different endpoint than Bitstamp's production API.
+    pub fn with_url(url: impl Into<String>) -> Self {
+        Self { url: url.into() }
+    }
+}
+
+impl Default for Bitstamp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PriceSource for Bitstamp {
+    fn name(&self) -> &'static str {
+        "bitstamp"
+    }
+
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn subscriptions(&self) -> Vec<String> {
+        vec![r#"{"event":"bts:subscribe","data":{"channel":"order_book_btcusd"}}"#.to_owned()]
+    }
+
+    fn parse(&self, text: &str) -> Result<Option<Quote>> {
+        let message = match serde_json::from_str::<wire::DataMessage>(text) {
+            Ok(message) if message.event == "data" => message,
+            _ => {
+                tracing::trace!(%text, "Not a 'data' message, skipping...");
+                return Ok(None);
+            }
+        };
+
+        let (bid, ask) = match (message.data.bids.first(), message.data.asks.first()) {
+            (Some([bid, _]), Some([ask, _])) => (bid, ask),
+            _ => return Ok(None),
+        };
+
+        let timestamp = message
+            .data
+            .timestamp
+            .parse::<i64>()
+            .context("Failed to parse timestamp")?;
+
+        Ok(Some(Quote {
+            timestamp: OffsetDateTime::from_unix_timestamp(timestamp)?,
+            bid: Decimal::from_str(bid).context("Failed to parse bid price")?,
+            ask: Decimal::from_str(ask).context("Failed to parse ask price")?,
+        }))
+    }
+}
+
+mod wire {
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    pub struct DataMessage {
+        pub event: String,
+        pub data: OrderBook,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct OrderBook {
+        /// Unix timestamp in seconds.
+        pub timestamp: String,
+        /// Price levels as `[price, amount]`, best bid first.
+        pub bids: Vec<[String; 2]>,
+        /// Price levels as `[price, amount]`, best ask first.
+        pub asks: Vec<[String; 2]>,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn can_deserialize_order_book_message() {
+        let quote = Bitstamp::new().parse(r#"{"data":{"timestamp":"1632192000","microtimestamp":"1632192000000000","bids":[["42640.5","0.1"],["42640","1.2"]],"asks":[["42641","0.3"],["42642","2.0"]]},"channel":"order_book_btcusd","event":"data"}"#).unwrap().unwrap();
+
+        assert_eq!(quote.bid, dec!(42640.5));
+        assert_eq!(quote.ask, dec!(42641));
+        assert_eq!(quote.timestamp.unix_timestamp(), 1632192000)
+    }
+
+    #[test]
+    fn skips_subscription_confirmation() {
+        let quote = Bitstamp::new()
+            .parse(
+                r#"{"event":"bts:subscription_succeeded","channel":"order_book_btcusd","data":{}}"#,
+            )
+            .unwrap();
+
+        assert!(quote.is_none())
+    }
+}
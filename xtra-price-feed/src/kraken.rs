@@ -0,0 +1,115 @@
+use crate::PriceSource;
+use anyhow::Context;
+use anyhow::Result;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::str::FromStr;
+use time::OffsetDateTime;
+use xtra_bitmex_price_feed::Quote;
+
+const URL: &str = "wss://ws.kraken.com";
+
+/// Quotes for XBT/USD from Kraken's `ticker` channel.
+///
+/// Kraken's ticker messages don't carry a timestamp, hence quotes are timestamped upon receipt.
+pub struct Kraken {
+    url: String,
+}
+
+impl Kraken {
+    pub fn new() -> Self {
+        Self::with_url(URL)
+    }
+
+    /// Connect to a different endpoint than Kraken's production API.
+    pub fn with_url(url: impl Into<String>) -> Self {
+        Self { url: url.into() }
+    }
+}
+
+impl Default for Kraken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PriceSource for Kraken {
+    fn name(&self) -> &'static str {
+        "kraken"
+    }
+
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn subscriptions(&self) -> Vec<String> {
+        vec![
+            r#"{"event":"subscribe","pair":["XBT/USD"],"subscription":{"name":"ticker"}}"#
+                .to_owned(),
+        ]
+    }
+
+    fn parse(&self, text: &str) -> Result<Option<Quote>> {
+        // Channel messages are arrays, events (heartbeats, subscription status) are objects.
+        if !text.trim_start().starts_with('[') {
+            tracing::trace!(%text, "Not a channel message, skipping...");
+            return Ok(None);
+        }
+
+        let (_, ticker, channel, _) =
+            match serde_json::from_str::<(u64, wire::Ticker, String, String)>(text) {
+                Ok(message) => message,
+                Err(e) => {
+                    tracing::warn!(%text, "Failed to parse channel message: {e:#}");
+                    return Ok(None);
+                }
+            };
+
+        if channel != "ticker" {
+            return Ok(None);
+        }
+
+        let (ask, ..) = ticker.a;
+        let (bid, ..) = ticker.b;
+
+        Ok(Some(Quote {
+            timestamp: OffsetDateTime::now_utc(),
+            bid: Decimal::from_str(&bid).context("Failed to parse bid price")?,
+            ask: Decimal::from_str(&ask).context("Failed to parse ask price")?,
+        }))
+    }
+}
+
+mod wire {
+    use super::*;
+
+    /// Only the prices are parsed, the volumes are sent as strings or integers.
+    #[derive(Debug, Deserialize)]
+    pub struct Ticker {
+        /// Best ask as `[price, whole lot volume, lot volume]`.
+        pub a: (String, serde_json::Value, serde_json::Value),
+        /// Best bid as `[price, whole lot volume, lot volume]`.
+        pub b: (String, serde_json::Value, serde_json::Value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn can_deserialize_ticker_message() {
+        let quote = Kraken::new().parse(r#"[340,{"a":["42641.00000",1,"1.000"],"b":["42640.50000",0,"0.500"],"c":["42640.90000","0.00500000"],"v":["1.0","2.0"],"p":["1.0","2.0"],"t":[1,2],"l":["1.0","2.0"],"h":["1.0","2.0"],"o":["1.0","2.0"]},"ticker","XBT/USD"]"#).unwrap().unwrap();
+
+        assert_eq!(quote.bid, dec!(42640.5));
+        assert_eq!(quote.ask, dec!(42641));
+    }
+
+    #[test]
+    fn skips_heartbeat() {
+        let quote = Kraken::new().parse(r#"{"event":"heartbeat"}"#).unwrap();
+
+        assert!(quote.is_none())
+    }
+}
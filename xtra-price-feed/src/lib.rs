@@ -0,0 +1,408 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_tasks::Tasks;
+use xtra::Address;
+use xtra_productivity::xtra_productivity;
+
+pub use crate::bitmex::BitMex;
+pub use crate::bitstamp::Bitstamp;
+pub use crate::kraken::Kraken;
+pub use crate::source::subscribe;
+pub use crate::source::PriceSource;
+pub use xtra_bitmex_price_feed::LatestQuote;
+pub use xtra_bitmex_price_feed::Quote;
+
+mod bitmex;
+mod bitstamp;
+mod kraken;
+mod source;
+
+/// How long to wait before reconnecting to a source whose stream failed or ended.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// An actor that connects to several exchanges and combines their quotes into a single [`Quote`].
+///
+/// Each source is streamed in its own task and reconnected independently, so an outage of one
+/// exchange does not affect the others. Quotes older than the configured maximum age are
+/// considered stale and excluded from the aggregate until the source recovers.
+pub struct Actor {
+    sources: Vec<(Arc<dyn PriceSource>, Decimal)>,
+    aggregation: Aggregation,
+    max_quote_age: time::Duration,
+    latest_quotes: HashMap<&'static str, Quote>,
+    stale_sources: HashSet<&'static str>,
+    tasks: Tasks,
+}
+
+/// How the quotes of the individual sources are combined.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Aggregation {
+    /// Take the median of bid and ask respectively.
+    ///
+    /// Robust against a single source reporting a wildly different price.
+    Median,
+    /// Take the mean of bid and ask respectively, weighted by the weight of each source.
+    WeightedMean,
+}
+
+impl Actor {
+    pub fn new(aggregation: Aggregation, max_quote_age: time::Duration) -> Self {
+        Self {
+            sources: Vec::new(),
+            aggregation,
+            max_quote_age,
+            latest_quotes: HashMap::new(),
+            stale_sources: HashSet::new(),
+            tasks: Tasks::default(),
+        }
+    }
+
+    /// Aggregate the median quote of all supported exchanges.
+    pub fn all_exchanges(max_quote_age: time::Duration) -> Self {
+        Self::new(Aggregation::Median, max_quote_age)
+            .with_source(BitMex::new())
+            .with_source(Kraken::new())
+            .with_source(Bitstamp::new())
+    }
+
+    /// Add a source with a weight of 1.
+    pub fn with_source(self, source: impl PriceSource) -> Self {
+        self.with_weighted_source(source, Decimal::ONE)
+    }
+
+    /// Add a source with the given weight.
+    ///
+    /// The weight is only taken into account for [`Aggregation::WeightedMean`].
+    pub fn with_weighted_source(mut self, source: impl PriceSource, weight: Decimal) -> Self {
+        self.sources.push((Arc::new(source), weight));
+        self
+    }
+
+    /// Returns the names of all sources whose latest quote is fresh enough to be used, together
+    /// with that quote.
+    fn fresh_quotes(&self) -> Vec<(&'static str, Quote)> {
+        self.latest_quotes
+            .iter()
+            .filter(|(_, quote)| !quote.is_older_than(self.max_quote_age))
+            .map(|(name, quote)| (*name, *quote))
+            .collect()
+    }
+
+    fn update_staleness(&mut self) {
+        for (source, _) in self.sources.iter() {
+            let name = source.name();
+            let is_stale = self
+                .latest_quotes
+                .get(name)
+                .map(|quote| quote.is_older_than(self.max_quote_age))
+                .unwrap_or(true);
+
+            match (is_stale, self.stale_sources.contains(name)) {
+                (true, false) => {
+                    tracing::warn!(source = %name, "Price source became stale");
+                    self.stale_sources.insert(name);
+                }
+                (false, true) => {
+                    tracing::info!(source = %name, "Price source recovered");
+                    self.stale_sources.remove(name);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn weight(&self, name: &str) -> Decimal {
+        self.sources
+            .iter()
+            .find(|(source, _)| source.name() == name)
+            .map(|(_, weight)| *weight)
+            .unwrap_or(Decimal::ZERO)
+    }
+}
+
+#[async_trait]
+impl xtra::Actor for Actor {
+    type Stop = Error;
+
+    async fn started(&mut self, ctx: &mut xtra::Context<Self>) {
+        let this = ctx.address().expect("we are alive");
+
+        for (source, _) in self.sources.iter() {
+            // Every source is marked stale until it delivered its first quote.
+            self.stale_sources.insert(source.name());
+
+            self.tasks.add(stream_quotes(source.clone(), this.clone()));
+        }
+    }
+
+    async fn stopped(self) -> Self::Stop {
+        Error::Unspecified
+    }
+}
+
+/// Stream quotes from the given source to the aggregator, reconnecting whenever the stream fails.
+///
+/// Returns once the aggregator is disconnected.
+async fn stream_quotes(source: Arc<dyn PriceSource>, this: Address<Actor>) {
+    let name = source.name();
+
+    loop {
+        let mut stream = subscribe(source.as_ref());
+
+        loop {
+            let text = match stream.try_next().await {
+                Ok(Some(text)) => text,
+                Ok(None) => {
+                    tracing::warn!(source = %name, "Websocket stream closed");
+                    break;
+                }
+                Err(e) => {
+                    tracing::warn!(source = %name, "Connection failed: {e:#}");
+                    break;
+                }
+            };
+
+            let quote = match source.parse(&text) {
+                Ok(Some(quote)) => quote,
+                Ok(None) => continue,
+                Err(e) => {
+                    tracing::warn!(source = %name, "Failed to parse quote: {e:#}");
+                    continue;
+                }
+            };
+
+            tracing::trace!(source = %name, bid = %quote.bid, ask = %quote.ask, "Received new quote");
+
+            if this
+                .send(NewQuoteReceived {
+                    source: name,
+                    quote,
+                })
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+
+        tokio::time::sleep(RECONNECT_INTERVAL).await;
+    }
+}
+
+#[xtra_productivity]
+impl Actor {
+    async fn handle(&mut self, msg: NewQuoteReceived) {
+        self.latest_quotes.insert(msg.source, msg.quote);
+        self.update_staleness();
+    }
+
+    async fn handle(&mut self, _: GetSourceStatus) -> Vec<SourceStatus> {
+        self.update_staleness();
+
+        self.sources
+            .iter()
+            .map(|(source, _)| {
+                let name = source.name();
+
+                SourceStatus {
+                    name,
+                    latest_quote: self.latest_quotes.get(name).copied(),
+                    is_stale: self.stale_sources.contains(name),
+                }
+            })
+            .collect()
+    }
+}
+
+#[xtra_productivity(message_impl = false)]
+impl Actor {
+    async fn handle(&mut self, _: LatestQuote) -> Option<Quote> {
+        self.update_staleness();
+
+        let quotes = self.fresh_quotes();
+
+        match self.aggregation {
+            Aggregation::Median => median(&quotes),
+            Aggregation::WeightedMean => {
+                let weighted = quotes
+                    .into_iter()
+                    .map(|(name, quote)| (quote, self.weight(name)))
+                    .collect::<Vec<_>>();
+
+                weighted_mean(&weighted)
+            }
+        }
+    }
+}
+
+/// Combine the quotes by taking the median of bid and ask respectively.
+///
+/// The timestamp of the result is the one of the oldest quote involved, hence the aggregate is
+/// never considered fresher than its inputs.
+fn median(quotes: &[(&'static str, Quote)]) -> Option<Quote> {
+    let timestamp = quotes.iter().map(|(_, quote)| quote.timestamp).min()?;
+
+    let mut bids = quotes
+        .iter()
+        .map(|(_, quote)| quote.bid)
+        .collect::<Vec<_>>();
+    let mut asks = quotes
+        .iter()
+        .map(|(_, quote)| quote.ask)
+        .collect::<Vec<_>>();
+
+    Some(Quote {
+        timestamp,
+        bid: median_of(&mut bids)?,
+        ask: median_of(&mut asks)?,
+    })
+}
+
+fn median_of(values: &mut [Decimal]) -> Option<Decimal> {
+    values.sort();
+
+    let len = values.len();
+    let mid = len / 2;
+
+    match len {
+        0 => None,
+        len if len % 2 == 1 => Some(values[mid]),
+        _ => Some((values[mid - 1] + values[mid]) / Decimal::TWO),
+    }
+}
+
+/// Combine the quotes by taking the weighted mean of bid and ask respectively.
+///
+/// Returns `None` if the sum of all weights is zero.
+fn weighted_mean(quotes: &[(Quote, Decimal)]) -> Option<Quote> {
+    let timestamp = quotes.iter().map(|(quote, _)| quote.timestamp).min()?;
+    let total_weight = quotes.iter().map(|(_, weight)| *weight).sum::<Decimal>();
+
+    if total_weight.is_zero() {
+        return None;
+    }
+
+    let bid = quotes
+        .iter()
+        .map(|(quote, weight)| quote.bid * weight)
+        .sum::<Decimal>()
+        / total_weight;
+    let ask = quotes
+        .iter()
+        .map(|(quote, weight)| quote.ask * weight)
+        .sum::<Decimal>()
+        / total_weight;
+
+    Some(Quote {
+        timestamp,
+        bid,
+        ask,
+    })
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Stop reason was not specified")]
+    Unspecified,
+}
+
+/// Private message to update our internal state with the latest quote of a source.
+#[derive(Debug)]
+struct NewQuoteReceived {
+    source: &'static str,
+    quote: Quote,
+}
+
+/// Request the status of all configured sources.
+#[derive(Debug, Clone, Copy)]
+pub struct GetSourceStatus;
+
+#[derive(Debug, Clone, Copy)]
+pub struct SourceStatus {
+    pub name: &'static str,
+    pub latest_quote: Option<Quote>,
+    /// Whether the source is currently excluded from the aggregate because it did not deliver a
+    /// recent enough quote.
+    pub is_stale: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use time::ext::NumericalDuration;
+    use time::OffsetDateTime;
+
+    #[test]
+    fn median_of_odd_number_of_quotes_is_middle_quote() {
+        let quotes = [
+            ("a", dummy_quote(dec!(100), dec!(101))),
+            ("b", dummy_quote(dec!(300), dec!(301))),
+            ("c", dummy_quote(dec!(200), dec!(201))),
+        ];
+
+        let quote = median(&quotes).unwrap();
+
+        assert_eq!(quote.bid, dec!(200));
+        assert_eq!(quote.ask, dec!(201));
+    }
+
+    #[test]
+    fn median_of_even_number_of_quotes_is_mean_of_middle_quotes() {
+        let quotes = [
+            ("a", dummy_quote(dec!(100), dec!(101))),
+            ("b", dummy_quote(dec!(200), dec!(201))),
+        ];
+
+        let quote = median(&quotes).unwrap();
+
+        assert_eq!(quote.bid, dec!(150));
+        assert_eq!(quote.ask, dec!(151));
+    }
+
+    #[test]
+    fn median_takes_oldest_timestamp() {
+        let now = OffsetDateTime::now_utc();
+        let older = Quote {
+            timestamp: now - 30.seconds(),
+            ..dummy_quote(dec!(100), dec!(101))
+        };
+
+        let quote = median(&[("a", dummy_quote(dec!(100), dec!(101))), ("b", older)]).unwrap();
+
+        assert_eq!(quote.timestamp, older.timestamp);
+    }
+
+    #[test]
+    fn weighted_mean_respects_weights() {
+        let quotes = [
+            (dummy_quote(dec!(100), dec!(101)), dec!(3)),
+            (dummy_quote(dec!(200), dec!(201)), dec!(1)),
+        ];
+
+        let quote = weighted_mean(&quotes).unwrap();
+
+        assert_eq!(quote.bid, dec!(125));
+        assert_eq!(quote.ask, dec!(126));
+    }
+
+    #[test]
+    fn no_quotes_yield_no_aggregate() {
+        assert!(median(&[]).is_none());
+        assert!(weighted_mean(&[]).is_none());
+    }
+
+    fn dummy_quote(bid: Decimal, ask: Decimal) -> Quote {
+        Quote {
+            timestamp: OffsetDateTime::now_utc(),
+            bid,
+            ask,
+        }
+    }
+}
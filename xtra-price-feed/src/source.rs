@@ -0,0 +1,94 @@
+use anyhow::Result;
+use async_stream::stream;
+use futures::SinkExt;
+use futures::Stream;
+use futures::StreamExt;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite;
+use xtra_bitmex_price_feed::Quote;
+
+pub use tokio_tungstenite::tungstenite::Error;
+
+/// An exchange that publishes quotes over a websocket API.
+///
+/// Implementations only describe how to talk to the exchange, the connection itself is managed by
+/// the [`Actor`](crate::Actor).
+pub trait PriceSource: Send + Sync + 'static {
+    /// The name of the exchange, used to identify the source in logs and status reports.
+    fn name(&self) -> &'static str;
+
+    /// The websocket URL to connect to.
+    fn url(&self) -> &str;
+
+    /// Messages to send right after the connection was established, e.g. to subscribe to a
+    /// channel.
+    fn subscriptions(&self) -> Vec<String>;
+
+    /// Parse a text message received from the exchange.
+    ///
+    /// Returns `None` for messages which don't contain a quote, e.g. heartbeats or subscription
+    /// confirmations.
+    fn parse(&self, text: &str) -> Result<Option<Quote>>;
+}
+
+/// Connects to the websocket API of the given source, sends all subscription messages and yields
+/// all text messages.
+///
+/// To keep the connection alive, a websocket `Ping` will be sent every 5 seconds in case no other
+/// message was received in-between.
+pub fn subscribe(source: &dyn PriceSource) -> impl Stream<Item = Result<String, Error>> + Unpin {
+    let name = source.name();
+    let url = source.url().to_owned();
+    let subscriptions = source.subscriptions();
+
+    let stream = stream! {
+        tracing::debug!(source = %name, "Connecting to realtime API");
+
+        let (mut connection, _) = tokio_tungstenite::connect_async(url).await?;
+
+        for subscription in subscriptions {
+            connection.send(tungstenite::Message::Text(subscription)).await?;
+        }
+
+        tracing::info!(source = %name, "Connected to realtime API");
+
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(Duration::from_secs(5)) => {
+                    tracing::trace!(source = %name, "No message in the last 5 seconds, pinging");
+                    let _ = connection.send(tungstenite::Message::Ping([0u8; 32].to_vec())).await;
+                },
+                msg = connection.next() => {
+                    let msg = match msg {
+                        Some(Ok(msg)) => {
+                            msg
+                        },
+                        None => {
+                            return;
+                        }
+                        Some(Err(e)) => {
+                            yield Err(e);
+                            return;
+                        }
+                    };
+
+                    match msg {
+                        tungstenite::Message::Pong(_) => {
+                            tracing::trace!(source = %name, "Received pong");
+                            continue;
+                        }
+                        tungstenite::Message::Text(text) => {
+                            yield Ok(text);
+                        }
+                        other => {
+                            tracing::trace!(source = %name, "Unsupported message: {:?}", other);
+                            continue;
+                        }
+                    }
+                }
+            }
+        }
+    };
+
+    stream.boxed()
+}
@@ -0,0 +1,209 @@
+use futures::SinkExt;
+use futures::StreamExt;
+use rust_decimal_macros::dec;
+use std::time::Duration;
+use time::ext::NumericalDuration;
+use time::OffsetDateTime;
+use tokio::net::TcpListener;
+use tokio_tungstenite::tungstenite::Message;
+use xtra::spawn::TokioGlobalSpawnExt;
+use xtra::Actor as _;
+use xtra::Address;
+use xtra_price_feed::Actor;
+use xtra_price_feed::Aggregation;
+use xtra_price_feed::BitMex;
+use xtra_price_feed::Bitstamp;
+use xtra_price_feed::GetSourceStatus;
+use xtra_price_feed::Kraken;
+use xtra_price_feed::LatestQuote;
+use xtra_price_feed::Quote;
+
+#[tokio::test]
+async fn aggregates_median_of_all_sources() {
+    let now = OffsetDateTime::now_utc();
+    let bitmex = stand_in(vec![bitmex_quote(now, "100", "101")]).await;
+    let kraken = stand_in(vec![kraken_quote("300", "301")]).await;
+    let bitstamp = stand_in(vec![bitstamp_quote(now, "200", "201")]).await;
+
+    let price_feed = Actor::new(Aggregation::Median, 1.minutes())
+        .with_source(BitMex::with_url(bitmex))
+        .with_source(Kraken::with_url(kraken))
+        .with_source(Bitstamp::with_url(bitstamp))
+        .create(None)
+        .spawn_global();
+
+    let quote = wait_for_quote(&price_feed, |quote| quote.bid == dec!(200)).await;
+
+    assert_eq!(quote.ask, dec!(201));
+}
+
+#[tokio::test]
+async fn aggregates_weighted_mean_of_all_sources() {
+    let now = OffsetDateTime::now_utc();
+    let bitmex = stand_in(vec![bitmex_quote(now, "100", "101")]).await;
+    let bitstamp = stand_in(vec![bitstamp_quote(now, "200", "201")]).await;
+
+    let price_feed = Actor::new(Aggregation::WeightedMean, 1.minutes())
+        .with_weighted_source(BitMex::with_url(bitmex), dec!(3))
+        .with_weighted_source(Bitstamp::with_url(bitstamp), dec!(1))
+        .create(None)
+        .spawn_global();
+
+    let quote = wait_for_quote(&price_feed, |quote| quote.bid == dec!(125)).await;
+
+    assert_eq!(quote.ask, dec!(126));
+}
+
+#[tokio::test]
+async fn stale_source_is_excluded() {
+    let now = OffsetDateTime::now_utc();
+    let bitmex = stand_in(vec![bitmex_quote(now - 1.hours(), "100", "101")]).await;
+    let bitstamp = stand_in(vec![bitstamp_quote(now, "200", "201")]).await;
+
+    let price_feed = Actor::new(Aggregation::Median, 1.minutes())
+        .with_source(BitMex::with_url(bitmex))
+        .with_source(Bitstamp::with_url(bitstamp))
+        .create(None)
+        .spawn_global();
+
+    let quote = wait_for_quote(&price_feed, |quote| quote.bid == dec!(200)).await;
+    assert_eq!(quote.ask, dec!(201));
+
+    let status = price_feed.send(GetSourceStatus).await.unwrap();
+    let bitmex_status = status.iter().find(|s| s.name == "bitmex").unwrap();
+    let bitstamp_status = status.iter().find(|s| s.name == "bitstamp").unwrap();
+
+    assert!(bitmex_status.is_stale);
+    assert!(!bitstamp_status.is_stale);
+}
+
+#[tokio::test]
+async fn unreachable_source_does_not_block_quotes() {
+    let now = OffsetDateTime::now_utc();
+    let unreachable = unused_url().await;
+    let bitstamp = stand_in(vec![bitstamp_quote(now, "200", "201")]).await;
+
+    let price_feed = Actor::new(Aggregation::Median, 1.minutes())
+        .with_source(BitMex::with_url(unreachable))
+        .with_source(Bitstamp::with_url(bitstamp))
+        .create(None)
+        .spawn_global();
+
+    let quote = wait_for_quote(&price_feed, |quote| quote.bid == dec!(200)).await;
+
+    assert_eq!(quote.ask, dec!(201));
+}
+
+#[tokio::test]
+async fn no_quote_if_all_sources_are_stale() {
+    let now = OffsetDateTime::now_utc();
+    let bitstamp = stand_in(vec![bitstamp_quote(now - 1.hours(), "200", "201")]).await;
+
+    let price_feed = Actor::new(Aggregation::Median, 1.minutes())
+        .with_source(Bitstamp::with_url(bitstamp))
+        .create(None)
+        .spawn_global();
+
+    // Give the stand-in time to deliver its quote.
+    wait_for_status(&price_feed).await;
+
+    let quote = price_feed.send(LatestQuote).await.unwrap();
+
+    assert!(quote.is_none());
+}
+
+/// Spawn a local websocket server which sends the given messages to every client after it
+/// received the client's subscription message.
+///
+/// Returns the URL of the server.
+async fn stand_in(messages: Vec<String>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let messages = messages.clone();
+
+            tokio::spawn(async move {
+                let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+
+                // wait for the subscription
+                let _ = ws.next().await;
+
+                for message in messages {
+                    ws.send(Message::Text(message)).await.unwrap();
+                }
+
+                // keep the connection open
+                while ws.next().await.is_some() {}
+            });
+        }
+    });
+
+    url
+}
+
+/// Returns a websocket URL that nobody is listening on.
+async fn unused_url() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    drop(listener);
+
+    url
+}
+
+async fn wait_for_quote(price_feed: &Address<Actor>, predicate: impl Fn(&Quote) -> bool) -> Quote {
+    tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            if let Some(quote) = price_feed.send(LatestQuote).await.unwrap() {
+                if predicate(&quote) {
+                    return quote;
+                }
+            }
+
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("quote within timeout")
+}
+
+async fn wait_for_status(price_feed: &Address<Actor>) {
+    tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            let status = price_feed.send(GetSourceStatus).await.unwrap();
+
+            if status.iter().all(|s| s.latest_quote.is_some()) {
+                return;
+            }
+
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("all sources to deliver a quote within timeout")
+}
+
+fn bitmex_quote(timestamp: OffsetDateTime, bid: &str, ask: &str) -> String {
+    let timestamp = timestamp
+        .format(&time::format_description::well_known::Rfc3339)
+        .unwrap();
+
+    format!(
+        r#"{{"table":"quoteBin1m","action":"insert","data":[{{"timestamp":"{timestamp}","symbol":"XBTUSD","bidSize":50200,"bidPrice":{bid},"askPrice":{ask},"askSize":363600}}]}}"#
+    )
+}
+
+fn kraken_quote(bid: &str, ask: &str) -> String {
+    format!(
+        r#"[340,{{"a":["{ask}",1,"1.000"],"b":["{bid}",0,"0.500"],"c":["{bid}","0.005"]}},"ticker","XBT/USD"]"#
+    )
+}
+
+fn bitstamp_quote(timestamp: OffsetDateTime, bid: &str, ask: &str) -> String {
+    let timestamp = timestamp.unix_timestamp();
+
+    format!(
+        r#"{{"data":{{"timestamp":"{timestamp}","bids":[["{bid}","0.1"]],"asks":[["{ask}","0.3"]]}},"channel":"order_book_btcusd","event":"data"}}"#
+    )
+}